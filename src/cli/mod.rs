@@ -1,4 +1,9 @@
-use clap::{Parser, Subcommand};
+use crate::services::kv::domain::KvKey;
+use crate::services::mail::archive::ArchiveFormat;
+use crate::services::mail::domain::{Folder, Priority};
+use crate::services::task::domain::TaskStatus;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "agent-office")]
@@ -17,12 +22,30 @@ pub enum Commands {
     /// Find your coworkers, let your coworkers know your status, and register yourself as a coworker
     #[command(subcommand)]
     Agent(AgentCommands),
+    /// Organize coworkers into groups you can mail as @group
+    #[command(subcommand)]
+    Group(GroupCommands),
     /// A Zettelkasten knowledge base with Markdown support for all coworkers to share
     #[command(subcommand)]
     Kb(KbCommands),
     /// Manage scheduled tasks for agents
     #[command(subcommand)]
     Schedule(ScheduleCommands),
+    /// Track who is working on what: create, assign, start and finish tasks
+    #[command(subcommand)]
+    Task(TaskCommands),
+    /// Break projects into steps that wait on each other, and find what's ready
+    #[command(subcommand)]
+    Work(WorkCommands),
+    /// Ask the human to sign off before doing something destructive
+    #[command(subcommand)]
+    Approval(ApprovalCommands),
+    /// A shared blackboard of named values, like the current release branch
+    #[command(subcommand)]
+    Kv(KvCommands),
+    /// Named locks so agents don't work on the same thing at once
+    #[command(subcommand)]
+    Lock(LockCommands),
     /// Human-only tools (not for AI agents)
     #[command(subcommand)]
     Human(HumanCommands),
@@ -44,9 +67,26 @@ pub enum HumanCommands {
         /// Port to listen on
         #[arg(short, long, default_value = "8080")]
         port: u16,
+        /// Process due delayed mail and apply the retention policy in the
+        /// background every N hours
+        #[arg(long)]
+        gc_interval_hours: Option<u64>,
+        #[command(flatten)]
+        retention: RetentionArgs,
     },
 }
 
+/// How long to keep mail; shared by `db gc` and the web server's background task
+#[derive(Args, Clone, Debug)]
+pub struct RetentionArgs {
+    /// Archive mail a recipient has read once it is this many days old (0 = never)
+    #[arg(long, default_value = "30")]
+    pub archive_after_days: u32,
+    /// Delete mail for everyone this many days after it was last delivered (0 = never)
+    #[arg(long, default_value = "365")]
+    pub delete_after_days: u32,
+}
+
 #[derive(Subcommand)]
 pub enum MailCommands {
     /// View recent mail for an agent (last 24 hours)
@@ -54,44 +94,273 @@ pub enum MailCommands {
         /// Agent ID to view mail for
         agent_id: String,
     },
-    /// Send mail from one agent to another (SIMPLE - uses agent names only!)
+    /// Send mail from one agent to others (SIMPLE - uses agent names only!)
+    /// Repeat --to/--cc or separate names with commas for several recipients;
+    /// @group sends to every member of a group
     Send {
         #[arg(short, long)]
         from: String,
-        #[arg(short, long)]
-        to: String,
+        #[arg(short, long, value_delimiter = ',', required_unless_present = "to_all")]
+        to: Vec<String>,
+        /// Agents to copy on the mail
+        #[arg(long, value_delimiter = ',')]
+        cc: Vec<String>,
+        /// Send to every registered agent except the sender
+        #[arg(long, conflicts_with = "to")]
+        to_all: bool,
         #[arg(short, long)]
         subject: String,
         #[arg(short, long)]
         body: String,
+        /// low, normal or urgent
+        #[arg(short, long, default_value = "normal")]
+        priority: Priority,
+        /// Labels every recipient starts out with
+        #[arg(short, long, value_delimiter = ',')]
+        label: Vec<String>,
+        /// JSON object to send as a structured payload ('-' reads stdin)
+        #[arg(long)]
+        payload_file: Option<PathBuf>,
+        /// Content type of the payload; checked against its registered schema
+        #[arg(long, requires = "payload_file")]
+        content_type: Option<String>,
+        /// File to attach (repeat for several)
+        #[arg(long)]
+        attach: Vec<PathBuf>,
+        /// Deliver later: +30m, +2h, +1d, 09:00, "tomorrow 09:00",
+        /// "2026-03-01 09:00" (local time) or RFC 3339
+        #[arg(long)]
+        at: Option<String>,
+    },
+    /// Cancel delayed mail before it is delivered
+    Cancel {
+        /// Mail number in the sender's mailbox (#42) or short ID
+        mail_id: String,
+        /// Sending agent; only the sender can cancel
+        #[arg(short, long)]
+        from: String,
     },
     /// View inbox of an agent
+    /// Usage: mail inbox alice --label bug --priority urgent
     Inbox {
         /// Agent ID to view inbox for
         agent_id: String,
+        /// Folder to list: inbox, archive or trash
+        #[arg(short, long, default_value = "inbox")]
+        folder: Folder,
+        /// Only mail with this label
+        #[arg(short, long)]
+        label: Option<String>,
+        /// Only mail with this priority
+        #[arg(short, long)]
+        priority: Option<Priority>,
     },
     /// View outbox (sent items) of an agent
     Outbox {
         /// Agent ID to view outbox for
         agent_id: String,
     },
-    /// Mark mail as read by short ID (first 8 chars of UUID)
+    /// Mark mail as read by number (alice#42) or short ID
     Read {
-        /// Short mail ID (first 8 characters of UUID)
+        /// Mail number (alice#42) or short ID (first 8 characters of UUID)
         mail_id: String,
+        /// Agent reading it; short IDs are looked up in their mailbox
+        #[arg(short, long)]
+        agent: Option<String>,
+    },
+    /// Print a mail's payload as JSON (for piping into other tools)
+    Payload {
+        /// Mail number (alice#42) or short ID (first 8 characters of UUID)
+        mail_id: String,
+        /// Agent whose mailbox to look for a short ID in
+        #[arg(short, long)]
+        agent: Option<String>,
+    },
+    /// Manage the JSON Schemas that payloads are validated against
+    #[command(subcommand)]
+    Schema(SchemaCommands),
+    /// List and download files attached to mail
+    #[command(subcommand)]
+    Attachment(AttachmentCommands),
+    /// Filter rules that label, file, mark read or forward incoming mail
+    #[command(subcommand)]
+    Rule(RuleCommands),
+    /// Move mail to a folder (inbox, archive or trash)
+    /// Usage: mail move alice#42 archive
+    Move {
+        /// Mail number (alice#42) or short ID (first 8 characters of UUID)
+        mail_id: String,
+        /// Destination folder
+        folder: Folder,
+        /// Agent whose copy to move (needed when the mail has several recipients)
+        #[arg(short, long)]
+        agent: Option<String>,
+    },
+    /// Add or remove labels on a mail
+    /// Usage: mail label alice#42 --add bug --remove triage
+    Label {
+        /// Mail number (alice#42) or short ID (first 8 characters of UUID)
+        mail_id: String,
+        /// Agent whose labels to change (needed when the mail has several recipients)
+        #[arg(short, long)]
+        agent: Option<String>,
+        #[arg(long, value_delimiter = ',')]
+        add: Vec<String>,
+        #[arg(long, value_delimiter = ',')]
+        remove: Vec<String>,
+    },
+    /// Reply to a mail, keeping it in the same conversation thread
+    Reply {
+        /// Mail number (alice#42) or short ID (first 8 characters of UUID)
+        mail_id: String,
+        /// Replying agent; short IDs are looked up in their mailbox
+        /// (defaults to the recipient of the original mail)
+        #[arg(short, long)]
+        from: Option<String>,
+        #[arg(short, long)]
+        body: String,
     },
     /// Check if agent should look at their mail (has unread messages)
     ShouldLook {
         /// Agent ID to check
         agent_id: String,
     },
-    /// Search mail by subject or body content
+    /// Search an agent's mail, received and sent
+    /// Usage: mail search alice 'from:bob subject:deploy is:unread after:2026-01-01 label:ci "exact phrase"'
     Search {
         /// Agent ID to search mail for
         agent_id: String,
-        /// Search query string (searches in subject and body)
+        /// Words and "quoted phrases" to find, plus any of from:, subject:,
+        /// is:read|unread, label:, in:inbox|archive|trash, after: and before:
+        /// (YYYY-MM-DD)
         query: String,
     },
+    /// Export everything an agent has sent and received
+    /// Usage: mail export alice --format mbox -o alice.mbox
+    Export {
+        /// Agent ID to export mail for
+        agent_id: String,
+        /// mbox, eml (one file per mail, needs --output) or jsonl
+        #[arg(short, long, default_value = "mbox")]
+        format: ArchiveFormat,
+        /// File (or directory, for eml) to write; stdout if omitted
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Import an mbox file, JSONL file, or EML file or directory into an
+    /// agent's mailbox; mail already there is skipped
+    /// Usage: mail import alice alice.mbox
+    Import {
+        /// Agent ID to import mail for
+        agent_id: String,
+        /// Archive to read
+        path: PathBuf,
+        /// mbox, eml or jsonl (guessed from the path if omitted)
+        #[arg(short, long)]
+        format: Option<ArchiveFormat>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SchemaCommands {
+    /// Register (or replace) the schema for a content type
+    /// Usage: mail schema register review-request review.schema.json
+    Register {
+        /// Content type the schema applies to
+        content_type: String,
+        /// File containing the JSON Schema
+        file: PathBuf,
+    },
+    /// Remove the schema for a content type
+    Remove {
+        content_type: String,
+    },
+    /// List registered schemas
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum AttachmentCommands {
+    /// List a mail's attachments
+    List {
+        /// Mail number (alice#42) or short ID (first 8 characters of UUID)
+        mail_id: String,
+        /// Agent whose mailbox to look for a short ID in
+        #[arg(short, long)]
+        agent: Option<String>,
+    },
+    /// Save an attachment by short ID (shown by `mail read` and `attachment list`)
+    /// Usage: mail attachment get 9f8e7d6c -o build.log
+    Get {
+        /// Short attachment ID (first 8 characters of UUID)
+        attachment_id: String,
+        /// Where to write it ('-' for stdout); defaults to its file name in
+        /// the current directory
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Overwrite the output file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RuleCommands {
+    /// Add a rule; every condition given must match, then every action runs
+    /// Usage: mail rule add triage --name ci-failures --from ci_bot --subject-contains FAILED --label ci --forward-to oncall
+    Add {
+        /// Agent whose incoming mail the rule applies to
+        agent_id: String,
+        #[arg(short, long)]
+        name: String,
+        #[command(flatten)]
+        rule: RuleArgs,
+    },
+    /// List an agent's rules in the order they run
+    List {
+        agent_id: String,
+    },
+    /// Remove a rule by short ID
+    Remove {
+        /// Short rule ID (first 8 characters of UUID)
+        rule_id: String,
+    },
+    /// Turn a rule back on
+    Enable {
+        /// Short rule ID (first 8 characters of UUID)
+        rule_id: String,
+    },
+    /// Turn a rule off without removing it
+    Disable {
+        /// Short rule ID (first 8 characters of UUID)
+        rule_id: String,
+    },
+}
+
+/// Conditions and actions of a mail rule
+#[derive(Args, Clone, Debug)]
+pub struct RuleArgs {
+    /// Only mail from this agent
+    #[arg(long)]
+    pub from: Option<String>,
+    /// Only mail whose subject contains this (ignoring case)
+    #[arg(long)]
+    pub subject_contains: Option<String>,
+    /// Only mail whose body contains this (ignoring case)
+    #[arg(long)]
+    pub body_contains: Option<String>,
+    /// Label to add
+    #[arg(short, long, value_delimiter = ',')]
+    pub label: Vec<String>,
+    /// Folder to file it in: inbox, archive or trash
+    #[arg(long)]
+    pub move_to: Option<Folder>,
+    /// Mark it read
+    #[arg(long)]
+    pub mark_read: bool,
+    /// Agents to forward a copy to
+    #[arg(long, value_delimiter = ',')]
+    pub forward_to: Vec<String>,
 }
 
 #[derive(Subcommand)]
@@ -126,9 +395,30 @@ pub enum AgentCommands {
         agent_id: String,
         /// Bash command to execute when unread mail is found
         bash: String,
-        /// Interval in seconds between checks (default: 60)
+        /// Interval in seconds between checks when polling (default: 60)
         #[arg(short, long, default_value = "60")]
         interval: u64,
+        /// Seconds between safety-net checks while Postgres notifications
+        /// wake the agent (default: 600)
+        #[arg(long, default_value = "600")]
+        fallback_interval: u64,
+        /// Don't wake up for unread mail that has been archived
+        #[arg(long)]
+        ignore_archived: bool,
+    },
+    /// Answer mail automatically while the agent is offline or away
+    /// Usage: agent auto-reply alice -m "Back Monday" --delegate bob
+    AutoReply {
+        agent_id: String,
+        /// What senders are told
+        #[arg(short, long, required_unless_present = "off")]
+        message: Option<String>,
+        /// Agent that gets a copy of mail arriving while away
+        #[arg(short, long, requires = "message")]
+        delegate: Option<String>,
+        /// Stop auto-replying
+        #[arg(long, conflicts_with = "message")]
+        off: bool,
     },
     /// Set agent session ID for consistent session tracking
     SetSession {
@@ -139,12 +429,54 @@ pub enum AgentCommands {
     },
 }
 
+#[derive(Subcommand)]
+pub enum GroupCommands {
+    /// Create a new, empty group
+    Create {
+        /// Group name (lowercase, no spaces)
+        name: String,
+    },
+    /// Delete a group (its members are not affected)
+    Delete {
+        /// Group name
+        name: String,
+    },
+    /// Add agents to a group
+    /// Usage: group add reviewers alice bob
+    Add {
+        /// Group name
+        group: String,
+        /// Agent IDs to add
+        #[arg(required = true)]
+        agent_ids: Vec<String>,
+    },
+    /// Remove an agent from a group
+    Remove {
+        /// Group name
+        group: String,
+        /// Agent ID to remove
+        agent_id: String,
+    },
+    /// List all groups and their members
+    List,
+}
+
 #[derive(Subcommand)]
 pub enum DbCommands {
-    /// Setup database tables (drops existing tables if they exist)
+    /// Setup database tables for a new office (existing data is kept)
     Setup,
     /// Reset the entire database - drops all data and recreates fresh tables
     Reset,
+    /// Upgrade the schema in place by applying pending migrations
+    Migrate,
+    /// Show which schema migrations have been applied
+    Status,
+    /// Run rules for delayed mail that has come due, archive old read mail
+    /// and delete expired mail
+    Gc {
+        #[command(flatten)]
+        retention: RetentionArgs,
+    },
 }
 
 /// Simplified KB commands - shared knowledge base, only Luhmann IDs
@@ -273,3 +605,263 @@ pub enum ScheduleCommands {
         schedule_id: String,
     },
 }
+
+#[derive(Subcommand)]
+pub enum TaskCommands {
+    /// Create a task, optionally assigning it right away
+    /// Usage: task create -f alice -t "Fix login bug" --assign bob --priority urgent --due 2026-03-01
+    Create {
+        /// Agent creating the task
+        #[arg(short, long)]
+        from: String,
+        #[arg(short, long)]
+        title: String,
+        #[arg(short, long, default_value = "")]
+        description: String,
+        /// Agent to assign it to; they are notified by mail
+        #[arg(short, long)]
+        assign: Option<String>,
+        /// Priority (low, normal, urgent)
+        #[arg(short, long, default_value = "normal")]
+        priority: Priority,
+        /// Due date: 2026-03-01, "2026-03-01 17:00" (local time), +2d or RFC 3339
+        #[arg(long)]
+        due: Option<String>,
+        /// Short IDs of tasks that must be done before this one can start
+        #[arg(long, value_delimiter = ',')]
+        blocked_by: Vec<String>,
+    },
+    /// Assign (or reassign) a task; the assignee is notified by mail
+    Assign {
+        /// Task short ID (first 8 characters of UUID)
+        task_id: String,
+        /// Agent to assign it to
+        agent_id: String,
+        /// Agent handing it over (defaults to whoever created the task)
+        #[arg(long)]
+        by: Option<String>,
+    },
+    /// Start working on a task
+    Start {
+        /// Task short ID (first 8 characters of UUID)
+        task_id: String,
+    },
+    /// Mark a task done; tasks it was blocking can then start
+    Done {
+        /// Task short ID (first 8 characters of UUID)
+        task_id: String,
+    },
+    /// List tasks, work in progress first
+    /// Usage: task list --agent bob
+    List {
+        /// Only tasks assigned to this agent
+        #[arg(short, long)]
+        agent: Option<String>,
+        /// Only tasks with this status (open, in_progress, blocked, done)
+        #[arg(short, long)]
+        status: Option<TaskStatus>,
+        /// Include finished tasks
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum WorkCommands {
+    /// Add a step to a project
+    /// Usage: work add -f alice -t "Build API" --agent bob --project launch --depends-on 1a2b3c4d
+    Add {
+        /// Agent adding the item
+        #[arg(short, long)]
+        from: String,
+        #[arg(short, long)]
+        title: String,
+        #[arg(short, long, default_value = "")]
+        description: String,
+        /// Agent who will do it; mailed when it becomes ready
+        #[arg(short, long)]
+        agent: Option<String>,
+        /// Project the item belongs to
+        #[arg(short, long)]
+        project: Option<String>,
+        /// Short IDs of items that must be complete first
+        #[arg(long, value_delimiter = ',')]
+        depends_on: Vec<String>,
+    },
+    /// Make an item wait for others; refused if that would create a cycle
+    /// Usage: work depend 5e6f7a8b 1a2b3c4d
+    Depend {
+        /// Short ID of the item that waits
+        item_id: String,
+        /// Short IDs of the items it waits for
+        #[arg(required = true)]
+        depends_on: Vec<String>,
+    },
+    /// Mark an item complete; assignees of items that become ready are mailed
+    Done {
+        /// Short ID of the item
+        item_id: String,
+        /// Agent who finished it (defaults to its assignee)
+        #[arg(long)]
+        by: Option<String>,
+    },
+    /// Items whose dependencies are all complete
+    /// Usage: work ready --agent alice
+    Ready {
+        /// Only items assigned to this agent
+        #[arg(short, long)]
+        agent: Option<String>,
+        #[arg(short, long)]
+        project: Option<String>,
+    },
+    /// Every item with its state and what it depends on
+    List {
+        #[arg(short, long)]
+        project: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ApprovalCommands {
+    /// Ask the human for sign-off; it shows up on the web dashboard
+    /// Usage: approval request -f alice -t "Drop the events table" -o "drop it" -o "archive first" --wait 3600
+    Request {
+        /// Agent asking
+        #[arg(short, long)]
+        from: String,
+        #[arg(short, long)]
+        title: String,
+        /// What you want to do and why
+        #[arg(short, long, default_value = "")]
+        description: String,
+        /// A way the human can approve; repeat for several (leave out for yes or no)
+        #[arg(short, long = "option")]
+        options: Vec<String>,
+        /// Block until decided or this many seconds pass, like `approval wait`
+        #[arg(short, long)]
+        wait: Option<u64>,
+    },
+    /// Block until the human decides. Exits 0 when approved, 2 when
+    /// rejected and 3 when the timeout runs out.
+    /// Usage: approval wait 1a2b3c4d --timeout 600
+    Wait {
+        /// Request short ID (first 8 characters of UUID)
+        request_id: String,
+        /// Seconds to wait before giving up (0 = no limit)
+        #[arg(short, long, default_value = "3600")]
+        timeout: u64,
+        /// Seconds between checks (default: 5)
+        #[arg(short, long, default_value = "5")]
+        interval: u64,
+    },
+    /// List approval requests, pending ones unless --all is given
+    List {
+        /// Only requests from this agent
+        #[arg(short, long)]
+        agent: Option<String>,
+        /// Include decided requests
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum KvCommands {
+    /// Print a key's value (fails if it has none)
+    /// Usage: kv get release/branch
+    Get {
+        /// namespace/key, or just key for the default namespace
+        key: KvKey,
+    },
+    /// Set a key, optionally only if it still holds what you expect
+    /// Usage: kv set build/status green -f ci --if-value red --ttl 1h
+    Set {
+        key: KvKey,
+        value: String,
+        /// Agent writing the value
+        #[arg(short, long)]
+        from: String,
+        /// Forget the value after this long: 30s, 15m, 2h, 1d
+        #[arg(long)]
+        ttl: Option<String>,
+        /// Only if the key has no value
+        #[arg(long, conflicts_with_all = ["if_version", "if_value"])]
+        if_absent: bool,
+        /// Only if the current value is at this version
+        #[arg(long, conflicts_with = "if_value")]
+        if_version: Option<i64>,
+        /// Only if this is the current value
+        #[arg(long)]
+        if_value: Option<String>,
+    },
+    /// Remove a key's value (its history is kept)
+    Delete {
+        key: KvKey,
+        /// Agent removing it
+        #[arg(short, long)]
+        from: String,
+        /// Only if the current value is at this version
+        #[arg(long, conflicts_with = "if_value")]
+        if_version: Option<i64>,
+        /// Only if this is the current value
+        #[arg(long)]
+        if_value: Option<String>,
+    },
+    /// List keys and their values
+    List {
+        /// Only keys in this namespace
+        namespace: Option<String>,
+    },
+    /// Print keys' values now and every time they change, until Ctrl+C
+    /// Usage: kv watch build/status release/branch
+    Watch {
+        #[arg(required = true)]
+        keys: Vec<KvKey>,
+        /// Seconds between checks (default: 2)
+        #[arg(short, long, default_value = "2")]
+        interval: u64,
+    },
+    /// Earlier values of a key, newest first
+    History {
+        key: KvKey,
+    },
+}
+
+/// Lock commands exit 0 on success and 2 when the lock is held by someone
+/// else (or, for renew and release, isn't held by you), so scripts can tell
+/// that apart from other errors.
+#[derive(Subcommand)]
+pub enum LockCommands {
+    /// Take a lock; it expires unless renewed
+    /// Usage: lock acquire repo/agent-office -a alice --ttl 30m --wait 120
+    Acquire {
+        /// Name of the lock, e.g. the repository or KB note it protects
+        name: String,
+        /// Agent taking the lock
+        #[arg(short, long)]
+        agent: String,
+        /// How long until it expires: 30s, 15m, 2h, 1d
+        #[arg(long, default_value = "15m")]
+        ttl: String,
+        /// Keep trying for this many seconds while someone else holds it
+        #[arg(short, long)]
+        wait: Option<u64>,
+    },
+    /// Push back the expiry of a lock you hold
+    Renew {
+        name: String,
+        #[arg(short, long)]
+        agent: String,
+        /// New time left: 30s, 15m, 2h, 1d
+        #[arg(long, default_value = "15m")]
+        ttl: String,
+    },
+    /// Give up a lock you hold
+    Release {
+        name: String,
+        #[arg(short, long)]
+        agent: String,
+    },
+    /// Locks currently held, and by whom
+    List,
+}
@@ -28,6 +28,29 @@ impl PropertyValue {
             _ => None,
         }
     }
+
+    /// Whether this value contains `expected`, following the rules of
+    /// Postgres JSONB containment (`@>`) so every backend filters alike:
+    /// scalars must be equal (numbers compare numerically), lists must hold
+    /// a match for every expected element, and maps must hold every
+    /// expected key with a containing value.
+    pub fn contains(&self, expected: &PropertyValue) -> bool {
+        use PropertyValue::*;
+        match (self, expected) {
+            (Integer(a), Float(b)) | (Float(b), Integer(a)) => *a as f64 == *b,
+            // Timestamps are stored as RFC 3339 strings in JSON
+            (Timestamp(t), String(s)) | (String(s), Timestamp(t)) => {
+                serde_json::to_value(t).is_ok_and(|v| v.as_str() == Some(s.as_str()))
+            }
+            (List(actual), List(wanted)) => wanted
+                .iter()
+                .all(|w| actual.iter().any(|a| a.contains(w))),
+            (Map(actual), Map(wanted)) => wanted
+                .iter()
+                .all(|(k, w)| actual.get(k).is_some_and(|a| a.contains(w))),
+            _ => self == expected,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -87,8 +110,12 @@ impl Edge {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GraphQuery {
     pub node_types: Option<Vec<String>>,
+    /// Only nodes with at least one incoming or outgoing edge of these types
     pub edge_types: Option<Vec<String>>,
+    /// Properties each node must contain (see [`PropertyValue::contains`])
     pub property_filters: Option<HashMap<String, PropertyValue>>,
+    /// String properties that must start with the given prefix
+    pub property_prefixes: Option<HashMap<String, String>>,
     pub limit: Option<usize>,
 }
 
@@ -98,6 +125,7 @@ impl GraphQuery {
             node_types: None,
             edge_types: None,
             property_filters: None,
+            property_prefixes: None,
             limit: None,
         }
     }
@@ -106,6 +134,63 @@ impl GraphQuery {
         self.node_types = Some(vec![node_type.into()]);
         self
     }
+
+    pub fn with_edge_type(mut self, edge_type: impl Into<String>) -> Self {
+        self.edge_types
+            .get_or_insert_with(Vec::new)
+            .push(edge_type.into());
+        self
+    }
+
+    pub fn with_property(mut self, key: impl Into<String>, value: PropertyValue) -> Self {
+        self.property_filters
+            .get_or_insert_with(HashMap::new)
+            .insert(key.into(), value);
+        self
+    }
+
+    pub fn with_property_prefix(
+        mut self,
+        key: impl Into<String>,
+        prefix: impl Into<String>,
+    ) -> Self {
+        self.property_prefixes
+            .get_or_insert_with(HashMap::new)
+            .insert(key.into(), prefix.into());
+        self
+    }
+
+    /// Whether a node's type and properties satisfy this query.
+    /// Edge type filtering needs the edges, so it is left to the backend.
+    pub fn matches_node(&self, node: &Node) -> bool {
+        if let Some(ref types) = self.node_types {
+            if !types.contains(&node.node_type) {
+                return false;
+            }
+        }
+
+        if let Some(ref filters) = self.property_filters {
+            let all_match = filters.iter().all(|(key, expected)| {
+                node.get_property(key).is_some_and(|actual| actual.contains(expected))
+            });
+            if !all_match {
+                return false;
+            }
+        }
+
+        if let Some(ref prefixes) = self.property_prefixes {
+            let all_match = prefixes.iter().all(|(key, prefix)| {
+                node.get_property(key)
+                    .and_then(|v| v.as_str())
+                    .is_some_and(|s| s.starts_with(prefix.as_str()))
+            });
+            if !all_match {
+                return false;
+            }
+        }
+
+        true
+    }
 }
 
 impl Default for GraphQuery {
@@ -5,13 +5,26 @@ mod storage;
 mod web;
 
 use clap::Parser;
-use cli::{AgentCommands, Cli, Commands, DbCommands, HumanCommands, KbCommands, MailCommands, ScheduleCommands};
+use cli::{AgentCommands, ApprovalCommands, AttachmentCommands, Cli, Commands, DbCommands, GroupCommands, HumanCommands, KbCommands, KvCommands, LockCommands, MailCommands, RuleCommands, ScheduleCommands, SchemaCommands, TaskCommands, WorkCommands};
+use services::approval::{ApprovalService, ApprovalServiceImpl};
+use services::approval::domain::{ApprovalRequest, ApprovalStatus};
 use services::kb::{KnowledgeBaseService, KnowledgeBaseServiceImpl};
 use services::kb::domain::LuhmannId;
+use services::kv::{KvService, KvServiceImpl};
+use services::kv::domain::{KvEntry, KvExpect};
+use services::lease::{LeaseError, LeaseService, LeaseServiceImpl};
+use services::lease::domain::Lease;
 use services::mail::{MailService, MailServiceImpl};
+use services::mail::archive::{self, ArchiveFormat, ArchivedMail};
+use services::mail::domain::{AttachmentLimits, AutoReply, Folder, InboxFilter, Mail, MailRef, MailRule, MailSearch, NewAttachment, Priority, RecipientKind, RetentionPolicy, SendOptions, parse_deliver_at};
 use services::schedule::{ScheduleService, ScheduleServiceImpl};
-use storage::memory::InMemoryStorage;
-use storage::postgres::PostgresStorage;
+use services::task::{TaskService, TaskServiceImpl};
+use services::task::domain::{Task, TaskFilter};
+use services::work::{WorkService, WorkServiceImpl};
+use services::work::domain::{WorkItem, WorkState};
+use storage::AnyStorage;
+use storage::blob::BlobLocation;
+use storage::notify::{Wakeup, WakeupListener};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -26,6 +39,15 @@ async fn main() -> anyhow::Result<()> {
         .or_else(|_| std::env::var("DATABASE_URL"))
         .ok();
     
+    // A SQLite file needs no setup step, so bring it up to date once per run
+    // rather than on every connect. `human db` commands manage the schema
+    // themselves, and Postgres is migrated explicitly with `human db migrate`.
+    if let Some(url) = database_url.as_deref().filter(|url| storage::is_sqlite_url(url)) {
+        if !matches!(cli.command, Commands::Human(HumanCommands::Db(_))) {
+            AnyStorage::connect(Some(url)).await?.migrate().await?;
+        }
+    }
+    
     match cli.command {
         Commands::HowWeWork => {
             print_welcome_message();
@@ -35,48 +57,68 @@ async fn main() -> anyhow::Result<()> {
                 HumanCommands::Db(db_cmd) => {
                     handle_db_command(database_url, db_cmd).await?;
                 }
-                HumanCommands::Web { host, port } => {
-                    web::run_web_server(database_url, host, port).await?;
+                HumanCommands::Web { host, port, gc_interval_hours, retention } => {
+                    let gc = gc_interval_hours.filter(|hours| *hours > 0).map(|hours| {
+                        let policy = RetentionPolicy::from_days(retention.archive_after_days, retention.delete_after_days);
+                        (policy, std::time::Duration::from_secs(hours * 3600))
+                    });
+                    web::run_web_server(database_url, host, port, gc).await?;
                 }
             }
         }
         Commands::Kb(kb_cmd) => {
-            if let Some(url) = database_url.clone() {
-                let pool = sqlx::postgres::PgPool::connect(&url).await?;
-                let storage = PostgresStorage::new(pool);
-                let kb_service = KnowledgeBaseServiceImpl::new(storage);
-                handle_kb_command(kb_service, kb_cmd).await?;
-            } else {
-                let storage = InMemoryStorage::new();
-                let kb_service = KnowledgeBaseServiceImpl::new(storage);
-                handle_kb_command(kb_service, kb_cmd).await?;
-            }
+            // Without a database URL this falls back to throwaway in-memory storage
+            let storage = AnyStorage::connect(database_url.as_deref()).await?;
+            let kb_service = KnowledgeBaseServiceImpl::new(storage);
+            handle_kb_command(kb_service, kb_cmd).await?;
         }
         Commands::Schedule(schedule_cmd) => {
             if let Some(url) = database_url {
-                let pool = sqlx::postgres::PgPool::connect(&url).await?;
-                // Migrate schedules table on startup
-                let storage = PostgresStorage::new(pool.clone());
-                storage.migrate_schedules_table().await?;
-                let schedule_service = ScheduleServiceImpl::new(pool);
+                let schedule_service = ScheduleServiceImpl::connect(&url).await?;
                 handle_schedule_command(schedule_service, schedule_cmd).await?;
             } else {
                 println!("Schedule commands require a database connection. Please set AGENT_OFFICE_URL or DATABASE_URL.");
                 std::process::exit(1);
             }
         }
+        Commands::Kv(kv_cmd) => {
+            if let Some(url) = database_url {
+                let storage = AnyStorage::connect(Some(&url)).await?;
+                handle_kv_command(KvServiceImpl::new(storage), kv_cmd).await?;
+            } else {
+                println!("Blackboard commands require a database connection. Please set AGENT_OFFICE_URL or DATABASE_URL.");
+                std::process::exit(1);
+            }
+        }
+        Commands::Lock(lock_cmd) => {
+            if let Some(url) = database_url {
+                let lease_service = LeaseServiceImpl::connect(&url).await?;
+                handle_lock_command(lease_service, lock_cmd).await?;
+            } else {
+                println!("Lock commands require a database connection. Please set AGENT_OFFICE_URL or DATABASE_URL.");
+                std::process::exit(1);
+            }
+        }
         _ => {
             if let Some(url) = database_url {
-                let pool = sqlx::postgres::PgPool::connect(&url).await?;
-                // Migrate schedules table
-                let storage = PostgresStorage::new(pool.clone());
-                storage.migrate_schedules_table().await?;
-                let mail_service = MailServiceImpl::new(storage);
-                let schedule_service = ScheduleServiceImpl::new(pool.clone());
+                // One pool serves every service the command needs
+                let storage = AnyStorage::connect(Some(&url)).await?;
+                let mail_service = MailServiceImpl::new(storage.clone())
+                    .with_attachments(BlobLocation::from_env(), AttachmentLimits::from_env());
                 
                 match cli.command {
                     Commands::Mail(mail_cmd) => handle_mail_command(mail_service, mail_cmd).await?,
-                    Commands::Agent(agent_cmd) => handle_agent_command(mail_service, schedule_service, agent_cmd).await?,
+                    Commands::Agent(agent_cmd) => {
+                        let schedule_service = ScheduleServiceImpl::from_storage(&storage)
+                            .ok_or_else(|| anyhow::anyhow!("Schedules need a database"))?;
+                        handle_agent_command(&url, mail_service, schedule_service, agent_cmd).await?
+                    }
+                    Commands::Group(group_cmd) => handle_group_command(mail_service, group_cmd).await?,
+                    Commands::Task(task_cmd) => handle_task_command(TaskServiceImpl::new(storage, mail_service), task_cmd).await?,
+                    Commands::Work(work_cmd) => handle_work_command(WorkServiceImpl::new(storage, mail_service), work_cmd).await?,
+                    Commands::Approval(approval_cmd) => {
+                        handle_approval_command(&url, ApprovalServiceImpl::new(storage, mail_service), approval_cmd).await?
+                    }
                     _ => {}
                 }
             } else {
@@ -109,6 +151,8 @@ fn print_welcome_message() {
     println!("other through mail. Think of it as our internal messaging system.");
     println!("You'll receive messages from your fellow agents, and you can send");
     println!("messages back whenever you need to collaborate or share information.");
+    println!("Teams have group addresses too: mail sent to @reviewers reaches");
+    println!("everyone in that group (run `group list` to see who is in which).");
     println!();
     println!("⏰ Scheduled Tasks");
     println!();
@@ -131,6 +175,28 @@ fn print_welcome_message() {
     println!("assignments to the team. When there's work to be done, the Coordinator");
     println!("will mail you with details about what needs to be completed.");
     println!();
+    println!("📋 Tasks");
+    println!();
+    println!("Bigger pieces of work are tracked as tasks. When a task is assigned");
+    println!("to you, you'll get a mail labelled #task. Run `task start <id>` when");
+    println!("you begin and `task done <id>` when you finish, so everyone can see");
+    println!("what you're working on with `task list --agent <your-id>`.");
+    println!();
+    println!("📌 The Blackboard");
+    println!();
+    println!("Shared facts like the current release branch or build status live");
+    println!("on the blackboard: `kv get release/branch`, `kv set build/status");
+    println!("green -f <your-id>`. Check it before asking around, and keep it up");
+    println!("to date when you change something others depend on.");
+    println!();
+    println!("🔐 Locks");
+    println!();
+    println!("Before working on something another agent might also touch, like a");
+    println!("repository or a KB note, take a lock: `lock acquire repo/<name> -a");
+    println!("<your-id>`. If it exits 2, someone else has it; work on something");
+    println!("else or wait with `--wait`. Locks expire, so renew long jobs with");
+    println!("`lock renew`, and always `lock release` when you're done.");
+    println!();
     println!("🧑‍💼 Working with Humans");
     println!();
     println!("We also have human colleagues! The human team members work closely");
@@ -138,6 +204,11 @@ fn print_welcome_message() {
     println!("guidance. The human gives the big-picture goals, and the Coordinator");
     println!("breaks those down into specific tasks for us AI agents to work on.");
     println!();
+    println!("Before doing anything destructive (deleting data, force-pushing,");
+    println!("touching production), ask the human first with `approval request`");
+    println!("and wait for their answer with `approval wait <id>`. The decision");
+    println!("also arrives in your mailbox, labelled #approval.");
+    println!();
     println!("📚 Our Shared Knowledge Base");
     println!();
     println!("The knowledge base (or KB) is our collective memory. It's where we");
@@ -194,30 +265,27 @@ async fn handle_db_command(
     database_url: Option<String>,
     cmd: DbCommands,
 ) -> anyhow::Result<()> {
+    let url = database_url.ok_or_else(|| {
+        anyhow::anyhow!("DATABASE_URL environment variable not set")
+    })?;
+
     match cmd {
         DbCommands::Setup => {
-            let url = database_url.ok_or_else(|| {
-                anyhow::anyhow!("DATABASE_URL environment variable not set")
-            })?;
-            
             println!("Connecting to database...");
-            let pool = sqlx::postgres::PgPool::connect(&url).await?;
-            let storage = PostgresStorage::new(pool);
-            
+            let storage = AnyStorage::connect(Some(&url)).await?;
             println!("Setting up database tables...");
-            storage.setup_tables().await.map_err(|e| {
+            let applied = storage.migrate().await.map_err(|e| {
                 anyhow::anyhow!("Failed to setup database: {}", e)
             })?;
             
             println!("Database setup complete!");
-            println!("Tables created: nodes, edges");
-            println!("Indexes created for performance");
+            if applied.is_empty() {
+                println!("Schema already up to date, existing data untouched");
+            } else {
+                println!("Applied {} migration(s)", applied.len());
+            }
         }
         DbCommands::Reset => {
-            let url = database_url.ok_or_else(|| {
-                anyhow::anyhow!("DATABASE_URL environment variable not set")
-            })?;
-            
             // Confirmation prompt
             println!("⚠️  WARNING: This will DELETE ALL DATA in the database!");
             println!("   All agents, mail, knowledge base notes, and everything else will be permanently removed.");
@@ -236,24 +304,111 @@ async fn handle_db_command(
             
             println!();
             println!("Connecting to database...");
-            let pool = sqlx::postgres::PgPool::connect(&url).await?;
-            let storage = PostgresStorage::new(pool);
-            
+            let storage = AnyStorage::connect(Some(&url)).await?;
             println!("Resetting database - dropping all tables...");
-            storage.setup_tables().await.map_err(|e| {
+            storage.reset_tables().await.map_err(|e| {
                 anyhow::anyhow!("Failed to reset database: {}", e)
             })?;
             
             println!();
             println!("✅ Database reset complete!");
             println!("   All previous data has been cleared.");
-            println!("   Fresh tables created: nodes, edges");
+            println!("   Fresh tables created at the latest schema version");
             println!("   Your database is now ready for new data.");
         }
+        DbCommands::Migrate => {
+            let storage = AnyStorage::connect(Some(&url)).await?;
+            let applied = storage.migrate().await.map_err(|e| {
+                anyhow::anyhow!("Migration failed: {}", e)
+            })?;
+
+            if applied.is_empty() {
+                println!("Schema is up to date, nothing to migrate.");
+            } else {
+                for migration in &applied {
+                    println!("✅ Applied {:04} {}", migration.version, migration.name);
+                }
+                println!("{} migration(s) applied.", applied.len());
+            }
+        }
+        DbCommands::Status => {
+            let storage = AnyStorage::connect(Some(&url)).await?;
+            let statuses = storage.migration_status().await?;
+
+            let pending = statuses.iter().filter(|s| s.applied_at.is_none()).count();
+            for status in &statuses {
+                match status.applied_at {
+                    Some(at) => println!(
+                        "  ✓ {:04} {:<28} applied {}",
+                        status.version,
+                        status.name,
+                        at.format("%Y-%m-%d %H:%M:%S UTC")
+                    ),
+                    None => println!("  · {:04} {:<28} pending", status.version, status.name),
+                }
+            }
+            println!();
+            if pending == 0 {
+                println!("Schema is up to date.");
+            } else {
+                println!("{} pending migration(s). Run `human db migrate` to apply.", pending);
+            }
+        }
+        DbCommands::Gc { retention } => {
+            let storage = AnyStorage::connect(Some(&url)).await?;
+            let service = MailServiceImpl::new(storage)
+                .with_attachments(BlobLocation::from_env(), AttachmentLimits::from_env());
+            let policy = RetentionPolicy::from_days(retention.archive_after_days, retention.delete_after_days);
+            let arrived = service.arrive_due_mail(chrono::Utc::now()).await?;
+            if arrived > 0 {
+                println!("📬 Processed {} delayed mail(s) that had come due", arrived);
+            }
+            let report = service.apply_retention(&policy, chrono::Utc::now()).await?;
+            println!("🧹 Archived {} read mail(s), deleted {} expired mail(s)", report.archived, report.deleted);
+        }
     }
     Ok(())
 }
 
+/// "alice, bob" for a list of mailboxes, with "Unknown" for deleted agents
+async fn mailbox_names(service: &impl MailService, mailbox_ids: &[uuid::Uuid]) -> String {
+    let mut names = Vec::with_capacity(mailbox_ids.len());
+    for mailbox_id in mailbox_ids {
+        match service.get_agent_by_mailbox(*mailbox_id).await {
+            Ok(agent) => names.push(agent.name),
+            Err(_) => names.push("Unknown".to_string()),
+        }
+    }
+    names.join(", ")
+}
+
+/// Priority, labels and payload type after a subject, e.g. " (urgent) #bug [review-request]"
+fn mail_tags(mail: &Mail) -> String {
+    let mut tags = String::new();
+    if mail.priority != Priority::Normal {
+        tags.push_str(&format!(" ({})", mail.priority.as_str()));
+    }
+    for label in &mail.labels {
+        tags.push_str(&format!(" #{}", label));
+    }
+    if let Some(ref content_type) = mail.content_type {
+        tags.push_str(&format!(" [{}]", content_type));
+    }
+    tags
+}
+
+/// Parse a JSON file, or stdin when the path is "-"
+fn read_json_file(path: &std::path::Path) -> anyhow::Result<serde_json::Value> {
+    let text = if path.as_os_str() == "-" {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Couldn't read {}: {}", path.display(), e))?
+    };
+    serde_json::from_str(&text)
+        .map_err(|e| anyhow::anyhow!("{} is not valid JSON: {}", path.display(), e))
+}
+
 async fn handle_mail_command(
     service: impl MailService,
     cmd: MailCommands,
@@ -268,31 +423,81 @@ async fn handle_mail_command(
                 println!("Recent mail for agent {} (last 24 hours):", agent_id);
                 for mail in mails {
                     let status = if mail.read { "[Read]" } else { "[Unread]" };
-                    let short_id = &mail.id.to_string()[..8];
+                    let reference = mail.reference(&agent_id);
                     match service.get_agent_by_mailbox(mail.from_mailbox_id).await {
-                        Ok(sender) => println!("  {} [{}] from {}: {}", status, short_id, sender.name, mail.subject),
-                        Err(_) => println!("  {} [{}]: {}", status, short_id, mail.subject),
+                        Ok(sender) => println!("  {} [{}] from {}: {}", status, reference, sender.name, mail.subject),
+                        Err(_) => println!("  {} [{}]: {}", status, reference, mail.subject),
                     }
                 }
             }
         }
-        MailCommands::Send { from, to, subject, body } => {
-            service.send_agent_to_agent(from.clone(), to.clone(), subject.clone(), body).await?;
-            println!("✉️  {} -> {}: {}", from, to, subject);
+        MailCommands::Send { from, to, cc, to_all, subject, body, priority, label, payload_file, content_type, attach, at } => {
+            let to = if to_all {
+                // Broadcast: every registered agent except the sender
+                service.list_agents().await?
+                    .into_iter()
+                    .map(|agent| agent.id)
+                    .filter(|id| *id != from)
+                    .collect()
+            } else {
+                to
+            };
+            let payload = match payload_file {
+                Some(path) => Some(read_json_file(&path)?),
+                None => None,
+            };
+            // Turn down oversized files before reading any of them in
+            let mut sizes = Vec::with_capacity(attach.len());
+            for path in &attach {
+                let metadata = std::fs::metadata(path)
+                    .map_err(|e| anyhow::anyhow!("Couldn't read {}: {}", path.display(), e))?;
+                sizes.push((path.to_string_lossy(), metadata.len()));
+            }
+            let sizes: Vec<_> = sizes.iter().map(|(name, size)| (name.as_ref(), *size)).collect();
+            AttachmentLimits::from_env().check_sizes(&sizes).map_err(|e| anyhow::anyhow!(e))?;
+            let mut attachments = Vec::with_capacity(attach.len());
+            for path in &attach {
+                let data = std::fs::read(path)
+                    .map_err(|e| anyhow::anyhow!("Couldn't read {}: {}", path.display(), e))?;
+                attachments.push(NewAttachment::new(&path.to_string_lossy(), data));
+            }
+            let deliver_at = match at {
+                Some(at) => Some(parse_deliver_at(&at, chrono::Local::now()).map_err(|e| anyhow::anyhow!(e))?),
+                None => None,
+            };
+            let options = SendOptions { priority, labels: label, payload, content_type, attachments, deliver_at };
+            let mail = service.send_mail_with(from.clone(), to, cc, subject.clone(), body, options).await?;
+            println!("✉️  {} -> {}: {}{}", from, mailbox_names(&service, &mail.to_mailbox_ids).await, subject, mail_tags(&mail));
+            if !mail.cc_mailbox_ids.is_empty() {
+                println!("   cc: {}", mailbox_names(&service, &mail.cc_mailbox_ids).await);
+            }
+            for attachment in service.get_attachments(mail.id).await? {
+                println!("   📎 {} ({})", attachment.filename, attachment.size_label());
+            }
+            if let Some(deliver_at) = mail.deliver_at {
+                println!("   ⏳ scheduled for {} [{}]", deliver_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M %:z"), mail.reference(&from));
+            }
+        }
+        MailCommands::Cancel { mail_id, from } => {
+            let mail = service.resolve_mail(&mail_id, Some(from.clone())).await?;
+            let mail = service.cancel_mail(mail.id, from).await?;
+            println!("🚫 Cancelled [{}] {}", &mail.id.to_string()[..8], mail.subject);
         }
-        MailCommands::Inbox { agent_id } => {
+        MailCommands::Inbox { agent_id, folder, label, priority } => {
             let mailbox = service.get_agent_mailbox(agent_id.clone()).await?;
-            let mails = service.get_mailbox_inbox(mailbox.id).await?;
+            let filter = InboxFilter { folder: Some(folder), label, priority };
+            let mails = service.get_mailbox_mail(mailbox.id, &filter).await?;
+            let title = if folder == Folder::Inbox { "Inbox".to_string() } else { format!("Folder '{}'", folder.as_str()) };
             if mails.is_empty() {
-                println!("Inbox is empty for agent {}", agent_id);
+                println!("{} is empty for agent {}", title, agent_id);
             } else {
-                println!("Inbox for agent {}:", agent_id);
+                println!("{} for agent {}:", title, agent_id);
                 for mail in mails {
                     let status = if mail.read { "[Read]" } else { "[Unread]" };
-                    let short_id = &mail.id.to_string()[..8];
+                    let reference = mail.reference(&agent_id);
                     match service.get_agent_by_mailbox(mail.from_mailbox_id).await {
-                        Ok(sender) => println!("  {} [{}] from {}: {}", status, short_id, sender.name, mail.subject),
-                        Err(_) => println!("  {} [{}]: {}", status, short_id, mail.subject),
+                        Ok(sender) => println!("  {} [{}] from {}: {}{}", status, reference, sender.name, mail.subject, mail_tags(&mail)),
+                        Err(_) => println!("  {} [{}]: {}{}", status, reference, mail.subject, mail_tags(&mail)),
                     }
                 }
             }
@@ -305,31 +510,189 @@ async fn handle_mail_command(
             } else {
                 println!("Outbox for agent {}:", agent_id);
                 for mail in mails {
-                    let short_id = &mail.id.to_string()[..8];
-                    match service.get_agent_by_mailbox(mail.to_mailbox_id).await {
-                        Ok(recipient) => println!("  [{}] to {}: {}", short_id, recipient.name, mail.subject),
-                        Err(_) => println!("  [{}]: {}", short_id, mail.subject),
+                    let recipients = mailbox_names(&service, &mail.to_mailbox_ids).await;
+                    let pending = if mail.is_pending(chrono::Utc::now()) { " ⏳" } else { "" };
+                    println!("  [{}] to {}: {}{}", mail.reference(&agent_id), recipients, mail.subject, pending);
+                    for delivery in service.get_deliveries(mail.id).await? {
+                        let name = mailbox_names(&service, &[delivery.mailbox_id]).await;
+                        let cc = if delivery.kind == RecipientKind::Cc { " (cc)" } else { "" };
+                        println!("      {}{}: {}", name, cc, delivery.status_line());
                     }
                 }
             }
         }
-        MailCommands::Read { mail_id } => {
-            let mail = service.mark_mail_as_read_by_short_id(&mail_id).await?;
+        MailCommands::Read { mail_id, agent } => {
+            let mail = service.mark_mail_as_read_by_short_id(&mail_id, agent.clone(), None).await?;
             let sender = service.get_agent_by_mailbox(mail.from_mailbox_id).await?;
             println!("📧 Mail from {}: {}", sender.name, mail.subject);
             println!("   ID: {}", mail.id);
+            if mail.seq.is_some() {
+                // Without --agent the mail has a single recipient
+                let reader = match MailRef::parse(&mail_id).owner(agent) {
+                    Some(agent) => agent,
+                    None => match mail.recipients().next() {
+                        Some((mailbox_id, _)) => service.get_agent_by_mailbox(mailbox_id).await?.id,
+                        None => String::new(),
+                    },
+                };
+                println!("   Number: {}", mail.reference(&reader));
+            }
             println!("   Date: {}", mail.created_at.format("%Y-%m-%d %H:%M:%S"));
+            if let Some(parent_id) = mail.in_reply_to {
+                println!("   In reply to: [{}]", &parent_id.to_string()[..8]);
+            }
             println!();
             println!("{}", mail.body);
+            if let Some(ref payload) = mail.payload {
+                println!();
+                println!("Payload ({}):", mail.content_type.as_deref().unwrap_or("application/json"));
+                println!("{}", serde_json::to_string_pretty(payload)?);
+            }
+            let attachments = service.get_attachments(mail.id).await?;
+            if !attachments.is_empty() {
+                println!();
+                for attachment in attachments {
+                    println!("📎 [{}] {} ({}, {})", &attachment.id.to_string()[..8], attachment.filename, attachment.content_type, attachment.size_label());
+                }
+            }
+        }
+        MailCommands::Payload { mail_id, agent } => {
+            let mail = service.resolve_mail(&mail_id, agent).await?;
+            match mail.payload {
+                Some(payload) => println!("{}", serde_json::to_string_pretty(&payload)?),
+                None => {
+                    eprintln!("Mail [{}] has no payload", &mail.id.to_string()[..8]);
+                    std::process::exit(1);
+                }
+            }
+        }
+        MailCommands::Attachment(attachment_cmd) => match attachment_cmd {
+            AttachmentCommands::List { mail_id, agent } => {
+                let mail = service.resolve_mail(&mail_id, agent).await?;
+                let attachments = service.get_attachments(mail.id).await?;
+                if attachments.is_empty() {
+                    println!("Mail [{}] has no attachments", &mail.id.to_string()[..8]);
+                } else {
+                    println!("Attachments on [{}] {}:", &mail.id.to_string()[..8], mail.subject);
+                    for attachment in attachments {
+                        println!("  📎 [{}] {} ({}, {})", &attachment.id.to_string()[..8], attachment.filename, attachment.content_type, attachment.size_label());
+                    }
+                }
+            }
+            AttachmentCommands::Get { attachment_id, output, force } => {
+                let attachment = service.get_attachment_by_short_id(&attachment_id).await?;
+                let (attachment, data) = service.read_attachment(attachment.id).await?;
+                let path = output.unwrap_or_else(|| std::path::PathBuf::from(&attachment.filename));
+                if path.as_os_str() == "-" {
+                    use std::io::Write;
+                    std::io::stdout().write_all(&data)?;
+                } else {
+                    if path.exists() && !force {
+                        anyhow::bail!("{} already exists (use --force to overwrite)", path.display());
+                    }
+                    std::fs::write(&path, &data)?;
+                    println!("📎 Saved {} ({}) to {}", attachment.filename, attachment.size_label(), path.display());
+                }
+            }
+        },
+        MailCommands::Schema(schema_cmd) => match schema_cmd {
+            SchemaCommands::Register { content_type, file } => {
+                let schema = service.register_payload_schema(content_type, read_json_file(&file)?).await?;
+                println!("📐 Registered schema for {}", schema.content_type);
+            }
+            SchemaCommands::Remove { content_type } => {
+                service.remove_payload_schema(&content_type).await?;
+                println!("🗑️  Removed schema for {}", content_type);
+            }
+            SchemaCommands::List => {
+                let schemas = service.list_payload_schemas().await?;
+                if schemas.is_empty() {
+                    println!("No payload schemas registered");
+                } else {
+                    println!("Payload schemas:");
+                    for schema in schemas {
+                        println!("  {} (updated {})", schema.content_type, schema.updated_at.format("%Y-%m-%d %H:%M"));
+                    }
+                }
+            }
+        },
+        MailCommands::Rule(rule_cmd) => match rule_cmd {
+            RuleCommands::Add { agent_id, name, rule } => {
+                let rule = MailRule {
+                    from: rule.from,
+                    subject_contains: rule.subject_contains,
+                    body_contains: rule.body_contains,
+                    add_labels: rule.label,
+                    move_to: rule.move_to,
+                    mark_read: rule.mark_read,
+                    forward_to: rule.forward_to,
+                    ..MailRule::new(agent_id, name)
+                };
+                let rule = service.add_mail_rule(rule).await?;
+                println!("🔀 Added rule [{}] {}: {} → {}", &rule.id.to_string()[..8], rule.name, rule.conditions_label(), rule.actions_label());
+            }
+            RuleCommands::List { agent_id } => {
+                let rules = service.list_mail_rules(agent_id.clone()).await?;
+                if rules.is_empty() {
+                    println!("No mail rules for agent {}", agent_id);
+                } else {
+                    println!("Mail rules for agent {}:", agent_id);
+                    for rule in rules {
+                        let disabled = if rule.enabled { "" } else { " (disabled)" };
+                        println!("  [{}] {}{}: {} → {}", &rule.id.to_string()[..8], rule.name, disabled, rule.conditions_label(), rule.actions_label());
+                    }
+                }
+            }
+            RuleCommands::Remove { rule_id } => {
+                let rule = service.get_mail_rule_by_short_id(&rule_id).await?;
+                service.remove_mail_rule(rule.id).await?;
+                println!("🗑️  Removed rule [{}] {}", &rule.id.to_string()[..8], rule.name);
+            }
+            RuleCommands::Enable { rule_id } => {
+                let rule = service.get_mail_rule_by_short_id(&rule_id).await?;
+                let rule = service.update_mail_rule(MailRule { enabled: true, ..rule }).await?;
+                println!("✅ Enabled rule [{}] {}", &rule.id.to_string()[..8], rule.name);
+            }
+            RuleCommands::Disable { rule_id } => {
+                let rule = service.get_mail_rule_by_short_id(&rule_id).await?;
+                let rule = service.update_mail_rule(MailRule { enabled: false, ..rule }).await?;
+                println!("⏸️  Disabled rule [{}] {}", &rule.id.to_string()[..8], rule.name);
+            }
+        },
+        MailCommands::Move { mail_id, folder, agent } => {
+            let owner = MailRef::parse(&mail_id).owner(agent.clone());
+            let mail = service.resolve_mail(&mail_id, agent).await?;
+            let mail = service.move_mail(mail.id, owner.clone(), folder).await?;
+            let reference = owner.map(|o| mail.reference(&o)).unwrap_or_else(|| mail.id.to_string()[..8].to_string());
+            println!("📁 Moved [{}] {} to {}", reference, mail.subject, folder.as_str());
+        }
+        MailCommands::Label { mail_id, agent, add, remove } => {
+            let owner = MailRef::parse(&mail_id).owner(agent.clone());
+            let mail = service.resolve_mail(&mail_id, agent).await?;
+            let mail = service.label_mail(mail.id, owner.clone(), add, remove).await?;
+            let reference = owner.map(|o| mail.reference(&o)).unwrap_or_else(|| mail.id.to_string()[..8].to_string());
+            if mail.labels.is_empty() {
+                println!("🏷️  [{}] {} has no labels", reference, mail.subject);
+            } else {
+                println!("🏷️  [{}] {}{}", reference, mail.subject, mail_tags(&mail));
+            }
+        }
+        MailCommands::Reply { mail_id, from, body } => {
+            let owner = MailRef::parse(&mail_id).owner(from.clone());
+            let parent = service.resolve_mail(&mail_id, from).await?;
+            let reply = service.reply_to_mail(parent.id, owner, body).await?;
+            let sender = service.get_agent_by_mailbox(reply.from_mailbox_id).await?;
+            let recipients = mailbox_names(&service, &reply.to_mailbox_ids).await;
+            println!("✉️  {} -> {}: {} [{}]", sender.name, recipients, reply.subject, reply.reference(&sender.id));
         }
         MailCommands::ShouldLook { agent_id } => {
-            let (has_unread, mails) = service.check_unread_mail(agent_id.clone()).await?;
+            let (has_unread, mails) = service.check_unread_mail(agent_id.clone(), true).await?;
             if has_unread {
                 println!("📬 Agent '{}' has {} unread message(s)", agent_id, mails.len());
                 for mail in &mails {
                     match service.get_agent_by_mailbox(mail.from_mailbox_id).await {
-                        Ok(sender) => println!("  [Unread] from {}: {}", sender.name, mail.subject),
-                        Err(_) => println!("  [Unread]: {}", mail.subject),
+                        Ok(sender) => println!("  [Unread] [{}] from {}: {}{}", mail.reference(&agent_id), sender.name, mail.subject, mail_tags(mail)),
+                        Err(_) => println!("  [Unread] [{}]: {}{}", mail.reference(&agent_id), mail.subject, mail_tags(mail)),
                     }
                 }
             } else {
@@ -337,37 +700,85 @@ async fn handle_mail_command(
             }
         }
         MailCommands::Search { agent_id, query } => {
+            let search = MailSearch::parse(&query, &chrono::Local).map_err(|e| anyhow::anyhow!(e))?;
             let mailbox = service.get_agent_mailbox(agent_id.clone()).await?;
-            let inbox = service.get_mailbox_inbox(mailbox.id).await?;
-            let outbox = service.get_mailbox_outbox(mailbox.id).await?;
-            
-            let query_lower = query.to_lowercase();
-            let mut results: Vec<_> = inbox.iter()
-                .chain(outbox.iter())
-                .filter(|m| {
-                    m.subject.to_lowercase().contains(&query_lower) ||
-                    m.body.to_lowercase().contains(&query_lower)
-                })
-                .collect();
-            
-            // Sort by date, newest first
-            results.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+            let results = service.search_mail(agent_id.clone(), &search, 50).await?;
             
             if results.is_empty() {
                 println!("No mail found matching '{}' for agent {}", query, agent_id);
             } else {
                 println!("Found {} mail(s) matching '{}' for agent {}:", results.len(), query, agent_id);
                 for mail in results {
-                    let direction = if inbox.iter().any(|m| m.id == mail.id) { "📥" } else { "📤" };
-                    let status = if mail.read { "Read" } else { "Unread" };
-                    let short_id = &mail.id.to_string()[..8];
-                    let other_agent = if direction == "📥" {
+                    let received = mail.recipients().any(|(id, _)| id == mailbox.id);
+                    let direction = if received { "📥" } else { "📤" };
+                    let status = if !received { "Sent" } else if mail.read { "Read" } else { "Unread" };
+                    let other_agent = if received {
                         service.get_agent_by_mailbox(mail.from_mailbox_id).await.map(|a| a.name).unwrap_or_else(|_| "Unknown".to_string())
                     } else {
-                        service.get_agent_by_mailbox(mail.to_mailbox_id).await.map(|a| a.name).unwrap_or_else(|_| "Unknown".to_string())
+                        mailbox_names(&service, &mail.to_mailbox_ids).await
                     };
-                    println!("  {} [{}] {} - {} (with {})", direction, status, short_id, mail.subject, other_agent);
+                    println!("  {} [{}] {} - {} (with {}){}", direction, status, mail.reference(&agent_id), mail.subject, other_agent, mail_tags(&mail));
+                }
+            }
+        }
+        MailCommands::Export { agent_id, format, output } => {
+            let mails = service.export_mail(agent_id.clone()).await?;
+            let text = match format {
+                ArchiveFormat::Mbox => archive::write_mbox(&mails),
+                ArchiveFormat::Jsonl => archive::write_jsonl(&mails).map_err(|e| anyhow::anyhow!(e))?,
+                ArchiveFormat::Eml => {
+                    let Some(dir) = output else {
+                        anyhow::bail!("eml export writes one file per mail; give a directory with --output");
+                    };
+                    std::fs::create_dir_all(&dir)?;
+                    for mail in &mails {
+                        std::fs::write(dir.join(archive::eml_file_name(mail)), mail.to_rfc5322())?;
+                    }
+                    eprintln!("📦 Exported {} mail(s) for {} to {}", mails.len(), agent_id, dir.display());
+                    return Ok(());
+                }
+            };
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, text)?;
+                    eprintln!("📦 Exported {} mail(s) for {} to {}", mails.len(), agent_id, path.display());
+                }
+                None => print!("{}", text),
+            }
+        }
+        MailCommands::Import { agent_id, path, format } => {
+            let read = |path: &std::path::Path| {
+                std::fs::read_to_string(path)
+                    .map_err(|e| anyhow::anyhow!("Couldn't read {}: {}", path.display(), e))
+            };
+            let mails = match format.unwrap_or_else(|| ArchiveFormat::for_path(&path)) {
+                ArchiveFormat::Mbox => archive::read_mbox(&read(&path)?),
+                ArchiveFormat::Jsonl => archive::read_jsonl(&read(&path)?),
+                ArchiveFormat::Eml if path.is_dir() => {
+                    let mut files: Vec<_> = std::fs::read_dir(&path)?
+                        .filter_map(|entry| entry.ok().map(|e| e.path()))
+                        .filter(|p| p.extension().is_some_and(|ext| ext == "eml"))
+                        .collect();
+                    files.sort();
+                    let mut mails = Vec::with_capacity(files.len());
+                    for file in files {
+                        let mail = ArchivedMail::from_rfc5322(&read(&file)?)
+                            .map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?;
+                        mails.push(mail);
+                    }
+                    Ok(mails)
                 }
+                ArchiveFormat::Eml => ArchivedMail::from_rfc5322(&read(&path)?).map(|m| vec![m]),
+            }
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+            
+            let report = service.import_mail(agent_id.clone(), mails).await?;
+            println!("📦 Imported {} mail(s) for {}", report.imported, agent_id);
+            if report.skipped > 0 {
+                println!("   {} already in the mailbox", report.skipped);
+            }
+            if report.ignored > 0 {
+                println!("   {} neither sent nor received by {}", report.ignored, agent_id);
             }
         }
     }
@@ -375,6 +786,7 @@ async fn handle_mail_command(
 }
 
 async fn handle_agent_command(
+    database_url: &str,
     service: impl MailService,
     schedule_service: impl ScheduleService,
     cmd: AgentCommands,
@@ -399,10 +811,11 @@ async fn handle_agent_command(
             } else {
                 println!("Agents:");
                 for agent in agents {
+                    let auto_reply = if agent.auto_reply.is_some() { " 🏖️ auto-reply" } else { "" };
                     if agent.id != agent.name {
-                        println!("  - {} [{}] ({})", agent.name, agent.id, agent.status);
+                        println!("  - {} [{}] ({}){}", agent.name, agent.id, agent.status, auto_reply);
                     } else {
-                        println!("  - {} ({})", agent.name, agent.status);
+                        println!("  - {} ({}){}", agent.name, agent.status, auto_reply);
                     }
                 }
             }
@@ -415,6 +828,10 @@ async fn handle_agent_command(
                 println!("Agent: {}", agent.name);
             }
             println!("Status: {}", agent.status);
+            if let Some(ref auto_reply) = agent.auto_reply {
+                let delegate = auto_reply.delegate.as_ref().map(|d| format!(" (delegate: {})", d)).unwrap_or_default();
+                println!("Auto-reply: {}{}", auto_reply.message, delegate);
+            }
             
             // Each agent has exactly one mailbox
             let _mailbox = service.get_agent_mailbox(id).await?;
@@ -424,6 +841,24 @@ async fn handle_agent_command(
             let agent = service.set_agent_status(id.clone(), status.clone()).await?;
             println!("Updated agent '{}' status to: {}", id, agent.status);
         }
+        AgentCommands::AutoReply { agent_id, message, delegate, off } => {
+            // `--off` clears the out-of-office reply, delegate and all
+            let auto_reply = if off {
+                None
+            } else {
+                message.map(|message| AutoReply { message, delegate })
+            };
+            let agent = service.set_auto_reply(agent_id.clone(), auto_reply).await?;
+            match agent.auto_reply {
+                Some(auto_reply) => {
+                    println!("🏖️  Auto-reply on for '{}' while offline or away", agent_id);
+                    if let Some(delegate) = auto_reply.delegate {
+                        println!("   Mail will be passed on to {}", delegate);
+                    }
+                }
+                None => println!("Auto-reply off for '{}'", agent_id),
+            }
+        }
         AgentCommands::SetSession { agent_id, session_id } => {
             let agent = service.set_agent_session(agent_id.clone(), session_id.clone()).await?;
             if let Some(ref sid) = agent.session_id {
@@ -432,37 +867,61 @@ async fn handle_agent_command(
                 println!("Cleared session ID for agent '{}' (using agent ID as fallback)", agent_id);
             }
         }
-        AgentCommands::Run { agent_id, bash, interval } => {
-            use tokio::time::{sleep, Duration};
+        AgentCommands::Run { agent_id, bash, interval, fallback_interval, ignore_archived } => {
+            use tokio::time::{sleep_until, Duration, Instant};
             use std::process::Command;
             
             // Get the agent to access session_id (for use inside the loop)
             let agent = service.get_agent(agent_id.clone()).await?;
+            let mailbox = service.get_agent_mailbox(agent_id.clone()).await?;
             let _ = service.set_agent_status(agent_id.clone(), "online").await;
             println!("Agent '{}' is now online", agent_id);
-            println!("Watching for new mail and schedules (checking every {} seconds)", interval);
+
+            // Postgres pushes a notification on new mail and schedule changes;
+            // without one (SQLite, or LISTEN failing) we fall back to polling
+            let mut wakeups = if storage::is_sqlite_url(database_url) {
+                None
+            } else {
+                match WakeupListener::connect(database_url).await {
+                    Ok(listener) => Some(listener),
+                    Err(e) => {
+                        println!("⚠️  Could not subscribe to notifications ({}), polling instead", e);
+                        None
+                    }
+                }
+            };
+            if wakeups.is_some() {
+                println!("Waiting for new mail and schedules (fallback check every {} seconds)", fallback_interval);
+            } else {
+                println!("Watching for new mail and schedules (checking every {} seconds)", interval);
+            }
             println!("Press Ctrl+C to stop");
             
             let ctrl_c = tokio::signal::ctrl_c();
             tokio::pin!(ctrl_c);
-            let interval_duration = Duration::from_secs(interval);
             let immediate_check_duration = Duration::from_millis(100);
             let mut running = true;
-            let mut check_immediately = true;
+            let mut next_check = Instant::now() + immediate_check_duration;
             
             // Determine session ID: use agent's session_id if set, otherwise use agent_id as fallback
             let session_id = agent.session_id.clone().unwrap_or_else(|| agent_id.clone());
             
-            // Helper function to execute bash command
-            async fn execute_bash(_agent_id: &str, session_id: &str, bash: &str, event_desc: &str) {
+            // Helper function to execute bash command. `payloads` is a JSON
+            // file with the payloads of the mail that triggered it, if any.
+            async fn execute_bash(_agent_id: &str, session_id: &str, bash: &str, event_desc: &str, payloads: Option<&std::path::Path>) {
                 use std::process::Stdio;
                 use std::io::{BufRead, BufReader};
                 
-                let mut child = Command::new("bash")
+                let mut command = Command::new("bash");
+                command
                     .arg("-c")
                     .arg(bash)
                     .env("AGENT_OFFICE_SESSION", session_id)
-                    .env("AGENT_OFFICE_EVENT", event_desc)
+                    .env("AGENT_OFFICE_EVENT", event_desc);
+                if let Some(payloads) = payloads {
+                    command.env("AGENT_OFFICE_PAYLOADS", payloads);
+                }
+                let mut child = command
                     .stdout(Stdio::piped())
                     .stderr(Stdio::piped())
                     .spawn()
@@ -470,50 +929,91 @@ async fn handle_agent_command(
                 
                 if let Some(stdout) = child.stdout.take() {
                     let reader = BufReader::new(stdout);
-                    for line in reader.lines() {
-                        if let Ok(line) = line {
-                            println!("{}", line);
-                        }
+                    for line in reader.lines().map_while(Result::ok) {
+                        println!("{}", line);
                     }
                 }
                 if let Some(stderr) = child.stderr.take() {
                     let reader = BufReader::new(stderr);
-                    for line in reader.lines() {
-                        if let Ok(line) = line {
-                            println!("{}", line);
-                        }
+                    for line in reader.lines().map_while(Result::ok) {
+                        println!("{}", line);
                     }
                 }
                 let _ = child.wait();
             }
-            
+
             while running {
-                let sleep_duration = if check_immediately {
-                    check_immediately = false;
-                    immediate_check_duration
-                } else {
-                    interval_duration
-                };
-                
                 tokio::select! {
                     _ = &mut ctrl_c => {
                         println!("\nStopping watch...");
                         running = false;
                     }
-                    _ = sleep(sleep_duration) => {
+                    wakeup = next_wakeup(&mut wakeups) => {
+                        match wakeup {
+                            Some(Wakeup::Mail(id)) if id == mailbox.id => next_check = Instant::now(),
+                            Some(Wakeup::Schedule(id)) if id == agent_id => next_check = Instant::now(),
+                            Some(Wakeup::Reconnected) => next_check = Instant::now(),
+                            // Somebody else's mail or schedule
+                            Some(_) => {}
+                            None => {
+                                println!("⚠️  Lost the notification connection, polling every {} seconds", interval);
+                                wakeups = None;
+                                next_check = Instant::now();
+                            }
+                        }
+                    }
+                    _ = sleep_until(next_check) => {
                         let current_time = chrono::Utc::now();
                         let mut triggered = false;
                         
+                        // Delayed mail that has come due gets its rules and
+                        // out-of-office replies before the inbox is checked
+                        if let Err(e) = service.arrive_due_mail(current_time).await {
+                            println!("⚠️  Could not process delayed mail: {}", e);
+                        }
+                        
                         // Check for unread mail
-                        let (has_unread, mails) = service.check_unread_mail(agent_id.clone()).await?;
+                        // Urgent mail comes first in the list
+                        let (has_unread, mails) = service.check_unread_mail(agent_id.clone(), !ignore_archived).await?;
                         if has_unread {
                             println!("\n📬 Found {} unread message(s)", mails.len());
                             for mail in &mails {
-                                println!("  - {}", mail.subject);
+                                println!("  - {}{}", mail.subject, mail_tags(mail));
                             }
                             println!("Executing: {}", bash);
-                            let event_desc = format!("agent id \"{}\" has unread mail", agent_id);
-                            execute_bash(&agent_id, &session_id, &bash, &event_desc).await;
+                            let event_desc = if mails.iter().any(|m| m.priority == Priority::Urgent) {
+                                format!("agent id \"{}\" has urgent unread mail", agent_id)
+                            } else {
+                                format!("agent id \"{}\" has unread mail", agent_id)
+                            };
+                            // Structured payloads go in a file rather than the
+                            // environment, which has a size limit
+                            let payloads: Vec<serde_json::Value> = mails.iter()
+                                .filter_map(|m| Some(serde_json::json!({
+                                    "id": m.id,
+                                    "subject": m.subject,
+                                    "content_type": m.content_type,
+                                    "payload": m.payload.as_ref()?,
+                                })))
+                                .collect();
+                            let payload_file = if payloads.is_empty() {
+                                None
+                            } else {
+                                // A fresh, owner-only file each time, so nobody
+                                // can plant or read one at a guessable path
+                                use std::io::Write;
+                                let mut file = tempfile::Builder::new()
+                                    .prefix("agent-office-payloads-")
+                                    .suffix(".json")
+                                    .tempfile()?;
+                                file.write_all(&serde_json::to_vec_pretty(&payloads)?)?;
+                                Some(file)
+                            };
+                            execute_bash(&agent_id, &session_id, &bash, &event_desc, payload_file.as_ref().map(|f| f.path())).await;
+                            // The command has exited by now
+                            if let Some(file) = payload_file {
+                                let _ = file.close();
+                            }
                             println!("\n✓ Command completed - waiting for new messages...");
                             triggered = true;
                         }
@@ -524,14 +1024,32 @@ async fn handle_agent_command(
                             println!("\n⏰ Schedule triggered: {}", action);
                             println!("Executing: {}", bash);
                             let event_desc = format!("agent id \"{}\" received a scheduled action request \"{}\"", agent_id, action);
-                            execute_bash(&agent_id, &session_id, &bash, &event_desc).await;
+                            execute_bash(&agent_id, &session_id, &bash, &event_desc, None).await;
                             println!("\n✓ Command completed - waiting for new messages...");
                             triggered = true;
                         }
                         
-                        if triggered {
-                            check_immediately = true;
-                        }
+                        next_check = if triggered {
+                            Instant::now() + immediate_check_duration
+                        } else if wakeups.is_some() {
+                            // Mail arrives as a notification, so only schedules
+                            // and delayed mail need a timer; the fallback covers
+                            // missed notifications
+                            let fallback = Instant::now() + Duration::from_secs(fallback_interval);
+                            let now = chrono::Utc::now();
+                            let delayed_mail = service.next_scheduled_delivery(agent_id.clone()).await?;
+                            schedule_service
+                                .list_schedules_by_agent(&agent_id)
+                                .await?
+                                .iter()
+                                .filter_map(|s| schedule_service.get_next_run(s, now))
+                                .chain(delayed_mail)
+                                .filter_map(|due| (due - now).to_std().ok())
+                                .map(|wait| Instant::now() + wait)
+                                .fold(fallback, Instant::min)
+                        } else {
+                            Instant::now() + Duration::from_secs(interval)
+                        };
                     }
                 }
             }
@@ -543,6 +1061,50 @@ async fn handle_agent_command(
     Ok(())
 }
 
+async fn handle_group_command(
+    service: impl MailService,
+    cmd: GroupCommands,
+) -> anyhow::Result<()> {
+    match cmd {
+        GroupCommands::Create { name } => {
+            let group = service.create_group(name).await?;
+            println!("Created group @{}", group.name);
+        }
+        GroupCommands::Delete { name } => {
+            service.delete_group(&name).await?;
+            println!("Deleted group @{}", name);
+        }
+        GroupCommands::Add { group, agent_ids } => {
+            for agent_id in agent_ids {
+                service.add_group_member(&group, agent_id.clone()).await?;
+                println!("Added {} to @{}", agent_id, group);
+            }
+        }
+        GroupCommands::Remove { group, agent_id } => {
+            service.remove_group_member(&group, agent_id.clone()).await?;
+            println!("Removed {} from @{}", agent_id, group);
+        }
+        GroupCommands::List => {
+            let groups = service.list_groups().await?;
+            if groups.is_empty() {
+                println!("No groups found");
+            } else {
+                println!("Groups:");
+                for group in groups {
+                    let members = service.get_group_members(&group.name).await?;
+                    let names: Vec<String> = members.into_iter().map(|agent| agent.name).collect();
+                    if names.is_empty() {
+                        println!("  @{} (no members)", group.name);
+                    } else {
+                        println!("  @{}: {}", group.name, names.join(", "));
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 async fn handle_kb_command(
     service: impl KnowledgeBaseService,
     cmd: KbCommands,
@@ -696,6 +1258,448 @@ async fn handle_kb_command(
     Ok(())
 }
 
+/// "[1a2b3c4d] Build API → bob (urgent, due 2026-03-01)"
+fn task_line(task: &Task) -> String {
+    let mut details = vec![task.status.as_str().to_string()];
+    if task.priority != Priority::Normal {
+        details.push(task.priority.as_str().to_string());
+    }
+    if let Some(due) = task.due {
+        let overdue = if task.is_overdue(chrono::Utc::now()) { ", overdue" } else { "" };
+        details.push(format!("due {}{}", due.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"), overdue));
+    }
+    let assignee = task.assignee.as_deref().map(|a| format!(" → {}", a)).unwrap_or_default();
+    format!("[{}] {}{} ({})", task.short_id(), task.title, assignee, details.join(", "))
+}
+
+async fn handle_task_command(
+    service: impl TaskService,
+    cmd: TaskCommands,
+) -> anyhow::Result<()> {
+    match cmd {
+        TaskCommands::Create { from, title, description, assign, priority, due, blocked_by } => {
+            let due = due
+                .map(|d| parse_deliver_at(&d, chrono::Local::now()))
+                .transpose()
+                .map_err(|e| anyhow::anyhow!(e))?;
+            let mut blockers = Vec::with_capacity(blocked_by.len());
+            for short_id in &blocked_by {
+                blockers.push(service.get_task_by_short_id(short_id).await?.id);
+            }
+            let task = Task {
+                description,
+                assignee: assign,
+                priority,
+                due,
+                ..Task::new(from, title)
+            };
+            let task = service.create_task(task, blockers).await?;
+            println!("📋 Created task {}", task_line(&task));
+            if let Some(ref assignee) = task.assignee {
+                println!("   {} has been notified by mail", assignee);
+            }
+        }
+        TaskCommands::Assign { task_id, agent_id, by } => {
+            let task = service.get_task_by_short_id(&task_id).await?;
+            let task = service.assign_task(task.id, agent_id.clone(), by).await?;
+            println!("📋 Assigned {}", task_line(&task));
+        }
+        TaskCommands::Start { task_id } => {
+            let task = service.get_task_by_short_id(&task_id).await?;
+            let task = service.start_task(task.id).await?;
+            println!("🔨 Started {}", task_line(&task));
+        }
+        TaskCommands::Done { task_id } => {
+            let task = service.get_task_by_short_id(&task_id).await?;
+            let (task, unblocked) = service.complete_task(task.id).await?;
+            println!("✅ Finished {}", task_line(&task));
+            for task in unblocked {
+                println!("   Unblocked {}", task_line(&task));
+            }
+        }
+        TaskCommands::List { agent, status, all } => {
+            let filter = TaskFilter { assignee: agent.clone(), status, include_done: all };
+            let tasks = service.list_tasks(&filter).await?;
+            let whose = agent.map(|a| format!(" for {}", a)).unwrap_or_default();
+            if tasks.is_empty() {
+                println!("No tasks{}", whose);
+            } else {
+                println!("Tasks{} ({} total):", whose, tasks.len());
+                for task in tasks {
+                    println!("  {}", task_line(&task));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// "[1a2b3c4d] Build API → bob (launch)"
+fn work_item_line(item: &WorkItem) -> String {
+    let assignee = item.assignee.as_deref().map(|a| format!(" → {}", a)).unwrap_or_default();
+    let project = item.project.as_deref().map(|p| format!(" ({})", p)).unwrap_or_default();
+    format!("[{}] {}{}{}", item.short_id(), item.title, assignee, project)
+}
+
+async fn handle_work_command(
+    service: impl WorkService,
+    cmd: WorkCommands,
+) -> anyhow::Result<()> {
+    match cmd {
+        WorkCommands::Add { from, title, description, agent, project, depends_on } => {
+            let mut dependencies = Vec::with_capacity(depends_on.len());
+            for short_id in &depends_on {
+                dependencies.push(service.get_work_item_by_short_id(short_id).await?.id);
+            }
+            let item = WorkItem {
+                description,
+                assignee: agent,
+                project,
+                ..WorkItem::new(from, title)
+            };
+            let item = service.create_work_item(item, dependencies).await?;
+            let state = service.get_work_state(&item).await?;
+            println!("🧩 Added {} - {}", work_item_line(&item), state.as_str());
+        }
+        WorkCommands::Depend { item_id, depends_on } => {
+            let item = service.get_work_item_by_short_id(&item_id).await?;
+            for short_id in &depends_on {
+                let dependency = service.get_work_item_by_short_id(short_id).await?;
+                service.add_dependency(item.id, dependency.id).await?;
+                println!("🔗 {} now depends on {}", work_item_line(&item), work_item_line(&dependency));
+            }
+        }
+        WorkCommands::Done { item_id, by } => {
+            let item = service.get_work_item_by_short_id(&item_id).await?;
+            let (item, ready) = service.complete_work_item(item.id, by).await?;
+            println!("✅ Completed {}", work_item_line(&item));
+            for item in ready {
+                println!("   Now ready: {}", work_item_line(&item));
+            }
+        }
+        WorkCommands::Ready { agent, project } => {
+            let items = service.ready_work_items(agent.clone(), project.as_deref()).await?;
+            let whose = agent.map(|a| format!(" for {}", a)).unwrap_or_default();
+            if items.is_empty() {
+                println!("Nothing ready{}", whose);
+            } else {
+                println!("Ready{} ({} total):", whose, items.len());
+                for item in items {
+                    println!("  {}", work_item_line(&item));
+                }
+            }
+        }
+        WorkCommands::List { project } => {
+            let graph = service.get_work_graph(project.as_deref()).await?;
+            if graph.items.is_empty() {
+                println!("No work items");
+            }
+            for (index, (item, state)) in graph.items.iter().enumerate() {
+                let icon = match state {
+                    WorkState::Waiting => "⏳",
+                    WorkState::Ready => "🟢",
+                    WorkState::Complete => "✅",
+                };
+                println!("  {} {} - {}", icon, work_item_line(item), state.as_str());
+                let dependencies: Vec<String> = graph.dependencies.iter()
+                    .filter(|(from, _)| *from == index)
+                    .map(|(_, to)| graph.items[*to].0.short_id())
+                    .collect();
+                if !dependencies.is_empty() {
+                    println!("      depends on {}", dependencies.join(", "));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Next notification, or never when we're polling. `None` means the
+// listener failed for good.
+async fn next_wakeup(listener: &mut Option<WakeupListener>) -> Option<Wakeup> {
+    match listener {
+        Some(listener) => listener.recv().await.ok(),
+        None => std::future::pending().await,
+    }
+}
+
+/// "[1a2b3c4d] Drop the events table from alice (pending)"
+fn approval_line(request: &ApprovalRequest) -> String {
+    format!("[{}] {} from {} ({})", request.short_id(), request.title, request.requested_by, request.status.as_str())
+}
+
+/// Block until `request` is decided or `timeout` seconds pass (0 = no
+/// limit), then exit 0 if it was approved, 2 if rejected and 3 on timeout.
+/// On Postgres the decision mail wakes us straight away; otherwise we poll.
+async fn wait_for_decision(
+    database_url: &str,
+    service: &impl ApprovalService,
+    request: ApprovalRequest,
+    timeout: u64,
+    interval: u64,
+) -> anyhow::Result<()> {
+    use tokio::time::{sleep_until, Duration, Instant};
+
+    let deadline = (timeout > 0).then(|| Instant::now() + Duration::from_secs(timeout));
+    let mut wakeups = if storage::is_sqlite_url(database_url) {
+        None
+    } else {
+        WakeupListener::connect(database_url).await.ok()
+    };
+    println!("⏳ Waiting for a decision on {}", approval_line(&request));
+
+    let request = loop {
+        let current = service.get_request(request.id).await?;
+        if !current.is_pending() {
+            break current;
+        }
+        let now = Instant::now();
+        if deadline.is_some_and(|deadline| deadline <= now) {
+            println!("⌛ No decision after {} seconds", timeout);
+            std::process::exit(3);
+        }
+        let next_check = now + Duration::from_secs(interval.max(1));
+        tokio::select! {
+            wakeup = next_wakeup(&mut wakeups) => {
+                if wakeup.is_none() {
+                    wakeups = None;
+                }
+            }
+            _ = sleep_until(deadline.map_or(next_check, |deadline| deadline.min(next_check))) => {}
+        }
+    };
+
+    let decided_by = request.decided_by.as_deref().unwrap_or("someone");
+    if request.status == ApprovalStatus::Approved {
+        println!("✅ Approved by {}", decided_by);
+        if let Some(ref option) = request.chosen_option {
+            println!("   Option: {}", option);
+        }
+    } else {
+        println!("❌ Rejected by {}", decided_by);
+    }
+    if !request.comment.is_empty() {
+        println!("   Comment: {}", request.comment);
+    }
+    if request.status != ApprovalStatus::Approved {
+        std::process::exit(2);
+    }
+    Ok(())
+}
+
+async fn handle_approval_command(
+    database_url: &str,
+    service: impl ApprovalService,
+    cmd: ApprovalCommands,
+) -> anyhow::Result<()> {
+    match cmd {
+        ApprovalCommands::Request { from, title, description, options, wait } => {
+            let request = ApprovalRequest {
+                description,
+                options,
+                ..ApprovalRequest::new(from, title)
+            };
+            let request = service.create_request(request).await?;
+            println!("🙋 Requested approval {}", approval_line(&request));
+            for option in &request.options {
+                println!("   • {}", option);
+            }
+            match wait {
+                Some(timeout) => wait_for_decision(database_url, &service, request, timeout, 5).await?,
+                None => println!("   Wait for the decision with: agent-office approval wait {}", request.short_id()),
+            }
+        }
+        ApprovalCommands::Wait { request_id, timeout, interval } => {
+            let request = service.get_request_by_short_id(&request_id).await?;
+            wait_for_decision(database_url, &service, request, timeout, interval).await?;
+        }
+        ApprovalCommands::List { agent, all } => {
+            let requests = service.list_requests(agent.clone(), !all).await?;
+            let whose = agent.map(|a| format!(" from {}", a)).unwrap_or_default();
+            let kind = if all { "Approval requests" } else { "Pending approval requests" };
+            if requests.is_empty() {
+                println!("No {}{}", kind.to_lowercase(), whose);
+            } else {
+                println!("{}{} ({} total):", kind, whose, requests.len());
+                for request in requests {
+                    println!("  {}", approval_line(&request));
+                    if let Some(ref option) = request.chosen_option {
+                        println!("      option: {}", option);
+                    }
+                    if !request.comment.is_empty() {
+                        println!("      comment: {}", request.comment);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A duration like "30s", "15m", "2h" or "1d"
+fn parse_ttl(input: &str) -> anyhow::Result<chrono::Duration> {
+    let now = chrono::Utc::now();
+    let until = parse_deliver_at(&format!("+{}", input.trim().trim_start_matches('+')), now)
+        .map_err(|_| anyhow::anyhow!("can't read '{}' as a duration (try 30s, 15m, 2h or 1d)", input))?;
+    Ok(until - now)
+}
+
+/// "repo/agent-office held by alice until 2026-03-01 17:00:00"
+fn lease_line(lease: &Lease) -> String {
+    format!(
+        "{} held by {} until {}",
+        lease.name,
+        lease.holder,
+        lease.expires_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S")
+    )
+}
+
+/// Exit 2 for "someone else has it", the outcome scripts branch on;
+/// anything else is an ordinary error
+fn exit_if_contended<T>(result: Result<T, LeaseError>) -> anyhow::Result<T> {
+    match result {
+        Err(e @ (LeaseError::Held(_) | LeaseError::NotHeld { .. })) => {
+            println!("🔒 {}", e);
+            std::process::exit(2);
+        }
+        result => Ok(result?),
+    }
+}
+
+async fn handle_lock_command(
+    service: impl LeaseService,
+    cmd: LockCommands,
+) -> anyhow::Result<()> {
+    match cmd {
+        LockCommands::Acquire { name, agent, ttl, wait } => {
+            use tokio::time::{sleep, Duration, Instant};
+
+            let ttl = parse_ttl(&ttl)?;
+            let deadline = wait.map(|secs| Instant::now() + Duration::from_secs(secs));
+            let lease = loop {
+                match service.acquire(&name, &agent, ttl).await {
+                    Err(LeaseError::Held(_)) if deadline.is_some_and(|deadline| Instant::now() < deadline) => {
+                        sleep(Duration::from_secs(1)).await;
+                    }
+                    result => break exit_if_contended(result)?,
+                }
+            };
+            println!("🔐 {}", lease_line(&lease));
+        }
+        LockCommands::Renew { name, agent, ttl } => {
+            let lease = exit_if_contended(service.renew(&name, &agent, parse_ttl(&ttl)?).await)?;
+            println!("🔐 {}", lease_line(&lease));
+        }
+        LockCommands::Release { name, agent } => {
+            exit_if_contended(service.release(&name, &agent).await)?;
+            println!("🔓 Released {}", name);
+        }
+        LockCommands::List => {
+            let leases = service.list_leases().await?;
+            if leases.is_empty() {
+                println!("No locks held");
+            } else {
+                println!("Locks ({} held):", leases.len());
+                for lease in leases {
+                    println!("  {}", lease_line(&lease));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// "release/branch = release-1.5 (v2 by alice, expires 2026-03-01 17:00)"
+fn kv_line(entry: &KvEntry) -> String {
+    let mut details = vec![format!("v{} by {}", entry.version, entry.updated_by)];
+    details.push(entry.updated_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string());
+    if let Some(expires_at) = entry.expires_at {
+        details.push(format!("expires {}", expires_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S")));
+    }
+    match entry.value {
+        Some(ref value) => format!("{} = {} ({})", entry.key, value, details.join(", ")),
+        None => format!("{} deleted ({})", entry.key, details.join(", ")),
+    }
+}
+
+/// The condition for a compare-and-swap write, from the --if-* flags
+fn kv_expect(if_absent: bool, if_version: Option<i64>, if_value: Option<String>) -> Option<KvExpect> {
+    if if_absent {
+        Some(KvExpect::Absent)
+    } else {
+        if_version.map(KvExpect::Version).or(if_value.map(KvExpect::Value))
+    }
+}
+
+async fn handle_kv_command(
+    service: impl KvService,
+    cmd: KvCommands,
+) -> anyhow::Result<()> {
+    match cmd {
+        KvCommands::Get { key } => {
+            let entry = service.get(&key).await?
+                .ok_or_else(|| anyhow::anyhow!("{} has no value", key))?;
+            println!("{}", entry.value.unwrap_or_default());
+        }
+        KvCommands::Set { key, value, from, ttl, if_absent, if_version, if_value } => {
+            let expires_at = ttl
+                .map(|ttl| parse_ttl(&ttl).map(|ttl| chrono::Utc::now() + ttl))
+                .transpose()?;
+            let entry = service.set(&key, value, from, expires_at, kv_expect(if_absent, if_version, if_value)).await?;
+            println!("📌 {}", kv_line(&entry));
+        }
+        KvCommands::Delete { key, from, if_version, if_value } => {
+            let entry = service.delete(&key, from, kv_expect(false, if_version, if_value)).await?;
+            println!("🗑️  {}", kv_line(&entry));
+        }
+        KvCommands::List { namespace } => {
+            let entries = service.list(namespace.as_deref()).await?;
+            let scope = namespace.map(|n| format!(" in {}", n)).unwrap_or_default();
+            if entries.is_empty() {
+                println!("No keys{}", scope);
+            } else {
+                println!("Keys{} ({} total):", scope, entries.len());
+                for entry in entries {
+                    println!("  {}", kv_line(&entry));
+                }
+            }
+        }
+        KvCommands::Watch { keys, interval } => {
+            let ctrl_c = tokio::signal::ctrl_c();
+            tokio::pin!(ctrl_c);
+            // Last version seen per key; None while it has no value
+            let mut seen: Vec<Option<Option<i64>>> = vec![None; keys.len()];
+            loop {
+                for (key, seen) in keys.iter().zip(seen.iter_mut()) {
+                    let entry = service.get(key).await?;
+                    let version = entry.as_ref().map(|e| e.version);
+                    if *seen != Some(version) {
+                        match entry {
+                            Some(ref entry) => println!("{}", kv_line(entry)),
+                            None => println!("{} has no value", key),
+                        }
+                        *seen = Some(version);
+                    }
+                }
+                tokio::select! {
+                    _ = &mut ctrl_c => break,
+                    _ = tokio::time::sleep(std::time::Duration::from_secs(interval.max(1))) => {}
+                }
+            }
+        }
+        KvCommands::History { key } => {
+            let history = service.history(&key).await?;
+            if history.is_empty() {
+                println!("{} has never been set", key);
+            }
+            for entry in history {
+                println!("  {}", kv_line(&entry));
+            }
+        }
+    }
+    Ok(())
+}
+
 async fn handle_schedule_command(
     service: impl ScheduleService,
     cmd: ScheduleCommands,
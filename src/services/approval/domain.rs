@@ -0,0 +1,148 @@
+use crate::domain::{Node, NodeId, Properties, PropertyValue, Timestamp};
+use crate::services::mail::domain::AgentId;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+pub type ApprovalId = NodeId;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalStatus {
+    #[default]
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl ApprovalStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApprovalStatus::Pending => "pending",
+            ApprovalStatus::Approved => "approved",
+            ApprovalStatus::Rejected => "rejected",
+        }
+    }
+}
+
+impl std::str::FromStr for ApprovalStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pending" => Ok(ApprovalStatus::Pending),
+            "approved" => Ok(ApprovalStatus::Approved),
+            "rejected" => Ok(ApprovalStatus::Rejected),
+            _ => Err(format!("unknown status '{}' (expected pending, approved or rejected)", s)),
+        }
+    }
+}
+
+/// An agent asking the human for sign-off before doing something it
+/// shouldn't do on its own. Stored as an approval_request node; the
+/// decision is written onto the same node and mailed to the requester.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ApprovalRequest {
+    pub id: ApprovalId,
+    pub title: String,
+    pub description: String,
+    pub requested_by: AgentId,
+    /// Ways the human can approve, e.g. "drop the table" or "rename it";
+    /// empty for a plain yes or no
+    pub options: Vec<String>,
+    pub status: ApprovalStatus,
+    /// The option picked when approving
+    pub chosen_option: Option<String>,
+    pub comment: String,
+    pub decided_by: Option<AgentId>,
+    pub decided_at: Option<Timestamp>,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+}
+
+impl ApprovalRequest {
+    pub fn new(requested_by: impl Into<AgentId>, title: impl Into<String>) -> Self {
+        let now = Utc::now();
+        Self {
+            id: ApprovalId::new_v4(),
+            title: title.into(),
+            description: String::new(),
+            requested_by: requested_by.into(),
+            options: Vec::new(),
+            status: ApprovalStatus::Pending,
+            chosen_option: None,
+            comment: String::new(),
+            decided_by: None,
+            decided_at: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn short_id(&self) -> String {
+        self.id.to_string()[..8].to_string()
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.status == ApprovalStatus::Pending
+    }
+
+    pub fn to_node(&self) -> Node {
+        let mut props = Properties::new();
+        props.insert("title".to_string(), PropertyValue::String(self.title.clone()));
+        props.insert("description".to_string(), PropertyValue::String(self.description.clone()));
+        props.insert("requested_by".to_string(), PropertyValue::String(self.requested_by.clone()));
+        props.insert(
+            "options".to_string(),
+            PropertyValue::List(self.options.iter().map(|o| PropertyValue::String(o.clone())).collect()),
+        );
+        props.insert("status".to_string(), PropertyValue::String(self.status.as_str().to_string()));
+        if let Some(ref option) = self.chosen_option {
+            props.insert("chosen_option".to_string(), PropertyValue::String(option.clone()));
+        }
+        props.insert("comment".to_string(), PropertyValue::String(self.comment.clone()));
+        if let Some(ref decided_by) = self.decided_by {
+            props.insert("decided_by".to_string(), PropertyValue::String(decided_by.clone()));
+        }
+        if let Some(decided_at) = self.decided_at {
+            props.insert("decided_at".to_string(), PropertyValue::Timestamp(decided_at));
+        }
+
+        let mut node = Node::new("approval_request", props);
+        node.id = self.id;
+        node.created_at = self.created_at;
+        node.updated_at = self.updated_at;
+        node
+    }
+
+    pub fn from_node(node: &Node) -> Option<Self> {
+        if node.node_type != "approval_request" {
+            return None;
+        }
+
+        let text = |key: &str| node.get_property(key).and_then(|v| v.as_str()).map(str::to_string);
+
+        Some(Self {
+            id: node.id,
+            title: text("title")?,
+            description: text("description").unwrap_or_default(),
+            requested_by: text("requested_by")?,
+            options: match node.get_property("options") {
+                Some(PropertyValue::List(items)) => items
+                    .iter()
+                    .filter_map(|item| item.as_str().map(str::to_string))
+                    .collect(),
+                _ => Vec::new(),
+            },
+            status: text("status").and_then(|s| s.parse().ok()).unwrap_or_default(),
+            chosen_option: text("chosen_option"),
+            comment: text("comment").unwrap_or_default(),
+            decided_by: text("decided_by"),
+            decided_at: match node.get_property("decided_at") {
+                Some(PropertyValue::Timestamp(t)) => Some(*t),
+                _ => None,
+            },
+            created_at: node.created_at,
+            updated_at: node.updated_at,
+        })
+    }
+}
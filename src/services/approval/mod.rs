@@ -0,0 +1,379 @@
+use crate::domain::{GraphQuery, Node, Properties, PropertyValue, string_to_node_id};
+use crate::services::approval::domain::{ApprovalId, ApprovalRequest, ApprovalStatus};
+use crate::services::mail::domain::{AgentId, Priority, SendOptions};
+use crate::services::mail::{MailError, MailService};
+use crate::storage::{GraphStorage, StorageError};
+use async_trait::async_trait;
+use thiserror::Error;
+
+pub mod domain;
+
+// One per decided request, under an ID fixed by the request, so when two
+// people decide at once only the first decision can be stored
+const DECISION: &str = "approval_decision";
+
+fn decision(request: &ApprovalRequest) -> Node {
+    let mut props = Properties::new();
+    props.insert("request_id".to_string(), PropertyValue::String(request.id.to_string()));
+    props.insert("status".to_string(), PropertyValue::String(request.status.as_str().to_string()));
+    if let Some(ref decided_by) = request.decided_by {
+        props.insert("decided_by".to_string(), PropertyValue::String(decided_by.clone()));
+    }
+    let mut node = Node::new(DECISION, props);
+    node.id = string_to_node_id(&format!("{}:{}", DECISION, request.id));
+    node
+}
+
+#[derive(Error, Debug)]
+pub enum ApprovalError {
+    #[error("Approval request not found: {0}")]
+    RequestNotFound(String),
+
+    #[error("Invalid operation: {0}")]
+    InvalidOperation(String),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error(transparent)]
+    Mail(#[from] MailError),
+}
+
+pub type Result<T> = std::result::Result<T, ApprovalError>;
+
+/// Sign-off from the human before an agent does something destructive.
+/// Requests wait on the web dashboard; the decision is mailed back to the
+/// requesting agent, which wakes `agent run`.
+#[async_trait]
+pub trait ApprovalService: Send + Sync {
+    // File a pending request; blank and repeated options are dropped
+    async fn create_request(&self, request: ApprovalRequest) -> Result<ApprovalRequest>;
+
+    async fn get_request(&self, request_id: ApprovalId) -> Result<ApprovalRequest>;
+
+    // Find a request by short ID (8-char prefix) or full ID
+    async fn get_request_by_short_id(&self, short_id: &str) -> Result<ApprovalRequest>;
+
+    // Requests (from one agent, if given), oldest first
+    async fn list_requests(&self, requested_by: Option<AgentId>, pending_only: bool) -> Result<Vec<ApprovalRequest>>;
+
+    // Approve a pending request. `option` must be one of the request's
+    // options, and can be left out when it has at most one.
+    async fn approve(&self, request_id: ApprovalId, decided_by: AgentId, option: Option<String>, comment: String) -> Result<ApprovalRequest>;
+
+    async fn reject(&self, request_id: ApprovalId, decided_by: AgentId, comment: String) -> Result<ApprovalRequest>;
+}
+
+pub struct ApprovalServiceImpl<S: GraphStorage, M: MailService> {
+    storage: S,
+    mail: M,
+}
+
+impl<S: GraphStorage, M: MailService> ApprovalServiceImpl<S, M> {
+    pub fn new(storage: S, mail: M) -> Self {
+        Self { storage, mail }
+    }
+
+    // Record the decision and mail it to the requester from whoever made it
+    async fn decide(
+        &self,
+        request_id: ApprovalId,
+        decided_by: AgentId,
+        status: ApprovalStatus,
+        chosen_option: Option<String>,
+        comment: String,
+    ) -> Result<ApprovalRequest> {
+        let mut request = self.get_request(request_id).await?;
+        if !request.is_pending() {
+            return Err(ApprovalError::InvalidOperation(format!(
+                "{} was already {}",
+                request.short_id(),
+                request.status.as_str()
+            )));
+        }
+        let decided_by = self.mail.get_agent(decided_by).await?.id;
+        if decided_by == request.requested_by {
+            return Err(ApprovalError::InvalidOperation(format!(
+                "{} can't decide their own request",
+                decided_by
+            )));
+        }
+
+        let now = chrono::Utc::now();
+        request.status = status;
+        request.chosen_option = chosen_option;
+        request.comment = comment.trim().to_string();
+        request.decided_by = Some(decided_by.clone());
+        request.decided_at = Some(now);
+        request.updated_at = now;
+        let mut tx = self.storage.begin();
+        tx.create_node(&decision(&request))
+            .update_node(&request.to_node());
+        match self.storage.commit(tx).await {
+            Ok(()) => {}
+            Err(StorageError::ConstraintViolation(_)) => {
+                let request = self.get_request(request.id).await?;
+                return Err(ApprovalError::InvalidOperation(format!(
+                    "{} was already {}",
+                    request.short_id(),
+                    request.status.as_str()
+                )));
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        let verdict = if status == ApprovalStatus::Approved { "Approved" } else { "Rejected" };
+        let mut body = format!("{} {} your request: {}\n", decided_by, status.as_str(), request.title);
+        if let Some(ref option) = request.chosen_option {
+            body.push_str(&format!("\nGo ahead with: {}\n", option));
+        }
+        if !request.comment.is_empty() {
+            body.push_str(&format!("\n{}\n", request.comment));
+        }
+        body.push_str(&format!("\nRequest {}", request.short_id()));
+
+        let options = SendOptions {
+            priority: Priority::Urgent,
+            labels: vec!["approval".to_string()],
+            payload: Some(serde_json::json!({
+                "approval_id": request.id,
+                "status": request.status.as_str(),
+                "option": request.chosen_option,
+                "comment": request.comment,
+            })),
+            ..Default::default()
+        };
+        self.mail.send_mail_with(
+            decided_by,
+            vec![request.requested_by.clone()],
+            Vec::new(),
+            format!("{}: {}", verdict, request.title),
+            body,
+            options,
+        ).await?;
+        Ok(request)
+    }
+}
+
+#[async_trait]
+impl<S: GraphStorage, M: MailService> ApprovalService for ApprovalServiceImpl<S, M> {
+    async fn create_request(&self, mut request: ApprovalRequest) -> Result<ApprovalRequest> {
+        if request.title.trim().is_empty() {
+            return Err(ApprovalError::InvalidOperation("An approval request needs a title".to_string()));
+        }
+        self.mail.get_agent(request.requested_by.clone()).await?;
+
+        let mut options: Vec<String> = Vec::with_capacity(request.options.len());
+        for option in request.options.iter().map(|o| o.trim()).filter(|o| !o.is_empty()) {
+            if !options.iter().any(|o| o == option) {
+                options.push(option.to_string());
+            }
+        }
+        request.options = options;
+        request.status = ApprovalStatus::Pending;
+
+        self.storage.create_node(&request.to_node()).await?;
+        Ok(request)
+    }
+
+    async fn get_request(&self, request_id: ApprovalId) -> Result<ApprovalRequest> {
+        match self.storage.get_node(request_id).await {
+            Ok(node) => ApprovalRequest::from_node(&node)
+                .ok_or_else(|| ApprovalError::RequestNotFound(request_id.to_string())),
+            Err(StorageError::NodeNotFound(_)) => Err(ApprovalError::RequestNotFound(request_id.to_string())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get_request_by_short_id(&self, short_id: &str) -> Result<ApprovalRequest> {
+        let requests = self.list_requests(None, false).await?;
+
+        let short_id_lower = short_id.to_lowercase();
+        let mut matching: Vec<_> = requests.into_iter()
+            .filter(|r| r.id.to_string().starts_with(&short_id_lower))
+            .collect();
+
+        match matching.len() {
+            0 => Err(ApprovalError::RequestNotFound(short_id.to_string())),
+            1 => Ok(matching.remove(0)),
+            _ => Err(ApprovalError::InvalidOperation(
+                format!("Multiple approval requests match short ID '{}', please use full ID", short_id)
+            )),
+        }
+    }
+
+    async fn list_requests(&self, requested_by: Option<AgentId>, pending_only: bool) -> Result<Vec<ApprovalRequest>> {
+        let mut query = GraphQuery::new().with_node_type("approval_request");
+        if let Some(requested_by) = requested_by {
+            query = query.with_property("requested_by", PropertyValue::String(requested_by));
+        }
+        if pending_only {
+            query = query.with_property("status", PropertyValue::String(ApprovalStatus::Pending.as_str().to_string()));
+        }
+        let mut requests: Vec<ApprovalRequest> = self.storage.query_nodes(&query).await?
+            .iter()
+            .filter_map(ApprovalRequest::from_node)
+            .collect();
+        requests.sort_by_key(|r| r.created_at);
+        Ok(requests)
+    }
+
+    async fn approve(&self, request_id: ApprovalId, decided_by: AgentId, option: Option<String>, comment: String) -> Result<ApprovalRequest> {
+        let request = self.get_request(request_id).await?;
+        let option = match option.map(|o| o.trim().to_string()).filter(|o| !o.is_empty()) {
+            Some(option) if request.options.contains(&option) => Some(option),
+            Some(option) => {
+                return Err(ApprovalError::InvalidOperation(format!(
+                    "'{}' isn't one of the options: {}",
+                    option,
+                    request.options.join(", ")
+                )));
+            }
+            None if request.options.len() > 1 => {
+                return Err(ApprovalError::InvalidOperation(format!(
+                    "Pick one of the options to approve: {}",
+                    request.options.join(", ")
+                )));
+            }
+            None => request.options.first().cloned(),
+        };
+        self.decide(request.id, decided_by, ApprovalStatus::Approved, option, comment).await
+    }
+
+    async fn reject(&self, request_id: ApprovalId, decided_by: AgentId, comment: String) -> Result<ApprovalRequest> {
+        self.decide(request_id, decided_by, ApprovalStatus::Rejected, None, comment).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::mail::MailServiceImpl;
+    use crate::storage::memory::InMemoryStorage;
+
+    type Approvals = ApprovalServiceImpl<InMemoryStorage, MailServiceImpl<InMemoryStorage>>;
+
+    // An approval service with agents alice and human, and a request from
+    // alice offering two ways to go ahead
+    async fn setup() -> (Approvals, ApprovalRequest) {
+        let storage = InMemoryStorage::new();
+        let mail = MailServiceImpl::new(storage.clone());
+        mail.create_agent("alice").await.unwrap();
+        mail.create_agent("human").await.unwrap();
+        let approvals = ApprovalServiceImpl::new(storage, mail);
+
+        let request = ApprovalRequest {
+            description: "Frees 40GB".to_string(),
+            options: vec!["drop it".to_string(), "archive first".to_string()],
+            ..ApprovalRequest::new("alice", "Drop the old events table")
+        };
+        let request = approvals.create_request(request).await.unwrap();
+        (approvals, request)
+    }
+
+    #[tokio::test]
+    async fn test_blank_and_repeated_options_are_dropped() {
+        let (approvals, _) = setup().await;
+        let request = ApprovalRequest {
+            options: vec!["drop it".to_string(), " ".to_string(), " archive first ".to_string(), "drop it".to_string()],
+            ..ApprovalRequest::new("alice", "Drop the old events table")
+        };
+        let request = approvals.create_request(request).await.unwrap();
+        assert_eq!(request.options, vec!["drop it", "archive first"]);
+        assert!(approvals.create_request(ApprovalRequest::new("alice", " ")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_approving_needs_one_of_the_options() {
+        let (approvals, request) = setup().await;
+        assert!(approvals.approve(request.id, "human".into(), None, String::new()).await.is_err());
+        assert!(approvals.approve(request.id, "human".into(), Some("truncate".to_string()), String::new()).await.is_err());
+        assert!(approvals.get_request(request.id).await.unwrap().is_pending());
+
+        // With a single option there is nothing to pick
+        let single = ApprovalRequest { options: vec!["go".to_string()], ..ApprovalRequest::new("alice", "Deploy") };
+        let single = approvals.create_request(single).await.unwrap();
+        let decided = approvals.approve(single.id, "human".into(), None, String::new()).await.unwrap();
+        assert_eq!(decided.chosen_option.as_deref(), Some("go"));
+    }
+
+    #[tokio::test]
+    async fn test_nobody_decides_their_own_request() {
+        let (approvals, request) = setup().await;
+        let err = approvals.approve(request.id, "alice".into(), Some("drop it".to_string()), String::new()).await;
+        assert!(matches!(err, Err(ApprovalError::InvalidOperation(_))));
+        assert!(approvals.reject(request.id, "alice".into(), String::new()).await.is_err());
+        assert!(approvals.get_request(request.id).await.unwrap().is_pending());
+    }
+
+    #[tokio::test]
+    async fn test_approval_is_mailed_to_requester() {
+        let (approvals, request) = setup().await;
+        let comment = "Archive to S3 first".to_string();
+        let decided = approvals.approve(request.id, "human".into(), Some("archive first".to_string()), comment).await.unwrap();
+        assert_eq!(decided.status, ApprovalStatus::Approved);
+        assert_eq!(decided.decided_by.as_deref(), Some("human"));
+        assert_eq!(approvals.get_request_by_short_id(&request.short_id()).await.unwrap(), decided);
+
+        let (_, unread) = approvals.mail.check_unread_mail("alice".into(), false).await.unwrap();
+        assert_eq!(unread[0].subject, "Approved: Drop the old events table");
+        assert_eq!(unread[0].priority, Priority::Urgent);
+        assert_eq!(unread[0].payload.as_ref().unwrap()["option"], "archive first");
+        assert_eq!(unread[0].payload.as_ref().unwrap()["comment"], "Archive to S3 first");
+    }
+
+    #[tokio::test]
+    async fn test_rejection_is_mailed_to_requester() {
+        let (approvals, request) = setup().await;
+        let decided = approvals.reject(request.id, "human".into(), "Not this week".to_string()).await.unwrap();
+        assert_eq!(decided.status, ApprovalStatus::Rejected);
+        assert_eq!(decided.chosen_option, None);
+
+        let (_, unread) = approvals.mail.check_unread_mail("alice".into(), false).await.unwrap();
+        assert_eq!(unread[0].subject, "Rejected: Drop the old events table");
+        assert_eq!(unread[0].payload.as_ref().unwrap()["status"], "rejected");
+    }
+
+    #[tokio::test]
+    async fn test_decided_request_leaves_the_pending_list_and_stays_decided() {
+        let (approvals, request) = setup().await;
+        let pending = approvals.list_requests(None, true).await.unwrap();
+        assert_eq!(pending.iter().map(|r| r.id).collect::<Vec<_>>(), vec![request.id]);
+
+        approvals.approve(request.id, "human".into(), Some("drop it".to_string()), String::new()).await.unwrap();
+        assert!(approvals.list_requests(None, true).await.unwrap().is_empty());
+        assert_eq!(approvals.list_requests(Some("alice".into()), false).await.unwrap().len(), 1);
+        assert!(approvals.reject(request.id, "human".into(), String::new()).await.is_err());
+        assert_eq!(approvals.get_request(request.id).await.unwrap().status, ApprovalStatus::Approved);
+    }
+
+    #[tokio::test]
+    async fn test_only_the_first_of_two_decisions_counts() {
+        let (approvals, request) = setup().await;
+        approvals.mail.create_agent("bob").await.unwrap();
+
+        let (approved, rejected) = tokio::join!(
+            approvals.approve(request.id, "human".into(), Some("drop it".to_string()), String::new()),
+            approvals.reject(request.id, "bob".into(), "No".to_string()),
+        );
+        assert_eq!([approved.is_ok(), rejected.is_ok()].iter().filter(|ok| **ok).count(), 1);
+        let decided = approved.or(rejected).unwrap();
+        assert_eq!(approvals.get_request(request.id).await.unwrap(), decided);
+
+        let (_, unread) = approvals.mail.check_unread_mail("alice".into(), false).await.unwrap();
+        assert_eq!(unread.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stored_decision_wins_over_a_stale_pending_read() {
+        let (approvals, request) = setup().await;
+        // As if another decision committed after this one read the request
+        let other = ApprovalRequest { status: ApprovalStatus::Rejected, decided_by: Some("bob".to_string()), ..request.clone() };
+        approvals.storage.create_node(&decision(&other)).await.unwrap();
+
+        let err = approvals.approve(request.id, "human".into(), Some("drop it".to_string()), String::new()).await;
+        assert!(matches!(err, Err(ApprovalError::InvalidOperation(_))));
+        assert!(approvals.get_request(request.id).await.unwrap().decided_by.is_none());
+        assert!(!approvals.mail.check_unread_mail("alice".into(), false).await.unwrap().0);
+    }
+}
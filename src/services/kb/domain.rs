@@ -76,13 +76,12 @@ impl LuhmannId {
                     new_parts.push(LuhmannPart::Number(n + 1));
                 }
                 LuhmannPart::Letter(c) => {
-                    if let Some(next_char) = (*c as u8 + 1).try_into().ok() {
-                        if next_char <= 'z' {
-                            new_parts.pop();
-                            new_parts.push(LuhmannPart::Letter(next_char));
-                        } else {
-                            return None; // Can't go past 'z'
-                        }
+                    let next_char = char::from(*c as u8 + 1);
+                    if next_char <= 'z' {
+                        new_parts.pop();
+                        new_parts.push(LuhmannPart::Letter(next_char));
+                    } else {
+                        return None; // Can't go past 'z'
                     }
                 }
             }
@@ -229,7 +228,7 @@ impl Note {
         let luhmann_id = node
             .get_property("luhmann_id")
             .and_then(|v| v.as_str())
-            .and_then(|s| LuhmannId::parse(s))?;
+            .and_then(LuhmannId::parse)?;
 
         let tags = node
             .get_property("tags")
@@ -294,6 +293,12 @@ pub struct NoteCounter {
     pub created_at: Timestamp,
 }
 
+impl Default for NoteCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl NoteCounter {
     pub fn new() -> Self {
         Self {
@@ -323,9 +328,9 @@ impl NoteCounter {
 
         let next_main_id = node
             .get_property("next_main_id")
-            .and_then(|v| match v {
-                PropertyValue::Integer(n) => Some(*n as u32),
-                _ => Some(1),
+            .map(|v| match v {
+                PropertyValue::Integer(n) => *n as u32,
+                _ => 1,
             })
             .unwrap_or(1);
 
@@ -1,4 +1,4 @@
-use crate::domain::{Edge, Properties, PropertyValue, string_to_node_id, NodeId};
+use crate::domain::{Edge, GraphQuery, Properties, PropertyValue, string_to_node_id, NodeId};
 use crate::services::kb::domain::{LinkType, LuhmannId, Note, NoteId, NoteLink, NoteCounter};
 use crate::storage::{GraphStorage, StorageError, SearchQuery, EdgeDirection};
 use async_trait::async_trait;
@@ -128,27 +128,12 @@ impl<S: GraphStorage> KnowledgeBaseServiceImpl<S> {
         }
     }
 
-    /// Update the counter
-    async fn update_counter(&self, counter: &NoteCounter) -> Result<()> {
-        let node = counter.to_node();
-        self.storage.update_node(&node).await?;
-        Ok(())
-    }
-
-    /// Generate next available top-level ID
-    async fn next_main_id(&self) -> Result<LuhmannId> {
-        let mut counter = self.get_or_init_counter().await?;
-        let id = counter.next_main_topic_id();
-        self.update_counter(&counter).await?;
-        Ok(id)
-    }
-
     /// Find the next available child ID under a parent
     async fn next_child_id(&self, parent_id: &LuhmannId) -> Result<LuhmannId> {
-        let all_notes = self.list_notes().await?;
+        let descendants = self.list_notes_by_prefix(parent_id).await?;
         
         // Collect existing children
-        let mut children: Vec<LuhmannId> = all_notes
+        let mut children: Vec<LuhmannId> = descendants
             .into_iter()
             .map(|n| n.id)
             .filter(|id| id.parent().as_ref() == Some(parent_id))
@@ -175,7 +160,8 @@ impl<S: GraphStorage> KnowledgeBaseService for KnowledgeBaseServiceImpl<S> {
         content: impl Into<String> + Send,
     ) -> Result<Note> {
         // Generate next available top-level Luhmann ID
-        let luhmann_id = self.next_main_id().await?;
+        let mut counter = self.get_or_init_counter().await?;
+        let luhmann_id = counter.next_main_topic_id();
         
         // Check if note already exists
         let node_id = self.to_node_id(&luhmann_id);
@@ -187,7 +173,12 @@ impl<S: GraphStorage> KnowledgeBaseService for KnowledgeBaseServiceImpl<S> {
         
         let note = Note::new(luhmann_id, title, content);
         let node = note.to_node();
-        self.storage.create_node(&node).await?;
+        
+        // Bump the counter and create the note together so an ID is never
+        // consumed without a note behind it
+        let mut tx = self.storage.begin();
+        tx.update_node(&counter.to_node()).create_node(&node);
+        self.storage.commit(tx).await?;
         
         Ok(note)
     }
@@ -241,7 +232,6 @@ impl<S: GraphStorage> KnowledgeBaseService for KnowledgeBaseServiceImpl<S> {
         // Create the note
         let note = Note::new(child_id.clone(), title, content);
         let node = note.to_node();
-        self.storage.create_node(&node).await?;
         
         // Create link to parent
         let mut props = Properties::new();
@@ -253,7 +243,11 @@ impl<S: GraphStorage> KnowledgeBaseService for KnowledgeBaseServiceImpl<S> {
             parent_node_id,
             props,
         );
-        self.storage.create_edge(&edge).await?;
+        
+        // Note and parent link are written together
+        let mut tx = self.storage.begin();
+        tx.create_node(&node).create_edge(&edge);
+        self.storage.commit(tx).await?;
         
         Ok(note)
     }
@@ -292,14 +286,21 @@ impl<S: GraphStorage> KnowledgeBaseService for KnowledgeBaseServiceImpl<S> {
     }
 
     async fn list_notes_by_prefix(&self, prefix: &LuhmannId) -> Result<Vec<Note>> {
-        let all_notes = self.list_notes().await?;
-        
-        let filtered: Vec<Note> = all_notes
-            .into_iter()
+        // A string prefix narrows the candidates in storage; "1" also matches
+        // "10", so the exact hierarchy check still happens here
+        let query = GraphQuery::new()
+            .with_node_type("note")
+            .with_property_prefix("luhmann_id", prefix.to_string());
+        let nodes = self.storage.query_nodes(&query).await?;
+        
+        let mut filtered: Vec<Note> = nodes
+            .iter()
+            .filter_map(Note::from_node)
             .filter(|note| {
                 note.id == *prefix || note.id.is_descendant_of(prefix)
             })
             .collect();
+        filtered.sort_by(|a, b| a.id.cmp(&b.id));
         
         Ok(filtered)
     }
@@ -378,7 +379,7 @@ impl<S: GraphStorage> KnowledgeBaseService for KnowledgeBaseServiceImpl<S> {
                 Ok(target_node) => {
                     if let Some(target_id) = target_node.properties.get("luhmann_id")
                         .and_then(|v| v.as_str())
-                        .and_then(|s| LuhmannId::parse(s))
+                        .and_then(LuhmannId::parse)
                     {
                         let context = edge.properties.get("context")
                             .and_then(|v| v.as_str())
@@ -428,9 +429,9 @@ impl<S: GraphStorage> KnowledgeBaseService for KnowledgeBaseServiceImpl<S> {
         let parent_note = self.get_note(parent_id).await?;
         
         // Find all direct children (notes that are immediate descendants)
-        let all_notes = self.list_notes().await?;
+        let descendants = self.list_notes_by_prefix(parent_id).await?;
         
-        let children: Vec<&Note> = all_notes
+        let children: Vec<&Note> = descendants
             .iter()
             .filter(|note| {
                 // Check if note's parent is the parent_id
@@ -471,7 +472,6 @@ impl<S: GraphStorage> KnowledgeBaseService for KnowledgeBaseServiceImpl<S> {
         );
         
         let node = index_note.to_node();
-        self.storage.create_node(&node).await?;
         
         // Create "child_of" relationship to parent
         let mut props = Properties::new();
@@ -483,7 +483,11 @@ impl<S: GraphStorage> KnowledgeBaseService for KnowledgeBaseServiceImpl<S> {
             self.to_node_id(parent_id),
             props,
         );
-        self.storage.create_edge(&edge).await?;
+        
+        // Index note and its child_of edge are written together
+        let mut tx = self.storage.begin();
+        tx.create_node(&node).create_edge(&edge);
+        self.storage.commit(tx).await?;
         
         Ok(index_note)
     }
@@ -500,8 +504,8 @@ impl<S: GraphStorage> KnowledgeBaseService for KnowledgeBaseServiceImpl<S> {
         };
         
         // Get all children (direct descendants)
-        let all_notes = self.list_notes().await?;
-        let children: Vec<Note> = all_notes
+        let descendants = self.list_notes_by_prefix(note_id).await?;
+        let children: Vec<Note> = descendants
             .into_iter()
             .filter(|n| n.id.parent().as_ref() == Some(note_id))
             .collect();
@@ -0,0 +1,224 @@
+use crate::domain::{Node, NodeId, Properties, PropertyValue, Timestamp, string_to_node_id};
+use crate::services::mail::domain::AgentId;
+use serde::{Deserialize, Serialize};
+
+/// Namespace for keys written without one
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+/// A key on the blackboard, written `namespace/key` ("release/branch").
+/// Anything after the first slash is the key, so keys can have slashes too.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct KvKey {
+    pub namespace: String,
+    pub key: String,
+}
+
+impl KvKey {
+    pub fn new(namespace: impl Into<String>, key: impl Into<String>) -> Self {
+        Self { namespace: namespace.into(), key: key.into() }
+    }
+}
+
+impl std::str::FromStr for KvKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (namespace, key) = s.split_once('/').unwrap_or((DEFAULT_NAMESPACE, s));
+        if namespace.is_empty() || key.is_empty() {
+            return Err(format!("'{}' isn't a key (expected namespace/key or just key)", s));
+        }
+        Ok(Self::new(namespace, key))
+    }
+}
+
+impl std::fmt::Display for KvKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.namespace, self.key)
+    }
+}
+
+/// One version of a key. Every write adds a kv_version node rather than
+/// changing one in place, which gives each key its history; the highest
+/// version is the current value. Deleting writes a version without a value.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct KvEntry {
+    pub key: KvKey,
+    pub version: i64,
+    /// None once the key has been deleted
+    pub value: Option<String>,
+    pub updated_by: AgentId,
+    pub expires_at: Option<Timestamp>,
+    pub updated_at: Timestamp,
+}
+
+impl KvEntry {
+    /// Node ID of a given version of a key. It is the same for every
+    /// writer, so two agents racing to write the same version can't both
+    /// succeed: the second insert is refused, which is what compare-and-swap
+    /// is built on.
+    pub fn node_id(key: &KvKey, version: i64) -> NodeId {
+        string_to_node_id(&format!("kv:{}#{}", key, version))
+    }
+
+    /// Has a value that hasn't expired
+    pub fn is_live(&self, now: Timestamp) -> bool {
+        self.value.is_some() && self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+
+    pub fn to_node(&self) -> Node {
+        let mut props = Properties::new();
+        props.insert("namespace".to_string(), PropertyValue::String(self.key.namespace.clone()));
+        props.insert("key".to_string(), PropertyValue::String(self.key.key.clone()));
+        props.insert("version".to_string(), PropertyValue::Integer(self.version));
+        if let Some(ref value) = self.value {
+            props.insert("value".to_string(), PropertyValue::String(value.clone()));
+        }
+        props.insert("updated_by".to_string(), PropertyValue::String(self.updated_by.clone()));
+        if let Some(expires_at) = self.expires_at {
+            props.insert("expires_at".to_string(), PropertyValue::Timestamp(expires_at));
+        }
+
+        let mut node = Node::new("kv_version", props);
+        node.id = Self::node_id(&self.key, self.version);
+        node.created_at = self.updated_at;
+        node.updated_at = self.updated_at;
+        node
+    }
+
+    pub fn from_node(node: &Node) -> Option<Self> {
+        if node.node_type != "kv_version" {
+            return None;
+        }
+
+        let text = |key: &str| node.get_property(key).and_then(|v| v.as_str()).map(str::to_string);
+
+        Some(Self {
+            key: KvKey::new(text("namespace")?, text("key")?),
+            version: match node.get_property("version") {
+                Some(PropertyValue::Integer(v)) => *v,
+                _ => return None,
+            },
+            value: text("value"),
+            updated_by: text("updated_by").unwrap_or_default(),
+            expires_at: match node.get_property("expires_at") {
+                Some(PropertyValue::Timestamp(t)) => Some(*t),
+                _ => None,
+            },
+            updated_at: node.updated_at,
+        })
+    }
+}
+
+/// What a key has to look like for a compare-and-swap write to go ahead
+#[derive(Debug, Clone, PartialEq)]
+pub enum KvExpect {
+    /// No value, or only an expired one
+    Absent,
+    /// The current value is at this version
+    Version(i64),
+    /// The current value is exactly this
+    Value(String),
+}
+
+impl KvExpect {
+    /// Whether `current` (the live entry, if any) is what's expected
+    pub fn matches(&self, current: Option<&KvEntry>) -> bool {
+        match self {
+            KvExpect::Absent => current.is_none(),
+            KvExpect::Version(version) => current.is_some_and(|c| c.version == *version),
+            KvExpect::Value(value) => current.and_then(|c| c.value.as_ref()) == Some(value),
+        }
+    }
+}
+
+impl std::fmt::Display for KvExpect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KvExpect::Absent => write!(f, "no value"),
+            KvExpect::Version(version) => write!(f, "version {}", version),
+            KvExpect::Value(value) => write!(f, "'{}'", value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn entry(value: Option<&str>, expires_at: Option<Timestamp>) -> KvEntry {
+        KvEntry {
+            key: KvKey::new("build", "status"),
+            version: 3,
+            value: value.map(str::to_string),
+            updated_by: "ci".to_string(),
+            expires_at,
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_key_parsing() {
+        assert_eq!("release/branch".parse::<KvKey>().unwrap(), KvKey::new("release", "branch"));
+        assert_eq!(" mode ".parse::<KvKey>().unwrap(), KvKey::new(DEFAULT_NAMESPACE, "mode"));
+        // Only the first slash separates the namespace
+        assert_eq!("repo/src/main.rs".parse::<KvKey>().unwrap(), KvKey::new("repo", "src/main.rs"));
+    }
+
+    #[test]
+    fn test_key_parsing_rejects_empty_parts() {
+        for bad in ["", "/branch", "release/", "/"] {
+            assert!(bad.parse::<KvKey>().is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_key_display_round_trip() {
+        let key = KvKey::new("repo", "src/main.rs");
+        assert_eq!(key.to_string(), "repo/src/main.rs");
+        assert_eq!(key.to_string().parse::<KvKey>().unwrap(), key);
+    }
+
+    #[test]
+    fn test_node_id_is_fixed_per_key_and_version() {
+        let key = KvKey::new("build", "status");
+        assert_eq!(KvEntry::node_id(&key, 1), KvEntry::node_id(&key, 1));
+        assert_ne!(KvEntry::node_id(&key, 1), KvEntry::node_id(&key, 2));
+        assert_ne!(KvEntry::node_id(&key, 1), KvEntry::node_id(&KvKey::new("build", "log"), 1));
+    }
+
+    #[test]
+    fn test_entry_node_round_trip() {
+        let live = entry(Some("green"), Some(Utc::now()));
+        let node = live.to_node();
+        assert_eq!(node.id, KvEntry::node_id(&live.key, 3));
+        assert_eq!(KvEntry::from_node(&node), Some(live));
+
+        let deleted = entry(None, None);
+        assert_eq!(KvEntry::from_node(&deleted.to_node()), Some(deleted));
+        assert_eq!(KvEntry::from_node(&Node::new("task", Properties::new())), None);
+    }
+
+    #[test]
+    fn test_is_live() {
+        let now = Utc::now();
+        assert!(entry(Some("green"), None).is_live(now));
+        assert!(entry(Some("green"), Some(now + chrono::Duration::seconds(1))).is_live(now));
+        assert!(!entry(Some("green"), Some(now)).is_live(now));
+        assert!(!entry(None, None).is_live(now));
+    }
+
+    #[test]
+    fn test_expect_matches() {
+        let current = entry(Some("green"), None);
+        assert!(KvExpect::Absent.matches(None));
+        assert!(!KvExpect::Absent.matches(Some(&current)));
+        assert!(KvExpect::Version(3).matches(Some(&current)));
+        assert!(!KvExpect::Version(2).matches(Some(&current)));
+        assert!(!KvExpect::Version(3).matches(None));
+        assert!(KvExpect::Value("green".to_string()).matches(Some(&current)));
+        assert!(!KvExpect::Value("red".to_string()).matches(Some(&current)));
+        assert!(!KvExpect::Value("green".to_string()).matches(None));
+    }
+}
@@ -0,0 +1,315 @@
+use crate::domain::{GraphQuery, PropertyValue, Timestamp};
+use crate::services::kv::domain::{KvEntry, KvExpect, KvKey};
+use crate::services::mail::domain::AgentId;
+use crate::storage::{GraphStorage, StorageError};
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+pub mod domain;
+
+/// Versions kept per key; older ones are dropped as new ones are written
+const HISTORY_LIMIT: usize = 50;
+
+/// Times a write retries after losing a race before giving up
+const WRITE_ATTEMPTS: usize = 10;
+
+#[derive(Error, Debug)]
+pub enum KvError {
+    #[error("Key not found: {0}")]
+    KeyNotFound(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Invalid operation: {0}")]
+    InvalidOperation(String),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+pub type Result<T> = std::result::Result<T, KvError>;
+
+/// A shared blackboard: one place for values like the current release
+/// branch, instead of agents mailing each other whatever they last heard.
+/// Writes can be made conditional on what the key holds (compare-and-swap),
+/// values can expire, and each key keeps its recent history.
+#[async_trait]
+pub trait KvService: Send + Sync {
+    // The current value, if the key has one that hasn't expired
+    async fn get(&self, key: &KvKey) -> Result<Option<KvEntry>>;
+
+    // Write a value. With `expect`, the write only happens if the key
+    // still looks like that, and fails with a conflict otherwise.
+    async fn set(
+        &self,
+        key: &KvKey,
+        value: String,
+        updated_by: AgentId,
+        expires_at: Option<Timestamp>,
+        expect: Option<KvExpect>,
+    ) -> Result<KvEntry>;
+
+    // Remove a key's value; its history is kept
+    async fn delete(&self, key: &KvKey, deleted_by: AgentId, expect: Option<KvExpect>) -> Result<KvEntry>;
+
+    // Keys with a live value (in one namespace, if given), by namespace and key
+    async fn list(&self, namespace: Option<&str>) -> Result<Vec<KvEntry>>;
+
+    // Every kept version of a key, deletions included, newest first
+    async fn history(&self, key: &KvKey) -> Result<Vec<KvEntry>>;
+}
+
+pub struct KvServiceImpl<S: GraphStorage> {
+    storage: S,
+}
+
+impl<S: GraphStorage> KvServiceImpl<S> {
+    pub fn new(storage: S) -> Self {
+        Self { storage }
+    }
+
+    // All kept versions of a key, oldest first
+    async fn versions(&self, key: &KvKey) -> Result<Vec<KvEntry>> {
+        let query = GraphQuery::new()
+            .with_node_type("kv_version")
+            .with_property("namespace", PropertyValue::String(key.namespace.clone()))
+            .with_property("key", PropertyValue::String(key.key.clone()));
+        let mut versions: Vec<KvEntry> = self.storage.query_nodes(&query).await?
+            .iter()
+            .filter_map(KvEntry::from_node)
+            .collect();
+        versions.sort_by_key(|v| v.version);
+        Ok(versions)
+    }
+
+    // Add the next version of a key. The version's node ID is fixed, so if
+    // another writer got there first the insert fails and we look again.
+    async fn write(
+        &self,
+        key: &KvKey,
+        value: Option<String>,
+        updated_by: AgentId,
+        expires_at: Option<Timestamp>,
+        expect: Option<KvExpect>,
+    ) -> Result<KvEntry> {
+        if key.namespace.trim().is_empty() || key.key.trim().is_empty() {
+            return Err(KvError::InvalidOperation(format!("'{}' needs a namespace and a key", key)));
+        }
+        if updated_by.trim().is_empty() {
+            return Err(KvError::InvalidOperation("Say which agent is writing".to_string()));
+        }
+
+        for _ in 0..WRITE_ATTEMPTS {
+            let versions = self.versions(key).await?;
+            let now = chrono::Utc::now();
+            let current = versions.last().filter(|v| v.is_live(now));
+            if value.is_none() && current.is_none() {
+                return Err(KvError::KeyNotFound(key.to_string()));
+            }
+            if let Some(expect) = expect.as_ref().filter(|e| !e.matches(current)) {
+                let found = match current {
+                    Some(entry) => format!("'{}' at version {}", entry.value.as_deref().unwrap_or_default(), entry.version),
+                    None => "no value".to_string(),
+                };
+                return Err(KvError::Conflict(format!("{} has {}, expected {}", key, found, expect)));
+            }
+
+            let entry = KvEntry {
+                key: key.clone(),
+                version: versions.last().map_or(1, |v| v.version + 1),
+                value: value.clone(),
+                updated_by: updated_by.clone(),
+                expires_at,
+                updated_at: now,
+            };
+            match self.storage.create_node(&entry.to_node()).await {
+                Ok(_) => {}
+                Err(StorageError::ConstraintViolation(_)) => continue,
+                Err(e) => return Err(e.into()),
+            }
+
+            let stale = (versions.len() + 1).saturating_sub(HISTORY_LIMIT);
+            for old in &versions[..stale] {
+                match self.storage.delete_node(KvEntry::node_id(key, old.version)).await {
+                    Ok(()) | Err(StorageError::NodeNotFound(_)) => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            return Ok(entry);
+        }
+        Err(KvError::Conflict(format!("{} is changing too quickly, try again", key)))
+    }
+}
+
+#[async_trait]
+impl<S: GraphStorage> KvService for KvServiceImpl<S> {
+    async fn get(&self, key: &KvKey) -> Result<Option<KvEntry>> {
+        let now = chrono::Utc::now();
+        Ok(self.versions(key).await?.pop().filter(|v| v.is_live(now)))
+    }
+
+    async fn set(
+        &self,
+        key: &KvKey,
+        value: String,
+        updated_by: AgentId,
+        expires_at: Option<Timestamp>,
+        expect: Option<KvExpect>,
+    ) -> Result<KvEntry> {
+        if expires_at.is_some_and(|expires_at| expires_at <= chrono::Utc::now()) {
+            return Err(KvError::InvalidOperation("The expiry time has already passed".to_string()));
+        }
+        self.write(key, Some(value), updated_by, expires_at, expect).await
+    }
+
+    async fn delete(&self, key: &KvKey, deleted_by: AgentId, expect: Option<KvExpect>) -> Result<KvEntry> {
+        self.write(key, None, deleted_by, None, expect).await
+    }
+
+    async fn list(&self, namespace: Option<&str>) -> Result<Vec<KvEntry>> {
+        let mut query = GraphQuery::new().with_node_type("kv_version");
+        if let Some(namespace) = namespace {
+            query = query.with_property("namespace", PropertyValue::String(namespace.to_string()));
+        }
+        let mut latest: BTreeMap<KvKey, KvEntry> = BTreeMap::new();
+        for entry in self.storage.query_nodes(&query).await?.iter().filter_map(KvEntry::from_node) {
+            if latest.get(&entry.key).is_none_or(|l| l.version < entry.version) {
+                latest.insert(entry.key.clone(), entry);
+            }
+        }
+        let now = chrono::Utc::now();
+        Ok(latest.into_values().filter(|e| e.is_live(now)).collect())
+    }
+
+    async fn history(&self, key: &KvKey) -> Result<Vec<KvEntry>> {
+        let mut versions = self.versions(key).await?;
+        versions.reverse();
+        Ok(versions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::InMemoryStorage;
+
+    fn key(s: &str) -> KvKey {
+        s.parse().unwrap()
+    }
+
+    async fn set(kv: &KvServiceImpl<InMemoryStorage>, key: &KvKey, value: &str, expect: Option<KvExpect>) -> Result<KvEntry> {
+        kv.set(key, value.to_string(), "alice".to_string(), None, expect).await
+    }
+
+    #[tokio::test]
+    async fn test_expect_absent_only_creates() {
+        let kv = KvServiceImpl::new(InMemoryStorage::new());
+        let branch = key("release/branch");
+        let entry = set(&kv, &branch, "release-1.4", Some(KvExpect::Absent)).await.unwrap();
+        assert_eq!(entry.version, 1);
+        assert!(matches!(set(&kv, &branch, "release-1.5", Some(KvExpect::Absent)).await, Err(KvError::Conflict(_))));
+        assert_eq!(kv.get(&branch).await.unwrap().unwrap().value.as_deref(), Some("release-1.4"));
+    }
+
+    #[tokio::test]
+    async fn test_expect_version_or_value_conflicts_when_stale() {
+        let kv = KvServiceImpl::new(InMemoryStorage::new());
+        let branch = key("release/branch");
+        set(&kv, &branch, "release-1.4", None).await.unwrap();
+
+        assert!(matches!(set(&kv, &branch, "release-1.5", Some(KvExpect::Version(2))).await, Err(KvError::Conflict(_))));
+        let err = set(&kv, &branch, "release-1.5", Some(KvExpect::Value("release-1.3".to_string()))).await;
+        assert!(matches!(err, Err(KvError::Conflict(ref msg)) if msg.contains("'release-1.4' at version 1")));
+
+        let entry = set(&kv, &branch, "release-1.5", Some(KvExpect::Value("release-1.4".to_string()))).await.unwrap();
+        assert_eq!(entry.version, 2);
+        set(&kv, &branch, "release-1.6", Some(KvExpect::Version(2))).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_racing_writers_expecting_the_same_version() {
+        let kv = KvServiceImpl::new(InMemoryStorage::new());
+        let branch = key("release/branch");
+        set(&kv, &branch, "release-1.4", None).await.unwrap();
+
+        let (first, second) = tokio::join!(
+            set(&kv, &branch, "release-1.5", Some(KvExpect::Version(1))),
+            set(&kv, &branch, "hotfix", Some(KvExpect::Version(1))),
+        );
+        assert_eq!([first.is_ok(), second.is_ok()].iter().filter(|ok| **ok).count(), 1);
+        assert_eq!(kv.get(&branch).await.unwrap().unwrap().version, 2);
+    }
+
+    #[tokio::test]
+    async fn test_delete_leaves_a_version_without_a_value() {
+        let kv = KvServiceImpl::new(InMemoryStorage::new());
+        let branch = key("release/branch");
+        set(&kv, &branch, "release-1.4", None).await.unwrap();
+
+        let deleted = kv.delete(&branch, "bob".to_string(), Some(KvExpect::Version(1))).await.unwrap();
+        assert_eq!((deleted.version, deleted.value), (2, None));
+        assert!(kv.get(&branch).await.unwrap().is_none());
+        assert!(matches!(kv.delete(&branch, "bob".to_string(), None).await, Err(KvError::KeyNotFound(_))));
+
+        let history: Vec<Option<String>> = kv.history(&branch).await.unwrap().into_iter().map(|e| e.value).collect();
+        assert_eq!(history, vec![None, Some("release-1.4".to_string())]);
+        set(&kv, &branch, "release-1.5", Some(KvExpect::Absent)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_expired_value_counts_as_absent() {
+        let kv = KvServiceImpl::new(InMemoryStorage::new());
+        let status = key("build/status");
+        let soon = chrono::Utc::now() + chrono::Duration::milliseconds(50);
+        kv.set(&status, "green".to_string(), "ci".to_string(), Some(soon), None).await.unwrap();
+        assert!(kv.get(&status).await.unwrap().is_some());
+
+        tokio::time::sleep(std::time::Duration::from_millis(80)).await;
+        assert!(kv.get(&status).await.unwrap().is_none());
+        assert!(kv.list(Some("build")).await.unwrap().is_empty());
+        set(&kv, &status, "red", Some(KvExpect::Absent)).await.unwrap();
+
+        let past = chrono::Utc::now() - chrono::Duration::seconds(1);
+        assert!(matches!(kv.set(&status, "x".to_string(), "ci".to_string(), Some(past), None).await, Err(KvError::InvalidOperation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_list_shows_latest_values_by_namespace() {
+        let kv = KvServiceImpl::new(InMemoryStorage::new());
+        set(&kv, &key("build/status"), "red", None).await.unwrap();
+        set(&kv, &key("build/status"), "green", None).await.unwrap();
+        set(&kv, &key("mode"), "calm", None).await.unwrap();
+        set(&kv, &key("gone"), "soon", None).await.unwrap();
+        kv.delete(&key("gone"), "alice".to_string(), None).await.unwrap();
+
+        let all: Vec<(String, Option<String>)> = kv.list(None).await.unwrap().into_iter().map(|e| (e.key.to_string(), e.value)).collect();
+        assert_eq!(all, vec![
+            ("build/status".to_string(), Some("green".to_string())),
+            ("default/mode".to_string(), Some("calm".to_string())),
+        ]);
+        assert_eq!(kv.list(Some("default")).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_history_is_trimmed_to_the_limit() {
+        let kv = KvServiceImpl::new(InMemoryStorage::new());
+        let status = key("build/status");
+        for i in 0..HISTORY_LIMIT + 5 {
+            set(&kv, &status, &i.to_string(), None).await.unwrap();
+        }
+
+        let history = kv.history(&status).await.unwrap();
+        assert_eq!(history.len(), HISTORY_LIMIT);
+        assert_eq!(history[0].version, (HISTORY_LIMIT + 5) as i64);
+        assert_eq!(history.last().unwrap().version, 6);
+    }
+
+    #[tokio::test]
+    async fn test_write_needs_a_writer() {
+        let kv = KvServiceImpl::new(InMemoryStorage::new());
+        let err = kv.set(&key("build/status"), "green".to_string(), " ".to_string(), None, None).await;
+        assert!(matches!(err, Err(KvError::InvalidOperation(_))));
+    }
+}
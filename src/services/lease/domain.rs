@@ -0,0 +1,14 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A named lock held by one agent until it expires, is renewed, or is
+/// released. Expiry means a crashed agent can't hold a lock forever.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Lease {
+    pub name: String,
+    pub holder: String,
+    pub acquired_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// When it was last acquired or renewed
+    pub updated_at: DateTime<Utc>,
+}
@@ -0,0 +1,50 @@
+use crate::services::lease::domain::Lease;
+use async_trait::async_trait;
+use chrono::Duration;
+use thiserror::Error;
+
+pub mod domain;
+pub mod service_impl;
+
+pub use service_impl::LeaseServiceImpl;
+
+#[derive(Error, Debug)]
+pub enum LeaseError {
+    #[error("'{}' is held by {} until {}", .0.name, .0.holder, .0.expires_at.format("%Y-%m-%d %H:%M:%S UTC"))]
+    Held(Lease),
+
+    #[error("'{name}' is not held by {holder}")]
+    NotHeld { name: String, holder: String },
+
+    #[error("Lock not found: {0}")]
+    LeaseNotFound(String),
+
+    #[error("Invalid operation: {0}")]
+    InvalidOperation(String),
+
+    #[error("Storage error: {0}")]
+    Storage(String),
+}
+
+pub type Result<T> = std::result::Result<T, LeaseError>;
+
+/// Named locks for agents that would otherwise step on each other, such as
+/// two agents editing the same repository. Only unexpired leases count.
+#[async_trait]
+pub trait LeaseService: Send + Sync {
+    /// Take the lock for `ttl`, if it is free, expired, or already ours
+    /// (which extends it). Fails with `Held` if someone else has it.
+    async fn acquire(&self, name: &str, holder: &str, ttl: Duration) -> Result<Lease>;
+
+    /// Push the expiry of a lock we hold to `ttl` from now
+    async fn renew(&self, name: &str, holder: &str, ttl: Duration) -> Result<Lease>;
+
+    /// Give up a lock we hold
+    async fn release(&self, name: &str, holder: &str) -> Result<()>;
+
+    /// Take a lock away from whoever holds it, for when a human has to step in
+    async fn force_release(&self, name: &str) -> Result<Lease>;
+
+    /// Unexpired leases, by name
+    async fn list_leases(&self) -> Result<Vec<Lease>>;
+}
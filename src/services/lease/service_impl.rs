@@ -0,0 +1,321 @@
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use sqlx::{Pool, Postgres, Row, Sqlite};
+
+use crate::services::lease::{LeaseError, LeaseService, Result};
+use crate::services::lease::domain::Lease;
+use crate::storage::is_sqlite_url;
+use crate::storage::sqlite::SqliteStorage;
+
+/// Connection pool for the `leases` table. As with schedules, the SQL is
+/// written once in the subset both databases accept.
+#[derive(Clone)]
+pub enum LeasePool {
+    Postgres(Pool<Postgres>),
+    Sqlite(Pool<Sqlite>),
+}
+
+impl From<Pool<Postgres>> for LeasePool {
+    fn from(pool: Pool<Postgres>) -> Self {
+        Self::Postgres(pool)
+    }
+}
+
+impl From<Pool<Sqlite>> for LeasePool {
+    fn from(pool: Pool<Sqlite>) -> Self {
+        Self::Sqlite(pool)
+    }
+}
+
+/// Run `$body` against whichever pool is configured, bound to `$pool`
+macro_rules! with_pool {
+    ($self:ident, $pool:ident => $body:expr) => {
+        match &$self.pool {
+            LeasePool::Postgres($pool) => $body,
+            LeasePool::Sqlite($pool) => $body,
+        }
+    };
+}
+
+macro_rules! lease_from_row {
+    ($row:expr) => {
+        Lease {
+            name: $row.get("name"),
+            holder: $row.get("holder"),
+            acquired_at: $row.get("acquired_at"),
+            expires_at: $row.get("expires_at"),
+            updated_at: $row.get("updated_at"),
+        }
+    };
+}
+
+const LEASE_COLUMNS: &str = "name, holder, acquired_at, expires_at, updated_at";
+
+pub struct LeaseServiceImpl {
+    pool: LeasePool,
+}
+
+impl LeaseServiceImpl {
+    pub fn new(pool: impl Into<LeasePool>) -> Self {
+        Self { pool: pool.into() }
+    }
+
+    /// Connect to the database at `database_url` (Postgres, or SQLite for a
+    /// `sqlite:` URL). The leases table comes from the schema migrations.
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        if is_sqlite_url(database_url) {
+            let storage = SqliteStorage::connect(database_url)
+                .await
+                .map_err(|e| LeaseError::Storage(e.to_string()))?;
+            Ok(Self::new(storage.pool().clone()))
+        } else {
+            let pool = sqlx::postgres::PgPool::connect(database_url)
+                .await
+                .map_err(|e| LeaseError::Storage(e.to_string()))?;
+            Ok(Self::new(pool))
+        }
+    }
+
+    fn check_args(name: &str, holder: &str, ttl: Duration) -> Result<()> {
+        if name.trim().is_empty() || holder.trim().is_empty() {
+            return Err(LeaseError::InvalidOperation("A lock needs a name and a holder".to_string()));
+        }
+        if ttl <= Duration::zero() {
+            return Err(LeaseError::InvalidOperation("A lock has to be held for some time".to_string()));
+        }
+        Ok(())
+    }
+
+    /// The lease on `name`, expired or not
+    async fn find(&self, name: &str) -> Result<Option<Lease>> {
+        let sql = format!("SELECT {} FROM leases WHERE name = $1", LEASE_COLUMNS);
+        with_pool!(self, pool => sqlx::query(&sql)
+            .bind(name)
+            .fetch_optional(pool)
+            .await
+            .map(|row| row.map(|row| lease_from_row!(row))))
+            .map_err(|e| LeaseError::Storage(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl LeaseService for LeaseServiceImpl {
+    async fn acquire(&self, name: &str, holder: &str, ttl: Duration) -> Result<Lease> {
+        Self::check_args(name, holder, ttl)?;
+
+        // One statement, so there is no gap between checking and taking the
+        // lock. On a conflict Postgres locks the existing row and checks the
+        // WHERE against its latest version, so of two agents racing for a
+        // free lock only one gets a row back; SQLite serializes writers anyway.
+        let sql = format!(
+            r#"
+            INSERT INTO leases ({columns})
+            VALUES ($1, $2, $3, $4, $3)
+            ON CONFLICT (name) DO UPDATE SET
+                holder = excluded.holder,
+                acquired_at = CASE
+                    WHEN leases.holder = excluded.holder AND leases.expires_at > $3 THEN leases.acquired_at
+                    ELSE excluded.acquired_at
+                END,
+                expires_at = excluded.expires_at,
+                updated_at = excluded.updated_at
+            WHERE leases.holder = excluded.holder OR leases.expires_at <= $3
+            RETURNING {columns}
+            "#,
+            columns = LEASE_COLUMNS
+        );
+        let now = Utc::now();
+        let acquired = with_pool!(self, pool => sqlx::query(&sql)
+            .bind(name)
+            .bind(holder)
+            .bind(now)
+            .bind(now + ttl)
+            .fetch_optional(pool)
+            .await
+            .map(|row| row.map(|row| lease_from_row!(row))))
+            .map_err(|e| LeaseError::Storage(e.to_string()))?;
+
+        match acquired {
+            Some(lease) => Ok(lease),
+            None => match self.find(name).await? {
+                Some(lease) => Err(LeaseError::Held(lease)),
+                // Released between the two statements; it's free now
+                None => self.acquire(name, holder, ttl).await,
+            },
+        }
+    }
+
+    async fn renew(&self, name: &str, holder: &str, ttl: Duration) -> Result<Lease> {
+        Self::check_args(name, holder, ttl)?;
+
+        let sql = format!(
+            r#"
+            UPDATE leases
+            SET expires_at = $3, updated_at = $4
+            WHERE name = $1 AND holder = $2 AND expires_at > $4
+            RETURNING {}
+            "#,
+            LEASE_COLUMNS
+        );
+        let now = Utc::now();
+        with_pool!(self, pool => sqlx::query(&sql)
+            .bind(name)
+            .bind(holder)
+            .bind(now + ttl)
+            .bind(now)
+            .fetch_optional(pool)
+            .await
+            .map(|row| row.map(|row| lease_from_row!(row))))
+            .map_err(|e| LeaseError::Storage(e.to_string()))?
+            .ok_or_else(|| LeaseError::NotHeld { name: name.to_string(), holder: holder.to_string() })
+    }
+
+    async fn release(&self, name: &str, holder: &str) -> Result<()> {
+        let released = with_pool!(self, pool => sqlx::query(
+            "DELETE FROM leases WHERE name = $1 AND holder = $2 AND expires_at > $3",
+        )
+        .bind(name)
+        .bind(holder)
+        .bind(Utc::now())
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected()))
+        .map_err(|e| LeaseError::Storage(e.to_string()))?;
+
+        if released == 0 {
+            return Err(LeaseError::NotHeld { name: name.to_string(), holder: holder.to_string() });
+        }
+        Ok(())
+    }
+
+    async fn force_release(&self, name: &str) -> Result<Lease> {
+        let sql = format!("DELETE FROM leases WHERE name = $1 RETURNING {}", LEASE_COLUMNS);
+        with_pool!(self, pool => sqlx::query(&sql)
+            .bind(name)
+            .fetch_optional(pool)
+            .await
+            .map(|row| row.map(|row| lease_from_row!(row))))
+            .map_err(|e| LeaseError::Storage(e.to_string()))?
+            .ok_or_else(|| LeaseError::LeaseNotFound(name.to_string()))
+    }
+
+    async fn list_leases(&self) -> Result<Vec<Lease>> {
+        let sql = format!("SELECT {} FROM leases WHERE expires_at > $1 ORDER BY name", LEASE_COLUMNS);
+        with_pool!(self, pool => sqlx::query(&sql)
+            .bind(Utc::now())
+            .fetch_all(pool)
+            .await
+            .map(|rows| rows.iter().map(|row| lease_from_row!(row)).collect()))
+            .map_err(|e| LeaseError::Storage(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn service() -> LeaseServiceImpl {
+        let storage = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+        storage.migrate().await.unwrap();
+        LeaseServiceImpl::new(storage.pool().clone())
+    }
+
+    #[tokio::test]
+    async fn test_held_lock_refuses_other_agents() {
+        let service = service().await;
+        service.acquire("repo", "alice", Duration::minutes(10)).await.unwrap();
+        assert!(matches!(service.acquire("repo", "bob", Duration::minutes(10)).await, Err(LeaseError::Held(l)) if l.holder == "alice"));
+        assert!(matches!(service.renew("repo", "bob", Duration::minutes(10)).await, Err(LeaseError::NotHeld { .. })));
+        assert!(matches!(service.release("repo", "bob").await, Err(LeaseError::NotHeld { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_taking_a_held_lock_again_extends_it() {
+        let service = service().await;
+        let lease = service.acquire("repo", "alice", Duration::minutes(10)).await.unwrap();
+        let again = service.acquire("repo", "alice", Duration::minutes(30)).await.unwrap();
+        assert_eq!(again.acquired_at, lease.acquired_at);
+        assert!(again.expires_at > lease.expires_at);
+
+        // Renewing sets a new expiry rather than adding to the old one
+        assert!(service.renew("repo", "alice", Duration::minutes(5)).await.unwrap().expires_at < again.expires_at);
+    }
+
+    #[tokio::test]
+    async fn test_released_lock_is_free() {
+        let service = service().await;
+        service.acquire("repo", "alice", Duration::minutes(10)).await.unwrap();
+        service.release("repo", "alice").await.unwrap();
+        assert!(service.release("repo", "alice").await.is_err());
+        assert_eq!(service.acquire("repo", "bob", Duration::minutes(10)).await.unwrap().holder, "bob");
+    }
+
+    #[tokio::test]
+    async fn test_expired_lock_is_free_and_unlisted() {
+        let service = service().await;
+        service.acquire("repo", "alice", Duration::minutes(10)).await.unwrap();
+        let old = service.acquire("kb/1a", "carol", Duration::milliseconds(30)).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let names: Vec<String> = service.list_leases().await.unwrap().into_iter().map(|l| l.name).collect();
+        assert_eq!(names, vec!["repo"]);
+        assert!(matches!(service.renew("kb/1a", "carol", Duration::minutes(1)).await, Err(LeaseError::NotHeld { .. })));
+        let taken = service.acquire("kb/1a", "alice", Duration::minutes(1)).await.unwrap();
+        assert_eq!(taken.holder, "alice");
+        assert!(taken.acquired_at > old.acquired_at);
+    }
+
+    #[tokio::test]
+    async fn test_force_release_frees_a_lock_whoever_holds_it() {
+        let service = service().await;
+        service.acquire("repo", "bob", Duration::minutes(10)).await.unwrap();
+        assert_eq!(service.force_release("repo").await.unwrap().holder, "bob");
+        assert!(matches!(service.force_release("repo").await, Err(LeaseError::LeaseNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_lock_needs_a_name_holder_and_time() {
+        let service = service().await;
+        assert!(matches!(service.acquire(" ", "alice", Duration::minutes(1)).await, Err(LeaseError::InvalidOperation(_))));
+        assert!(matches!(service.acquire("repo", "", Duration::minutes(1)).await, Err(LeaseError::InvalidOperation(_))));
+        assert!(matches!(service.acquire("repo", "alice", Duration::zero()).await, Err(LeaseError::InvalidOperation(_))));
+    }
+
+    // Start `racers` agents taking `name` at once; exactly one may get it
+    async fn assert_one_winner(service: LeaseServiceImpl, name: &str, racers: usize) {
+        let service = std::sync::Arc::new(service);
+        let attempts = (0..racers).map(|i| {
+            let service = service.clone();
+            let name = name.to_string();
+            tokio::spawn(async move { service.acquire(&name, &format!("agent-{}", i), Duration::minutes(1)).await })
+        });
+        let results: Vec<Result<Lease>> = futures::future::join_all(attempts).await
+            .into_iter()
+            .map(|joined| joined.unwrap())
+            .collect();
+
+        let winners: Vec<&Lease> = results.iter().filter_map(|r| r.as_ref().ok()).collect();
+        assert_eq!(winners.len(), 1, "{:?}", results);
+        for result in &results {
+            match result {
+                Ok(_) => {}
+                Err(LeaseError::Held(lease)) => assert_eq!(lease.holder, winners[0].holder),
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+        }
+        service.release(name, &winners[0].holder).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_acquire_has_one_winner_on_sqlite() {
+        assert_one_winner(service().await, "repo", 16).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    #[ignore = "needs DATABASE_URL pointing at a migrated Postgres database"]
+    async fn test_concurrent_acquire_has_one_winner_on_postgres() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+        let service = LeaseServiceImpl::connect(&url).await.unwrap();
+        assert_one_winner(service, &format!("test/{}", uuid::Uuid::new_v4()), 16).await;
+    }
+}
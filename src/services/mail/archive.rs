@@ -0,0 +1,397 @@
+use crate::domain::{string_to_node_id, Timestamp};
+use crate::services::mail::domain::{AgentId, Folder, MailId, Priority};
+use serde::{Deserialize, Serialize};
+
+/// Domain used for agent addresses and message IDs in RFC 5322 headers
+pub const ADDRESS_DOMAIN: &str = "agent-office";
+
+/// One mail as it leaves or enters an office: the mail as the exporting
+/// agent saw it, with every mailbox named by agent ID so it means the same
+/// thing in another office
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ArchivedMail {
+    pub id: MailId,
+    pub from: AgentId,
+    pub to: Vec<AgentId>,
+    #[serde(default)]
+    pub cc: Vec<AgentId>,
+    pub subject: String,
+    pub body: String,
+    pub date: Timestamp,
+    #[serde(default)]
+    pub in_reply_to: Option<MailId>,
+    pub thread_id: MailId,
+    #[serde(default)]
+    pub priority: Priority,
+    /// The exporting agent's read state, labels and folder; meaningless for
+    /// mail it sent
+    #[serde(default)]
+    pub read: bool,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub folder: Folder,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub payload: Option<serde_json::Value>,
+}
+
+/// How many mails an import added, and how many it left alone
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    pub imported: usize,
+    /// Already in the mailbox
+    pub skipped: usize,
+    /// Neither sent nor received by the importing agent
+    pub ignored: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// Every message in one file, each after a `From ` line (mboxrd)
+    Mbox,
+    /// One RFC 5322 file per message, in a directory
+    Eml,
+    /// One JSON object per line; keeps payloads exactly
+    Jsonl,
+}
+
+impl ArchiveFormat {
+    /// Guess the format of an archive from its path: a directory of EML
+    /// files, a .jsonl or .eml file, or else mbox
+    pub fn for_path(path: &std::path::Path) -> Self {
+        if path.is_dir() {
+            return ArchiveFormat::Eml;
+        }
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("jsonl") => ArchiveFormat::Jsonl,
+            Some("eml") => ArchiveFormat::Eml,
+            _ => ArchiveFormat::Mbox,
+        }
+    }
+}
+
+impl std::str::FromStr for ArchiveFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mbox" => Ok(ArchiveFormat::Mbox),
+            "eml" => Ok(ArchiveFormat::Eml),
+            "jsonl" => Ok(ArchiveFormat::Jsonl),
+            _ => Err(format!("unknown format '{}' (expected mbox, eml or jsonl)", s)),
+        }
+    }
+}
+
+impl ArchivedMail {
+    /// The mail as an RFC 5322 message. Threading goes in Message-ID,
+    /// In-Reply-To and References (thread root first); what mail clients
+    /// have no header for goes in X-Agent-Office-* headers.
+    pub fn to_rfc5322(&self) -> String {
+        let mut headers = vec![
+            ("From", address(&self.from)),
+            ("To", self.to.iter().map(|a| address(a)).collect::<Vec<_>>().join(", ")),
+        ];
+        if !self.cc.is_empty() {
+            headers.push(("Cc", self.cc.iter().map(|a| address(a)).collect::<Vec<_>>().join(", ")));
+        }
+        headers.push(("Subject", one_line(&self.subject)));
+        headers.push(("Date", self.date.to_rfc2822()));
+        headers.push(("Message-ID", message_id(self.id)));
+        if let Some(parent) = self.in_reply_to {
+            headers.push(("In-Reply-To", message_id(parent)));
+            let mut references = vec![message_id(self.thread_id)];
+            if parent != self.thread_id {
+                references.push(message_id(parent));
+            }
+            headers.push(("References", references.join(" ")));
+        }
+        if self.priority != Priority::Normal {
+            headers.push(("X-Agent-Office-Priority", self.priority.as_str().to_string()));
+        }
+        if !self.labels.is_empty() {
+            headers.push(("X-Agent-Office-Labels", self.labels.join(", ")));
+        }
+        if self.folder != Folder::Inbox {
+            headers.push(("X-Agent-Office-Folder", self.folder.as_str().to_string()));
+        }
+        if self.read {
+            headers.push(("X-Agent-Office-Read", "yes".to_string()));
+        }
+        if let Some(ref content_type) = self.content_type {
+            headers.push(("X-Agent-Office-Content-Type", one_line(content_type)));
+        }
+        if let Some(ref payload) = self.payload {
+            headers.push(("X-Agent-Office-Payload", payload.to_string()));
+        }
+        headers.push(("MIME-Version", "1.0".to_string()));
+        headers.push(("Content-Type", "text/plain; charset=utf-8".to_string()));
+        headers.push(("Content-Transfer-Encoding", "8bit".to_string()));
+
+        let mut message = String::new();
+        for (name, value) in headers {
+            message.push_str(&format!("{}: {}\n", name, value));
+        }
+        message.push('\n');
+        message.push_str(&self.body);
+        if !self.body.ends_with('\n') {
+            message.push('\n');
+        }
+        message
+    }
+
+    /// Read a message written by `to_rfc5322`, or plain RFC 5322 mail from
+    /// elsewhere. Message IDs that aren't ours become stable mail IDs, so
+    /// importing the same message twice finds the same mail.
+    pub fn from_rfc5322(text: &str) -> Result<Self, String> {
+        let text = text.replace("\r\n", "\n");
+        let (head, body) = text.split_once("\n\n").unwrap_or((text.as_str(), ""));
+
+        // Unfold continuation lines, then split into (lowercased name, value)
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for line in head.lines() {
+            if line.starts_with([' ', '\t']) {
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+            } else if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+            }
+        }
+        let header = |name: &str| headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+
+        let from = header("from")
+            .and_then(|v| addresses(v).into_iter().next())
+            .ok_or("message has no From header")?;
+        let date = header("date")
+            .and_then(|v| chrono::DateTime::parse_from_rfc2822(v).ok())
+            .map(|d| d.with_timezone(&chrono::Utc))
+            .ok_or("message has no readable Date header")?;
+        let id = header("message-id").map(parse_message_id).ok_or("message has no Message-ID header")?;
+        let in_reply_to = header("in-reply-to").map(parse_message_id);
+        let thread_id = header("references")
+            .and_then(|v| v.split_whitespace().next())
+            .map(parse_message_id)
+            .or(in_reply_to)
+            .unwrap_or(id);
+
+        Ok(Self {
+            id,
+            from,
+            to: header("to").map(addresses).unwrap_or_default(),
+            cc: header("cc").map(addresses).unwrap_or_default(),
+            subject: header("subject").unwrap_or_default().to_string(),
+            body: body.strip_suffix('\n').unwrap_or(body).to_string(),
+            date,
+            in_reply_to,
+            thread_id,
+            priority: header("x-agent-office-priority").and_then(|v| v.parse().ok()).unwrap_or_default(),
+            read: header("x-agent-office-read").is_some_and(|v| v.eq_ignore_ascii_case("yes")),
+            labels: header("x-agent-office-labels")
+                .map(|v| v.split(',').map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).collect())
+                .unwrap_or_default(),
+            folder: header("x-agent-office-folder").and_then(|v| v.parse().ok()).unwrap_or_default(),
+            content_type: header("x-agent-office-content-type").map(str::to_string),
+            payload: header("x-agent-office-payload").and_then(|v| serde_json::from_str(v).ok()),
+        })
+    }
+}
+
+/// Write mail as an mboxrd file: body lines that look like a `From ` line
+/// get one more `>` so they can't start a message
+pub fn write_mbox(mails: &[ArchivedMail]) -> String {
+    let mut mbox = String::new();
+    for mail in mails {
+        mbox.push_str(&format!(
+            "From {}@{} {}\n",
+            mail.from,
+            ADDRESS_DOMAIN,
+            mail.date.format("%a %b %e %H:%M:%S %Y")
+        ));
+        for line in mail.to_rfc5322().lines() {
+            if line.trim_start_matches('>').starts_with("From ") {
+                mbox.push('>');
+            }
+            mbox.push_str(line);
+            mbox.push('\n');
+        }
+        mbox.push('\n');
+    }
+    mbox
+}
+
+pub fn read_mbox(text: &str) -> Result<Vec<ArchivedMail>, String> {
+    let mut messages: Vec<String> = Vec::new();
+    let mut previous_blank = true;
+    for line in text.replace("\r\n", "\n").lines() {
+        if line.starts_with("From ") && previous_blank {
+            messages.push(String::new());
+        } else if let Some(message) = messages.last_mut() {
+            let unescaped = match line.strip_prefix('>') {
+                Some(rest) if rest.trim_start_matches('>').starts_with("From ") => rest,
+                _ => line,
+            };
+            message.push_str(unescaped);
+            message.push('\n');
+        }
+        previous_blank = line.is_empty();
+    }
+    messages
+        .iter()
+        // Drop the blank line that separates messages
+        .map(|m| ArchivedMail::from_rfc5322(m.strip_suffix('\n').unwrap_or(m)))
+        .collect()
+}
+
+pub fn write_jsonl(mails: &[ArchivedMail]) -> Result<String, String> {
+    let mut jsonl = String::new();
+    for mail in mails {
+        jsonl.push_str(&serde_json::to_string(mail).map_err(|e| e.to_string())?);
+        jsonl.push('\n');
+    }
+    Ok(jsonl)
+}
+
+pub fn read_jsonl(text: &str) -> Result<Vec<ArchivedMail>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| serde_json::from_str(line).map_err(|e| format!("line {}: {}", n + 1, e)))
+        .collect()
+}
+
+/// File name for a mail in an EML directory; sorts by date
+pub fn eml_file_name(mail: &ArchivedMail) -> String {
+    format!("{}-{}.eml", mail.date.format("%Y%m%d-%H%M%S"), &mail.id.to_string()[..8])
+}
+
+fn address(agent_id: &str) -> String {
+    format!("{} <{}@{}>", agent_id, agent_id, ADDRESS_DOMAIN)
+}
+
+/// Agent IDs from an address list: the local part of each address
+fn addresses(value: &str) -> Vec<AgentId> {
+    value
+        .split(',')
+        .filter_map(|part| {
+            let part = part.trim();
+            let addr = match (part.find('<'), part.rfind('>')) {
+                (Some(start), Some(end)) if start < end => &part[start + 1..end],
+                _ => part,
+            };
+            let local = addr.split('@').next()?.trim();
+            (!local.is_empty()).then(|| local.to_lowercase())
+        })
+        .collect()
+}
+
+fn message_id(id: MailId) -> String {
+    format!("<{}@{}>", id, ADDRESS_DOMAIN)
+}
+
+/// Our own message IDs carry the mail ID; any other is hashed into one
+fn parse_message_id(value: &str) -> MailId {
+    let value = value.trim();
+    let inner = value.trim_start_matches('<').trim_end_matches('>');
+    inner
+        .strip_suffix(&format!("@{}", ADDRESS_DOMAIN))
+        .and_then(|id| uuid::Uuid::parse_str(id).ok())
+        .unwrap_or_else(|| string_to_node_id(&format!("message-id:{}", inner)))
+}
+
+fn one_line(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::mail::{MailService, MailServiceImpl};
+    use crate::storage::memory::InMemoryStorage;
+
+    // As another mail client might write it: CRLF line ends, folded
+    // headers and Message-IDs that aren't ours
+    const FOREIGN_EML: &str = "From: Bob Builder <Bob@example.com>\r\n\
+        To: alice@example.com,\r\n\
+        \tcarol@example.com\r\n\
+        Subject: Quarterly numbers,\r\n  \
+        second draft\r\n\
+        Date: Tue, 3 Mar 2026 09:15:00 +0100\r\n\
+        Message-ID: <CAF=abc123@mail.example.com>\r\n\
+        In-Reply-To: <root-1@mail.example.com>\r\n\
+        References: <root-1@mail.example.com>\r\n\
+        \r\n\
+        See attached.\r\n\
+        \r\n\
+        Bob\r\n";
+
+    #[test]
+    fn test_reads_foreign_eml_with_folded_headers() {
+        let mail = ArchivedMail::from_rfc5322(FOREIGN_EML).unwrap();
+        assert_eq!(mail.from, "bob");
+        assert_eq!(mail.to, vec!["alice".to_string(), "carol".to_string()]);
+        assert_eq!(mail.subject, "Quarterly numbers, second draft");
+        assert_eq!(mail.body, "See attached.\n\nBob");
+        assert_eq!(mail.date.to_rfc3339(), "2026-03-03T08:15:00+00:00");
+        assert_eq!(mail.priority, Priority::Normal);
+        assert!(!mail.read);
+
+        // Foreign IDs hash to the same mail ID every time, and the thread
+        // root is the first reference
+        let again = ArchivedMail::from_rfc5322(FOREIGN_EML).unwrap();
+        assert_eq!(mail.id, again.id);
+        assert_eq!(mail.id, string_to_node_id("message-id:CAF=abc123@mail.example.com"));
+        assert_eq!(mail.in_reply_to, Some(string_to_node_id("message-id:root-1@mail.example.com")));
+        assert_eq!(mail.thread_id, mail.in_reply_to.unwrap());
+    }
+
+    #[test]
+    fn test_eml_round_trip_keeps_office_headers() {
+        let id = uuid::Uuid::new_v4();
+        let parent = uuid::Uuid::new_v4();
+        let mail = ArchivedMail {
+            id,
+            from: "alice".to_string(),
+            to: vec!["bob".to_string()],
+            cc: vec!["carol".to_string()],
+            subject: "Deploy\nplan".to_string(),
+            body: "Step one\n\nStep two".to_string(),
+            date: chrono::DateTime::parse_from_rfc3339("2026-03-03T08:15:00Z").unwrap().to_utc(),
+            in_reply_to: Some(parent),
+            thread_id: uuid::Uuid::new_v4(),
+            priority: Priority::Urgent,
+            read: true,
+            labels: vec!["ops".to_string(), "release".to_string()],
+            folder: Folder::Archive,
+            content_type: Some("deploy-plan".to_string()),
+            payload: Some(serde_json::json!({ "steps": 2 })),
+        };
+
+        let eml = mail.to_rfc5322();
+        assert!(eml.contains(&format!("Message-ID: <{}@{}>", id, ADDRESS_DOMAIN)));
+        assert!(eml.contains("Subject: Deploy plan\n"));
+        let read = ArchivedMail::from_rfc5322(&eml).unwrap();
+        assert_eq!(read, ArchivedMail { subject: "Deploy plan".to_string(), ..mail });
+    }
+
+    #[tokio::test]
+    async fn test_importing_the_same_eml_twice_adds_nothing() {
+        let service = MailServiceImpl::new(InMemoryStorage::new());
+        for name in ["alice", "bob"] {
+            service.create_agent(name).await.unwrap();
+        }
+        let mail = ArchivedMail::from_rfc5322(FOREIGN_EML).unwrap();
+
+        let first = service.import_mail("alice".into(), vec![mail.clone()]).await.unwrap();
+        assert_eq!(first, ImportReport { imported: 1, ..Default::default() });
+        let second = service.import_mail("alice".into(), vec![ArchivedMail::from_rfc5322(FOREIGN_EML).unwrap()]).await.unwrap();
+        assert_eq!(second, ImportReport { skipped: 1, ..Default::default() });
+
+        let inbox = service.get_mailbox_inbox(string_to_node_id("alice")).await.unwrap();
+        assert_eq!(inbox.len(), 1);
+        assert_eq!(inbox[0].id, mail.id);
+    }
+}
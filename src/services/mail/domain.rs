@@ -1,4 +1,4 @@
-use crate::domain::{string_to_node_id, Node, NodeId, Properties, PropertyValue, Timestamp};
+use crate::domain::{string_to_node_id, Edge, Node, NodeId, Properties, PropertyValue, Timestamp};
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -18,51 +18,366 @@ pub struct Mailbox {
 
 impl Mailbox {}
 
+/// How a recipient was addressed; stored as the `kind` of their sent_to edge
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecipientKind {
+    To,
+    Cc,
+}
+
+impl RecipientKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecipientKind::To => "to",
+            RecipientKind::Cc => "cc",
+        }
+    }
+}
+
+/// How soon a mail needs attention; urgent mail is handled first
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    Urgent,
+}
+
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::Urgent => "urgent",
+        }
+    }
+}
+
+impl std::str::FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "urgent" => Ok(Priority::Urgent),
+            _ => Err(format!("unknown priority '{}' (expected low, normal or urgent)", s)),
+        }
+    }
+}
+
+/// Where a recipient has filed a mail; stored on their sent_to edge
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Folder {
+    #[default]
+    Inbox,
+    Archive,
+    Trash,
+}
+
+impl Folder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Folder::Inbox => "inbox",
+            Folder::Archive => "archive",
+            Folder::Trash => "trash",
+        }
+    }
+}
+
+impl std::str::FromStr for Folder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "inbox" => Ok(Folder::Inbox),
+            "archive" => Ok(Folder::Archive),
+            "trash" => Ok(Folder::Trash),
+            _ => Err(format!("unknown folder '{}' (expected inbox, archive or trash)", s)),
+        }
+    }
+}
+
+/// One logical message, however many mailboxes it was delivered to.
+/// Each recipient has its own sent_to edge carrying their read state.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Mail {
     pub id: MailId,
     pub from_mailbox_id: MailboxId,
-    pub to_mailbox_id: MailboxId,
+    pub to_mailbox_ids: Vec<MailboxId>,
+    pub cc_mailbox_ids: Vec<MailboxId>,
     pub subject: String,
     pub body: String,
+    pub priority: Priority,
+    /// Read state for the mailbox this mail was loaded for (always false
+    /// when loaded from an outbox)
     pub read: bool,
+    /// That recipient's labels; when sending, the labels every recipient
+    /// starts out with
+    pub labels: Vec<String>,
+    /// Folder that recipient filed it in
+    pub folder: Folder,
+    /// The mail this one answers, if any
+    pub in_reply_to: Option<MailId>,
+    /// Id of the first mail in the conversation (its own id for new mail)
+    pub thread_id: MailId,
+    /// What kind of message `payload` is, e.g. "review-request"; schemas
+    /// are registered per content type
+    pub content_type: Option<String>,
+    /// Structured data sent along with the body, always a JSON object
+    pub payload: Option<serde_json::Value>,
+    /// When recipients get to see it; `None` for mail delivered on sending
+    pub deliver_at: Option<Timestamp>,
+    /// For mail forwarded by a rule: the original sender, then every agent
+    /// that forwarded it on. Empty for mail sent directly.
+    pub forward_chain: Vec<MailboxId>,
+    /// Sent automatically while its sender was away; never answered
+    /// automatically in turn
+    pub auto_reply: bool,
+    /// Its number in the mailbox it was loaded for, as in `alice#42`;
+    /// `None` for mail delivered before numbering existed
+    pub seq: Option<i64>,
     pub created_at: Timestamp,
 }
 
+/// How many times rules may forward a mail on before the chain stops
+pub const MAX_FORWARD_HOPS: usize = 5;
+
 impl Mail {
+    /// Duplicate recipients are dropped, and anyone in `to` is not also cc'd
     pub fn new(
         from_mailbox_id: MailboxId,
-        to_mailbox_id: MailboxId,
+        to_mailbox_ids: Vec<MailboxId>,
+        cc_mailbox_ids: Vec<MailboxId>,
         subject: impl Into<String>,
         body: impl Into<String>,
     ) -> Self {
+        let mut to = Vec::new();
+        for id in to_mailbox_ids {
+            if !to.contains(&id) {
+                to.push(id);
+            }
+        }
+        let mut cc = Vec::new();
+        for id in cc_mailbox_ids {
+            if !to.contains(&id) && !cc.contains(&id) {
+                cc.push(id);
+            }
+        }
+
+        let id = MailId::new_v4();
         Self {
-            id: MailId::new_v4(),
+            id,
             from_mailbox_id,
-            to_mailbox_id,
+            to_mailbox_ids: to,
+            cc_mailbox_ids: cc,
             subject: subject.into(),
             body: body.into(),
+            priority: Priority::Normal,
             read: false,
+            labels: Vec::new(),
+            folder: Folder::Inbox,
+            in_reply_to: None,
+            thread_id: id,
+            content_type: None,
+            payload: None,
+            deliver_at: None,
+            forward_chain: Vec::new(),
+            auto_reply: false,
+            seq: None,
             created_at: Utc::now(),
         }
     }
 
+    /// Every recipient with how they were addressed, `to` first
+    pub fn recipients(&self) -> impl Iterator<Item = (MailboxId, RecipientKind)> + '_ {
+        self.to_mailbox_ids
+            .iter()
+            .map(|id| (*id, RecipientKind::To))
+            .chain(self.cc_mailbox_ids.iter().map(|id| (*id, RecipientKind::Cc)))
+    }
+
+    /// The sent_to edges delivering this mail, each starting out unread
+    pub fn delivery_edges(&self) -> Vec<Edge> {
+        self.recipients()
+            .map(|(mailbox_id, kind)| {
+                let mut props = Properties::new();
+                props.insert("kind".to_string(), PropertyValue::String(kind.as_str().to_string()));
+                props.insert("read".to_string(), PropertyValue::Boolean(false));
+                props.insert("labels".to_string(), string_list(&self.labels));
+                if let Some(deliver_at) = self.deliver_at {
+                    props.insert("deliver_at".to_string(), PropertyValue::Timestamp(deliver_at));
+                }
+                Edge::new("sent_to", self.id, mailbox_id, props)
+            })
+            .collect()
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Labels each recipient starts out with
+    pub fn with_labels(mut self, labels: Vec<String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// Attach a structured payload of the given content type
+    pub fn with_payload(mut self, content_type: impl Into<String>, payload: serde_json::Value) -> Self {
+        self.content_type = Some(content_type.into());
+        self.payload = Some(payload);
+        self
+    }
+
+    /// Hold the mail back from recipients until `deliver_at`
+    pub fn deliver_at(mut self, deliver_at: Timestamp) -> Self {
+        self.deliver_at = Some(deliver_at);
+        self
+    }
+
+    /// When recipients got (or will get) it
+    pub fn delivered_at(&self) -> Timestamp {
+        self.deliver_at.unwrap_or(self.created_at)
+    }
+
+    /// Whether it is still waiting for its delivery time
+    pub fn is_pending(&self, now: Timestamp) -> bool {
+        self.deliver_at.is_some_and(|at| at > now)
+    }
+
+    /// Whether a rule of `by` (a recipient) may forward this mail to `to`.
+    /// Nobody gets it back once it has passed through them, and chains end
+    /// after `MAX_FORWARD_HOPS`, so rules can't forward in circles.
+    pub fn can_forward(&self, by: MailboxId, to: MailboxId) -> bool {
+        to != by
+            && to != self.from_mailbox_id
+            && !self.forward_chain.contains(&to)
+            && self.forward_chain.len() <= MAX_FORWARD_HOPS
+    }
+
+    /// A copy of this mail sent on by `by` to `to`, quoting who it was
+    /// originally `from`
+    pub fn forward(&self, by: MailboxId, to: MailboxId, from: &str) -> Mail {
+        let subject = if self.subject.starts_with("Fwd: ") {
+            self.subject.clone()
+        } else {
+            format!("Fwd: {}", self.subject)
+        };
+        let body = format!(
+            "---------- Forwarded message ----------\nFrom: {}\nSubject: {}\n\n{}",
+            from, self.subject, self.body
+        );
+        let mut forward = Mail::new(by, vec![to], Vec::new(), subject, body).with_priority(self.priority);
+        forward.content_type = self.content_type.clone();
+        forward.payload = self.payload.clone();
+        forward.deliver_at = self.deliver_at;
+        forward.forward_chain = if self.forward_chain.is_empty() {
+            vec![self.from_mailbox_id]
+        } else {
+            self.forward_chain.clone()
+        };
+        forward.forward_chain.push(by);
+        forward
+    }
+
+    /// The out-of-office answer `by` (a recipient) sends back to the sender
+    pub fn auto_reply_from(&self, by: MailboxId, message: &str, delegate: Option<&str>) -> Mail {
+        let mut body = message.to_string();
+        if let Some(delegate) = delegate {
+            body.push_str(&format!("\n\nYour mail has been passed on to {}.", delegate));
+        }
+        let mut reply = Mail::new(by, vec![self.from_mailbox_id], Vec::new(), format!("Auto-reply: {}", self.subject), body)
+            .in_reply_to(self);
+        reply.auto_reply = true;
+        reply
+    }
+
+    /// Take on one recipient's view of this mail
+    pub fn apply_delivery(&mut self, delivery: &Delivery) {
+        self.read = delivery.read;
+        self.labels = delivery.labels.clone();
+        self.folder = delivery.folder;
+        self.seq = delivery.seq;
+    }
+
+    /// How to refer to this mail from `agent_id`'s mailbox: its number
+    /// there, or its short id for mail that has none
+    pub fn reference(&self, agent_id: &str) -> String {
+        match self.seq {
+            Some(seq) => format!("{}#{}", agent_id, seq),
+            None => self.id.to_string()[..8].to_string(),
+        }
+    }
+
+    /// Mark this mail as a reply to `parent`, joining its thread
+    pub fn in_reply_to(mut self, parent: &Mail) -> Self {
+        self.in_reply_to = Some(parent.id);
+        self.thread_id = parent.thread_id;
+        self
+    }
+
+    /// Subject for a reply, without stacking "Re: Re:" prefixes
+    pub fn reply_subject(&self) -> String {
+        if self.subject.to_lowercase().starts_with("re:") {
+            self.subject.clone()
+        } else {
+            format!("Re: {}", self.subject)
+        }
+    }
+
     pub fn to_node(&self) -> Node {
         let mut props = Properties::new();
         props.insert(
             "from_mailbox_id".to_string(),
             PropertyValue::String(self.from_mailbox_id.to_string()),
         );
-        props.insert(
-            "to_mailbox_id".to_string(),
-            PropertyValue::String(self.to_mailbox_id.to_string()),
-        );
+        props.insert("to_mailbox_ids".to_string(), id_list(&self.to_mailbox_ids));
+        props.insert("cc_mailbox_ids".to_string(), id_list(&self.cc_mailbox_ids));
+        // Flat list of everyone it was delivered to
+        let recipients: Vec<MailboxId> = self.recipients().map(|(id, _)| id).collect();
+        props.insert("recipients".to_string(), id_list(&recipients));
         props.insert(
             "subject".to_string(),
             PropertyValue::String(self.subject.clone()),
         );
         props.insert("body".to_string(), PropertyValue::String(self.body.clone()));
-        props.insert("read".to_string(), PropertyValue::Boolean(self.read));
+        props.insert(
+            "priority".to_string(),
+            PropertyValue::String(self.priority.as_str().to_string()),
+        );
+        if let Some(in_reply_to) = self.in_reply_to {
+            props.insert(
+                "in_reply_to".to_string(),
+                PropertyValue::String(in_reply_to.to_string()),
+            );
+        }
+        props.insert(
+            "thread_id".to_string(),
+            PropertyValue::String(self.thread_id.to_string()),
+        );
+        if let Some(ref content_type) = self.content_type {
+            props.insert(
+                "content_type".to_string(),
+                PropertyValue::String(content_type.clone()),
+            );
+        }
+        if let Some(payload) = self.payload.as_ref().and_then(json_to_property) {
+            props.insert("payload".to_string(), payload);
+        }
+        if let Some(deliver_at) = self.deliver_at {
+            props.insert("deliver_at".to_string(), PropertyValue::Timestamp(deliver_at));
+        }
+        if !self.forward_chain.is_empty() {
+            props.insert("forward_chain".to_string(), id_list(&self.forward_chain));
+        }
+        if self.auto_reply {
+            props.insert("auto_reply".to_string(), PropertyValue::Boolean(true));
+        }
 
         let mut node = Node::new("mail", props);
         node.id = self.id;
@@ -79,10 +394,8 @@ impl Mail {
             _ => None,
         })?;
 
-        let to_mailbox_id = node.get_property("to_mailbox_id").and_then(|v| match v {
-            PropertyValue::String(s) => Uuid::parse_str(s).ok(),
-            _ => None,
-        })?;
+        let to_mailbox_ids = parse_id_list(node.get_property("to_mailbox_ids"))?;
+        let cc_mailbox_ids = parse_id_list(node.get_property("cc_mailbox_ids")).unwrap_or_default();
 
         let subject = node.get_property("subject").and_then(|v| match v {
             PropertyValue::String(s) => Some(s.clone()),
@@ -94,27 +407,510 @@ impl Mail {
             _ => None,
         })?;
 
-        let read = node
-            .get_property("read")
+        // Mail written before priorities existed is normal priority
+        let priority = node
+            .get_property("priority")
+            .and_then(|v| v.as_str())
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_default();
+
+        let in_reply_to = node.get_property("in_reply_to").and_then(|v| match v {
+            PropertyValue::String(s) => Uuid::parse_str(s).ok(),
+            _ => None,
+        });
+
+        // Mail written before threading existed starts its own thread
+        let thread_id = node
+            .get_property("thread_id")
             .and_then(|v| match v {
-                PropertyValue::Boolean(b) => Some(*b),
+                PropertyValue::String(s) => Uuid::parse_str(s).ok(),
                 _ => None,
             })
-            .unwrap_or(false);
+            .unwrap_or(node.id);
+
+        let content_type = node
+            .get_property("content_type")
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        let payload = node.get_property("payload").and_then(property_to_json);
+        let deliver_at = node.get_property("deliver_at").and_then(|v| match v {
+            PropertyValue::Timestamp(t) => Some(*t),
+            _ => None,
+        });
+        let forward_chain = parse_id_list(node.get_property("forward_chain")).unwrap_or_default();
+        let auto_reply = matches!(node.get_property("auto_reply"), Some(PropertyValue::Boolean(true)));
 
         Some(Self {
             id: node.id,
             from_mailbox_id,
-            to_mailbox_id,
+            to_mailbox_ids,
+            cc_mailbox_ids,
             subject,
             body,
-            read,
+            priority,
+            read: false,
+            labels: Vec::new(),
+            folder: Folder::Inbox,
+            in_reply_to,
+            thread_id,
+            content_type,
+            payload,
+            deliver_at,
+            forward_chain,
+            auto_reply,
+            seq: None,
             created_at: node.created_at,
         })
     }
+}
+
+/// Whether a sent_to edge has been marked read by its recipient
+pub fn delivery_is_read(edge: &Edge) -> bool {
+    matches!(edge.properties.get("read"), Some(PropertyValue::Boolean(true)))
+}
+
+/// The mailbox number stored on a sent_to or sent_from edge
+pub fn edge_seq(edge: &Edge) -> Option<i64> {
+    match edge.properties.get("seq") {
+        Some(PropertyValue::Integer(n)) => Some(*n),
+        _ => None,
+    }
+}
+
+/// Receipt for one recipient of a mail, read off their sent_to edge
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Delivery {
+    pub mailbox_id: MailboxId,
+    pub kind: RecipientKind,
+    /// When it reached the mailbox, or will for mail sent with a delay
+    pub delivered_at: Timestamp,
+    pub read: bool,
+    /// When it was first marked read; unknown for mail read before
+    /// receipts were recorded
+    pub read_at: Option<Timestamp>,
+    /// Who marked it read: the recipient, or e.g. "web" for the dashboard
+    pub read_by: Option<String>,
+    pub labels: Vec<String>,
+    pub folder: Folder,
+    /// Number of this mail in the recipient's mailbox
+    pub seq: Option<i64>,
+}
+
+impl Delivery {
+    pub fn from_edge(edge: &Edge) -> Option<Self> {
+        if edge.edge_type != "sent_to" {
+            return None;
+        }
+
+        let kind = match edge.properties.get("kind") {
+            Some(PropertyValue::String(s)) if s == "cc" => RecipientKind::Cc,
+            _ => RecipientKind::To,
+        };
+
+        let read_at = edge.properties.get("read_at").and_then(|v| match v {
+            PropertyValue::Timestamp(t) => Some(*t),
+            _ => None,
+        });
+
+        let read_by = edge.properties.get("read_by").and_then(|v| match v {
+            PropertyValue::String(s) => Some(s.clone()),
+            _ => None,
+        });
+
+        let labels = match edge.properties.get("labels") {
+            Some(PropertyValue::List(items)) => items
+                .iter()
+                .filter_map(|item| item.as_str().map(str::to_string))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let folder = edge
+            .properties
+            .get("folder")
+            .and_then(|v| v.as_str())
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_default();
+
+        let delivered_at = match edge.properties.get("deliver_at") {
+            Some(PropertyValue::Timestamp(t)) => *t,
+            _ => edge.created_at,
+        };
+
+        Some(Self {
+            mailbox_id: edge.to_node_id,
+            kind,
+            delivered_at,
+            read: delivery_is_read(edge),
+            read_at,
+            read_by,
+            labels,
+            folder,
+            seq: edge_seq(edge),
+        })
+    }
+
+    /// Whether the recipient is still waiting for it
+    pub fn is_pending(&self, now: Timestamp) -> bool {
+        self.delivered_at > now
+    }
+
+    /// "delivered <time>, read <time> by <who>" for outbox listings
+    pub fn status_line(&self) -> String {
+        if self.is_pending(Utc::now()) {
+            return format!("scheduled for {}", self.delivered_at.format("%Y-%m-%d %H:%M"));
+        }
+        let delivered = format!("delivered {}", self.delivered_at.format("%Y-%m-%d %H:%M"));
+        if !self.read {
+            return format!("{}, unread", delivered);
+        }
+        let mut line = format!("{}, read", delivered);
+        if let Some(read_at) = self.read_at {
+            line.push_str(&format!(" {}", read_at.format("%Y-%m-%d %H:%M")));
+        }
+        if let Some(ref read_by) = self.read_by {
+            line.push_str(&format!(" by {}", read_by));
+        }
+        line
+    }
+}
+
+/// Record on a sent_to edge that it was read. The first receipt wins, so
+/// marking an already-read mail again doesn't rewrite who read it or when.
+pub fn mark_delivery_read(edge: &mut Edge, read_by: &str) {
+    if delivery_is_read(edge) {
+        return;
+    }
+    edge.properties.insert("read".to_string(), PropertyValue::Boolean(true));
+    edge.properties.insert("read_at".to_string(), PropertyValue::Timestamp(Utc::now()));
+    edge.properties.insert("read_by".to_string(), PropertyValue::String(read_by.to_string()));
+}
+
+/// Move a delivery to another folder for its recipient
+pub fn set_delivery_folder(edge: &mut Edge, folder: Folder) {
+    edge.properties.insert("folder".to_string(), PropertyValue::String(folder.as_str().to_string()));
+}
+
+/// Replace a delivery's labels
+pub fn set_delivery_labels(edge: &mut Edge, labels: &[String]) {
+    edge.properties.insert("labels".to_string(), string_list(labels));
+}
+
+/// Optional settings for a new mail
+#[derive(Debug, Clone, Default)]
+pub struct SendOptions {
+    pub priority: Priority,
+    /// Labels every recipient starts out with
+    pub labels: Vec<String>,
+    /// Structured payload; must be a JSON object, and is checked against
+    /// the schema registered for `content_type` if there is one
+    pub payload: Option<serde_json::Value>,
+    /// Content type of the payload; defaults to [`DEFAULT_CONTENT_TYPE`]
+    pub content_type: Option<String>,
+    /// Files to attach
+    pub attachments: Vec<NewAttachment>,
+    /// Hold the mail back until this time
+    pub deliver_at: Option<Timestamp>,
+}
+
+/// Parse a delivery time for delayed mail, relative to `now` in its time
+/// zone: "+30m", "+2h", "+1d", "09:00" (the next time it is 9am),
+/// "tomorrow 09:00", "2026-03-01 09:00", or RFC 3339
+pub fn parse_deliver_at<Tz: chrono::TimeZone>(input: &str, now: chrono::DateTime<Tz>) -> Result<Timestamp, String> {
+    use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+
+    let input = input.trim();
+    let invalid = || format!(
+        "can't read '{}' as a time (try +2h, 09:00, tomorrow 09:00, 2026-03-01 09:00)",
+        input
+    );
+
+    if let Some(offset) = input.strip_prefix('+') {
+        let split = offset.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+        let (amount, unit) = offset.split_at(split);
+        let amount: i64 = amount.parse().map_err(|_| invalid())?;
+        let delay = match unit {
+            "s" => chrono::Duration::seconds(amount),
+            "m" => chrono::Duration::minutes(amount),
+            "h" => chrono::Duration::hours(amount),
+            "d" => chrono::Duration::days(amount),
+            _ => return Err(invalid()),
+        };
+        return Ok(now.with_timezone(&Utc) + delay);
+    }
+
+    if let Ok(at) = chrono::DateTime::parse_from_rfc3339(input) {
+        return Ok(at.with_timezone(&Utc));
+    }
+
+    let tz = now.timezone();
+    let local = |naive: NaiveDateTime| {
+        naive.and_local_timezone(tz.clone()).earliest().map(|t| t.with_timezone(&Utc)).ok_or_else(invalid)
+    };
+    let time_of_day = |s: &str| NaiveTime::parse_from_str(s, "%H:%M").map_err(|_| invalid());
+
+    if let Some(time) = input.strip_prefix("tomorrow ") {
+        let date = now.date_naive().succ_opt().ok_or_else(invalid)?;
+        return local(date.and_time(time_of_day(time.trim())?));
+    }
+    if let Ok(time) = time_of_day(input) {
+        let today = local(now.date_naive().and_time(time))?;
+        if today > now.with_timezone(&Utc) {
+            return Ok(today);
+        }
+        let date = now.date_naive().succ_opt().ok_or_else(invalid)?;
+        return local(date.and_time(time));
+    }
+    for format in ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(input, format) {
+            return local(naive);
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return local(date.and_time(NaiveTime::MIN));
+    }
+    Err(invalid())
+}
+
+/// A mail search such as
+/// `from:bob subject:deploy is:unread after:2026-01-01 label:ci "exact phrase"`.
+/// Words and quoted phrases must all appear in the mail; each `key:value`
+/// narrows the search further.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MailSearch {
+    /// Free words and phrases, phrases kept in their quotes
+    pub text: Vec<String>,
+    /// `from:bob`
+    pub from: Option<AgentId>,
+    /// `subject:deploy`; every one must appear in the subject
+    pub subject: Vec<String>,
+    /// `is:read` or `is:unread`
+    pub read: Option<bool>,
+    /// `label:ci`; every one must be on the mail
+    pub labels: Vec<String>,
+    /// `in:archive`; mail in any folder matches without it
+    pub folder: Option<Folder>,
+    /// `after:2026-01-01`, from midnight local time
+    pub after: Option<Timestamp>,
+    /// `before:2026-02-01`, up to midnight local time
+    pub before: Option<Timestamp>,
+}
+
+impl MailSearch {
+    /// Parse a query, reading dates as midnight in `tz`
+    pub fn parse<Tz: chrono::TimeZone>(input: &str, tz: &Tz) -> Result<Self, String> {
+        let mut search = Self::default();
+        for (token, quoted) in search_tokens(input) {
+            let field = if quoted { None } else { token.split_once(':') };
+            let Some((key, value)) = field.filter(|(key, _)| SEARCH_KEYS.contains(key)) else {
+                search.text.push(if quoted { format!("\"{}\"", token) } else { token });
+                continue;
+            };
+            if value.is_empty() {
+                return Err(format!("'{}:' needs a value", key));
+            }
+            match key {
+                "from" => search.from = Some(value.to_string()),
+                "subject" => search.subject.push(value.to_string()),
+                "label" => search.labels.push(value.to_string()),
+                "is" => search.read = match value {
+                    "read" => Some(true),
+                    "unread" => Some(false),
+                    _ => return Err(format!("unknown 'is:{}' (use is:read or is:unread)", value)),
+                },
+                "in" => search.folder = Some(value.parse()?),
+                "after" => search.after = Some(search_date(value, tz)?),
+                "before" => search.before = Some(search_date(value, tz)?),
+                _ => unreachable!(),
+            }
+        }
+        Ok(search)
+    }
+
+    /// The free words and phrases as one full-text query
+    pub fn full_text(&self) -> Option<String> {
+        Some(self.text.join(" ")).filter(|t| !t.is_empty())
+    }
+
+    /// Whether a recipient's copy passes `is:`, `label:` and `in:`
+    pub fn matches_delivery(&self, delivery: &Delivery) -> bool {
+        self.read.is_none_or(|read| delivery.read == read)
+            && self.labels.iter().all(|l| delivery.labels.contains(l))
+            && self.folder.is_none_or(|f| delivery.folder == f)
+    }
+
+    /// Whether sent mail can match: it has no read state, labels or folder
+    pub fn includes_sent(&self) -> bool {
+        self.read.is_none() && self.labels.is_empty() && self.folder.is_none()
+    }
+}
+
+const SEARCH_KEYS: &[&str] = &["from", "subject", "is", "label", "in", "after", "before"];
+
+/// Split a search on whitespace, keeping quoted stretches together. Each
+/// token says whether it was a quoted phrase; `subject:"two words"` is a
+/// field whose value was quoted.
+fn search_tokens(input: &str) -> Vec<(String, bool)> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut phrase = false;
+    for c in input.chars() {
+        match c {
+            '"' => {
+                if !in_quotes && current.is_empty() {
+                    phrase = true;
+                }
+                in_quotes = !in_quotes;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push((std::mem::take(&mut current), phrase));
+                }
+                phrase = false;
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push((current, phrase));
+    }
+    tokens
+}
+
+fn search_date<Tz: chrono::TimeZone>(value: &str, tz: &Tz) -> Result<Timestamp, String> {
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_time(chrono::NaiveTime::MIN).and_local_timezone(tz.clone()).earliest())
+        .map(|t| t.with_timezone(&Utc))
+        .ok_or_else(|| format!("can't read '{}' as a date (use YYYY-MM-DD)", value))
+}
+
+/// How a mail is named on the command line or in a URL
+#[derive(Debug, Clone, PartialEq)]
+pub enum MailRef {
+    /// `alice#42`, or `#42` when the mailbox is known from elsewhere
+    Number(Option<AgentId>, i64),
+    /// A mail id or a prefix of one
+    Id(String),
+}
+
+impl MailRef {
+    pub fn parse(input: &str) -> Self {
+        let input = input.trim();
+        if let Some((agent, number)) = input.rsplit_once('#') {
+            if let Ok(number) = number.parse() {
+                let agent = Some(agent.to_string()).filter(|a| !a.is_empty());
+                return MailRef::Number(agent, number);
+            }
+        }
+        MailRef::Id(input.to_string())
+    }
+
+    /// Whose mailbox the reference points into: the one it names, else
+    /// `default`
+    pub fn owner(&self, default: Option<AgentId>) -> Option<AgentId> {
+        match self {
+            MailRef::Number(Some(agent), _) => Some(agent.clone()),
+            _ => default,
+        }
+    }
+}
+
+/// Content type of payloads sent without one; never has a schema
+pub const DEFAULT_CONTENT_TYPE: &str = "application/json";
+
+/// Which of a mailbox's mail to list; `None` fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct InboxFilter {
+    pub folder: Option<Folder>,
+    pub label: Option<String>,
+    pub priority: Option<Priority>,
+}
+
+impl InboxFilter {
+    /// The regular inbox: everything not archived or trashed
+    pub fn inbox() -> Self {
+        Self {
+            folder: Some(Folder::Inbox),
+            ..Self::default()
+        }
+    }
+
+    /// The part of the filter a delivery can answer without loading the mail
+    pub fn matches_delivery(&self, delivery: &Delivery) -> bool {
+        self.folder.is_none_or(|f| delivery.folder == f)
+            && self.label.as_ref().is_none_or(|l| delivery.labels.contains(l))
+    }
+
+    /// Check a mail that has had its recipient's delivery applied
+    pub fn matches(&self, mail: &Mail) -> bool {
+        self.folder.is_none_or(|f| mail.folder == f)
+            && self.priority.is_none_or(|p| mail.priority == p)
+            && self.label.as_ref().is_none_or(|l| mail.labels.contains(l))
+    }
+}
+
+/// How long mail is kept around. `None` turns a step off.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Move mail a recipient has read out of their inbox after this long
+    pub archive_read_after: Option<chrono::Duration>,
+    /// Delete mail for everyone after this long, read or not
+    pub delete_after: Option<chrono::Duration>,
+}
+
+impl RetentionPolicy {
+    /// Build a policy from day counts, where 0 means never
+    pub fn from_days(archive_read_after_days: u32, delete_after_days: u32) -> Self {
+        let days = |n: u32| (n > 0).then(|| chrono::Duration::days(n.into()));
+        Self {
+            archive_read_after: days(archive_read_after_days),
+            delete_after: days(delete_after_days),
+        }
+    }
+}
+
+/// What one retention run changed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionReport {
+    /// Deliveries moved to the archive
+    pub archived: usize,
+    /// Mail deleted outright
+    pub deleted: usize,
+}
+
+/// Payloads are stored as a property map. Strings that look like RFC 3339
+/// timestamps come back normalized to UTC.
+fn json_to_property(value: &serde_json::Value) -> Option<PropertyValue> {
+    serde_json::from_value(value.clone()).ok()
+}
+
+fn property_to_json(value: &PropertyValue) -> Option<serde_json::Value> {
+    serde_json::to_value(value).ok()
+}
 
-    pub fn mark_as_read(&mut self) {
-        self.read = true;
+fn string_list(items: &[String]) -> PropertyValue {
+    PropertyValue::List(items.iter().map(|s| PropertyValue::String(s.clone())).collect())
+}
+
+fn id_list(ids: &[MailboxId]) -> PropertyValue {
+    PropertyValue::List(
+        ids.iter()
+            .map(|id| PropertyValue::String(id.to_string()))
+            .collect(),
+    )
+}
+
+fn parse_id_list(value: Option<&PropertyValue>) -> Option<Vec<MailboxId>> {
+    match value? {
+        PropertyValue::List(items) => Some(
+            items
+                .iter()
+                .filter_map(|item| item.as_str().and_then(|s| Uuid::parse_str(s).ok()))
+                .collect(),
+        ),
+        _ => None,
     }
 }
 
@@ -124,9 +920,22 @@ pub struct Agent {
     pub name: String,
     pub status: String,
     pub session_id: Option<String>,
+    /// Out-of-office answer sent while the agent is offline or away
+    pub auto_reply: Option<AutoReply>,
     pub created_at: Timestamp,
 }
 
+/// What an agent tells senders while it is away, and who covers its mail
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AutoReply {
+    pub message: String,
+    /// Agent that gets a copy of mail arriving while away
+    pub delegate: Option<AgentId>,
+}
+
+/// A sender hears the same agent's auto-reply at most once in this many hours
+pub const AUTO_REPLY_COOLDOWN_HOURS: i64 = 24;
+
 impl Default for Agent {
     fn default() -> Self {
         let name = String::from("Unnamed");
@@ -135,6 +944,7 @@ impl Default for Agent {
             name,
             status: String::from("offline"),
             session_id: None,
+            auto_reply: None,
             created_at: Utc::now(),
         }
     }
@@ -159,10 +969,16 @@ impl Agent {
             name,
             status: String::from("offline"),
             session_id: None,
+            auto_reply: None,
             created_at: Utc::now(),
         }
     }
 
+    /// Whether an auto-reply should answer for it
+    pub fn is_away(&self) -> bool {
+        matches!(self.status.as_str(), "offline" | "away")
+    }
+
     pub fn to_node(&self) -> Node {
         let mut props = Properties::new();
         props.insert("name".to_string(), PropertyValue::String(self.name.clone()));
@@ -180,6 +996,18 @@ impl Agent {
                 PropertyValue::String(session_id.clone()),
             );
         }
+        if let Some(ref auto_reply) = self.auto_reply {
+            props.insert(
+                "auto_reply_message".to_string(),
+                PropertyValue::String(auto_reply.message.clone()),
+            );
+            if let Some(ref delegate) = auto_reply.delegate {
+                props.insert(
+                    "auto_reply_delegate".to_string(),
+                    PropertyValue::String(delegate.clone()),
+                );
+            }
+        }
 
         let mut node = Node::new("agent", props);
         // Convert string ID to deterministic UUID for storage
@@ -221,12 +1049,467 @@ impl Agent {
             _ => None,
         });
 
+        let auto_reply = node.get_property("auto_reply_message").and_then(|v| v.as_str()).map(|message| AutoReply {
+            message: message.to_string(),
+            delegate: node.get_property("auto_reply_delegate").and_then(|v| v.as_str()).map(str::to_string),
+        });
+
         Some(Self {
             id,
             name,
             status,
             session_id,
+            auto_reply,
+            created_at: node.created_at,
+        })
+    }
+}
+
+/// A named set of agents that mail can be addressed to as `@name`.
+/// Members are linked with member_of edges from the agent to the group.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Group {
+    pub name: String,
+    pub created_at: Timestamp,
+}
+
+impl Group {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Node id for a group name; namespaced so a group can share its name
+    /// with an agent
+    pub fn node_id(name: &str) -> NodeId {
+        string_to_node_id(&format!("group:{}", name))
+    }
+
+    pub fn to_node(&self) -> Node {
+        let mut props = Properties::new();
+        props.insert("name".to_string(), PropertyValue::String(self.name.clone()));
+
+        let mut node = Node::new("group", props);
+        node.id = Self::node_id(&self.name);
+        node
+    }
+
+    pub fn from_node(node: &Node) -> Option<Self> {
+        if node.node_type != "group" {
+            return None;
+        }
+
+        let name = node.get_property("name").and_then(|v| match v {
+            PropertyValue::String(s) => Some(s.clone()),
+            _ => None,
+        })?;
+
+        Some(Self {
+            name,
             created_at: node.created_at,
         })
     }
 }
+
+/// A filter an agent sets up for its own mail, applied as mail is delivered
+/// to it. Every condition given must hold; the actions then run on that
+/// agent's copy. Stored as a mail_rule node linked from the agent by a
+/// has_rule edge.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MailRule {
+    pub id: NodeId,
+    pub agent_id: AgentId,
+    pub name: String,
+    /// Sender's agent id
+    pub from: Option<AgentId>,
+    /// Case-insensitive substring of the subject
+    pub subject_contains: Option<String>,
+    /// Case-insensitive substring of the body
+    pub body_contains: Option<String>,
+    pub add_labels: Vec<String>,
+    pub move_to: Option<Folder>,
+    pub mark_read: bool,
+    /// Agents that get a copy
+    pub forward_to: Vec<AgentId>,
+    pub enabled: bool,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+}
+
+impl MailRule {
+    /// An enabled rule with no conditions or actions yet
+    pub fn new(agent_id: impl Into<AgentId>, name: impl Into<String>) -> Self {
+        let now = Utc::now();
+        Self {
+            id: NodeId::new_v4(),
+            agent_id: agent_id.into(),
+            name: name.into(),
+            from: None,
+            subject_contains: None,
+            body_contains: None,
+            add_labels: Vec::new(),
+            move_to: None,
+            mark_read: false,
+            forward_to: Vec::new(),
+            enabled: true,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Whether the rule does anything when it matches
+    pub fn has_actions(&self) -> bool {
+        !self.add_labels.is_empty() || self.move_to.is_some() || self.mark_read || !self.forward_to.is_empty()
+    }
+
+    pub fn matches(&self, mail: &Mail) -> bool {
+        let contains = |text: &str, needle: &Option<String>| {
+            needle.as_ref().is_none_or(|n| text.to_lowercase().contains(&n.to_lowercase()))
+        };
+        self.from.as_ref().is_none_or(|from| string_to_node_id(from) == mail.from_mailbox_id)
+            && contains(&mail.subject, &self.subject_contains)
+            && contains(&mail.body, &self.body_contains)
+    }
+
+    /// "from ci_bot, subject contains \"FAILED\"", or "all mail"
+    pub fn conditions_label(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(ref from) = self.from {
+            parts.push(format!("from {}", from));
+        }
+        if let Some(ref text) = self.subject_contains {
+            parts.push(format!("subject contains \"{}\"", text));
+        }
+        if let Some(ref text) = self.body_contains {
+            parts.push(format!("body contains \"{}\"", text));
+        }
+        if parts.is_empty() { "all mail".to_string() } else { parts.join(", ") }
+    }
+
+    /// "label ci, forward to triage"
+    pub fn actions_label(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.add_labels.is_empty() {
+            parts.push(format!("label {}", self.add_labels.join(", ")));
+        }
+        if let Some(folder) = self.move_to {
+            parts.push(format!("move to {}", folder.as_str()));
+        }
+        if self.mark_read {
+            parts.push("mark read".to_string());
+        }
+        if !self.forward_to.is_empty() {
+            parts.push(format!("forward to {}", self.forward_to.join(", ")));
+        }
+        parts.join(", ")
+    }
+
+    pub fn to_node(&self) -> Node {
+        let mut props = Properties::new();
+        props.insert("agent_id".to_string(), PropertyValue::String(self.agent_id.clone()));
+        props.insert("name".to_string(), PropertyValue::String(self.name.clone()));
+        for (key, value) in [
+            ("from", &self.from),
+            ("subject_contains", &self.subject_contains),
+            ("body_contains", &self.body_contains),
+        ] {
+            if let Some(value) = value {
+                props.insert(key.to_string(), PropertyValue::String(value.clone()));
+            }
+        }
+        props.insert("add_labels".to_string(), string_list(&self.add_labels));
+        if let Some(folder) = self.move_to {
+            props.insert("move_to".to_string(), PropertyValue::String(folder.as_str().to_string()));
+        }
+        props.insert("mark_read".to_string(), PropertyValue::Boolean(self.mark_read));
+        props.insert("forward_to".to_string(), string_list(&self.forward_to));
+        props.insert("enabled".to_string(), PropertyValue::Boolean(self.enabled));
+
+        let mut node = Node::new("mail_rule", props);
+        node.id = self.id;
+        node.created_at = self.created_at;
+        node.updated_at = self.updated_at;
+        node
+    }
+
+    pub fn from_node(node: &Node) -> Option<Self> {
+        if node.node_type != "mail_rule" {
+            return None;
+        }
+
+        let text = |key: &str| node.get_property(key).and_then(|v| v.as_str()).map(str::to_string);
+        let list = |key: &str| match node.get_property(key) {
+            Some(PropertyValue::List(items)) => items.iter().filter_map(|v| v.as_str()).map(str::to_string).collect(),
+            _ => Vec::new(),
+        };
+        let flag = |key: &str| matches!(node.get_property(key), Some(PropertyValue::Boolean(true)));
+
+        Some(Self {
+            id: node.id,
+            agent_id: text("agent_id")?,
+            name: text("name")?,
+            from: text("from"),
+            subject_contains: text("subject_contains"),
+            body_contains: text("body_contains"),
+            add_labels: list("add_labels"),
+            move_to: text("move_to").and_then(|s| s.parse().ok()),
+            mark_read: flag("mark_read"),
+            forward_to: list("forward_to"),
+            enabled: flag("enabled"),
+            created_at: node.created_at,
+            updated_at: node.updated_at,
+        })
+    }
+}
+
+/// JSON Schema that payloads of one content type must satisfy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayloadSchema {
+    pub content_type: String,
+    pub schema: serde_json::Value,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+}
+
+impl PayloadSchema {
+    /// Fails with the compiler's message if `schema` isn't a usable JSON Schema
+    pub fn new(content_type: impl Into<String>, schema: serde_json::Value) -> Result<Self, String> {
+        jsonschema::validator_for(&schema).map_err(|e| e.to_string())?;
+        let now = Utc::now();
+        Ok(Self {
+            content_type: content_type.into(),
+            schema,
+            created_at: now,
+            updated_at: now,
+        })
+    }
+
+    /// Node id for a content type; one schema per type
+    pub fn node_id(content_type: &str) -> NodeId {
+        string_to_node_id(&format!("payload_schema:{}", content_type))
+    }
+
+    /// Every way `payload` breaks the schema, as "/path: message" lines
+    pub fn validate(&self, payload: &serde_json::Value) -> Result<(), Vec<String>> {
+        let validator = jsonschema::validator_for(&self.schema).map_err(|e| vec![e.to_string()])?;
+        let errors: Vec<String> = validator
+            .iter_errors(payload)
+            .map(|e| {
+                let path = e.instance_path.to_string();
+                format!("{}: {}", if path.is_empty() { "/" } else { &path }, e)
+            })
+            .collect();
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    pub fn to_node(&self) -> Node {
+        let mut props = Properties::new();
+        props.insert(
+            "content_type".to_string(),
+            PropertyValue::String(self.content_type.clone()),
+        );
+        if let Some(schema) = json_to_property(&self.schema) {
+            props.insert("schema".to_string(), schema);
+        }
+
+        let mut node = Node::new("payload_schema", props);
+        node.id = Self::node_id(&self.content_type);
+        node.created_at = self.created_at;
+        node.updated_at = self.updated_at;
+        node
+    }
+
+    pub fn from_node(node: &Node) -> Option<Self> {
+        if node.node_type != "payload_schema" {
+            return None;
+        }
+
+        let content_type = node.get_property("content_type")?.as_str()?.to_string();
+        let schema = node.get_property("schema").and_then(property_to_json)?;
+
+        Some(Self {
+            content_type,
+            schema,
+            created_at: node.created_at,
+            updated_at: node.updated_at,
+        })
+    }
+}
+
+/// A file attached to a mail, linked by a has_attachment edge from the mail.
+/// The bytes live in the blob store under `sha256`; the node only holds what
+/// is needed to list and serve them.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Attachment {
+    pub id: NodeId,
+    pub mail_id: MailId,
+    pub filename: String,
+    pub content_type: String,
+    pub size: u64,
+    pub sha256: String,
+    pub created_at: Timestamp,
+}
+
+impl Attachment {
+    pub fn new(mail_id: MailId, file: &NewAttachment, sha256: String) -> Self {
+        Self {
+            id: NodeId::new_v4(),
+            mail_id,
+            filename: file.filename.clone(),
+            content_type: file.content_type(),
+            size: file.data.len() as u64,
+            sha256,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Size for listings, e.g. "12.5 KB"
+    pub fn size_label(&self) -> String {
+        const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+        let mut size = self.size as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        if unit == 0 {
+            format!("{} B", self.size)
+        } else {
+            format!("{:.1} {}", size, UNITS[unit])
+        }
+    }
+
+    pub fn to_node(&self) -> Node {
+        let mut props = Properties::new();
+        props.insert("mail_id".to_string(), PropertyValue::String(self.mail_id.to_string()));
+        props.insert("filename".to_string(), PropertyValue::String(self.filename.clone()));
+        props.insert(
+            "content_type".to_string(),
+            PropertyValue::String(self.content_type.clone()),
+        );
+        props.insert("size".to_string(), PropertyValue::Integer(self.size as i64));
+        props.insert("sha256".to_string(), PropertyValue::String(self.sha256.clone()));
+
+        let mut node = Node::new("attachment", props);
+        node.id = self.id;
+        node.created_at = self.created_at;
+        node.updated_at = self.created_at;
+        node
+    }
+
+    pub fn from_node(node: &Node) -> Option<Self> {
+        if node.node_type != "attachment" {
+            return None;
+        }
+
+        let text = |key: &str| node.get_property(key).and_then(|v| v.as_str()).map(str::to_string);
+        let mail_id = text("mail_id").and_then(|s| Uuid::parse_str(&s).ok())?;
+        let size = match node.get_property("size") {
+            Some(PropertyValue::Integer(n)) => u64::try_from(*n).ok()?,
+            _ => return None,
+        };
+
+        Some(Self {
+            id: node.id,
+            mail_id,
+            filename: text("filename")?,
+            content_type: text("content_type").unwrap_or_else(|| "application/octet-stream".to_string()),
+            size,
+            sha256: text("sha256")?,
+            created_at: node.created_at,
+        })
+    }
+}
+
+/// A file to attach to a new mail
+#[derive(Debug, Clone)]
+pub struct NewAttachment {
+    /// Bare file name; any directories in the name given are dropped
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+impl NewAttachment {
+    pub fn new(filename: &str, data: Vec<u8>) -> Self {
+        let filename = std::path::Path::new(filename)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .filter(|name| !name.is_empty())
+            .unwrap_or("attachment")
+            .to_string();
+        Self { filename, data }
+    }
+
+    /// MIME type guessed from the file extension
+    pub fn content_type(&self) -> String {
+        mime_guess::from_path(&self.filename)
+            .first_or_octet_stream()
+            .essence_str()
+            .to_string()
+    }
+}
+
+/// How large attachments may be
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttachmentLimits {
+    /// Largest single file
+    pub max_file_bytes: u64,
+    /// Largest total across all files on one mail
+    pub max_mail_bytes: u64,
+}
+
+impl Default for AttachmentLimits {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: 10 * 1024 * 1024,
+            max_mail_bytes: 25 * 1024 * 1024,
+        }
+    }
+}
+
+impl AttachmentLimits {
+    /// Defaults, overridden in megabytes by `AGENT_OFFICE_MAX_ATTACHMENT_MB`
+    /// and `AGENT_OFFICE_MAX_MAIL_ATTACHMENTS_MB`
+    pub fn from_env() -> Self {
+        let megabytes = |var: &str| {
+            std::env::var(var)
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(|mb| mb * 1024 * 1024)
+        };
+        let defaults = Self::default();
+        Self {
+            max_file_bytes: megabytes("AGENT_OFFICE_MAX_ATTACHMENT_MB").unwrap_or(defaults.max_file_bytes),
+            max_mail_bytes: megabytes("AGENT_OFFICE_MAX_MAIL_ATTACHMENTS_MB").unwrap_or(defaults.max_mail_bytes),
+        }
+    }
+
+    /// Why these files can't be sent together, if they can't
+    pub fn check(&self, files: &[NewAttachment]) -> Result<(), String> {
+        let sizes: Vec<_> = files.iter().map(|f| (f.filename.as_str(), f.data.len() as u64)).collect();
+        self.check_sizes(&sizes)
+    }
+
+    /// `check` for files known only by name and size, so oversized ones
+    /// can be turned down before they are read
+    pub fn check_sizes(&self, files: &[(&str, u64)]) -> Result<(), String> {
+        for &(filename, size) in files {
+            if size > self.max_file_bytes {
+                return Err(format!(
+                    "{} is {} bytes, over the {} byte limit per attachment",
+                    filename, size, self.max_file_bytes
+                ));
+            }
+        }
+        let total: u64 = files.iter().map(|&(_, size)| size).sum();
+        if total > self.max_mail_bytes {
+            return Err(format!(
+                "attachments total {} bytes, over the {} byte limit per mail",
+                total, self.max_mail_bytes
+            ));
+        }
+        Ok(())
+    }
+}
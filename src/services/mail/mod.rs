@@ -1,11 +1,42 @@
-use crate::domain::{Edge, GraphQuery, Properties, string_to_node_id};
-use crate::services::mail::domain::{Agent, AgentId, Mail, Mailbox, MailboxId};
-use crate::storage::{GraphStorage, StorageError};
+use crate::domain::{Edge, GraphQuery, Node, Properties, PropertyValue, string_to_node_id};
+use crate::services::mail::domain::{
+    edge_seq, mark_delivery_read, set_delivery_folder, set_delivery_labels, Agent, AgentId, Attachment,
+    AttachmentLimits, AutoReply, AUTO_REPLY_COOLDOWN_HOURS, Delivery, Folder, Group, InboxFilter, Mail, MailRef, MailRule, MailSearch, Mailbox, MailboxId,
+    PayloadSchema, RetentionPolicy, RetentionReport, SendOptions, DEFAULT_CONTENT_TYPE,
+};
+use crate::domain::Timestamp;
+use crate::storage::blob::BlobLocation;
+use crate::storage::{EdgeDirection, GraphStorage, SearchQuery, StorageError, Transaction};
 use async_trait::async_trait;
 use thiserror::Error;
 
+pub mod archive;
 pub mod domain;
 
+use archive::{ArchivedMail, ImportReport};
+
+/// How many mails or deliveries a retention pass changes per transaction
+const RETENTION_BATCH: usize = 500;
+
+/// Node type marking delayed mail whose recipients' rules and
+/// out-of-office replies haven't run yet
+const ARRIVAL_MARKER: &str = "pending_arrival";
+
+fn arrival_marker_id(mail_id: uuid::Uuid) -> uuid::Uuid {
+    string_to_node_id(&format!("{}:{}", ARRIVAL_MARKER, mail_id))
+}
+
+fn arrival_marker(mail: &Mail) -> Node {
+    let mut props = Properties::new();
+    props.insert("mail_id".to_string(), PropertyValue::String(mail.id.to_string()));
+    if let Some(deliver_at) = mail.deliver_at {
+        props.insert("deliver_at".to_string(), PropertyValue::Timestamp(deliver_at));
+    }
+    let mut node = Node::new(ARRIVAL_MARKER, props);
+    node.id = arrival_marker_id(mail.id);
+    node
+}
+
 #[derive(Error, Debug)]
 pub enum MailError {
     #[error("Mailbox not found: {0}")]
@@ -25,6 +56,30 @@ pub enum MailError {
     
     #[error("Invalid agent name: {0}")]
     InvalidAgentName(String),
+    
+    #[error("Group not found: {0}")]
+    GroupNotFound(String),
+    
+    #[error("Invalid group name: {0}")]
+    InvalidGroupName(String),
+    
+    #[error("Invalid payload: {0}")]
+    InvalidPayload(String),
+    
+    #[error("Invalid schema: {0}")]
+    InvalidSchema(String),
+    
+    #[error("Attachment not found: {0}")]
+    AttachmentNotFound(String),
+    
+    #[error("Attachment too large: {0}")]
+    AttachmentTooLarge(String),
+    
+    #[error("Rule not found: {0}")]
+    RuleNotFound(String),
+    
+    #[error("Invalid rule: {0}")]
+    InvalidRule(String),
 }
 
 pub type Result<T> = std::result::Result<T, MailError>;
@@ -39,13 +94,46 @@ pub trait MailService: Send + Sync {
     async fn set_agent_status(&self, agent_id: AgentId, status: impl Into<String> + Send) -> Result<Agent>;
     async fn set_agent_session(&self, agent_id: AgentId, session_id: Option<String>) -> Result<Agent>;
     
+    // Set (or with `None` clear) the out-of-office reply sent while the
+    // agent is offline or away
+    async fn set_auto_reply(&self, agent_id: AgentId, auto_reply: Option<AutoReply>) -> Result<Agent>;
+    
     // Get agent by their mailbox ID (each agent has exactly one mailbox)
     async fn get_agent_by_mailbox(&self, mailbox_id: MailboxId) -> Result<Agent>;
     
     // Get the single mailbox for an agent (auto-creates if doesn't exist)
     async fn get_agent_mailbox(&self, agent_id: AgentId) -> Result<Mailbox>;
     
-    // Send mail from one agent to another
+    // Group operations; mail addressed to `@name` goes to every member
+    async fn create_group(&self, name: impl Into<String> + Send) -> Result<Group>;
+    async fn delete_group(&self, name: &str) -> Result<()>;
+    async fn list_groups(&self) -> Result<Vec<Group>>;
+    async fn add_group_member(&self, name: &str, agent_id: AgentId) -> Result<()>;
+    async fn remove_group_member(&self, name: &str, agent_id: AgentId) -> Result<()>;
+    async fn get_group_members(&self, name: &str) -> Result<Vec<Agent>>;
+    async fn get_agent_groups(&self, agent_id: AgentId) -> Result<Vec<Group>>;
+    
+    // Payload schemas; registering a content type again replaces its schema
+    async fn register_payload_schema(
+        &self,
+        content_type: impl Into<String> + Send,
+        schema: serde_json::Value,
+    ) -> Result<PayloadSchema>;
+    async fn remove_payload_schema(&self, content_type: &str) -> Result<()>;
+    async fn list_payload_schemas(&self) -> Result<Vec<PayloadSchema>>;
+    
+    // Filter rules an agent applies to mail delivered to it, oldest first
+    async fn add_mail_rule(&self, rule: MailRule) -> Result<MailRule>;
+    async fn update_mail_rule(&self, rule: MailRule) -> Result<MailRule>;
+    async fn remove_mail_rule(&self, rule_id: uuid::Uuid) -> Result<()>;
+    async fn list_mail_rules(&self, agent_id: AgentId) -> Result<Vec<MailRule>>;
+    
+    // Find a rule by short ID (8-char prefix) or full ID
+    async fn get_mail_rule_by_short_id(&self, short_id: &str) -> Result<MailRule>;
+    
+    // Send mail from one agent to another (shorthand for `send_mail` with a
+    // single recipient; the CLI and web go through `send_mail`)
+    #[allow(dead_code)]
     async fn send_agent_to_agent(
         &self,
         from_agent_id: AgentId,
@@ -54,32 +142,156 @@ pub trait MailService: Send + Sync {
         body: impl Into<String> + Send,
     ) -> Result<Mail>;
     
-    // Get mail received by an agent's mailbox
+    // Send one mail to several agents; each recipient gets its own delivery
+    // and read state. `@group` entries expand to the group's current members,
+    // leaving out the sender.
+    async fn send_mail(
+        &self,
+        from_agent_id: AgentId,
+        to_agent_ids: Vec<AgentId>,
+        cc_agent_ids: Vec<AgentId>,
+        subject: impl Into<String> + Send,
+        body: impl Into<String> + Send,
+    ) -> Result<Mail>;
+    
+    // `send_mail` with a priority, starting labels, and a structured payload
+    async fn send_mail_with(
+        &self,
+        from_agent_id: AgentId,
+        to_agent_ids: Vec<AgentId>,
+        cc_agent_ids: Vec<AgentId>,
+        subject: impl Into<String> + Send,
+        body: impl Into<String> + Send,
+        options: SendOptions,
+    ) -> Result<Mail>;
+    
+    // Reply to a mail; the reply goes to the other party and joins its thread.
+    // `from_agent_id` defaults to the original recipient when there is only one.
+    async fn reply_to_mail(
+        &self,
+        mail_id: uuid::Uuid,
+        from_agent_id: Option<AgentId>,
+        body: impl Into<String> + Send,
+    ) -> Result<Mail>;
+    
+    // Every mail in the conversation containing `mail_id`, oldest first
+    async fn get_thread(&self, mail_id: uuid::Uuid) -> Result<Vec<Mail>>;
+    
+    // Get mail received by an agent's mailbox, leaving out archived and
+    // trashed mail
     async fn get_mailbox_inbox(&self, mailbox_id: MailboxId) -> Result<Vec<Mail>>;
     
+    // Get received mail by folder, label, and priority
+    async fn get_mailbox_mail(&self, mailbox_id: MailboxId, filter: &InboxFilter) -> Result<Vec<Mail>>;
+    
+    // File a mail in a folder for one recipient
+    async fn move_mail(&self, mail_id: uuid::Uuid, agent_id: Option<AgentId>, folder: Folder) -> Result<Mail>;
+    
+    // Add and remove one recipient's labels on a mail
+    async fn label_mail(
+        &self,
+        mail_id: uuid::Uuid,
+        agent_id: Option<AgentId>,
+        add: Vec<String>,
+        remove: Vec<String>,
+    ) -> Result<Mail>;
+    
     // Get mail sent by an agent's mailbox
     async fn get_mailbox_outbox(&self, mailbox_id: MailboxId) -> Result<Vec<Mail>>;
     
     // Get recent mail for an agent (received in last N hours)
     async fn get_recent_mail(&self, mailbox_id: MailboxId, hours: i64, limit: usize) -> Result<Vec<Mail>>;
     
-    // Mark mail as read
-    async fn mark_mail_as_read(&self, mail_id: uuid::Uuid) -> Result<Mail>;
+    // Search an agent's mail, received (in any folder) and sent, best matches
+    // first where the storage backend ranks them
+    async fn search_mail(&self, agent_id: AgentId, search: &MailSearch, limit: usize) -> Result<Vec<Mail>>;
+    
+    // Withdraw delayed mail before its delivery time comes; only its
+    // sender `from_agent_id` may
+    async fn cancel_mail(&self, mail_id: uuid::Uuid, from_agent_id: AgentId) -> Result<Mail>;
     
-    // Mark mail as read by short ID (8-char prefix) - searches all mail system-wide
-    async fn mark_mail_as_read_by_short_id(&self, short_id: &str) -> Result<Mail>;
+    // Everything an agent has received and sent, oldest first, with each
+    // mailbox named by agent ID; delayed mail that hasn't gone out is left out
+    async fn export_mail(&self, agent_id: AgentId) -> Result<Vec<ArchivedMail>>;
     
-    // Check if agent has unread mail
-    async fn check_unread_mail(&self, agent_id: AgentId) -> Result<(bool, Vec<Mail>)>;
+    // Put archived mail into an agent's mailbox as it was, without running
+    // rules or auto-replies. Mail the agent already has (by message ID) is
+    // skipped, so importing an archive twice changes nothing.
+    async fn import_mail(&self, agent_id: AgentId, mails: Vec<ArchivedMail>) -> Result<ImportReport>;
+    
+    // When the next delayed mail for an agent is due, if any is waiting
+    async fn next_scheduled_delivery(&self, agent_id: AgentId) -> Result<Option<Timestamp>>;
+    
+    // Mark mail as read for one recipient. `agent_id` may be omitted when
+    // the mail has a single recipient. `marked_by` records who did it when
+    // that isn't the recipient themselves (e.g. "web").
+    async fn mark_mail_as_read(
+        &self,
+        mail_id: uuid::Uuid,
+        agent_id: Option<AgentId>,
+        marked_by: Option<String>,
+    ) -> Result<Mail>;
+    
+    // Delivery and read receipts for each recipient, in address order
+    async fn get_deliveries(&self, mail_id: uuid::Uuid) -> Result<Vec<Delivery>>;
+    
+    // Find mail by its number in a mailbox ("alice#42", or "#42" given
+    // `agent_id`) or by ID prefix. Prefixes only match mail in the agent's
+    // own mailbox, and the mail comes back as that mailbox sees it; without
+    // an agent only a full mail ID will do.
+    async fn resolve_mail(&self, reference: &str, agent_id: Option<AgentId>) -> Result<Mail>;
+    
+    // Mark mail as read by number or short ID, resolved as in `resolve_mail`;
+    // "alice#42" marks it read for alice
+    async fn mark_mail_as_read_by_short_id(
+        &self,
+        short_id: &str,
+        agent_id: Option<AgentId>,
+        marked_by: Option<String>,
+    ) -> Result<Mail>;
+    
+    // Files attached to a mail, in the order they were attached
+    async fn get_attachments(&self, mail_id: uuid::Uuid) -> Result<Vec<Attachment>>;
+    
+    // Find an attachment by short ID (8-char prefix) or full ID
+    async fn get_attachment_by_short_id(&self, short_id: &str) -> Result<Attachment>;
+    
+    // An attachment together with its contents
+    async fn read_attachment(&self, attachment_id: uuid::Uuid) -> Result<(Attachment, Vec<u8>)>;
+    
+    // Archive and delete old mail according to `policy`, as of `now`
+    async fn apply_retention(&self, policy: &RetentionPolicy, now: Timestamp) -> Result<RetentionReport>;
+    
+    // Run recipients' rules and out-of-office replies for delayed mail due
+    // by `now`. Reading mail never does this; the agent runner and the gc
+    // tick call it. Returns how many mails arrived.
+    async fn arrive_due_mail(&self, now: Timestamp) -> Result<usize>;
+    
+    // Check if agent has unread mail, urgent first. Trashed mail never
+    // counts; archived mail only with `include_archived`.
+    async fn check_unread_mail(&self, agent_id: AgentId, include_archived: bool) -> Result<(bool, Vec<Mail>)>;
 }
 
 pub struct MailServiceImpl<S: GraphStorage> {
     storage: S,
+    blobs: BlobLocation,
+    attachment_limits: AttachmentLimits,
 }
 
 impl<S: GraphStorage> MailServiceImpl<S> {
     pub fn new(storage: S) -> Self {
-        Self { storage }
+        Self {
+            storage,
+            blobs: BlobLocation::default(),
+            attachment_limits: AttachmentLimits::default(),
+        }
+    }
+
+    /// Keep attachment contents in `blobs` and enforce `limits` when sending
+    pub fn with_attachments(mut self, blobs: BlobLocation, limits: AttachmentLimits) -> Self {
+        self.blobs = blobs;
+        self.attachment_limits = limits;
+        self
     }
 
     /// Helper to get mail by ID
@@ -92,6 +304,392 @@ impl<S: GraphStorage> MailServiceImpl<S> {
         Mail::from_node(&node)
             .ok_or(MailError::MailNotFound(mail_id))
     }
+
+    /// Write a mail node with its sent_from edge and one sent_to edge per
+    /// recipient, plus a reply_to edge to the parent for replies and its
+    /// attachment nodes (whose blobs must already be stored). Each
+    /// recipient's rules run on their delivery first, and any forwards they
+    /// ask for are delivered the same way in turn.
+    async fn deliver(&self, mail: Mail, attachments: &[Attachment]) -> Result<Mail> {
+        let mut sent = mail.clone();
+        let mut queue = vec![(mail, attachments.to_vec())];
+        while let Some((mail, attachments)) = queue.pop() {
+            let mut deliveries = mail.delivery_edges();
+            // Delayed mail meets its recipients' rules and out-of-office
+            // replies when it arrives, not when it is sent
+            let pending = mail.is_pending(chrono::Utc::now());
+            let (forwards, replies) = if pending {
+                (Vec::new(), Vec::new())
+            } else {
+                self.on_arrival(&mail, &mut deliveries).await?
+            };
+            
+            // Number the mail in the sender's mailbox and in each recipient's.
+            // A failed commit leaves a gap in the numbering, never a repeat.
+            let from_seq = self.storage.next_sequence(&mailbox_sequence(mail.from_mailbox_id)).await?;
+            for edge in &mut deliveries {
+                let seq = self.storage.next_sequence(&mailbox_sequence(edge.to_node_id)).await?;
+                edge.properties.insert("seq".to_string(), PropertyValue::Integer(seq));
+            }
+            if mail.id == sent.id {
+                sent.seq = Some(from_seq);
+            }
+            
+            let node = mail.to_node();
+            let mut from_props = Properties::new();
+            from_props.insert("seq".to_string(), PropertyValue::Integer(from_seq));
+            let from_edge = Edge::new("sent_from", mail.from_mailbox_id, mail.id, from_props);
+            
+            // Write the mail node and its edges atomically so a failure can't
+            // leave mail that reached only some of its recipients
+            let mut tx = self.storage.begin();
+            tx.create_node(&node).create_edge(&from_edge);
+            for edge in &deliveries {
+                tx.create_edge(edge);
+            }
+            if pending {
+                tx.create_node(&arrival_marker(&mail));
+            }
+            if let Some(parent_id) = mail.in_reply_to {
+                tx.create_edge(&Edge::new("reply_to", mail.id, parent_id, Properties::new()));
+            }
+            for (position, attachment) in attachments.iter().enumerate() {
+                let mut props = Properties::new();
+                props.insert("position".to_string(), PropertyValue::Integer(position as i64));
+                tx.create_node(&attachment.to_node())
+                    .create_edge(&Edge::new("has_attachment", mail.id, attachment.id, props));
+            }
+            self.storage.commit(tx).await?;
+            
+            queue.extend(Self::follow_ups(forwards, replies, &attachments));
+        }
+        
+        Ok(sent)
+    }
+
+    /// What recipients' rules and out-of-office settings make of `mail` as
+    /// it reaches them: updates their sent_to edges, and returns the
+    /// forwards and auto-replies to send on
+    async fn on_arrival(&self, mail: &Mail, deliveries: &mut [Edge]) -> Result<(Vec<Mail>, Vec<Mail>)> {
+        let mut forwards = self.apply_rules(mail, deliveries).await?;
+        let (replies, handoffs) = self.out_of_office(mail).await?;
+        forwards.extend(handoffs);
+        Ok((forwards, replies))
+    }
+
+    /// Forwards and auto-replies, ready for `deliver`. Forwards share the
+    /// attachments' blobs.
+    fn follow_ups(forwards: Vec<Mail>, replies: Vec<Mail>, attachments: &[Attachment]) -> Vec<(Mail, Vec<Attachment>)> {
+        let mut follow_ups = Vec::new();
+        for forward in forwards {
+            let copies = attachments.iter()
+                .map(|a| Attachment { id: uuid::Uuid::new_v4(), mail_id: forward.id, ..a.clone() })
+                .collect();
+            follow_ups.push((forward, copies));
+        }
+        for reply in replies {
+            follow_ups.push((reply, Vec::new()));
+        }
+        follow_ups
+    }
+
+    /// Auto-replies and delegate forwards for recipients who are away.
+    /// Auto-replies are never answered automatically, forwarded mail isn't
+    /// answered at all, and each sender hears from an agent at most once per
+    /// cooldown, so away agents can't keep each other busy.
+    async fn out_of_office(&self, mail: &Mail) -> Result<(Vec<Mail>, Vec<Mail>)> {
+        let mut replies = Vec::new();
+        let mut forwards = Vec::new();
+        if mail.auto_reply {
+            return Ok((replies, forwards));
+        }
+        
+        for (recipient, _) in mail.recipients() {
+            if recipient == mail.from_mailbox_id {
+                continue;
+            }
+            let Ok(agent) = self.get_agent_by_mailbox(recipient).await else {
+                continue;
+            };
+            let Some(auto_reply) = agent.auto_reply.as_ref().filter(|_| agent.is_away()) else {
+                continue;
+            };
+            
+            let mut delegated = None;
+            if let Some(ref delegate) = auto_reply.delegate {
+                let to = string_to_node_id(delegate);
+                if mail.can_forward(recipient, to) && self.get_agent(delegate.clone()).await.is_ok() {
+                    let sender = self.mailbox_name(mail.from_mailbox_id).await;
+                    forwards.push(mail.forward(recipient, to, &sender));
+                    delegated = Some(delegate.as_str());
+                }
+            }
+            if mail.forward_chain.is_empty() && !self.auto_replied_recently(recipient, mail.from_mailbox_id).await? {
+                replies.push(mail.auto_reply_from(recipient, &auto_reply.message, delegated));
+            }
+        }
+        Ok((replies, forwards))
+    }
+
+    /// Whether `from` has auto-replied to `to` within the cooldown
+    async fn auto_replied_recently(&self, from: MailboxId, to: MailboxId) -> Result<bool> {
+        let since = chrono::Utc::now() - chrono::Duration::hours(AUTO_REPLY_COOLDOWN_HOURS);
+        // Only what `from` sent within the cooldown is looked at, however
+        // long the two have been writing to each other
+        let mut query = SearchQuery {
+            node_types: vec!["mail".to_string()],
+            created_after: Some(since),
+            property_filters: vec![("from_mailbox_id".to_string(), from.to_string())],
+            limit: 100,
+            ..SearchQuery::default()
+        };
+        loop {
+            let batch = self.storage.search_nodes(&query).await?.items;
+            if batch.iter()
+                .filter_map(Mail::from_node)
+                .any(|mail| mail.auto_reply && mail.to_mailbox_ids.contains(&to))
+            {
+                return Ok(true);
+            }
+            if batch.len() < query.limit {
+                return Ok(false);
+            }
+            query.offset += query.limit;
+        }
+    }
+
+    /// Agent id owning a mailbox, for quoting senders in forwards
+    async fn mailbox_name(&self, mailbox_id: MailboxId) -> String {
+        self.get_agent_by_mailbox(mailbox_id).await
+            .map(|a| a.id)
+            .unwrap_or_else(|_| "unknown".to_string())
+    }
+
+    /// Run each recipient's enabled rules against `mail`, updating their
+    /// sent_to edge (labels, folder, read state) before it is written.
+    /// Returns the forwards the rules asked for.
+    async fn apply_rules(&self, mail: &Mail, deliveries: &mut [Edge]) -> Result<Vec<Mail>> {
+        let mut forwards = Vec::new();
+        for edge in deliveries.iter_mut() {
+            let recipient = edge.to_node_id;
+            let rules = self.rules_of(recipient).await?;
+            let mut forwarded_to = std::collections::HashSet::new();
+            for rule in rules.iter().filter(|r| r.enabled && r.matches(mail)) {
+                if !rule.add_labels.is_empty() {
+                    let mut labels = Delivery::from_edge(edge).map(|d| d.labels).unwrap_or_default();
+                    for label in &rule.add_labels {
+                        if !labels.contains(label) {
+                            labels.push(label.clone());
+                        }
+                    }
+                    set_delivery_labels(edge, &labels);
+                }
+                if let Some(folder) = rule.move_to {
+                    set_delivery_folder(edge, folder);
+                }
+                if rule.mark_read {
+                    mark_delivery_read(edge, &format!("rule:{}", rule.name));
+                }
+                for agent_id in &rule.forward_to {
+                    let to = string_to_node_id(agent_id);
+                    // Targets may have been deleted since the rule was made
+                    if !mail.can_forward(recipient, to) || !forwarded_to.insert(to) || self.get_agent(agent_id.clone()).await.is_err() {
+                        continue;
+                    }
+                    let sender = self.mailbox_name(mail.from_mailbox_id).await;
+                    forwards.push(mail.forward(recipient, to, &sender));
+                }
+            }
+        }
+        Ok(forwards)
+    }
+
+    /// Rules belonging to the agent with this mailbox, oldest first
+    async fn rules_of(&self, mailbox_id: MailboxId) -> Result<Vec<MailRule>> {
+        let edges = self.storage.get_edges_from(mailbox_id, Some("has_rule")).await?;
+        let ids: Vec<uuid::Uuid> = edges.iter().map(|e| e.to_node_id).collect();
+        let mut rules: Vec<MailRule> = self.storage.get_nodes(&ids).await?
+            .iter()
+            .filter_map(MailRule::from_node)
+            .collect();
+        rules.sort_by_key(|r| r.created_at);
+        Ok(rules)
+    }
+
+    /// Reject rules that wouldn't do anything or would forward nowhere
+    async fn check_rule(&self, rule: &MailRule) -> Result<()> {
+        if rule.name.trim().is_empty() {
+            return Err(MailError::InvalidRule("a rule needs a name".to_string()));
+        }
+        if !rule.has_actions() {
+            return Err(MailError::InvalidRule(format!("rule '{}' has no actions", rule.name)));
+        }
+        for agent_id in &rule.forward_to {
+            if *agent_id == rule.agent_id {
+                return Err(MailError::InvalidRule("an agent can't forward mail to itself".to_string()));
+            }
+            self.get_agent(agent_id.clone()).await?;
+        }
+        Ok(())
+    }
+
+    /// Stage deleting a mail along with its attachment nodes. Returns the
+    /// attachments' blob hashes, to hand to `release_blobs` once committed.
+    async fn stage_mail_deletion(&self, tx: &mut Transaction, mail_id: uuid::Uuid) -> Result<Vec<String>> {
+        let attachments = self.get_attachments(mail_id).await?;
+        for attachment in &attachments {
+            tx.delete_node(attachment.id);
+        }
+        tx.delete_node(mail_id);
+        let marker = arrival_marker_id(mail_id);
+        if self.storage.get_node(marker).await.is_ok() {
+            tx.delete_node(marker);
+        }
+        Ok(attachments.into_iter().map(|a| a.sha256).collect())
+    }
+
+    /// Delete blobs no remaining attachment refers to
+    async fn release_blobs(&self, hashes: Vec<String>) -> Result<()> {
+        let mut seen = std::collections::HashSet::new();
+        for hash in hashes {
+            if !seen.insert(hash.clone()) {
+                continue;
+            }
+            let query = GraphQuery::new()
+                .with_node_type("attachment")
+                .with_property("sha256", PropertyValue::String(hash.clone()));
+            if self.storage.query_nodes(&query).await?.is_empty() {
+                self.blobs.delete(&self.storage, &hash).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// When a mail last reached a mailbox here, or will for delayed mail;
+    /// imported mail counts from when it was imported, not its original date
+    async fn last_arrival(&self, node: &Node) -> Result<Timestamp> {
+        let edges = self.storage.get_edges_from(node.id, Some("sent_to")).await?;
+        let delivered = edges.iter()
+            .filter_map(Delivery::from_edge)
+            .map(|delivery| delivery.delivered_at)
+            .max()
+            .unwrap_or(node.created_at);
+        let imported = match node.get_property("imported_at") {
+            Some(PropertyValue::Timestamp(t)) => Some(*t),
+            _ => None,
+        };
+        Ok(delivered.max(imported.unwrap_or(delivered)))
+    }
+
+    async fn get_group(&self, name: &str) -> Result<Group> {
+        let node = self.storage.get_node(Group::node_id(name)).await
+            .map_err(|e| match e {
+                StorageError::NodeNotFound(_) => MailError::GroupNotFound(name.to_string()),
+                _ => MailError::Storage(e),
+            })?;
+        Group::from_node(&node)
+            .ok_or_else(|| MailError::GroupNotFound(name.to_string()))
+    }
+
+    /// The member_of edge tying an agent to a group, if they are a member
+    async fn membership(&self, name: &str, agent_id: &AgentId) -> Result<Option<Edge>> {
+        let group_node_id = Group::node_id(name);
+        let edges = self.storage.get_edges_from(string_to_node_id(agent_id), Some("member_of")).await?;
+        Ok(edges.into_iter().find(|edge| edge.to_node_id == group_node_id))
+    }
+
+    /// Replace `@group` recipients with the group's members, minus the sender
+    async fn expand_groups(&self, from_agent_id: &AgentId, agent_ids: Vec<AgentId>) -> Result<Vec<AgentId>> {
+        let mut expanded = Vec::with_capacity(agent_ids.len());
+        for agent_id in agent_ids {
+            match agent_id.strip_prefix('@') {
+                Some(group) => {
+                    let members = self.get_group_members(group).await?;
+                    expanded.extend(members.into_iter().map(|a| a.id).filter(|id| id != from_agent_id));
+                }
+                None => expanded.push(agent_id),
+            }
+        }
+        Ok(expanded)
+    }
+
+    async fn get_payload_schema(&self, content_type: &str) -> Result<Option<PayloadSchema>> {
+        match self.storage.get_node(PayloadSchema::node_id(content_type)).await {
+            Ok(node) => Ok(PayloadSchema::from_node(&node)),
+            Err(StorageError::NodeNotFound(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Check a payload before sending: it must be a JSON object and match
+    /// the schema registered for its content type, if any
+    async fn check_payload(&self, content_type: &str, payload: &serde_json::Value) -> Result<()> {
+        if !payload.is_object() {
+            return Err(MailError::InvalidPayload("payload must be a JSON object".to_string()));
+        }
+        if let Some(schema) = self.get_payload_schema(content_type).await? {
+            schema.validate(payload).map_err(|errors| MailError::InvalidPayload(
+                format!("does not match the '{}' schema: {}", content_type, errors.join("; "))
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Mailbox ids for a list of agents, failing on the first unknown agent
+    async fn resolve_mailboxes(&self, agent_ids: Vec<AgentId>) -> Result<Vec<MailboxId>> {
+        let mut mailbox_ids = Vec::with_capacity(agent_ids.len());
+        for agent_id in agent_ids {
+            let agent = self.get_agent(agent_id).await?;
+            mailbox_ids.push(string_to_node_id(&agent.id));
+        }
+        Ok(mailbox_ids)
+    }
+
+    /// This recipient's delivery of every mail that has reached a mailbox;
+    /// delayed mail whose time hasn't come is left out
+    async fn deliveries_to(&self, mailbox_id: MailboxId) -> Result<std::collections::HashMap<uuid::Uuid, Delivery>> {
+        let now = chrono::Utc::now();
+        let edges = self.storage.get_edges_to(mailbox_id, Some("sent_to")).await?;
+        Ok(edges.iter()
+            .filter_map(|edge| Some((edge.from_node_id, Delivery::from_edge(edge)?)))
+            .filter(|(_, delivery)| !delivery.is_pending(now))
+            .collect())
+    }
+
+    /// The sent_to edge for one recipient of a mail. `agent_id` may be
+    /// omitted when the mail has a single recipient. Delayed mail can't be
+    /// touched by its recipients until it has been delivered.
+    async fn recipient_edge(&self, mail: &Mail, agent_id: Option<AgentId>) -> Result<Edge> {
+        if mail.is_pending(chrono::Utc::now()) {
+            return Err(MailError::InvalidOperation(
+                "Mail hasn't been delivered yet".to_string()
+            ));
+        }
+        let mailbox_id = match agent_id {
+            Some(agent_id) => string_to_node_id(&self.get_agent(agent_id).await?.id),
+            None => {
+                let recipients: Vec<_> = mail.recipients().collect();
+                match recipients.as_slice() {
+                    [(only, _)] => *only,
+                    _ => return Err(MailError::InvalidOperation(
+                        "Mail has several recipients, say which agent it is for".to_string()
+                    )),
+                }
+            }
+        };
+        
+        self.storage.get_edges_from(mail.id, Some("sent_to")).await?
+            .into_iter()
+            .find(|e| e.to_node_id == mailbox_id)
+            .ok_or_else(|| MailError::InvalidOperation(
+                "That agent is not a recipient of this mail".to_string()
+            ))
+    }
+}
+
+/// Name of the counter numbering a mailbox's mail
+fn mailbox_sequence(mailbox_id: MailboxId) -> String {
+    format!("mailbox:{}", mailbox_id)
 }
 
 #[async_trait]
@@ -119,16 +717,27 @@ impl<S: GraphStorage> MailService for MailServiceImpl<S> {
         let agent_node_id = string_to_node_id(&agent.id);
         
         // Get all mail in inbox and outbox to clear them
-        let inbox = self.get_mailbox_inbox(agent_node_id).await?;
+        let inbox = self.get_mailbox_mail(agent_node_id, &InboxFilter::default()).await?;
         let outbox = self.get_mailbox_outbox(agent_node_id).await?;
         
-        // Delete all mail nodes
-        for mail in inbox.iter().chain(outbox.iter()) {
-            let _ = self.storage.delete_node(mail.id).await;
+        // Delete the agent's sent mail, and received mail nobody else got,
+        // together with the agent node. Mail sent to oneself shows up in
+        // both boxes, so only delete each node once.
+        let mut tx = self.storage.begin();
+        let mut seen = std::collections::HashSet::new();
+        let mut blobs = Vec::new();
+        let only_recipient = |mail: &Mail| mail.recipients().all(|(id, _)| id == agent_node_id);
+        for mail in outbox.iter().chain(inbox.iter().filter(|m| only_recipient(m))) {
+            if seen.insert(mail.id) {
+                blobs.extend(self.stage_mail_deletion(&mut tx, mail.id).await?);
+            }
         }
-        
-        // Finally, delete the agent node
-        self.storage.delete_node(agent_node_id).await?;
+        for rule in self.rules_of(agent_node_id).await? {
+            tx.delete_node(rule.id);
+        }
+        tx.delete_node(agent_node_id);
+        self.storage.commit(tx).await?;
+        self.release_blobs(blobs).await?;
         
         Ok(())
     }
@@ -170,6 +779,24 @@ impl<S: GraphStorage> MailService for MailServiceImpl<S> {
         Ok(agent)
     }
 
+    async fn set_auto_reply(&self, agent_id: AgentId, auto_reply: Option<AutoReply>) -> Result<Agent> {
+        let mut agent = self.get_agent(agent_id).await?;
+        if let Some(ref auto_reply) = auto_reply {
+            if auto_reply.message.trim().is_empty() {
+                return Err(MailError::InvalidOperation("An auto-reply needs a message".to_string()));
+            }
+            if let Some(ref delegate) = auto_reply.delegate {
+                if *delegate == agent.id {
+                    return Err(MailError::InvalidOperation("An agent can't delegate to itself".to_string()));
+                }
+                self.get_agent(delegate.clone()).await?;
+            }
+        }
+        agent.auto_reply = auto_reply;
+        self.storage.update_node(&agent.to_node()).await?;
+        Ok(agent)
+    }
+
     async fn get_agent_by_mailbox(&self, mailbox_id: MailboxId) -> Result<Agent> {
         // The mailbox ID is the agent's node ID, so get the agent directly
         let node = self.storage.get_node(mailbox_id).await
@@ -200,6 +827,197 @@ impl<S: GraphStorage> MailService for MailServiceImpl<S> {
         Ok(mailbox)
     }
 
+    async fn create_group(&self, name: impl Into<String> + Send) -> Result<Group> {
+        let name = name.into();
+        
+        // Same rules as agent names, and no characters used when addressing
+        if name.is_empty() || name.chars().any(|c| c.is_uppercase() || c.is_whitespace() || c == '@' || c == ',') {
+            return Err(MailError::InvalidGroupName(
+                format!("Group name '{}' is invalid. Names must be lowercase with no spaces, '@' or ',' (e.g., 'reviewers').", name)
+            ));
+        }
+        if self.get_group(&name).await.is_ok() {
+            return Err(MailError::InvalidOperation(format!("Group '{}' already exists", name)));
+        }
+        
+        let group = Group::new(name);
+        self.storage.create_node(&group.to_node()).await?;
+        Ok(group)
+    }
+
+    async fn delete_group(&self, name: &str) -> Result<()> {
+        self.get_group(name).await?;
+        // Memberships go with the node
+        self.storage.delete_node(Group::node_id(name)).await?;
+        Ok(())
+    }
+
+    async fn list_groups(&self) -> Result<Vec<Group>> {
+        let query = GraphQuery::new().with_node_type("group");
+        let nodes = self.storage.query_nodes(&query).await?;
+        let mut groups: Vec<Group> = nodes.iter()
+            .filter_map(Group::from_node)
+            .collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(groups)
+    }
+
+    async fn register_payload_schema(
+        &self,
+        content_type: impl Into<String> + Send,
+        schema: serde_json::Value,
+    ) -> Result<PayloadSchema> {
+        let content_type = content_type.into();
+        if content_type.is_empty() || content_type.chars().any(char::is_whitespace) {
+            return Err(MailError::InvalidSchema(
+                format!("Content type '{}' is invalid. It must be non-empty with no spaces.", content_type)
+            ));
+        }
+        if content_type == DEFAULT_CONTENT_TYPE {
+            return Err(MailError::InvalidSchema(
+                format!("'{}' is for untyped payloads and can't have a schema", DEFAULT_CONTENT_TYPE)
+            ));
+        }
+        
+        let mut schema = PayloadSchema::new(content_type, schema).map_err(MailError::InvalidSchema)?;
+        match self.get_payload_schema(&schema.content_type).await? {
+            Some(existing) => {
+                schema.created_at = existing.created_at;
+                self.storage.update_node(&schema.to_node()).await?;
+            }
+            None => {
+                self.storage.create_node(&schema.to_node()).await?;
+            }
+        }
+        Ok(schema)
+    }
+
+    async fn remove_payload_schema(&self, content_type: &str) -> Result<()> {
+        if self.get_payload_schema(content_type).await?.is_none() {
+            return Err(MailError::InvalidOperation(format!("No schema registered for '{}'", content_type)));
+        }
+        self.storage.delete_node(PayloadSchema::node_id(content_type)).await?;
+        Ok(())
+    }
+
+    async fn list_payload_schemas(&self) -> Result<Vec<PayloadSchema>> {
+        let query = GraphQuery::new().with_node_type("payload_schema");
+        let nodes = self.storage.query_nodes(&query).await?;
+        let mut schemas: Vec<PayloadSchema> = nodes.iter()
+            .filter_map(PayloadSchema::from_node)
+            .collect();
+        schemas.sort_by(|a, b| a.content_type.cmp(&b.content_type));
+        Ok(schemas)
+    }
+
+    async fn add_mail_rule(&self, rule: MailRule) -> Result<MailRule> {
+        let agent = self.get_agent(rule.agent_id.clone()).await?;
+        self.check_rule(&rule).await?;
+        
+        let edge = Edge::new("has_rule", string_to_node_id(&agent.id), rule.id, Properties::new());
+        let mut tx = self.storage.begin();
+        tx.create_node(&rule.to_node()).create_edge(&edge);
+        self.storage.commit(tx).await?;
+        Ok(rule)
+    }
+
+    async fn update_mail_rule(&self, mut rule: MailRule) -> Result<MailRule> {
+        let existing = self.get_mail_rule_by_short_id(&rule.id.to_string()).await?;
+        if existing.agent_id != rule.agent_id {
+            return Err(MailError::InvalidRule("a rule can't move to another agent".to_string()));
+        }
+        self.check_rule(&rule).await?;
+        
+        rule.updated_at = chrono::Utc::now();
+        self.storage.update_node(&rule.to_node()).await?;
+        Ok(rule)
+    }
+
+    async fn remove_mail_rule(&self, rule_id: uuid::Uuid) -> Result<()> {
+        let rule = self.get_mail_rule_by_short_id(&rule_id.to_string()).await?;
+        self.storage.delete_node(rule.id).await?;
+        Ok(())
+    }
+
+    async fn list_mail_rules(&self, agent_id: AgentId) -> Result<Vec<MailRule>> {
+        let agent = self.get_agent(agent_id).await?;
+        self.rules_of(string_to_node_id(&agent.id)).await
+    }
+
+    async fn get_mail_rule_by_short_id(&self, short_id: &str) -> Result<MailRule> {
+        // Two hits are enough to know the prefix is ambiguous
+        let query = SearchQuery {
+            node_types: vec!["mail_rule".to_string()],
+            id_prefix: Some(short_id.to_string()),
+            limit: 2,
+            ..SearchQuery::default()
+        };
+        let mut matching: Vec<_> = self.storage.search_nodes(&query).await?
+            .items.iter()
+            .filter_map(MailRule::from_node)
+            .collect();
+        
+        match matching.len() {
+            0 => Err(MailError::RuleNotFound(short_id.to_string())),
+            1 => Ok(matching.remove(0)),
+            _ => Err(MailError::InvalidOperation(
+                format!("Multiple rules match short ID '{}', please use full ID", short_id)
+            )),
+        }
+    }
+
+    async fn add_group_member(&self, name: &str, agent_id: AgentId) -> Result<()> {
+        self.get_group(name).await?;
+        let agent = self.get_agent(agent_id).await?;
+        
+        // Adding an existing member is a no-op
+        if self.membership(name, &agent.id).await?.is_some() {
+            return Ok(());
+        }
+        let edge = Edge::new(
+            "member_of",
+            string_to_node_id(&agent.id),
+            Group::node_id(name),
+            Properties::new(),
+        );
+        self.storage.create_edge(&edge).await?;
+        Ok(())
+    }
+
+    async fn remove_group_member(&self, name: &str, agent_id: AgentId) -> Result<()> {
+        self.get_group(name).await?;
+        let edge = self.membership(name, &agent_id).await?
+            .ok_or_else(|| MailError::InvalidOperation(
+                format!("Agent '{}' is not a member of group '{}'", agent_id, name)
+            ))?;
+        self.storage.delete_edge(edge.id).await?;
+        Ok(())
+    }
+
+    async fn get_group_members(&self, name: &str) -> Result<Vec<Agent>> {
+        self.get_group(name).await?;
+        let nodes = self.storage
+            .get_neighbors(Group::node_id(name), Some("member_of"), EdgeDirection::Incoming)
+            .await?;
+        let mut members: Vec<Agent> = nodes.iter()
+            .filter_map(Agent::from_node)
+            .collect();
+        members.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(members)
+    }
+
+    async fn get_agent_groups(&self, agent_id: AgentId) -> Result<Vec<Group>> {
+        let agent = self.get_agent(agent_id).await?;
+        let nodes = self.storage
+            .get_neighbors(string_to_node_id(&agent.id), Some("member_of"), EdgeDirection::Outgoing)
+            .await?;
+        let mut groups: Vec<Group> = nodes.iter()
+            .filter_map(Group::from_node)
+            .collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(groups)
+    }
+
     async fn send_agent_to_agent(
         &self,
         from_agent_id: AgentId,
@@ -207,42 +1025,134 @@ impl<S: GraphStorage> MailService for MailServiceImpl<S> {
         subject: impl Into<String> + Send,
         body: impl Into<String> + Send,
     ) -> Result<Mail> {
-        // Verify both agents exist
+        self.send_mail(from_agent_id, vec![to_agent_id], Vec::new(), subject, body).await
+    }
+
+    async fn send_mail(
+        &self,
+        from_agent_id: AgentId,
+        to_agent_ids: Vec<AgentId>,
+        cc_agent_ids: Vec<AgentId>,
+        subject: impl Into<String> + Send,
+        body: impl Into<String> + Send,
+    ) -> Result<Mail> {
+        self.send_mail_with(from_agent_id, to_agent_ids, cc_agent_ids, subject, body, SendOptions::default()).await
+    }
+
+    async fn send_mail_with(
+        &self,
+        from_agent_id: AgentId,
+        to_agent_ids: Vec<AgentId>,
+        cc_agent_ids: Vec<AgentId>,
+        subject: impl Into<String> + Send,
+        body: impl Into<String> + Send,
+        options: SendOptions,
+    ) -> Result<Mail> {
+        // Verify every agent exists; agent node IDs double as mailbox IDs
         let from_agent = self.get_agent(from_agent_id).await?;
-        let to_agent = self.get_agent(to_agent_id).await?;
+        let to_agent_ids = self.expand_groups(&from_agent.id, to_agent_ids).await?;
+        let cc_agent_ids = self.expand_groups(&from_agent.id, cc_agent_ids).await?;
+        if to_agent_ids.is_empty() {
+            return Err(MailError::InvalidOperation("Mail needs at least one recipient".to_string()));
+        }
+        
+        self.attachment_limits.check(&options.attachments).map_err(MailError::AttachmentTooLarge)?;
         
-        // Use agent node IDs as mailbox IDs
         let from_mailbox_id = string_to_node_id(&from_agent.id);
-        let to_mailbox_id = string_to_node_id(&to_agent.id);
+        let to_mailbox_ids = self.resolve_mailboxes(to_agent_ids).await?;
+        let cc_mailbox_ids = self.resolve_mailboxes(cc_agent_ids).await?;
+        
+        let mut mail = Mail::new(from_mailbox_id, to_mailbox_ids, cc_mailbox_ids, subject, body)
+            .with_priority(options.priority)
+            .with_labels(options.labels);
+        if let Some(deliver_at) = options.deliver_at {
+            if deliver_at <= mail.created_at {
+                return Err(MailError::InvalidOperation(format!(
+                    "Delivery time {} is in the past", deliver_at.format("%Y-%m-%d %H:%M")
+                )));
+            }
+            mail = mail.deliver_at(deliver_at);
+        }
+        match (options.payload, options.content_type) {
+            (Some(payload), content_type) => {
+                let content_type = content_type.unwrap_or_else(|| DEFAULT_CONTENT_TYPE.to_string());
+                self.check_payload(&content_type, &payload).await?;
+                mail = mail.with_payload(content_type, payload);
+            }
+            (None, Some(_)) => {
+                return Err(MailError::InvalidPayload("content type given without a payload".to_string()));
+            }
+            (None, None) => {}
+        }
         
-        // Create mail
-        let mail = Mail::new(from_mailbox_id, to_mailbox_id, subject, body);
-        let node = mail.to_node();
+        // Blobs go in first; identical files are only stored once
+        let mut attachments = Vec::with_capacity(options.attachments.len());
+        for file in &options.attachments {
+            let hash = self.blobs.put(&self.storage, &file.data).await?;
+            attachments.push(Attachment::new(mail.id, file, hash));
+        }
+        self.deliver(mail, &attachments).await
+    }
+
+    async fn reply_to_mail(
+        &self,
+        mail_id: uuid::Uuid,
+        from_agent_id: Option<AgentId>,
+        body: impl Into<String> + Send,
+    ) -> Result<Mail> {
+        let parent = self.get_mail(mail_id).await?;
+        
+        let from_mailbox_id = match from_agent_id {
+            Some(agent_id) => string_to_node_id(&self.get_agent(agent_id).await?.id),
+            None => match parent.to_mailbox_ids.as_slice() {
+                [only] => *only,
+                _ => return Err(MailError::InvalidOperation(
+                    "Mail has several recipients, say which agent is replying".to_string()
+                )),
+            },
+        };
+        // Answer the other side of the conversation; a sender following up
+        // on their own mail writes to the original recipients again
+        let to_mailbox_ids = if from_mailbox_id == parent.from_mailbox_id {
+            parent.to_mailbox_ids.clone()
+        } else {
+            vec![parent.from_mailbox_id]
+        };
         
-        // Create mail node
-        self.storage.create_node(&node).await?;
+        let mail = Mail::new(from_mailbox_id, to_mailbox_ids, Vec::new(), parent.reply_subject(), body)
+            .in_reply_to(&parent);
+        self.deliver(mail, &[]).await
+    }
+
+    async fn get_thread(&self, mail_id: uuid::Uuid) -> Result<Vec<Mail>> {
+        let mail = self.get_mail(mail_id).await?;
         
-        // Create edges for sender and receiver
-        let from_edge = Edge::new(
-            "sent_from",
-            from_mailbox_id,
-            mail.id,
-            Properties::new(),
-        );
-        self.storage.create_edge(&from_edge).await?;
+        let query = GraphQuery::new()
+            .with_node_type("mail")
+            .with_property("thread_id", PropertyValue::String(mail.thread_id.to_string()));
+        let nodes = self.storage.query_nodes(&query).await?;
+        let mut thread: Vec<Mail> = nodes.iter()
+            .filter_map(Mail::from_node)
+            .collect();
         
-        let to_edge = Edge::new(
-            "sent_to",
-            mail.id,
-            to_mailbox_id,
-            Properties::new(),
-        );
-        self.storage.create_edge(&to_edge).await?;
+        // A root written before threading has no thread_id property
+        if !thread.iter().any(|m| m.id == mail.thread_id) {
+            if let Ok(root) = self.get_mail(mail.thread_id).await {
+                thread.push(root);
+            }
+        }
         
-        Ok(mail)
+        // Oldest first so the conversation reads top to bottom
+        thread.sort_by_key(|m| m.created_at);
+        
+        Ok(thread)
     }
 
     async fn get_mailbox_inbox(&self, mailbox_id: MailboxId) -> Result<Vec<Mail>> {
+        self.get_mailbox_mail(mailbox_id, &InboxFilter::inbox()).await
+    }
+
+    async fn get_mailbox_mail(&self, mailbox_id: MailboxId, filter: &InboxFilter) -> Result<Vec<Mail>> {
         // Verify mailbox (agent) exists
         let _agent = self.storage.get_node(mailbox_id).await
             .map_err(|e| match e {
@@ -250,20 +1160,27 @@ impl<S: GraphStorage> MailService for MailServiceImpl<S> {
                 _ => MailError::Storage(e),
             })?;
         
-        // Get all mail where there's an edge from mail -> mailbox (sent_to)
-        let incoming_edges = self.storage
-            .get_edges_to(mailbox_id, Some("sent_to"))
-            .await?;
+        // Start from this recipient's deliveries (read state, labels,
+        // folder) and only load the mail the filter can match, so archived
+        // mail costs an inbox poll nothing but its edge
+        let deliveries = self.deliveries_to(mailbox_id).await?;
+        let wanted: Vec<uuid::Uuid> = deliveries.iter()
+            .filter(|(_, delivery)| filter.matches_delivery(delivery))
+            .map(|(mail_id, _)| *mail_id)
+            .collect();
+        let nodes = self.storage.get_nodes(&wanted).await?;
         
-        let mut mails = Vec::new();
-        for edge in incoming_edges {
-            if let Ok(mail) = self.get_mail(edge.from_node_id).await {
-                mails.push(mail);
-            }
-        }
+        let mut mails: Vec<Mail> = nodes.iter()
+            .filter_map(Mail::from_node)
+            .filter_map(|mut mail| {
+                mail.apply_delivery(deliveries.get(&mail.id)?);
+                Some(mail)
+            })
+            .filter(|mail| filter.matches(mail))
+            .collect();
         
-        // Sort by creation date, newest first
-        mails.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        // Urgent first, then by delivery date, newest first
+        mails.sort_by_key(|m| (std::cmp::Reverse(m.priority), std::cmp::Reverse(m.delivered_at())));
         
         Ok(mails)
     }
@@ -276,82 +1193,619 @@ impl<S: GraphStorage> MailService for MailServiceImpl<S> {
                 _ => MailError::Storage(e),
             })?;
         
-        // Get all mail where there's an edge from mailbox -> mail (sent_from)
-        let outgoing_edges = self.storage
-            .get_edges_from(mailbox_id, Some("sent_from"))
-            .await?;
+        // Get all mail sent from this mailbox
+        let query = GraphQuery::new()
+            .with_node_type("mail")
+            .with_edge_type("sent_from")
+            .with_property("from_mailbox_id", PropertyValue::String(mailbox_id.to_string()));
+        let nodes = self.storage.query_nodes(&query).await?;
+        
+        let numbers: std::collections::HashMap<uuid::Uuid, i64> = self.storage
+            .get_edges_from(mailbox_id, Some("sent_from")).await?
+            .iter()
+            .filter_map(|edge| Some((edge.to_node_id, edge_seq(edge)?)))
+            .collect();
+        let mut mails: Vec<Mail> = nodes.iter()
+            .filter_map(Mail::from_node)
+            .map(|mut mail| {
+                mail.seq = numbers.get(&mail.id).copied();
+                mail
+            })
+            .collect();
+        
+        // Sort by creation date, newest first
+        mails.sort_by_key(|m| std::cmp::Reverse(m.created_at));
+        
+        Ok(mails)
+    }
+
+    async fn get_recent_mail(&self, mailbox_id: MailboxId, hours: i64, limit: usize) -> Result<Vec<Mail>> {
+        let since = chrono::Utc::now() - chrono::Duration::hours(hours);
+        
+        // Get all mail in the inbox
+        let inbox = self.get_mailbox_inbox(mailbox_id).await?;
+        
+        // Filter to recent mail only
+        let recent: Vec<Mail> = inbox.into_iter()
+            .filter(|mail| mail.delivered_at() >= since)
+            .take(limit)
+            .collect();
         
-        let mut mails = Vec::new();
-        for edge in outgoing_edges {
-            if let Ok(mail) = self.get_mail(edge.to_node_id).await {
-                mails.push(mail);
+        Ok(recent)
+    }
+
+    async fn search_mail(&self, agent_id: AgentId, search: &MailSearch, limit: usize) -> Result<Vec<Mail>> {
+        let agent = self.get_agent(agent_id).await?;
+        let mailbox_id = string_to_node_id(&agent.id);
+        
+        // Settle the per-recipient conditions from the mailbox's own edges,
+        // so storage only has to search the mail that can still match
+        let deliveries: std::collections::HashMap<_, _> = self.deliveries_to(mailbox_id).await?
+            .into_iter()
+            .filter(|(_, delivery)| search.matches_delivery(delivery))
+            .collect();
+        let sent: std::collections::HashMap<_, _> = if search.includes_sent() {
+            self.storage.get_edges_from(mailbox_id, Some("sent_from")).await?
+                .iter()
+                .map(|edge| (edge.to_node_id, edge_seq(edge)))
+                .collect()
+        } else {
+            std::collections::HashMap::new()
+        };
+        let mut ids: Vec<uuid::Uuid> = deliveries.keys().chain(sent.keys()).copied().collect();
+        ids.sort();
+        ids.dedup();
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        
+        let mut query = SearchQuery {
+            node_types: vec!["mail".to_string()],
+            full_text: search.full_text(),
+            created_after: search.after,
+            created_before: search.before,
+            property_contains: search.subject.iter().map(|s| ("subject".to_string(), s.clone())).collect(),
+            node_ids: Some(ids),
+            limit,
+            ..SearchQuery::default()
+        };
+        if let Some(ref from) = search.from {
+            query.property_filters.push(("from_mailbox_id".to_string(), string_to_node_id(from).to_string()));
+        }
+        
+        let nodes = self.storage.search_nodes(&query).await?.items;
+        Ok(nodes.iter()
+            .filter_map(Mail::from_node)
+            .map(|mut mail| {
+                match deliveries.get(&mail.id) {
+                    Some(delivery) => mail.apply_delivery(delivery),
+                    None => mail.seq = sent.get(&mail.id).copied().flatten(),
+                }
+                mail
+            })
+            .collect())
+    }
+
+    async fn export_mail(&self, agent_id: AgentId) -> Result<Vec<ArchivedMail>> {
+        let agent = self.get_agent(agent_id).await?;
+        let mailbox_id = string_to_node_id(&agent.id);
+        let now = chrono::Utc::now();
+        
+        let deliveries = self.deliveries_to(mailbox_id).await?;
+        let sent: std::collections::HashMap<uuid::Uuid, Option<i64>> = self.storage.get_edges_from(mailbox_id, Some("sent_from")).await?
+            .iter()
+            .filter(|edge| !deliveries.contains_key(&edge.to_node_id))
+            .map(|edge| (edge.to_node_id, edge_seq(edge)))
+            .collect();
+        let ids: Vec<uuid::Uuid> = deliveries.keys().chain(sent.keys()).copied().collect();
+        
+        let mut names: std::collections::HashMap<MailboxId, AgentId> = std::collections::HashMap::new();
+        let mut archived: Vec<(Option<i64>, ArchivedMail)> = Vec::with_capacity(ids.len());
+        for node in self.storage.get_nodes(&ids).await? {
+            let Some(mut mail) = Mail::from_node(&node) else { continue };
+            match deliveries.get(&mail.id) {
+                Some(delivery) => mail.apply_delivery(delivery),
+                None if mail.is_pending(now) => continue,
+                None => mail.seq = sent.get(&mail.id).copied().flatten(),
+            }
+            
+            // Deleted agents keep their mailbox ID as a name
+            let mut name = async |mailbox_id: MailboxId| -> AgentId {
+                if let Some(name) = names.get(&mailbox_id) {
+                    return name.clone();
+                }
+                let name = self.get_agent_by_mailbox(mailbox_id).await
+                    .map(|a| a.id)
+                    .unwrap_or_else(|_| mailbox_id.to_string());
+                names.insert(mailbox_id, name.clone());
+                name
+            };
+            let from = name(mail.from_mailbox_id).await;
+            let mut to = Vec::with_capacity(mail.to_mailbox_ids.len());
+            for id in &mail.to_mailbox_ids {
+                to.push(name(*id).await);
+            }
+            let mut cc = Vec::with_capacity(mail.cc_mailbox_ids.len());
+            for id in &mail.cc_mailbox_ids {
+                cc.push(name(*id).await);
+            }
+            
+            archived.push((mail.seq, ArchivedMail {
+                id: mail.id,
+                from,
+                to,
+                cc,
+                subject: mail.subject,
+                body: mail.body,
+                date: mail.deliver_at.unwrap_or(mail.created_at),
+                in_reply_to: mail.in_reply_to,
+                thread_id: mail.thread_id,
+                priority: mail.priority,
+                read: mail.read,
+                labels: mail.labels,
+                folder: mail.folder,
+                content_type: mail.content_type,
+                payload: mail.payload,
+            }));
+        }
+        // Mail sent within the same instant keeps the order it was numbered in
+        archived.sort_by_key(|(seq, m)| (m.date, *seq));
+        Ok(archived.into_iter().map(|(_, m)| m).collect())
+    }
+
+    async fn import_mail(&self, agent_id: AgentId, mut mails: Vec<ArchivedMail>) -> Result<ImportReport> {
+        let agent = self.get_agent(agent_id).await?;
+        let mailbox_id = string_to_node_id(&agent.id);
+        let mut report = ImportReport::default();
+        
+        // Oldest first, so replies find their parents and numbers run in order.
+        // Archive dates may stop at seconds, so a reply can tie with its
+        // parent; depth in the thread breaks the tie.
+        let parents: std::collections::HashMap<uuid::Uuid, Option<uuid::Uuid>> =
+            mails.iter().map(|m| (m.id, m.in_reply_to)).collect();
+        let depth = |mail: &ArchivedMail| {
+            let mut depth = 0;
+            let mut parent = mail.in_reply_to;
+            while let Some(id) = parent.filter(|_| depth < parents.len()) {
+                depth += 1;
+                parent = parents.get(&id).copied().flatten();
+            }
+            depth
+        };
+        mails.sort_by_cached_key(|m| (m.date, depth(m)));
+        for archived in mails {
+            let sender = archived.from == agent.id;
+            let recipient = archived.to.contains(&agent.id) || archived.cc.contains(&agent.id);
+            if !sender && !recipient {
+                report.ignored += 1;
+                continue;
+            }
+            
+            let mut mail = Mail::new(
+                string_to_node_id(&archived.from),
+                archived.to.iter().map(|a| string_to_node_id(a)).collect(),
+                archived.cc.iter().map(|a| string_to_node_id(a)).collect(),
+                archived.subject,
+                archived.body,
+            );
+            mail.id = archived.id;
+            mail.thread_id = archived.thread_id;
+            mail.in_reply_to = archived.in_reply_to;
+            mail.priority = archived.priority;
+            mail.content_type = archived.content_type;
+            mail.payload = archived.payload;
+            
+            let mut tx = self.storage.begin();
+            let exists = self.storage.get_node(mail.id).await.is_ok();
+            if !exists {
+                let mut node = mail.to_node();
+                node.created_at = archived.date;
+                node.updated_at = archived.date;
+                node.properties.insert("imported_at".to_string(), PropertyValue::Timestamp(chrono::Utc::now()));
+                tx.create_node(&node);
+                if let Some(parent_id) = mail.in_reply_to {
+                    if self.storage.get_node(parent_id).await.is_ok() {
+                        tx.create_edge(&Edge::new("reply_to", mail.id, parent_id, Properties::new()));
+                    }
+                }
+            }
+            
+            let mut added = false;
+            let has_sent_from = exists && self.storage.get_edges_to(mail.id, Some("sent_from")).await?
+                .iter()
+                .any(|e| e.from_node_id == mailbox_id);
+            if sender && !has_sent_from {
+                let mut props = Properties::new();
+                let seq = self.storage.next_sequence(&mailbox_sequence(mailbox_id)).await?;
+                props.insert("seq".to_string(), PropertyValue::Integer(seq));
+                let mut edge = Edge::new("sent_from", mailbox_id, mail.id, props);
+                edge.created_at = archived.date;
+                tx.create_edge(&edge);
+                added = true;
+            }
+            let has_sent_to = exists && self.storage.get_edges_from(mail.id, Some("sent_to")).await?
+                .iter()
+                .any(|e| e.to_node_id == mailbox_id);
+            if recipient && !has_sent_to {
+                if let Some(mut edge) = mail.delivery_edges().into_iter().find(|e| e.to_node_id == mailbox_id) {
+                    let seq = self.storage.next_sequence(&mailbox_sequence(mailbox_id)).await?;
+                    edge.properties.insert("seq".to_string(), PropertyValue::Integer(seq));
+                    edge.properties.insert("read".to_string(), PropertyValue::Boolean(archived.read));
+                    set_delivery_labels(&mut edge, &archived.labels);
+                    set_delivery_folder(&mut edge, archived.folder);
+                    edge.created_at = archived.date;
+                    tx.create_edge(&edge);
+                    added = true;
+                }
+            }
+            
+            if added {
+                self.storage.commit(tx).await?;
+                report.imported += 1;
+            } else {
+                report.skipped += 1;
             }
         }
+        Ok(report)
+    }
+
+    async fn cancel_mail(&self, mail_id: uuid::Uuid, from_agent_id: AgentId) -> Result<Mail> {
+        let mail = self.get_mail(mail_id).await?;
+        let sender = self.get_agent(from_agent_id).await?;
+        if string_to_node_id(&sender.id) != mail.from_mailbox_id {
+            return Err(MailError::InvalidOperation(
+                format!("Only the sender can cancel mail, and {} didn't send it", sender.id)
+            ));
+        }
+        if !mail.is_pending(chrono::Utc::now()) {
+            return Err(MailError::InvalidOperation(
+                "Mail has already been delivered and can't be cancelled".to_string()
+            ));
+        }
         
-        // Sort by creation date, newest first
-        mails.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        let mut tx = self.storage.begin();
+        let hashes = self.stage_mail_deletion(&mut tx, mail_id).await?;
+        self.storage.commit(tx).await?;
+        self.release_blobs(hashes).await?;
+        
+        Ok(mail)
+    }
+
+    async fn next_scheduled_delivery(&self, agent_id: AgentId) -> Result<Option<Timestamp>> {
+        let mailbox = self.get_agent_mailbox(agent_id).await?;
+        let now = chrono::Utc::now();
+        let edges = self.storage.get_edges_to(mailbox.id, Some("sent_to")).await?;
+        Ok(edges.iter()
+            .filter_map(Delivery::from_edge)
+            .filter(|delivery| delivery.is_pending(now))
+            .map(|delivery| delivery.delivered_at)
+            .min())
+    }
+
+    async fn mark_mail_as_read(
+        &self,
+        mail_id: uuid::Uuid,
+        agent_id: Option<AgentId>,
+        marked_by: Option<String>,
+    ) -> Result<Mail> {
+        let mut mail = self.get_mail(mail_id).await?;
+        let mut edge = self.recipient_edge(&mail, agent_id).await?;
+        let reader = match marked_by {
+            Some(marked_by) => marked_by,
+            None => self.get_agent_by_mailbox(edge.to_node_id).await?.id,
+        };
+        mark_delivery_read(&mut edge, &reader);
+        self.storage.update_edge(&edge).await?;
         
-        Ok(mails)
+        if let Some(delivery) = Delivery::from_edge(&edge) {
+            mail.apply_delivery(&delivery);
+        }
+        Ok(mail)
     }
 
-    async fn get_recent_mail(&self, mailbox_id: MailboxId, hours: i64, limit: usize) -> Result<Vec<Mail>> {
-        let since = chrono::Utc::now() - chrono::Duration::hours(hours);
-        
-        // Get all mail in the inbox
-        let inbox = self.get_mailbox_inbox(mailbox_id).await?;
-        
-        // Filter to recent mail only
-        let recent: Vec<Mail> = inbox.into_iter()
-            .filter(|mail| mail.created_at >= since)
-            .take(limit)
-            .collect();
+    async fn move_mail(&self, mail_id: uuid::Uuid, agent_id: Option<AgentId>, folder: Folder) -> Result<Mail> {
+        let mut mail = self.get_mail(mail_id).await?;
+        let mut edge = self.recipient_edge(&mail, agent_id).await?;
+        set_delivery_folder(&mut edge, folder);
+        self.storage.update_edge(&edge).await?;
         
-        Ok(recent)
+        if let Some(delivery) = Delivery::from_edge(&edge) {
+            mail.apply_delivery(&delivery);
+        }
+        Ok(mail)
     }
 
-    async fn mark_mail_as_read(&self, mail_id: uuid::Uuid) -> Result<Mail> {
+    async fn label_mail(
+        &self,
+        mail_id: uuid::Uuid,
+        agent_id: Option<AgentId>,
+        add: Vec<String>,
+        remove: Vec<String>,
+    ) -> Result<Mail> {
         let mut mail = self.get_mail(mail_id).await?;
-        mail.mark_as_read();
-        
-        let node = mail.to_node();
-        self.storage.update_node(&node).await?;
+        let mut edge = self.recipient_edge(&mail, agent_id).await?;
+        let mut labels = Delivery::from_edge(&edge).map(|d| d.labels).unwrap_or_default();
+        for label in add {
+            if !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+        labels.retain(|label| !remove.contains(label));
+        set_delivery_labels(&mut edge, &labels);
+        self.storage.update_edge(&edge).await?;
         
+        if let Some(delivery) = Delivery::from_edge(&edge) {
+            mail.apply_delivery(&delivery);
+        }
         Ok(mail)
     }
 
-    async fn mark_mail_as_read_by_short_id(&self, short_id: &str) -> Result<Mail> {
-        // Query all mail nodes in the system
-        let query = GraphQuery::new().with_node_type("mail");
-        let nodes = self.storage.query_nodes(&query).await?;
-        
-        // Convert nodes to Mail and find matching short ID
-        let short_id_lower = short_id.to_lowercase();
-        let matching: Vec<_> = nodes.iter()
-            .filter_map(Mail::from_node)
-            .filter(|m| m.id.to_string().to_lowercase().starts_with(&short_id_lower))
+    async fn get_deliveries(&self, mail_id: uuid::Uuid) -> Result<Vec<Delivery>> {
+        let mail = self.get_mail(mail_id).await?;
+        let edges = self.storage.get_edges_from(mail_id, Some("sent_to")).await?;
+        let deliveries: Vec<Delivery> = mail.recipients()
+            .filter_map(|(mailbox_id, _)| {
+                edges.iter()
+                    .find(|e| e.to_node_id == mailbox_id)
+                    .and_then(Delivery::from_edge)
+            })
             .collect();
+        Ok(deliveries)
+    }
+
+    async fn resolve_mail(&self, reference: &str, agent_id: Option<AgentId>) -> Result<Mail> {
+        let mail_ref = MailRef::parse(reference);
+        let Some(agent_id) = mail_ref.owner(agent_id) else {
+            return match mail_ref {
+                MailRef::Number(..) => Err(MailError::InvalidOperation(format!(
+                    "Say whose mailbox '{}' is in, e.g. alice{}", reference, reference
+                ))),
+                MailRef::Id(prefix) => match uuid::Uuid::parse_str(&prefix) {
+                    Ok(id) => self.get_mail(id).await,
+                    Err(_) => Err(MailError::InvalidOperation(format!(
+                        "Say whose mailbox to look for '{}' in, or give the full mail ID", reference
+                    ))),
+                },
+            };
+        };
+        let agent = self.get_agent(agent_id).await?;
+        let mailbox_id = string_to_node_id(&agent.id);
+        
+        // Everything in the mailbox with its number there; received mail
+        // also carries the recipient's delivery
+        let received = self.deliveries_to(mailbox_id).await?
+            .into_iter()
+            .map(|(id, delivery)| (id, delivery.seq, Some(delivery)));
+        let sent = self.storage.get_edges_from(mailbox_id, Some("sent_from")).await?
+            .into_iter()
+            .map(|edge| (edge.to_node_id, edge_seq(&edge), None));
+        let mut matching: Vec<_> = Vec::new();
+        for (id, seq, delivery) in received.chain(sent) {
+            let found = match &mail_ref {
+                MailRef::Number(_, number) => seq == Some(*number),
+                MailRef::Id(prefix) => id.to_string().starts_with(&prefix.to_lowercase()),
+            };
+            // Mail sent to yourself is in the mailbox twice
+            if found && !matching.iter().any(|(m, _, _)| *m == id) {
+                matching.push((id, seq, delivery));
+            }
+        }
         
         match matching.len() {
-            0 => Err(MailError::MailNotFound(uuid::Uuid::nil())),
+            0 => Err(MailError::InvalidOperation(
+                format!("No mail '{}' in {}'s mailbox", reference, agent.id)
+            )),
             1 => {
-                let mail_id = matching[0].id;
-                self.mark_mail_as_read(mail_id).await
+                let (id, seq, delivery) = matching.remove(0);
+                let mut mail = self.get_mail(id).await?;
+                match delivery {
+                    Some(delivery) => mail.apply_delivery(&delivery),
+                    None => mail.seq = seq,
+                }
+                Ok(mail)
             }
             _ => Err(MailError::InvalidOperation(
-                format!("Multiple mails match short ID '{}', please use full ID", short_id)
+                format!("Multiple mails match short ID '{}', please use full ID", reference)
+            )),
+        }
+    }
+
+    async fn mark_mail_as_read_by_short_id(
+        &self,
+        short_id: &str,
+        agent_id: Option<AgentId>,
+        marked_by: Option<String>,
+    ) -> Result<Mail> {
+        let owner = MailRef::parse(short_id).owner(agent_id.clone());
+        let mail = self.resolve_mail(short_id, agent_id).await?;
+        self.mark_mail_as_read(mail.id, owner, marked_by).await
+    }
+
+    async fn get_attachments(&self, mail_id: uuid::Uuid) -> Result<Vec<Attachment>> {
+        let mut edges = self.storage.get_edges_from(mail_id, Some("has_attachment")).await?;
+        edges.sort_by_key(|e| match e.properties.get("position") {
+            Some(PropertyValue::Integer(n)) => *n,
+            _ => 0,
+        });
+        let ids: Vec<uuid::Uuid> = edges.iter().map(|e| e.to_node_id).collect();
+        let mut attachments: Vec<Attachment> = self.storage.get_nodes(&ids).await?
+            .iter()
+            .filter_map(Attachment::from_node)
+            .collect();
+        attachments.sort_by_key(|a| ids.iter().position(|id| *id == a.id));
+        Ok(attachments)
+    }
+
+    async fn get_attachment_by_short_id(&self, short_id: &str) -> Result<Attachment> {
+        // Two hits are enough to know the prefix is ambiguous
+        let query = SearchQuery {
+            node_types: vec!["attachment".to_string()],
+            id_prefix: Some(short_id.to_string()),
+            limit: 2,
+            ..SearchQuery::default()
+        };
+        let mut matching: Vec<_> = self.storage.search_nodes(&query).await?
+            .items.iter()
+            .filter_map(Attachment::from_node)
+            .collect();
+        
+        match matching.len() {
+            0 => Err(MailError::AttachmentNotFound(short_id.to_string())),
+            1 => Ok(matching.remove(0)),
+            _ => Err(MailError::InvalidOperation(
+                format!("Multiple attachments match short ID '{}', please use full ID", short_id)
             )),
         }
     }
 
-    async fn check_unread_mail(&self, agent_id: AgentId) -> Result<(bool, Vec<Mail>)> {
+    async fn read_attachment(&self, attachment_id: uuid::Uuid) -> Result<(Attachment, Vec<u8>)> {
+        let not_found = || MailError::AttachmentNotFound(attachment_id.to_string());
+        let node = self.storage.get_node(attachment_id).await
+            .map_err(|e| match e {
+                StorageError::NodeNotFound(_) => not_found(),
+                _ => MailError::Storage(e),
+            })?;
+        let attachment = Attachment::from_node(&node).ok_or_else(not_found)?;
+        let data = self.blobs.get(&self.storage, &attachment.sha256).await?;
+        Ok((attachment, data))
+    }
+
+    async fn arrive_due_mail(&self, now: Timestamp) -> Result<usize> {
+        // Each mail's marker is deleted in the same commit as its rule
+        // changes, so when two callers race only one gets to commit. Any
+        // beyond this batch are left for the next call.
+        let query = SearchQuery {
+            node_types: vec![ARRIVAL_MARKER.to_string()],
+            property_before: vec![("deliver_at".to_string(), now)],
+            limit: 500,
+            ..SearchQuery::default()
+        };
+        let markers = self.storage.search_nodes(&query).await?.items;
+        let mut arrived = 0;
+        for marker in markers {
+            let Some(mail_id) = marker.get_property("mail_id")
+                .and_then(|v| v.as_str())
+                .and_then(|id| uuid::Uuid::parse_str(id).ok())
+            else {
+                continue;
+            };
+            
+            let Ok(mail) = self.get_mail(mail_id).await else {
+                // The mail is gone, and the marker with it
+                let _ = self.storage.delete_node(marker.id).await;
+                continue;
+            };
+            let mut deliveries = self.storage.get_edges_from(mail.id, Some("sent_to")).await?;
+            let (forwards, replies) = self.on_arrival(&mail, &mut deliveries).await?;
+            let mut tx = self.storage.begin();
+            tx.delete_node(marker.id);
+            for edge in &deliveries {
+                tx.update_edge(edge);
+            }
+            match self.storage.commit(tx).await {
+                Ok(()) => arrived += 1,
+                Err(StorageError::NodeNotFound(_)) => continue,
+                Err(e) => return Err(e.into()),
+            }
+            
+            let attachments = self.get_attachments(mail.id).await?;
+            for (follow_up, copies) in Self::follow_ups(forwards, replies, &attachments) {
+                self.deliver(follow_up, &copies).await?;
+            }
+        }
+        Ok(arrived)
+    }
+
+    async fn apply_retention(&self, policy: &RetentionPolicy, now: Timestamp) -> Result<RetentionReport> {
+        let mut report = RetentionReport::default();
+        
+        // Delete first so we don't bother archiving mail that's about to go
+        if let Some(delete_after) = policy.delete_after {
+            let cutoff = now - delete_after;
+            // Mail can't have reached anyone before it was created, so this
+            // narrows things down; what counts is `last_arrival`
+            let mut query = SearchQuery {
+                node_types: vec!["mail".to_string()],
+                created_before: Some(cutoff),
+                limit: RETENTION_BATCH,
+                ..SearchQuery::default()
+            };
+            // Deleted mail drops out of the results, so the next page starts
+            // after the mail we kept
+            loop {
+                let batch = self.storage.search_nodes(&query).await?.items;
+                let mut tx = self.storage.begin();
+                let mut blobs = Vec::new();
+                let mut deleted = 0;
+                for node in &batch {
+                    if self.last_arrival(node).await? < cutoff {
+                        blobs.extend(self.stage_mail_deletion(&mut tx, node.id).await?);
+                        deleted += 1;
+                    } else {
+                        query.offset += 1;
+                    }
+                }
+                if deleted > 0 {
+                    self.storage.commit(tx).await?;
+                    self.release_blobs(blobs).await?;
+                    report.deleted += deleted;
+                }
+                if batch.len() < query.limit {
+                    break;
+                }
+            }
+        }
+        
+        if let Some(archive_read_after) = policy.archive_read_after {
+            let cutoff = now - archive_read_after;
+            let mut staged = Vec::new();
+            for agent in self.list_agents().await? {
+                let edges = self.storage.get_edges_to(string_to_node_id(&agent.id), Some("sent_to")).await?;
+                for mut edge in edges {
+                    let Some(delivery) = Delivery::from_edge(&edge) else { continue };
+                    if delivery.read && delivery.folder == Folder::Inbox && delivery.delivered_at < cutoff {
+                        set_delivery_folder(&mut edge, Folder::Archive);
+                        staged.push(edge);
+                    }
+                }
+            }
+            for batch in staged.chunks(RETENTION_BATCH) {
+                let mut tx = self.storage.begin();
+                for edge in batch {
+                    tx.update_edge(edge);
+                }
+                self.storage.commit(tx).await?;
+                report.archived += batch.len();
+            }
+        }
+        
+        Ok(report)
+    }
+
+    async fn check_unread_mail(&self, agent_id: AgentId, include_archived: bool) -> Result<(bool, Vec<Mail>)> {
         // Get the agent's mailbox ID
         let mailbox = self.get_agent_mailbox(agent_id).await?;
         
-        // Get inbox and filter for unread
-        let inbox = self.get_mailbox_inbox(mailbox.id).await?;
-        let unread: Vec<Mail> = inbox.into_iter()
-            .filter(|mail| !mail.read)
+        // Unread deliveries to this mailbox, then the mail they carry
+        let deliveries = self.deliveries_to(mailbox.id).await?;
+        let wanted: Vec<uuid::Uuid> = deliveries.iter()
+            .filter(|(_, delivery)| !delivery.read && match delivery.folder {
+                Folder::Inbox => true,
+                Folder::Archive => include_archived,
+                Folder::Trash => false,
+            })
+            .map(|(mail_id, _)| *mail_id)
+            .collect();
+        let nodes = self.storage.get_nodes(&wanted).await?;
+        let mut unread: Vec<Mail> = nodes.iter()
+            .filter_map(Mail::from_node)
+            .filter_map(|mut mail| {
+                mail.apply_delivery(deliveries.get(&mail.id)?);
+                Some(mail)
+            })
             .collect();
+        // Urgent first, then newest first
+        unread.sort_by_key(|m| (std::cmp::Reverse(m.priority), std::cmp::Reverse(m.delivered_at())));
         
         let has_unread = !unread.is_empty();
         
@@ -362,6 +1816,7 @@ impl<S: GraphStorage> MailService for MailServiceImpl<S> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::mail::domain::{NewAttachment, Priority, RecipientKind, parse_deliver_at};
     use crate::storage::memory::InMemoryStorage;
 
     #[tokio::test]
@@ -457,7 +1912,7 @@ mod tests {
         
         assert!(!mail.read);
         
-        let updated = service.mark_mail_as_read(mail.id).await.unwrap();
+        let updated = service.mark_mail_as_read(mail.id, None, None).await.unwrap();
         assert!(updated.read);
     }
 
@@ -470,7 +1925,7 @@ mod tests {
         let agent2 = service.create_agent("receiver").await.unwrap();
         
         // Initially no unread mail
-        let (has_unread, unread) = service.check_unread_mail(agent2.id.clone()).await.unwrap();
+        let (has_unread, unread) = service.check_unread_mail(agent2.id.clone(), true).await.unwrap();
         assert!(!has_unread);
         assert!(unread.is_empty());
         
@@ -478,16 +1933,16 @@ mod tests {
         service.send_agent_to_agent(agent1.id, agent2.id.clone(), "Test", "Body").await.unwrap();
         
         // Now there is unread mail
-        let (has_unread, unread) = service.check_unread_mail(agent2.id.clone()).await.unwrap();
+        let (has_unread, unread) = service.check_unread_mail(agent2.id.clone(), true).await.unwrap();
         assert!(has_unread);
         assert_eq!(unread.len(), 1);
         
         // Mark as read
         let mail_id = unread[0].id;
-        service.mark_mail_as_read(mail_id).await.unwrap();
+        service.mark_mail_as_read(mail_id, None, None).await.unwrap();
         
         // No more unread
-        let (has_unread, unread) = service.check_unread_mail(agent2.id).await.unwrap();
+        let (has_unread, unread) = service.check_unread_mail(agent2.id, true).await.unwrap();
         assert!(!has_unread);
         assert!(unread.is_empty());
     }
@@ -514,6 +1969,25 @@ mod tests {
         assert_eq!(recent[0].subject, "Recent");
     }
 
+    #[tokio::test]
+    async fn test_delete_agent_clears_mail() {
+        let storage = InMemoryStorage::new();
+        let service = MailServiceImpl::new(storage);
+        
+        let agent1 = service.create_agent("sender").await.unwrap();
+        let agent2 = service.create_agent("receiver").await.unwrap();
+        
+        let mail = service.send_agent_to_agent(agent1.id.clone(), agent2.id.clone(), "Bye", "Body").await.unwrap();
+        service.send_agent_to_agent(agent1.id.clone(), agent1.id.clone(), "Note to self", "Body").await.unwrap();
+        
+        service.delete_agent(agent1.id.clone()).await.unwrap();
+        
+        assert!(matches!(service.get_agent(agent1.id).await, Err(MailError::AgentNotFound(_))));
+        assert!(matches!(service.get_mail(mail.id).await, Err(MailError::MailNotFound(_))));
+        let inbox = service.get_mailbox_inbox(string_to_node_id(&agent2.id)).await.unwrap();
+        assert!(inbox.is_empty());
+    }
+
     #[tokio::test]
     async fn test_get_nonexistent_agent() {
         let storage = InMemoryStorage::new();
@@ -591,4 +2065,789 @@ mod tests {
         assert_eq!(inbox[1].subject, "Second");
         assert_eq!(inbox[2].subject, "First");
     }
+
+    #[tokio::test]
+    async fn test_reply_threads_conversation() {
+        let storage = InMemoryStorage::new();
+        let service = MailServiceImpl::new(storage);
+        
+        let alice = service.create_agent("alice").await.unwrap();
+        let bob = service.create_agent("bob").await.unwrap();
+        
+        let original = service.send_agent_to_agent(alice.id.clone(), bob.id.clone(), "Plan", "Ready?").await.unwrap();
+        tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        
+        // Bob answers by default; the reply goes back to alice
+        let reply = service.reply_to_mail(original.id, None, "Yes").await.unwrap();
+        assert_eq!(reply.subject, "Re: Plan");
+        assert_eq!(reply.to_mailbox_ids, vec![string_to_node_id(&alice.id)]);
+        assert_eq!(reply.in_reply_to, Some(original.id));
+        assert_eq!(reply.thread_id, original.id);
+        tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        
+        // Replying to the reply doesn't stack prefixes
+        let again = service.reply_to_mail(reply.id, Some(alice.id.clone()), "Go").await.unwrap();
+        assert_eq!(again.subject, "Re: Plan");
+        assert_eq!(again.to_mailbox_ids, vec![string_to_node_id(&bob.id)]);
+        
+        let edges = service.storage.get_edges_from(reply.id, Some("reply_to")).await.unwrap();
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].to_node_id, original.id);
+        
+        // Unrelated mail stays out of the thread
+        service.send_agent_to_agent(alice.id.clone(), bob.id.clone(), "Other", "Hi").await.unwrap();
+        
+        let thread = service.get_thread(again.id).await.unwrap();
+        let bodies: Vec<_> = thread.iter().map(|m| m.body.as_str()).collect();
+        assert_eq!(bodies, vec!["Ready?", "Yes", "Go"]);
+    }
+
+    #[tokio::test]
+    async fn test_multi_recipient_mail_has_per_recipient_read_state() {
+        let storage = InMemoryStorage::new();
+        let service = MailServiceImpl::new(storage);
+        
+        let boss = service.create_agent("boss").await.unwrap();
+        let alice = service.create_agent("alice").await.unwrap();
+        let bob = service.create_agent("bob").await.unwrap();
+        let carol = service.create_agent("carol").await.unwrap();
+        
+        let mail = service
+            .send_mail(
+                boss.id.clone(),
+                vec![alice.id.clone(), bob.id.clone(), alice.id.clone()],
+                vec![carol.id.clone(), bob.id.clone()],
+                "Standup",
+                "10am",
+            )
+            .await
+            .unwrap();
+        
+        // One node, duplicates dropped, bob not cc'd as well as to'd
+        assert_eq!(mail.to_mailbox_ids.len(), 2);
+        assert_eq!(mail.cc_mailbox_ids, vec![string_to_node_id(&carol.id)]);
+        assert_eq!(service.get_mailbox_outbox(string_to_node_id(&boss.id)).await.unwrap().len(), 1);
+        let edges = service.storage.get_edges_from(mail.id, Some("sent_to")).await.unwrap();
+        assert_eq!(edges.len(), 3);
+        
+        for agent in [&alice, &bob, &carol] {
+            let inbox = service.get_mailbox_inbox(string_to_node_id(&agent.id)).await.unwrap();
+            assert_eq!(inbox.len(), 1);
+            assert_eq!(inbox[0].id, mail.id);
+        }
+        
+        // Without saying who is reading, a shared mail can't be marked read
+        assert!(matches!(
+            service.mark_mail_as_read(mail.id, None, None).await,
+            Err(MailError::InvalidOperation(_))
+        ));
+        service.mark_mail_as_read(mail.id, Some(alice.id.clone()), None).await.unwrap();
+        
+        let (alice_unread, _) = service.check_unread_mail(alice.id.clone(), true).await.unwrap();
+        let (bob_unread, _) = service.check_unread_mail(bob.id.clone(), true).await.unwrap();
+        assert!(!alice_unread);
+        assert!(bob_unread);
+        let bob_inbox = service.get_mailbox_inbox(string_to_node_id(&bob.id)).await.unwrap();
+        assert!(!bob_inbox[0].read);
+        
+        // Outsiders can't mark it read
+        assert!(service.mark_mail_as_read(mail.id, Some(boss.id.clone()), None).await.is_err());
+        
+        // Removing a recipient keeps the mail for everyone else
+        service.delete_agent(carol.id.clone()).await.unwrap();
+        assert!(service.get_mail(mail.id).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_group_membership_and_mail_to_group() {
+        let storage = InMemoryStorage::new();
+        let service = MailServiceImpl::new(storage);
+        
+        let alice = service.create_agent("alice").await.unwrap();
+        let bob = service.create_agent("bob").await.unwrap();
+        let carol = service.create_agent("carol").await.unwrap();
+        
+        service.create_group("reviewers").await.unwrap();
+        assert!(matches!(service.create_group("Reviewers").await, Err(MailError::InvalidGroupName(_))));
+        assert!(service.create_group("reviewers").await.is_err());
+        
+        for agent in [&alice, &bob, &carol] {
+            service.add_group_member("reviewers", agent.id.clone()).await.unwrap();
+        }
+        // Adding twice doesn't duplicate the membership
+        service.add_group_member("reviewers", bob.id.clone()).await.unwrap();
+        assert_eq!(service.get_group_members("reviewers").await.unwrap().len(), 3);
+        
+        // A group expands to its members at send time, without the sender
+        let mail = service
+            .send_mail(alice.id.clone(), vec!["@reviewers".to_string()], Vec::new(), "PR", "Please look")
+            .await
+            .unwrap();
+        assert_eq!(
+            mail.to_mailbox_ids,
+            vec![string_to_node_id(&bob.id), string_to_node_id(&carol.id)]
+        );
+        
+        service.remove_group_member("reviewers", carol.id.clone()).await.unwrap();
+        assert!(service.remove_group_member("reviewers", carol.id.clone()).await.is_err());
+        let groups = service.get_agent_groups(bob.id.clone()).await.unwrap();
+        assert_eq!(groups.len(), 1);
+        assert!(service.get_agent_groups(carol.id.clone()).await.unwrap().is_empty());
+        
+        // Later mail only reaches the current members
+        let mail = service
+            .send_mail(carol.id.clone(), vec!["@reviewers".to_string()], Vec::new(), "PR", "Ping")
+            .await
+            .unwrap();
+        assert_eq!(mail.to_mailbox_ids.len(), 2);
+        
+        service.delete_group("reviewers").await.unwrap();
+        assert!(service.list_groups().await.unwrap().is_empty());
+        assert!(matches!(
+            service.send_mail(alice.id.clone(), vec!["@reviewers".to_string()], Vec::new(), "PR", "?").await,
+            Err(MailError::GroupNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_receipts_record_who_and_when() {
+        let storage = InMemoryStorage::new();
+        let service = MailServiceImpl::new(storage);
+        
+        let boss = service.create_agent("boss").await.unwrap();
+        let alice = service.create_agent("alice").await.unwrap();
+        let bob = service.create_agent("bob").await.unwrap();
+        
+        let mail = service
+            .send_mail(boss.id.clone(), vec![alice.id.clone()], vec![bob.id.clone()], "Task", "Do it")
+            .await
+            .unwrap();
+        
+        let deliveries = service.get_deliveries(mail.id).await.unwrap();
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[1].kind, RecipientKind::Cc);
+        assert!(deliveries.iter().all(|d| !d.read && d.read_at.is_none()));
+        
+        service.mark_mail_as_read(mail.id, Some(alice.id.clone()), None).await.unwrap();
+        service.mark_mail_as_read(mail.id, Some(bob.id.clone()), Some("web".to_string())).await.unwrap();
+        
+        let deliveries = service.get_deliveries(mail.id).await.unwrap();
+        assert_eq!(deliveries[0].read_by.as_deref(), Some("alice"));
+        assert_eq!(deliveries[1].read_by.as_deref(), Some("web"));
+        let first_read_at = deliveries[0].read_at.unwrap();
+        
+        // Marking again keeps the original receipt
+        service.mark_mail_as_read(mail.id, Some(alice.id.clone()), Some("web".to_string())).await.unwrap();
+        let deliveries = service.get_deliveries(mail.id).await.unwrap();
+        assert_eq!(deliveries[0].read_by.as_deref(), Some("alice"));
+        assert_eq!(deliveries[0].read_at, Some(first_read_at));
+    }
+
+    #[tokio::test]
+    async fn test_priority_labels_and_folders() {
+        let storage = InMemoryStorage::new();
+        let service = MailServiceImpl::new(storage);
+        
+        let boss = service.create_agent("boss").await.unwrap();
+        let alice = service.create_agent("alice").await.unwrap();
+        let bob = service.create_agent("bob").await.unwrap();
+        let alice_box = string_to_node_id(&alice.id);
+        
+        let routine = service.send_mail(boss.id.clone(), vec![alice.id.clone()], Vec::new(), "Routine", "fyi").await.unwrap();
+        let options = SendOptions { priority: Priority::Urgent, labels: vec!["bug".to_string()], ..SendOptions::default() };
+        let outage = service
+            .send_mail_with(boss.id.clone(), vec![alice.id.clone(), bob.id.clone()], Vec::new(), "Outage", "now", options)
+            .await
+            .unwrap();
+        
+        // Urgent mail sorts ahead of newer normal mail
+        let later = service.send_mail(boss.id.clone(), vec![alice.id.clone()], Vec::new(), "Later", "meh").await.unwrap();
+        let (_, unread) = service.check_unread_mail(alice.id.clone(), true).await.unwrap();
+        assert_eq!(unread[0].id, outage.id);
+        assert_eq!(unread[1].id, later.id);
+        
+        let filter = InboxFilter { label: Some("bug".to_string()), ..InboxFilter::inbox() };
+        assert_eq!(service.get_mailbox_mail(alice_box, &filter).await.unwrap().len(), 1);
+        let filter = InboxFilter { priority: Some(Priority::Urgent), ..InboxFilter::inbox() };
+        assert_eq!(service.get_mailbox_mail(alice_box, &filter).await.unwrap()[0].id, outage.id);
+        
+        // Labels and folders belong to each recipient
+        let relabelled = service
+            .label_mail(outage.id, Some(alice.id.clone()), vec!["ops".to_string()], vec!["bug".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(relabelled.labels, vec!["ops".to_string()]);
+        let bob_inbox = service.get_mailbox_inbox(string_to_node_id(&bob.id)).await.unwrap();
+        assert_eq!(bob_inbox[0].labels, vec!["bug".to_string()]);
+        
+        service.move_mail(routine.id, None, Folder::Archive).await.unwrap();
+        service.move_mail(later.id, None, Folder::Trash).await.unwrap();
+        let inbox = service.get_mailbox_inbox(alice_box).await.unwrap();
+        assert_eq!(inbox.len(), 1);
+        let archive = InboxFilter { folder: Some(Folder::Archive), ..InboxFilter::default() };
+        assert_eq!(service.get_mailbox_mail(alice_box, &archive).await.unwrap()[0].id, routine.id);
+        
+        // Trash never wakes anyone; archive only when asked
+        let (_, unread) = service.check_unread_mail(alice.id.clone(), true).await.unwrap();
+        assert_eq!(unread.len(), 2);
+        let (_, unread) = service.check_unread_mail(alice.id.clone(), false).await.unwrap();
+        assert_eq!(unread.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retention_archives_read_mail_and_deletes_expired_mail() {
+        let storage = InMemoryStorage::new();
+        let service = MailServiceImpl::new(storage);
+        
+        let boss = service.create_agent("boss").await.unwrap();
+        let alice = service.create_agent("alice").await.unwrap();
+        let alice_box = string_to_node_id(&alice.id);
+        
+        let read = service.send_mail(boss.id.clone(), vec![alice.id.clone()], Vec::new(), "Old news", "x").await.unwrap();
+        let unread = service.send_mail(boss.id.clone(), vec![alice.id.clone()], Vec::new(), "Still open", "y").await.unwrap();
+        service.mark_mail_as_read(read.id, None, None).await.unwrap();
+        
+        let policy = RetentionPolicy::from_days(30, 365);
+        
+        // Nothing is old enough yet
+        let report = service.apply_retention(&policy, chrono::Utc::now()).await.unwrap();
+        assert_eq!(report, RetentionReport::default());
+        
+        // A month on, read mail leaves the inbox but stays searchable
+        let later = chrono::Utc::now() + chrono::Duration::days(31);
+        let report = service.apply_retention(&policy, later).await.unwrap();
+        assert_eq!(report.archived, 1);
+        let inbox = service.get_mailbox_inbox(alice_box).await.unwrap();
+        assert_eq!(inbox.len(), 1);
+        assert_eq!(inbox[0].id, unread.id);
+        let everything = service.get_mailbox_mail(alice_box, &InboxFilter::default()).await.unwrap();
+        assert_eq!(everything.len(), 2);
+        
+        // A year on, all of it is gone
+        let much_later = chrono::Utc::now() + chrono::Duration::days(366);
+        let report = service.apply_retention(&policy, much_later).await.unwrap();
+        assert_eq!(report.deleted, 2);
+        assert!(service.get_mailbox_mail(alice_box, &InboxFilter::default()).await.unwrap().is_empty());
+        assert!(service.get_mailbox_outbox(string_to_node_id(&boss.id)).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_retention_keeps_imported_and_scheduled_mail() {
+        let service = MailServiceImpl::new(InMemoryStorage::new());
+        let boss = service.create_agent("boss").await.unwrap();
+        let alice = service.create_agent("alice").await.unwrap();
+        let policy = RetentionPolicy::from_days(30, 365);
+        let now = chrono::Utc::now();
+        
+        // Two years old in the archive, but only just arrived here
+        let old = ArchivedMail {
+            id: uuid::Uuid::new_v4(),
+            from: boss.id.clone(),
+            to: vec![alice.id.clone()],
+            cc: Vec::new(),
+            subject: "From the old office".to_string(),
+            body: "x".to_string(),
+            date: now - chrono::Duration::days(730),
+            in_reply_to: None,
+            thread_id: uuid::Uuid::new_v4(),
+            priority: Priority::Normal,
+            read: true,
+            labels: Vec::new(),
+            folder: Folder::Inbox,
+            content_type: None,
+            payload: None,
+        };
+        let old = ArchivedMail { thread_id: old.id, ..old };
+        service.import_mail(alice.id.clone(), vec![old]).await.unwrap();
+        
+        let options = SendOptions { deliver_at: Some(now + chrono::Duration::days(400)), ..SendOptions::default() };
+        let scheduled = service.send_mail_with(boss.id.clone(), vec![alice.id.clone()], Vec::new(), "Anniversary", "y", options)
+            .await.unwrap();
+        
+        // Imported mail is dated by its archive date, so it is archived right
+        // away once read, but nothing is deleted
+        let report = service.apply_retention(&policy, now + chrono::Duration::days(1)).await.unwrap();
+        assert_eq!(report, RetentionReport { archived: 1, deleted: 0 });
+        
+        // The delayed mail is still waiting to be delivered a year on
+        let report = service.apply_retention(&policy, now + chrono::Duration::days(366)).await.unwrap();
+        assert_eq!(report.deleted, 1);
+        assert!(service.get_mail(scheduled.id).await.is_ok());
+        
+        // and only goes a year after it arrives
+        let report = service.apply_retention(&policy, now + chrono::Duration::days(766)).await.unwrap();
+        assert_eq!(report.deleted, 1);
+        assert!(service.get_mail(scheduled.id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_payloads_are_validated_against_registered_schemas() {
+        let storage = InMemoryStorage::new();
+        let service = MailServiceImpl::new(storage);
+        
+        let alice = service.create_agent("alice").await.unwrap();
+        let bob = service.create_agent("bob").await.unwrap();
+        let schema = serde_json::json!({
+            "type": "object",
+            "required": ["pr"],
+            "properties": { "pr": { "type": "integer" } }
+        });
+        service.register_payload_schema("review-request", schema).await.unwrap();
+        
+        let send = |payload: serde_json::Value, content_type: Option<&str>| {
+            let options = SendOptions {
+                payload: Some(payload),
+                content_type: content_type.map(str::to_string),
+                ..SendOptions::default()
+            };
+            service.send_mail_with(alice.id.clone(), vec![bob.id.clone()], Vec::new(), "Review", "please", options)
+        };
+        
+        let mail = send(serde_json::json!({ "pr": 42, "files": ["a.rs"] }), Some("review-request")).await.unwrap();
+        let inbox = service.get_mailbox_inbox(string_to_node_id(&bob.id)).await.unwrap();
+        assert_eq!(inbox[0].id, mail.id);
+        assert_eq!(inbox[0].content_type.as_deref(), Some("review-request"));
+        assert_eq!(inbox[0].payload, Some(serde_json::json!({ "pr": 42, "files": ["a.rs"] })));
+        
+        // Wrong shape for the type, not an object, or no type and no schema
+        let err = send(serde_json::json!({ "pr": "42" }), Some("review-request")).await.unwrap_err();
+        assert!(matches!(err, MailError::InvalidPayload(ref msg) if msg.contains("/pr")));
+        assert!(matches!(send(serde_json::json!([1]), None).await, Err(MailError::InvalidPayload(_))));
+        let untyped = send(serde_json::json!({ "anything": true }), None).await.unwrap();
+        assert_eq!(untyped.content_type.as_deref(), Some(DEFAULT_CONTENT_TYPE));
+        
+        // Broken schemas are refused; registering again replaces the schema
+        let broken = service.register_payload_schema("review-request", serde_json::json!({ "type": 5 })).await;
+        assert!(matches!(broken, Err(MailError::InvalidSchema(_))));
+        service.register_payload_schema("review-request", serde_json::json!({ "type": "object" })).await.unwrap();
+        send(serde_json::json!({ "pr": "42" }), Some("review-request")).await.unwrap();
+        assert_eq!(service.list_payload_schemas().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_attachments_share_blobs_and_respect_limits() {
+        let storage = InMemoryStorage::new();
+        let limits = AttachmentLimits { max_file_bytes: 16, max_mail_bytes: 24 };
+        let service = MailServiceImpl::new(storage.clone()).with_attachments(BlobLocation::Database, limits.clone());
+        
+        let alice = service.create_agent("alice").await.unwrap();
+        let bob = service.create_agent("bob").await.unwrap();
+        let send = |files: Vec<NewAttachment>| {
+            let options = SendOptions { attachments: files, ..SendOptions::default() };
+            service.send_mail_with(alice.id.clone(), vec![bob.id.clone()], Vec::new(), "Logs", "attached", options)
+        };
+        
+        let log = || NewAttachment::new("/tmp/build/build.log", b"error: oops".to_vec());
+        let first = send(vec![log(), NewAttachment::new("fix.patch", b"+ok".to_vec())]).await.unwrap();
+        let second = send(vec![log()]).await.unwrap();
+        
+        let attachments = service.get_attachments(first.id).await.unwrap();
+        assert_eq!(attachments.len(), 2);
+        assert_eq!(attachments[0].filename, "build.log");
+        assert_eq!(attachments[1].filename, "fix.patch");
+        let short_id = &attachments[0].id.to_string()[..8];
+        let found = service.get_attachment_by_short_id(short_id).await.unwrap();
+        let (_, data) = service.read_attachment(found.id).await.unwrap();
+        assert_eq!(data, b"error: oops");
+        
+        // Too big on its own, or too big together
+        let big = NewAttachment::new("big.bin", vec![0; 17]);
+        assert!(matches!(send(vec![big]).await, Err(MailError::AttachmentTooLarge(_))));
+        let halves = vec![NewAttachment::new("a", vec![1; 13]), NewAttachment::new("b", vec![2; 13])];
+        assert!(matches!(send(halves).await, Err(MailError::AttachmentTooLarge(_))));
+        // The same answer from sizes alone, before anything is read
+        assert!(limits.check_sizes(&[("big.bin", 17)]).is_err());
+        assert!(limits.check_sizes(&[("a", 13), ("b", 13)]).is_err());
+        assert!(limits.check_sizes(&[("a", 12), ("b", 12)]).is_ok());
+        
+        // The log is stored once; it stays until the last mail using it goes
+        let hash = attachments[0].sha256.clone();
+        let policy = RetentionPolicy { delete_after: Some(chrono::Duration::zero()), ..RetentionPolicy::default() };
+        let mut tx = storage.begin();
+        let blobs = service.stage_mail_deletion(&mut tx, first.id).await.unwrap();
+        storage.commit(tx).await.unwrap();
+        service.release_blobs(blobs).await.unwrap();
+        assert!(storage.get_blob(&hash).await.is_ok());
+        assert!(storage.get_blob(&attachments[1].sha256).await.is_err());
+        
+        service.apply_retention(&policy, chrono::Utc::now() + chrono::Duration::seconds(1)).await.unwrap();
+        assert!(service.get_attachments(second.id).await.unwrap().is_empty());
+        assert!(storage.get_blob(&hash).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delayed_mail_is_hidden_until_due_and_can_be_cancelled() {
+        use chrono::TimeZone;
+        
+        let storage = InMemoryStorage::new();
+        let service = MailServiceImpl::new(storage);
+        let alice = service.create_agent("alice").await.unwrap();
+        let bob = service.create_agent("bob").await.unwrap();
+        let bob_mailbox = service.get_agent_mailbox(bob.id.clone()).await.unwrap();
+        let send_at = |deliver_at: Timestamp| {
+            let options = SendOptions { deliver_at: Some(deliver_at), ..SendOptions::default() };
+            service.send_mail_with(alice.id.clone(), vec![bob.id.clone()], Vec::new(), "Later", "not yet", options)
+        };
+        
+        let tomorrow = chrono::Utc::now() + chrono::Duration::days(1);
+        let later = send_at(tomorrow).await.unwrap();
+        assert!(service.get_mailbox_inbox(bob_mailbox.id).await.unwrap().is_empty());
+        assert!(!service.check_unread_mail(bob.id.clone(), true).await.unwrap().0);
+        assert_eq!(service.get_mailbox_outbox(later.from_mailbox_id).await.unwrap().len(), 1);
+        assert_eq!(service.next_scheduled_delivery(bob.id.clone()).await.unwrap(), Some(tomorrow));
+        assert!(service.mark_mail_as_read(later.id, None, None).await.is_err());
+        
+        // Once its time comes it shows up like any other mail
+        let soon = send_at(chrono::Utc::now() + chrono::Duration::milliseconds(50)).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let inbox = service.get_mailbox_inbox(bob_mailbox.id).await.unwrap();
+        assert_eq!(inbox.iter().map(|m| m.id).collect::<Vec<_>>(), vec![soon.id]);
+        assert!(service.cancel_mail(soon.id, alice.id.clone()).await.is_err());
+        
+        // Nobody but the sender can take it back
+        assert!(service.cancel_mail(later.id, bob.id.clone()).await.is_err());
+        service.cancel_mail(later.id, alice.id.clone()).await.unwrap();
+        assert_eq!(service.get_mailbox_outbox(later.from_mailbox_id).await.unwrap().len(), 1);
+        assert_eq!(service.next_scheduled_delivery(bob.id.clone()).await.unwrap(), None);
+        assert!(send_at(chrono::Utc::now() - chrono::Duration::minutes(1)).await.is_err());
+        
+        let now = chrono::Utc.with_ymd_and_hms(2026, 3, 1, 10, 0, 0).unwrap();
+        let at = |h, m| chrono::Utc.with_ymd_and_hms(2026, 3, 1, h, m, 0).unwrap();
+        assert_eq!(parse_deliver_at("+2h", now), Ok(at(12, 0)));
+        assert_eq!(parse_deliver_at("+30m", now), Ok(at(10, 30)));
+        assert_eq!(parse_deliver_at("11:30", now), Ok(at(11, 30)));
+        assert_eq!(parse_deliver_at("09:00", now), Ok(at(9, 0) + chrono::Duration::days(1)));
+        assert_eq!(parse_deliver_at("tomorrow 08:15", now), Ok(at(8, 15) + chrono::Duration::days(1)));
+        assert_eq!(parse_deliver_at("2026-03-01 17:45", now), Ok(at(17, 45)));
+        assert_eq!(parse_deliver_at("2026-03-01T18:00:00+02:00", now), Ok(at(16, 0)));
+        assert!(parse_deliver_at("+2w", now).is_err());
+        assert!(parse_deliver_at("next tuesday", now).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rules_label_and_forward_without_looping() {
+        use crate::services::mail::domain::{MailRule, MAX_FORWARD_HOPS};
+        
+        let storage = InMemoryStorage::new();
+        let service = MailServiceImpl::new(storage);
+        for name in ["ci_bot", "triage", "oncall"] {
+            service.create_agent(name).await.unwrap();
+        }
+        let inbox = |agent: &'static str| {
+            let service = &service;
+            async move {
+                let mailbox = service.get_agent_mailbox(agent.to_string()).await.unwrap();
+                service.get_mailbox_inbox(mailbox.id).await.unwrap()
+            }
+        };
+        
+        let failures = MailRule {
+            from: Some("ci_bot".to_string()),
+            subject_contains: Some("failed".to_string()),
+            add_labels: vec!["ci".to_string()],
+            forward_to: vec!["oncall".to_string()],
+            ..MailRule::new("triage", "ci-failures")
+        };
+        service.add_mail_rule(failures).await.unwrap();
+        // Forwarding everything straight back must not bounce forever
+        let bounce = MailRule {
+            mark_read: true,
+            forward_to: vec!["triage".to_string()],
+            ..MailRule::new("oncall", "bounce")
+        };
+        let bounce = service.add_mail_rule(bounce).await.unwrap();
+        
+        service.send_agent_to_agent("ci_bot".into(), "triage".into(), "Build FAILED", "log").await.unwrap();
+        service.send_agent_to_agent("ci_bot".into(), "triage".into(), "Build passed", "log").await.unwrap();
+        
+        let triage = inbox("triage").await;
+        assert_eq!(triage.len(), 2);
+        let failed = triage.iter().find(|m| m.subject == "Build FAILED").unwrap();
+        assert_eq!(failed.labels, vec!["ci"]);
+        assert!(triage.iter().find(|m| m.subject == "Build passed").unwrap().labels.is_empty());
+        let oncall = inbox("oncall").await;
+        assert_eq!(oncall.len(), 1);
+        assert_eq!(oncall[0].subject, "Fwd: Build FAILED");
+        assert!(oncall[0].read);
+        assert!(oncall[0].body.contains("From: ci_bot"));
+        
+        // Disabled rules don't run; rules have to do something
+        service.update_mail_rule(MailRule { enabled: false, ..bounce }).await.unwrap();
+        assert!(!service.list_mail_rules("oncall".into()).await.unwrap()[0].enabled);
+        let idle = MailRule::new("oncall", "idle");
+        assert!(matches!(service.add_mail_rule(idle).await, Err(MailError::InvalidRule(_))));
+        let to_self = MailRule { forward_to: vec!["oncall".to_string()], ..MailRule::new("oncall", "me") };
+        assert!(matches!(service.add_mail_rule(to_self).await, Err(MailError::InvalidRule(_))));
+        
+        // A long chain of forwards stops after MAX_FORWARD_HOPS
+        let hops: Vec<String> = (0..MAX_FORWARD_HOPS + 2).map(|i| format!("hop{}", i)).collect();
+        for name in &hops {
+            service.create_agent(name.clone()).await.unwrap();
+        }
+        for pair in hops.windows(2) {
+            let rule = MailRule { forward_to: vec![pair[1].clone()], ..MailRule::new(pair[0].clone(), "pass-on") };
+            service.add_mail_rule(rule).await.unwrap();
+        }
+        service.send_agent_to_agent("ci_bot".into(), hops[0].clone(), "Relay", "go").await.unwrap();
+        for (i, name) in hops.iter().enumerate() {
+            let mailbox = service.get_agent_mailbox(name.clone()).await.unwrap();
+            let got = !service.get_mailbox_inbox(mailbox.id).await.unwrap().is_empty();
+            assert_eq!(got, i <= MAX_FORWARD_HOPS, "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delayed_mail_to_away_agent_is_answered_when_it_arrives() {
+        use crate::services::mail::domain::AutoReply;
+        
+        let service = MailServiceImpl::new(InMemoryStorage::new());
+        for name in ["alice", "bob", "carol"] {
+            service.create_agent(name).await.unwrap();
+        }
+        service.set_agent_status("alice".into(), "away").await.unwrap();
+        let auto_reply = AutoReply { message: "On holiday".to_string(), delegate: Some("carol".to_string()) };
+        service.set_auto_reply("alice".into(), Some(auto_reply)).await.unwrap();
+        let inbox = |agent: &'static str| {
+            let service = &service;
+            async move { service.get_mailbox_inbox(string_to_node_id(agent)).await.unwrap() }
+        };
+        let send_at = |deliver_at: Timestamp| {
+            let options = SendOptions { deliver_at: Some(deliver_at), ..SendOptions::default() };
+            service.send_mail_with("bob".into(), vec!["alice".into()], Vec::new(), "Later", "?", options)
+        };
+        
+        // Nothing goes out while the mail is waiting, or once it is cancelled
+        let cancelled = send_at(chrono::Utc::now() + chrono::Duration::days(1)).await.unwrap();
+        let later = send_at(chrono::Utc::now() + chrono::Duration::days(2)).await.unwrap();
+        let soon = send_at(chrono::Utc::now() + chrono::Duration::milliseconds(50)).await.unwrap();
+        assert_eq!(service.arrive_due_mail(chrono::Utc::now()).await.unwrap(), 0);
+        assert!(inbox("bob").await.is_empty());
+        assert!(inbox("carol").await.is_empty());
+        service.cancel_mail(cancelled.id, "bob".into()).await.unwrap();
+        
+        // Reading the inbox shows the mail but leaves the replies to the runner
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert_eq!(inbox("alice").await.iter().map(|m| m.id).collect::<Vec<_>>(), vec![soon.id]);
+        assert!(inbox("bob").await.is_empty());
+        
+        // On arrival bob hears back and carol gets a copy, once
+        assert_eq!(service.arrive_due_mail(chrono::Utc::now()).await.unwrap(), 1);
+        assert_eq!(service.arrive_due_mail(chrono::Utc::now()).await.unwrap(), 0);
+        let replies = inbox("bob").await;
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].in_reply_to, Some(soon.id));
+        assert_eq!(inbox("carol").await.len(), 1);
+        
+        // Only the mail that isn't due yet is still waiting
+        let markers = service.storage.query_nodes(&GraphQuery::new().with_node_type(ARRIVAL_MARKER)).await.unwrap();
+        assert_eq!(markers.iter().map(|m| m.id).collect::<Vec<_>>(), vec![arrival_marker_id(later.id)]);
+    }
+
+    #[tokio::test]
+    async fn test_auto_reply_and_delegate_while_away() {
+        use crate::services::mail::domain::AutoReply;
+        
+        let storage = InMemoryStorage::new();
+        let service = MailServiceImpl::new(storage);
+        for name in ["alice", "bob", "carol"] {
+            service.create_agent(name).await.unwrap();
+            service.set_agent_status(name.into(), "online").await.unwrap();
+        }
+        let inbox = |agent: &'static str| {
+            let service = &service;
+            async move {
+                let mailbox = service.get_agent_mailbox(agent.to_string()).await.unwrap();
+                service.get_mailbox_inbox(mailbox.id).await.unwrap()
+            }
+        };
+        let away = |message: &str, delegate: &str| Some(AutoReply {
+            message: message.to_string(),
+            delegate: Some(delegate.to_string()),
+        });
+        
+        // Nothing happens while the agent is around
+        service.set_auto_reply("alice".into(), away("On holiday", "carol")).await.unwrap();
+        service.send_agent_to_agent("bob".into(), "alice".into(), "Question", "?").await.unwrap();
+        assert!(inbox("bob").await.is_empty());
+        
+        // Away: the sender hears back once per cooldown, the delegate gets every mail
+        service.set_agent_status("alice".into(), "away").await.unwrap();
+        service.send_agent_to_agent("bob".into(), "alice".into(), "Question 2", "?").await.unwrap();
+        service.send_agent_to_agent("bob".into(), "alice".into(), "Question 3", "?").await.unwrap();
+        let replies = inbox("bob").await;
+        assert_eq!(replies.len(), 1);
+        assert!(replies[0].auto_reply);
+        assert_eq!(replies[0].subject, "Auto-reply: Question 2");
+        assert!(replies[0].body.contains("passed on to carol"));
+        assert_eq!(inbox("carol").await.len(), 2);
+        
+        // Two away agents covering for each other don't ping-pong
+        service.set_agent_status("carol".into(), "offline").await.unwrap();
+        service.set_auto_reply("carol".into(), away("Out too", "alice")).await.unwrap();
+        service.send_agent_to_agent("alice".into(), "carol".into(), "Hi", "!").await.unwrap();
+        assert_eq!(inbox("alice").await.iter().filter(|m| m.auto_reply).count(), 1);
+        assert_eq!(inbox("carol").await.len(), 3);
+        
+        service.set_auto_reply("alice".into(), None).await.unwrap();
+        assert!(service.get_agent("alice".into()).await.unwrap().auto_reply.is_none());
+        assert!(service.set_auto_reply("bob".into(), away("Gone", "bob")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_auto_reply_cooldown_only_counts_recent_replies() {
+        let service = MailServiceImpl::new(InMemoryStorage::new());
+        let alice = string_to_node_id(&service.create_agent("alice").await.unwrap().id);
+        let bob = string_to_node_id(&service.create_agent("bob").await.unwrap().id);
+        
+        let question = Mail::new(bob, vec![alice], Vec::new(), "Question", "?");
+        let mut old_reply = question.auto_reply_from(alice, "On holiday", None).to_node();
+        old_reply.created_at = chrono::Utc::now() - chrono::Duration::hours(AUTO_REPLY_COOLDOWN_HOURS + 1);
+        service.storage.create_node(&old_reply).await.unwrap();
+        assert!(!service.auto_replied_recently(alice, bob).await.unwrap());
+        
+        let reply = question.auto_reply_from(alice, "On holiday", None);
+        service.storage.create_node(&reply.to_node()).await.unwrap();
+        assert!(service.auto_replied_recently(alice, bob).await.unwrap());
+        assert!(!service.auto_replied_recently(bob, alice).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_mail_numbers_are_per_mailbox_and_resolve() {
+        use crate::services::mail::domain::MailRef;
+        
+        let storage = InMemoryStorage::new();
+        let service = MailServiceImpl::new(storage);
+        let alice = service.create_agent("alice").await.unwrap();
+        let bob = service.create_agent("bob").await.unwrap();
+        let carol = service.create_agent("carol").await.unwrap();
+        
+        let first = service.send_mail(alice.id.clone(), vec![bob.id.clone()], Vec::new(), "One", "first").await.unwrap();
+        let second = service.send_mail(carol.id.clone(), vec![bob.id.clone()], Vec::new(), "Two", "second").await.unwrap();
+        let to_carol = service.send_mail(alice.id.clone(), vec![carol.id.clone()], Vec::new(), "Three", "third").await.unwrap();
+        assert_eq!((first.seq, second.seq, to_carol.seq), (Some(1), Some(1), Some(2)));
+        
+        // Bob's inbox counts from 1 whoever the mail came from
+        let bob_mailbox = service.get_agent_mailbox(bob.id.clone()).await.unwrap();
+        let inbox = service.get_mailbox_inbox(bob_mailbox.id).await.unwrap();
+        let numbers: Vec<_> = inbox.iter().map(|m| (m.id, m.seq)).collect();
+        assert!(numbers.contains(&(first.id, Some(1))) && numbers.contains(&(second.id, Some(2))));
+        assert_eq!(first.reference("alice"), "alice#1");
+        
+        assert_eq!(service.resolve_mail("bob#2", None).await.unwrap().id, second.id);
+        assert_eq!(service.resolve_mail("#1", Some(bob.id.clone())).await.unwrap().id, first.id);
+        assert_eq!(service.resolve_mail("alice#2", None).await.unwrap().id, to_carol.id);
+        assert!(service.resolve_mail("bob#3", None).await.is_err());
+        assert!(service.resolve_mail("#1", None).await.is_err());
+        
+        // Prefixes only match mail in the given mailbox
+        let prefix = &to_carol.id.to_string()[..8];
+        assert_eq!(service.resolve_mail(prefix, Some(carol.id.clone())).await.unwrap().id, to_carol.id);
+        assert!(service.resolve_mail(prefix, Some(bob.id.clone())).await.is_err());
+        // and with nobody's mailbox to search, only the full ID will do
+        assert!(service.resolve_mail(prefix, None).await.is_err());
+        assert_eq!(service.resolve_mail(&to_carol.id.to_string(), None).await.unwrap().id, to_carol.id);
+        
+        let read = service.mark_mail_as_read_by_short_id("bob#1", None, None).await.unwrap();
+        assert!(read.read && read.id == first.id);
+        assert_eq!(MailRef::parse("alice#42"), MailRef::Number(Some("alice".to_string()), 42));
+        assert_eq!(MailRef::parse("1a2b3c4d"), MailRef::Id("1a2b3c4d".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_search_mail_with_query_syntax() {
+        use crate::services::mail::domain::MailSearch;
+        
+        let storage = InMemoryStorage::new();
+        let service = MailServiceImpl::new(storage);
+        let alice = service.create_agent("alice").await.unwrap();
+        let bob = service.create_agent("bob").await.unwrap();
+        let carol = service.create_agent("carol").await.unwrap();
+        let send = |from: &str, subject: &str, body: &str| {
+            service.send_mail(from.to_string(), vec![alice.id.clone()], Vec::new(), subject.to_string(), body.to_string())
+        };
+        
+        let deploy = send(&bob.id, "Deploy tonight", "Rolling out the new cache layer").await.unwrap();
+        let ci = send(&bob.id, "CI broken", "The deploy step fails").await.unwrap();
+        let other = send(&carol.id, "Lunch", "Deploy yourself to the cafeteria").await.unwrap();
+        service.label_mail(ci.id, None, vec!["ci".to_string()], Vec::new()).await.unwrap();
+        service.mark_mail_as_read(other.id, None, None).await.unwrap();
+        let sent = service.send_mail(alice.id.clone(), vec![bob.id.clone()], Vec::new(), "Deploy plan", "See attached").await.unwrap();
+        
+        let ids = |query: &str| {
+            let search = MailSearch::parse(query, &chrono::Utc).unwrap();
+            let service = &service;
+            let alice = alice.id.clone();
+            async move {
+                let mut ids: Vec<_> = service.search_mail(alice, &search, 50).await.unwrap().iter().map(|m| m.id).collect();
+                ids.sort();
+                ids
+            }
+        };
+        let sorted = |mut v: Vec<uuid::Uuid>| { v.sort(); v };
+        
+        assert_eq!(ids("deploy").await, sorted(vec![deploy.id, ci.id, other.id, sent.id]));
+        assert_eq!(ids("from:bob deploy").await, sorted(vec![deploy.id, ci.id]));
+        assert_eq!(ids("subject:deploy").await, sorted(vec![deploy.id, sent.id]));
+        assert_eq!(ids("is:unread deploy").await, sorted(vec![deploy.id, ci.id]));
+        assert_eq!(ids("label:ci").await, vec![ci.id]);
+        assert_eq!(ids("\"new cache\"").await, vec![deploy.id]);
+        assert!(ids("\"cache new\"").await.is_empty());
+        assert!(ids("after:2999-01-01").await.is_empty());
+        
+        let search = MailSearch::parse("subject:\"two words\" in:archive \"from:x\"", &chrono::Utc).unwrap();
+        assert_eq!(search.subject, vec!["two words".to_string()]);
+        assert_eq!(search.folder, Some(Folder::Archive));
+        assert_eq!(search.text, vec!["\"from:x\"".to_string()]);
+        assert!(MailSearch::parse("is:maybe", &chrono::Utc).is_err());
+        assert!(MailSearch::parse("before:yesterday", &chrono::Utc).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_export_and_import_mail_round_trip() {
+        let service = MailServiceImpl::new(InMemoryStorage::new());
+        let alice = service.create_agent("alice").await.unwrap();
+        let bob = service.create_agent("bob").await.unwrap();
+        let hello = service.send_mail(bob.id.clone(), vec![alice.id.clone()], Vec::new(), "Hello", "From the top\n>From below").await.unwrap();
+        service.label_mail(hello.id, None, vec!["greeting".to_string()], Vec::new()).await.unwrap();
+        service.mark_mail_as_read(hello.id, None, None).await.unwrap();
+        let reply = service.reply_to_mail(hello.id, Some(alice.id.clone()), "Hi bob").await.unwrap();
+        
+        let exported = service.export_mail(alice.id.clone()).await.unwrap();
+        assert_eq!(exported.iter().map(|m| m.id).collect::<Vec<_>>(), vec![hello.id, reply.id]);
+        
+        let mbox = archive::read_mbox(&archive::write_mbox(&exported)).unwrap();
+        assert_eq!(mbox[0].body, "From the top\n>From below");
+        assert_eq!(mbox[0].from, "bob");
+        assert_eq!(mbox[0].labels, vec!["greeting".to_string()]);
+        assert!(mbox[0].read);
+        assert_eq!(mbox[1].in_reply_to, Some(hello.id));
+        assert_eq!(mbox[1].thread_id, hello.thread_id);
+        
+        // mbox dates stop at seconds; JSONL keeps everything
+        let imported = archive::read_jsonl(&archive::write_jsonl(&exported).unwrap()).unwrap();
+        assert_eq!(imported, exported);
+        
+        let office = MailServiceImpl::new(InMemoryStorage::new());
+        office.create_agent("alice").await.unwrap();
+        office.create_agent("bob").await.unwrap();
+        let report = office.import_mail(alice.id.clone(), imported.clone()).await.unwrap();
+        assert_eq!(report, ImportReport { imported: 2, ..Default::default() });
+        let again = office.import_mail(alice.id.clone(), imported.clone()).await.unwrap();
+        assert_eq!(again, ImportReport { skipped: 2, ..Default::default() });
+        
+        let inbox = office.get_mailbox_inbox(string_to_node_id(&alice.id)).await.unwrap();
+        assert_eq!(inbox.len(), 1);
+        assert_eq!(inbox[0].subject, "Hello");
+        assert_eq!(inbox[0].labels, vec!["greeting".to_string()]);
+        assert!(inbox[0].read);
+        assert_eq!(inbox[0].seq, Some(1));
+        let thread = office.get_thread(reply.id).await.unwrap();
+        assert_eq!(thread.iter().map(|m| m.id).collect::<Vec<_>>(), vec![hello.id, reply.id]);
+        
+        // Bob's side of the same mail joins the nodes already imported
+        let report = office.import_mail(bob.id.clone(), imported).await.unwrap();
+        assert_eq!(report.imported, 2);
+        let bob_inbox = office.get_mailbox_inbox(string_to_node_id(&bob.id)).await.unwrap();
+        assert_eq!(bob_inbox.iter().map(|m| m.id).collect::<Vec<_>>(), vec![reply.id]);
+    }
 }
@@ -1,3 +1,8 @@
+pub mod approval;
 pub mod kb;
+pub mod kv;
+pub mod lease;
 pub mod mail;
 pub mod schedule;
+pub mod task;
+pub mod work;
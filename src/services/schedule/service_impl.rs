@@ -1,20 +1,95 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Duration, Timelike, Utc};
 use cron::Schedule as CronSchedule;
-use sqlx::{Pool, Postgres, Row};
+use sqlx::{Pool, Postgres, Row, Sqlite};
 use std::str::FromStr;
 use uuid::Uuid;
 
 use crate::services::schedule::{Result, ScheduleError, ScheduleService};
 use crate::services::schedule::domain::Schedule;
+use crate::storage::{AnyStorage, is_sqlite_url};
+use crate::storage::sqlite::SqliteStorage;
+
+/// Connection pool for the `schedules` table. The SQL below sticks to what
+/// both databases accept, so each query is written once and run on either.
+#[derive(Clone)]
+pub enum SchedulePool {
+    Postgres(Pool<Postgres>),
+    Sqlite(Pool<Sqlite>),
+}
+
+impl From<Pool<Postgres>> for SchedulePool {
+    fn from(pool: Pool<Postgres>) -> Self {
+        Self::Postgres(pool)
+    }
+}
+
+impl From<Pool<Sqlite>> for SchedulePool {
+    fn from(pool: Pool<Sqlite>) -> Self {
+        Self::Sqlite(pool)
+    }
+}
+
+/// Run `$body` against whichever pool is configured, bound to `$pool`
+macro_rules! with_pool {
+    ($self:ident, $pool:ident => $body:expr) => {
+        match &$self.pool {
+            SchedulePool::Postgres($pool) => $body,
+            SchedulePool::Sqlite($pool) => $body,
+        }
+    };
+}
+
+macro_rules! schedule_from_row {
+    ($row:expr) => {
+        Schedule {
+            id: $row.get("id"),
+            agent_id: $row.get("agent_id"),
+            cron_expression: $row.get("cron_expression"),
+            action: $row.get("action"),
+            is_active: $row.get("is_active"),
+            last_fired_at: $row.get("last_fired_at"),
+            created_at: $row.get("created_at"),
+            updated_at: $row.get("updated_at"),
+        }
+    };
+}
 
+/// Cloning shares the connection pool
+#[derive(Clone)]
 pub struct ScheduleServiceImpl {
-    pool: Pool<Postgres>,
+    pool: SchedulePool,
 }
 
 impl ScheduleServiceImpl {
-    pub fn new(pool: Pool<Postgres>) -> Self {
-        Self { pool }
+    pub fn new(pool: impl Into<SchedulePool>) -> Self {
+        Self { pool: pool.into() }
+    }
+
+    /// Share the connection pool of `storage`. In-memory storage has no
+    /// schedules table, so there is nothing to share.
+    pub fn from_storage(storage: &AnyStorage) -> Option<Self> {
+        match storage {
+            AnyStorage::Postgres(s) => Some(Self::new(s.pool().clone())),
+            AnyStorage::Sqlite(s) => Some(Self::new(s.pool().clone())),
+            AnyStorage::Memory(_) => None,
+        }
+    }
+
+    /// Connect to the database at `database_url` (Postgres, or SQLite for a
+    /// `sqlite:` URL). The schedules table comes from the schema migrations.
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        if is_sqlite_url(database_url) {
+            let storage = SqliteStorage::connect(database_url)
+                .await
+                .map_err(|e| ScheduleError::Storage(e.to_string()))?;
+            Ok(Self::new(storage.pool().clone()))
+        } else {
+            let pool = sqlx::postgres::PgPool::connect(database_url)
+                .await
+                .map_err(|e| ScheduleError::Storage(e.to_string()))?;
+            Ok(Self::new(pool))
+        }
     }
 
     /// Helper to validate cron expression
@@ -87,7 +162,7 @@ impl ScheduleService for ScheduleServiceImpl {
 
         let schedule = Schedule::new(agent_id, cron_expression, action);
 
-        sqlx::query(
+        with_pool!(self, pool => sqlx::query(
             r#"
             INSERT INTO schedules (id, agent_id, cron_expression, action, is_active, last_fired_at, created_at, updated_at)
             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
@@ -101,15 +176,16 @@ impl ScheduleService for ScheduleServiceImpl {
         .bind(schedule.last_fired_at)
         .bind(schedule.created_at)
         .bind(schedule.updated_at)
-        .execute(&self.pool)
+        .execute(pool)
         .await
+        .map(|_| ()))
         .map_err(|e| ScheduleError::Storage(e.to_string()))?;
 
         Ok(schedule)
     }
 
     async fn get_schedule(&self, id: Uuid) -> Result<Schedule> {
-        let row = sqlx::query(
+        with_pool!(self, pool => sqlx::query(
             r#"
             SELECT id, agent_id, cron_expression, action, is_active, last_fired_at, created_at, updated_at
             FROM schedules
@@ -117,27 +193,17 @@ impl ScheduleService for ScheduleServiceImpl {
             "#,
         )
         .bind(id)
-        .fetch_one(&self.pool)
+        .fetch_one(pool)
         .await
+        .map(|row| schedule_from_row!(row)))
         .map_err(|e| match e {
             sqlx::Error::RowNotFound => ScheduleError::ScheduleNotFound(id),
             _ => ScheduleError::Storage(e.to_string()),
-        })?;
-
-        Ok(Schedule {
-            id: row.get("id"),
-            agent_id: row.get("agent_id"),
-            cron_expression: row.get("cron_expression"),
-            action: row.get("action"),
-            is_active: row.get("is_active"),
-            last_fired_at: row.get("last_fired_at"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
         })
     }
 
     async fn list_schedules_by_agent(&self, agent_id: &str) -> Result<Vec<Schedule>> {
-        let rows = sqlx::query(
+        with_pool!(self, pool => sqlx::query(
             r#"
             SELECT id, agent_id, cron_expression, action, is_active, last_fired_at, created_at, updated_at
             FROM schedules
@@ -146,25 +212,10 @@ impl ScheduleService for ScheduleServiceImpl {
             "#,
         )
         .bind(agent_id)
-        .fetch_all(&self.pool)
+        .fetch_all(pool)
         .await
-        .map_err(|e| ScheduleError::Storage(e.to_string()))?;
-
-        let schedules = rows
-            .iter()
-            .map(|row| Schedule {
-                id: row.get("id"),
-                agent_id: row.get("agent_id"),
-                cron_expression: row.get("cron_expression"),
-                action: row.get("action"),
-                is_active: row.get("is_active"),
-                last_fired_at: row.get("last_fired_at"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            })
-            .collect();
-
-        Ok(schedules)
+        .map(|rows| rows.iter().map(|row| schedule_from_row!(row)).collect()))
+        .map_err(|e| ScheduleError::Storage(e.to_string()))
     }
 
     async fn update_schedule(
@@ -183,7 +234,7 @@ impl ScheduleService for ScheduleServiceImpl {
         let new_cron = cron_expression.unwrap_or_else(|| schedule.cron_expression.clone());
         let new_action = action.unwrap_or_else(|| schedule.action.clone());
 
-        sqlx::query(
+        with_pool!(self, pool => sqlx::query(
             r#"
             UPDATE schedules
             SET cron_expression = $1, action = $2, updated_at = $3
@@ -194,8 +245,9 @@ impl ScheduleService for ScheduleServiceImpl {
         .bind(&new_action)
         .bind(Utc::now())
         .bind(id)
-        .execute(&self.pool)
+        .execute(pool)
         .await
+        .map(|_| ()))
         .map_err(|e| ScheduleError::Storage(e.to_string()))?;
 
         let mut updated = schedule;
@@ -207,13 +259,14 @@ impl ScheduleService for ScheduleServiceImpl {
     }
 
     async fn delete_schedule(&self, id: Uuid) -> Result<()> {
-        let result = sqlx::query("DELETE FROM schedules WHERE id = $1")
+        let rows_affected = with_pool!(self, pool => sqlx::query("DELETE FROM schedules WHERE id = $1")
             .bind(id)
-            .execute(&self.pool)
+            .execute(pool)
             .await
+            .map(|result| result.rows_affected()))
             .map_err(|e| ScheduleError::Storage(e.to_string()))?;
 
-        if result.rows_affected() == 0 {
+        if rows_affected == 0 {
             return Err(ScheduleError::ScheduleNotFound(id));
         }
 
@@ -224,7 +277,7 @@ impl ScheduleService for ScheduleServiceImpl {
         let schedule = self.get_schedule(id).await?;
         let new_status = !schedule.is_active;
 
-        sqlx::query(
+        with_pool!(self, pool => sqlx::query(
             r#"
             UPDATE schedules
             SET is_active = $1, updated_at = $2
@@ -234,8 +287,9 @@ impl ScheduleService for ScheduleServiceImpl {
         .bind(new_status)
         .bind(Utc::now())
         .bind(id)
-        .execute(&self.pool)
+        .execute(pool)
         .await
+        .map(|_| ()))
         .map_err(|e| ScheduleError::Storage(e.to_string()))?;
 
         let mut updated = schedule;
@@ -256,7 +310,7 @@ impl ScheduleService for ScheduleServiceImpl {
         for schedule in schedules {
             if self.should_fire(&schedule, current_time) {
                 // Update last_fired_at
-                sqlx::query(
+                with_pool!(self, pool => sqlx::query(
                     r#"
                     UPDATE schedules
                     SET last_fired_at = $1, updated_at = $2
@@ -266,8 +320,9 @@ impl ScheduleService for ScheduleServiceImpl {
                 .bind(current_time)
                 .bind(Utc::now())
                 .bind(schedule.id)
-                .execute(&self.pool)
+                .execute(pool)
                 .await
+                .map(|_| ()))
                 .map_err(|e| ScheduleError::Storage(e.to_string()))?;
 
                 fired_actions.push(schedule.action.clone());
@@ -286,3 +341,58 @@ impl ScheduleService for ScheduleServiceImpl {
         cron.after(&current_time).next()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    async fn service() -> ScheduleServiceImpl {
+        let storage = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+        storage.migrate().await.unwrap();
+        ScheduleServiceImpl::new(storage.pool().clone())
+    }
+
+    #[tokio::test]
+    async fn test_schedule_crud_on_sqlite() {
+        let service = service().await;
+
+        let created = service
+            .create_schedule("alice".to_string(), "0 9 * * *".to_string(), "standup".to_string())
+            .await
+            .unwrap();
+        assert_eq!(service.get_schedule(created.id).await.unwrap(), created);
+
+        let updated = service
+            .update_schedule(created.id, None, Some("report".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(updated.action, "report");
+
+        let toggled = service.toggle_schedule(created.id).await.unwrap();
+        assert!(!toggled.is_active);
+
+        assert_eq!(service.list_schedules_by_agent("alice").await.unwrap().len(), 1);
+        service.delete_schedule(created.id).await.unwrap();
+        assert!(matches!(
+            service.delete_schedule(created.id).await,
+            Err(ScheduleError::ScheduleNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_schedule_fires_once_per_minute() {
+        let service = service().await;
+        service
+            .create_schedule("alice".to_string(), "*/5 * * * *".to_string(), "check".to_string())
+            .await
+            .unwrap();
+
+        let due = Utc.with_ymd_and_hms(2025, 1, 1, 9, 5, 10).unwrap();
+        assert_eq!(service.check_and_fire_schedules("alice", due).await.unwrap(), vec!["check"]);
+        assert!(service.check_and_fire_schedules("alice", due).await.unwrap().is_empty());
+
+        let not_due = Utc.with_ymd_and_hms(2025, 1, 1, 9, 6, 0).unwrap();
+        assert!(service.check_and_fire_schedules("alice", not_due).await.unwrap().is_empty());
+    }
+}
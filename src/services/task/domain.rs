@@ -0,0 +1,231 @@
+use crate::domain::{Node, NodeId, Properties, PropertyValue, Timestamp};
+use crate::services::mail::domain::{AgentId, Priority};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+pub type TaskId = NodeId;
+
+/// Where a task is in its life. A task is blocked while a task that blocks
+/// it is unfinished, and opens up again once they are all done.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    #[default]
+    Open,
+    InProgress,
+    Blocked,
+    Done,
+}
+
+impl TaskStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskStatus::Open => "open",
+            TaskStatus::InProgress => "in_progress",
+            TaskStatus::Blocked => "blocked",
+            TaskStatus::Done => "done",
+        }
+    }
+
+    /// Listing order: work under way first, finished work last
+    fn rank(&self) -> u8 {
+        match self {
+            TaskStatus::InProgress => 0,
+            TaskStatus::Open => 1,
+            TaskStatus::Blocked => 2,
+            TaskStatus::Done => 3,
+        }
+    }
+}
+
+impl std::str::FromStr for TaskStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "open" => Ok(TaskStatus::Open),
+            "in_progress" => Ok(TaskStatus::InProgress),
+            "blocked" => Ok(TaskStatus::Blocked),
+            "done" => Ok(TaskStatus::Done),
+            _ => Err(format!("unknown status '{}' (expected open, in_progress, blocked or done)", s)),
+        }
+    }
+}
+
+/// A piece of work someone owns. Stored as a task node; the assignee is
+/// also linked by an assigned_to edge to their agent, and a `blocks` edge
+/// runs from each task to every task that can't start until it is done.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Task {
+    pub id: TaskId,
+    pub title: String,
+    pub description: String,
+    pub created_by: AgentId,
+    pub assignee: Option<AgentId>,
+    pub status: TaskStatus,
+    pub priority: Priority,
+    pub due: Option<Timestamp>,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+}
+
+impl Task {
+    /// An open, unassigned task of normal priority
+    pub fn new(created_by: impl Into<AgentId>, title: impl Into<String>) -> Self {
+        let now = Utc::now();
+        Self {
+            id: TaskId::new_v4(),
+            title: title.into(),
+            description: String::new(),
+            created_by: created_by.into(),
+            assignee: None,
+            status: TaskStatus::Open,
+            priority: Priority::Normal,
+            due: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn short_id(&self) -> String {
+        self.id.to_string()[..8].to_string()
+    }
+
+    pub fn is_overdue(&self, now: Timestamp) -> bool {
+        self.status != TaskStatus::Done && self.due.is_some_and(|due| due < now)
+    }
+
+    /// Sort key for listings: by status, then most urgent, then soonest due
+    pub fn list_order(&self) -> impl Ord + use<> {
+        (
+            self.status.rank(),
+            std::cmp::Reverse(self.priority),
+            self.due.is_none(),
+            self.due,
+            self.created_at,
+        )
+    }
+
+    pub fn to_node(&self) -> Node {
+        let mut props = Properties::new();
+        props.insert("title".to_string(), PropertyValue::String(self.title.clone()));
+        props.insert("description".to_string(), PropertyValue::String(self.description.clone()));
+        props.insert("created_by".to_string(), PropertyValue::String(self.created_by.clone()));
+        if let Some(ref assignee) = self.assignee {
+            props.insert("assignee".to_string(), PropertyValue::String(assignee.clone()));
+        }
+        props.insert("status".to_string(), PropertyValue::String(self.status.as_str().to_string()));
+        props.insert("priority".to_string(), PropertyValue::String(self.priority.as_str().to_string()));
+        if let Some(due) = self.due {
+            props.insert("due".to_string(), PropertyValue::Timestamp(due));
+        }
+
+        let mut node = Node::new("task", props);
+        node.id = self.id;
+        node.created_at = self.created_at;
+        node.updated_at = self.updated_at;
+        node
+    }
+
+    pub fn from_node(node: &Node) -> Option<Self> {
+        if node.node_type != "task" {
+            return None;
+        }
+
+        let text = |key: &str| node.get_property(key).and_then(|v| v.as_str()).map(str::to_string);
+
+        Some(Self {
+            id: node.id,
+            title: text("title")?,
+            description: text("description").unwrap_or_default(),
+            created_by: text("created_by")?,
+            assignee: text("assignee"),
+            status: text("status").and_then(|s| s.parse().ok()).unwrap_or_default(),
+            priority: text("priority").and_then(|s| s.parse().ok()).unwrap_or_default(),
+            due: match node.get_property("due") {
+                Some(PropertyValue::Timestamp(t)) => Some(*t),
+                _ => None,
+            },
+            created_at: node.created_at,
+            updated_at: node.updated_at,
+        })
+    }
+}
+
+/// Which tasks to list; done tasks are left out unless asked for by status
+/// or with `include_done`
+#[derive(Debug, Clone, Default)]
+pub struct TaskFilter {
+    pub assignee: Option<AgentId>,
+    pub status: Option<TaskStatus>,
+    pub include_done: bool,
+}
+
+impl TaskFilter {
+    pub fn matches(&self, task: &Task) -> bool {
+        self.assignee.as_ref().is_none_or(|a| task.assignee.as_ref() == Some(a))
+            && match self.status {
+                Some(status) => task.status == status,
+                None => self.include_done || task.status != TaskStatus::Done,
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_status_parsing() {
+        assert_eq!("open".parse::<TaskStatus>().unwrap(), TaskStatus::Open);
+        assert_eq!("In-Progress".parse::<TaskStatus>().unwrap(), TaskStatus::InProgress);
+        assert_eq!("in_progress".parse::<TaskStatus>().unwrap(), TaskStatus::InProgress);
+        assert_eq!("DONE".parse::<TaskStatus>().unwrap(), TaskStatus::Done);
+        assert!("finished".parse::<TaskStatus>().is_err());
+    }
+
+    #[test]
+    fn test_task_status_round_trip() {
+        for status in [TaskStatus::Open, TaskStatus::InProgress, TaskStatus::Blocked, TaskStatus::Done] {
+            assert_eq!(status.as_str().parse::<TaskStatus>().unwrap(), status);
+        }
+    }
+
+    #[test]
+    fn test_task_node_round_trip() {
+        let task = Task {
+            description: "REST endpoints".to_string(),
+            assignee: Some("bob".to_string()),
+            status: TaskStatus::InProgress,
+            priority: Priority::Urgent,
+            due: Some(Utc::now()),
+            ..Task::new("alice", "Build API")
+        };
+        assert_eq!(Task::from_node(&task.to_node()), Some(task));
+        assert_eq!(Task::from_node(&Node::new("note", Properties::new())), None);
+    }
+
+    #[test]
+    fn test_list_order() {
+        let now = Utc::now();
+        let started = Task { status: TaskStatus::InProgress, ..Task::new("alice", "Started") };
+        let urgent = Task { priority: Priority::Urgent, ..Task::new("alice", "Urgent") };
+        let due = Task { due: Some(now), ..Task::new("alice", "Due") };
+        let plain = Task::new("alice", "Plain");
+        let done = Task { status: TaskStatus::Done, priority: Priority::Urgent, ..Task::new("alice", "Done") };
+
+        let mut tasks = [done, plain, due, urgent, started];
+        tasks.sort_by_key(|t| t.list_order());
+        let titles: Vec<&str> = tasks.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, vec!["Started", "Urgent", "Due", "Plain", "Done"]);
+    }
+
+    #[test]
+    fn test_overdue_unless_done() {
+        let now = Utc::now();
+        let late = Task { due: Some(now - chrono::Duration::hours(1)), ..Task::new("alice", "Late") };
+        assert!(late.is_overdue(now));
+        assert!(!Task { status: TaskStatus::Done, ..late.clone() }.is_overdue(now));
+        assert!(!Task::new("alice", "Whenever").is_overdue(now));
+    }
+}
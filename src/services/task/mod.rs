@@ -0,0 +1,422 @@
+use crate::domain::{Edge, GraphQuery, Properties, PropertyValue, string_to_node_id};
+use crate::services::mail::domain::{AgentId, SendOptions};
+use crate::services::mail::{MailError, MailService};
+use crate::services::task::domain::{Task, TaskFilter, TaskId, TaskStatus};
+use crate::storage::{GraphStorage, StorageError};
+use async_trait::async_trait;
+use thiserror::Error;
+
+pub mod domain;
+
+#[derive(Error, Debug)]
+pub enum TaskError {
+    #[error("Task not found: {0}")]
+    TaskNotFound(String),
+
+    #[error("Invalid operation: {0}")]
+    InvalidOperation(String),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error(transparent)]
+    Mail(#[from] MailError),
+}
+
+pub type Result<T> = std::result::Result<T, TaskError>;
+
+/// Tasks agents hand each other, so "what is bob working on?" has an answer.
+/// Assignees hear about new work by mail, which wakes `agent run`.
+#[async_trait]
+pub trait TaskService: Send + Sync {
+    // Create a task that can't start until every task in `blocked_by` is
+    // done. If it already has an assignee they are notified as by `assign_task`.
+    async fn create_task(&self, task: Task, blocked_by: Vec<TaskId>) -> Result<Task>;
+
+    async fn get_task(&self, task_id: TaskId) -> Result<Task>;
+
+    // Find a task by short ID (8-char prefix) or full ID
+    async fn get_task_by_short_id(&self, short_id: &str) -> Result<Task>;
+
+    // Tasks matching `filter`, under way first, then by priority and due date
+    async fn list_tasks(&self, filter: &TaskFilter) -> Result<Vec<Task>>;
+
+    // Hand a task to an agent and mail them about it. `assigned_by` sends
+    // the mail and defaults to whoever created the task; nobody is mailed
+    // about a task they assign to themselves.
+    async fn assign_task(&self, task_id: TaskId, assignee: AgentId, assigned_by: Option<AgentId>) -> Result<Task>;
+
+    // Begin work on a task; fails while a task blocking it is unfinished
+    async fn start_task(&self, task_id: TaskId) -> Result<Task>;
+
+    // Finish a task; fails while a task blocking it is unfinished. Returns it
+    // with the tasks it was blocking that can now start, which go back to open.
+    async fn complete_task(&self, task_id: TaskId) -> Result<(Task, Vec<Task>)>;
+
+    // Unfinished tasks that `task_id` is waiting on
+    async fn get_blockers(&self, task_id: TaskId) -> Result<Vec<Task>>;
+}
+
+pub struct TaskServiceImpl<S: GraphStorage, M: MailService> {
+    storage: S,
+    mail: M,
+}
+
+impl<S: GraphStorage, M: MailService> TaskServiceImpl<S, M> {
+    pub fn new(storage: S, mail: M) -> Self {
+        Self { storage, mail }
+    }
+
+    async fn save(&self, mut task: Task) -> Result<Task> {
+        task.updated_at = chrono::Utc::now();
+        self.storage.update_node(&task.to_node()).await?;
+        Ok(task)
+    }
+
+    // Fail if a task `task` is waiting on is unfinished
+    async fn check_unblocked(&self, task: &Task) -> Result<()> {
+        let blockers = self.get_blockers(task.id).await?;
+        if blockers.is_empty() {
+            return Ok(());
+        }
+        let names: Vec<String> = blockers.iter()
+            .map(|b| format!("{} ({})", b.short_id(), b.title))
+            .collect();
+        Err(TaskError::InvalidOperation(
+            format!("Task {} is blocked by {}", task.short_id(), names.join(", "))
+        ))
+    }
+
+    // Tell the assignee about their new task, from the agent who handed it over
+    async fn notify_assignee(&self, task: &Task, assigned_by: &AgentId) -> Result<()> {
+        let Some(ref assignee) = task.assignee else { return Ok(()) };
+        if assignee == assigned_by {
+            return Ok(());
+        }
+
+        let mut body = format!("{} assigned you a task: {}\n", assigned_by, task.title);
+        if !task.description.is_empty() {
+            body.push_str(&format!("\n{}\n", task.description));
+        }
+        body.push_str(&format!("\nPriority: {}\n", task.priority.as_str()));
+        if let Some(due) = task.due {
+            body.push_str(&format!("Due: {}\n", due.format("%Y-%m-%d %H:%M UTC")));
+        }
+        body.push_str(&format!(
+            "\nRun `agent-office task start {}` when you begin and `agent-office task done {}` when it's finished.",
+            task.short_id(),
+            task.short_id()
+        ));
+
+        let options = SendOptions {
+            priority: task.priority,
+            labels: vec!["task".to_string()],
+            ..Default::default()
+        };
+        self.mail.send_mail_with(
+            assigned_by.clone(),
+            vec![assignee.clone()],
+            Vec::new(),
+            format!("Task assigned: {}", task.title),
+            body,
+            options,
+        ).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<S: GraphStorage, M: MailService> TaskService for TaskServiceImpl<S, M> {
+    async fn create_task(&self, mut task: Task, blocked_by: Vec<TaskId>) -> Result<Task> {
+        if task.title.trim().is_empty() {
+            return Err(TaskError::InvalidOperation("A task needs a title".to_string()));
+        }
+        self.mail.get_agent(task.created_by.clone()).await?;
+        if let Some(ref assignee) = task.assignee {
+            self.mail.get_agent(assignee.clone()).await?;
+        }
+
+        let mut blockers = Vec::with_capacity(blocked_by.len());
+        for id in blocked_by {
+            blockers.push(self.get_task(id).await?);
+        }
+        if blockers.iter().any(|b| b.status != TaskStatus::Done) {
+            task.status = TaskStatus::Blocked;
+        }
+
+        let mut tx = self.storage.begin();
+        tx.create_node(&task.to_node());
+        if let Some(ref assignee) = task.assignee {
+            tx.create_edge(&Edge::new("assigned_to", task.id, string_to_node_id(assignee), Properties::new()));
+        }
+        for blocker in &blockers {
+            tx.create_edge(&Edge::new("blocks", blocker.id, task.id, Properties::new()));
+        }
+        self.storage.commit(tx).await?;
+
+        self.notify_assignee(&task, &task.created_by).await?;
+        Ok(task)
+    }
+
+    async fn get_task(&self, task_id: TaskId) -> Result<Task> {
+        match self.storage.get_node(task_id).await {
+            Ok(node) => Task::from_node(&node).ok_or_else(|| TaskError::TaskNotFound(task_id.to_string())),
+            Err(StorageError::NodeNotFound(_)) => Err(TaskError::TaskNotFound(task_id.to_string())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get_task_by_short_id(&self, short_id: &str) -> Result<Task> {
+        let query = GraphQuery::new().with_node_type("task");
+        let nodes = self.storage.query_nodes(&query).await?;
+
+        let short_id_lower = short_id.to_lowercase();
+        let mut matching: Vec<_> = nodes.iter()
+            .filter_map(Task::from_node)
+            .filter(|t| t.id.to_string().starts_with(&short_id_lower))
+            .collect();
+
+        match matching.len() {
+            0 => Err(TaskError::TaskNotFound(short_id.to_string())),
+            1 => Ok(matching.remove(0)),
+            _ => Err(TaskError::InvalidOperation(
+                format!("Multiple tasks match short ID '{}', please use full ID", short_id)
+            )),
+        }
+    }
+
+    async fn list_tasks(&self, filter: &TaskFilter) -> Result<Vec<Task>> {
+        let mut query = GraphQuery::new().with_node_type("task");
+        if let Some(ref assignee) = filter.assignee {
+            query = query.with_property("assignee", PropertyValue::String(assignee.clone()));
+        }
+        let nodes = self.storage.query_nodes(&query).await?;
+        let mut tasks: Vec<Task> = nodes.iter()
+            .filter_map(Task::from_node)
+            .filter(|t| filter.matches(t))
+            .collect();
+        tasks.sort_by_key(|t| t.list_order());
+        Ok(tasks)
+    }
+
+    async fn assign_task(&self, task_id: TaskId, assignee: AgentId, assigned_by: Option<AgentId>) -> Result<Task> {
+        let mut task = self.get_task(task_id).await?;
+        if task.status == TaskStatus::Done {
+            return Err(TaskError::InvalidOperation(format!("Task {} is already done", task.short_id())));
+        }
+        let assignee = self.mail.get_agent(assignee).await?.id;
+        // Checked up front so an unknown assigner can't reassign the task
+        // and then fail to send the notice
+        let assigned_by = assigned_by.unwrap_or_else(|| task.created_by.clone());
+        let assigned_by = self.mail.get_agent(assigned_by).await?.id;
+        if task.assignee.as_ref() == Some(&assignee) {
+            return Ok(task);
+        }
+
+        // Work someone else had started goes back to the pile
+        if task.status == TaskStatus::InProgress {
+            task.status = TaskStatus::Open;
+        }
+        task.assignee = Some(assignee.clone());
+        task.updated_at = chrono::Utc::now();
+
+        let mut tx = self.storage.begin();
+        for edge in self.storage.get_edges_from(task.id, Some("assigned_to")).await? {
+            tx.delete_edge(edge.id);
+        }
+        tx.update_node(&task.to_node())
+            .create_edge(&Edge::new("assigned_to", task.id, string_to_node_id(&assignee), Properties::new()));
+        self.storage.commit(tx).await?;
+
+        self.notify_assignee(&task, &assigned_by).await?;
+        Ok(task)
+    }
+
+    async fn start_task(&self, task_id: TaskId) -> Result<Task> {
+        let mut task = self.get_task(task_id).await?;
+        match task.status {
+            TaskStatus::InProgress => return Ok(task),
+            TaskStatus::Done => {
+                return Err(TaskError::InvalidOperation(format!("Task {} is already done", task.short_id())));
+            }
+            TaskStatus::Open | TaskStatus::Blocked => {}
+        }
+        self.check_unblocked(&task).await?;
+        task.status = TaskStatus::InProgress;
+        self.save(task).await
+    }
+
+    async fn complete_task(&self, task_id: TaskId) -> Result<(Task, Vec<Task>)> {
+        let mut task = self.get_task(task_id).await?;
+        if task.status == TaskStatus::Done {
+            return Ok((task, Vec::new()));
+        }
+        self.check_unblocked(&task).await?;
+        task.status = TaskStatus::Done;
+        let task = self.save(task).await?;
+
+        let mut unblocked = Vec::new();
+        for edge in self.storage.get_edges_from(task.id, Some("blocks")).await? {
+            let Ok(mut blocked) = self.get_task(edge.to_node_id).await else { continue };
+            if blocked.status == TaskStatus::Blocked && self.get_blockers(blocked.id).await?.is_empty() {
+                blocked.status = TaskStatus::Open;
+                unblocked.push(self.save(blocked).await?);
+            }
+        }
+        Ok((task, unblocked))
+    }
+
+    async fn get_blockers(&self, task_id: TaskId) -> Result<Vec<Task>> {
+        let ids: Vec<TaskId> = self.storage.get_edges_to(task_id, Some("blocks")).await?
+            .iter()
+            .map(|edge| edge.from_node_id)
+            .collect();
+        let mut blockers: Vec<Task> = self.storage.get_nodes(&ids).await?
+            .iter()
+            .filter_map(Task::from_node)
+            .filter(|t| t.status != TaskStatus::Done)
+            .collect();
+        blockers.sort_by_key(|t| t.created_at);
+        Ok(blockers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::mail::MailServiceImpl;
+    use crate::services::mail::domain::Priority;
+    use crate::storage::memory::InMemoryStorage;
+
+    type Tasks = TaskServiceImpl<InMemoryStorage, MailServiceImpl<InMemoryStorage>>;
+
+    // A task service with agents alice and bob
+    async fn setup() -> Tasks {
+        let storage = InMemoryStorage::new();
+        let mail = MailServiceImpl::new(storage.clone());
+        mail.create_agent("alice").await.unwrap();
+        mail.create_agent("bob").await.unwrap();
+        TaskServiceImpl::new(storage, mail)
+    }
+
+    #[tokio::test]
+    async fn test_assigning_mails_the_assignee() {
+        let tasks = setup().await;
+        let api = Task { priority: Priority::Urgent, ..Task::new("alice", "Build API") };
+        let api = tasks.create_task(api, Vec::new()).await.unwrap();
+
+        let api = tasks.assign_task(api.id, "bob".into(), None).await.unwrap();
+        assert_eq!(api.assignee.as_deref(), Some("bob"));
+        let (has_unread, unread) = tasks.mail.check_unread_mail("bob".into(), false).await.unwrap();
+        assert!(has_unread);
+        assert_eq!(unread[0].subject, "Task assigned: Build API");
+        assert_eq!(unread[0].from_mailbox_id, string_to_node_id("alice"));
+        assert_eq!(unread[0].priority, Priority::Urgent);
+        assert_eq!(unread[0].labels, vec!["task".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_nobody_is_mailed_about_a_task_they_assign_themselves() {
+        let tasks = setup().await;
+        let task = Task { assignee: Some("alice".to_string()), ..Task::new("alice", "Tidy up") };
+        tasks.create_task(task, Vec::new()).await.unwrap();
+        assert!(!tasks.mail.check_unread_mail("alice".into(), false).await.unwrap().0);
+    }
+
+    #[tokio::test]
+    async fn test_reassigning_moves_the_edge_and_reopens_started_work() {
+        let tasks = setup().await;
+        let task = Task { assignee: Some("alice".to_string()), ..Task::new("alice", "Build API") };
+        let task = tasks.create_task(task, Vec::new()).await.unwrap();
+        tasks.start_task(task.id).await.unwrap();
+
+        let task = tasks.assign_task(task.id, "bob".into(), None).await.unwrap();
+        assert_eq!(task.status, TaskStatus::Open);
+        let edges = tasks.storage.get_edges_from(task.id, Some("assigned_to")).await.unwrap();
+        assert_eq!(edges.iter().map(|e| e.to_node_id).collect::<Vec<_>>(), vec![string_to_node_id("bob")]);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_assigner_leaves_the_assignee_unchanged() {
+        let tasks = setup().await;
+        let task = Task { assignee: Some("alice".to_string()), ..Task::new("alice", "Build API") };
+        let task = tasks.create_task(task, Vec::new()).await.unwrap();
+
+        let result = tasks.assign_task(task.id, "bob".into(), Some("mallory".into())).await;
+        assert!(result.is_err());
+        let task = tasks.get_task(task.id).await.unwrap();
+        assert_eq!(task.assignee.as_deref(), Some("alice"));
+        let edges = tasks.storage.get_edges_from(task.id, Some("assigned_to")).await.unwrap();
+        assert_eq!(edges.iter().map(|e| e.to_node_id).collect::<Vec<_>>(), vec![string_to_node_id("alice")]);
+        assert!(!tasks.mail.check_unread_mail("bob".into(), false).await.unwrap().0);
+    }
+
+    #[tokio::test]
+    async fn test_blocked_task_cannot_start_until_blockers_are_done() {
+        let tasks = setup().await;
+        let schema = tasks.create_task(Task::new("alice", "Design schema"), Vec::new()).await.unwrap();
+        let api = tasks.create_task(Task::new("alice", "Build API"), vec![schema.id]).await.unwrap();
+        assert_eq!(api.status, TaskStatus::Blocked);
+        assert_eq!(tasks.get_blockers(api.id).await.unwrap(), vec![schema.clone()]);
+        assert!(matches!(tasks.start_task(api.id).await, Err(TaskError::InvalidOperation(_))));
+
+        let (_, unblocked) = tasks.complete_task(schema.id).await.unwrap();
+        assert_eq!(unblocked.iter().map(|t| (t.id, t.status)).collect::<Vec<_>>(), vec![(api.id, TaskStatus::Open)]);
+        assert_eq!(tasks.start_task(api.id).await.unwrap().status, TaskStatus::InProgress);
+    }
+
+    #[tokio::test]
+    async fn test_blocked_task_cannot_be_completed() {
+        let tasks = setup().await;
+        let schema = tasks.create_task(Task::new("alice", "Design schema"), Vec::new()).await.unwrap();
+        let api = tasks.create_task(Task::new("alice", "Build API"), vec![schema.id]).await.unwrap();
+
+        let err = tasks.complete_task(api.id).await.unwrap_err();
+        assert!(matches!(err, TaskError::InvalidOperation(ref msg) if msg.contains("Design schema")));
+        assert_eq!(tasks.get_task(api.id).await.unwrap().status, TaskStatus::Blocked);
+
+        tasks.complete_task(schema.id).await.unwrap();
+        assert_eq!(tasks.complete_task(api.id).await.unwrap().0.status, TaskStatus::Done);
+    }
+
+    #[tokio::test]
+    async fn test_blocked_by_a_done_task_starts_open() {
+        let tasks = setup().await;
+        let schema = tasks.create_task(Task::new("alice", "Design schema"), Vec::new()).await.unwrap();
+        tasks.complete_task(schema.id).await.unwrap();
+        let api = tasks.create_task(Task::new("alice", "Build API"), vec![schema.id]).await.unwrap();
+        assert_eq!(api.status, TaskStatus::Open);
+    }
+
+    #[tokio::test]
+    async fn test_done_task_cannot_be_reassigned() {
+        let tasks = setup().await;
+        let task = tasks.create_task(Task::new("alice", "Build API"), Vec::new()).await.unwrap();
+        let (done, _) = tasks.complete_task(task.id).await.unwrap();
+        assert_eq!(done.status, TaskStatus::Done);
+        assert!(matches!(tasks.assign_task(task.id, "bob".into(), None).await, Err(TaskError::InvalidOperation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_listing_by_assignee_leaves_out_done_tasks() {
+        let tasks = setup().await;
+        let bobs = |title: &str| Task { assignee: Some("bob".to_string()), ..Task::new("alice", title) };
+        let api = tasks.create_task(bobs("Build API"), Vec::new()).await.unwrap();
+        let docs = tasks.create_task(bobs("Write docs"), Vec::new()).await.unwrap();
+        tasks.create_task(Task::new("alice", "Unassigned"), Vec::new()).await.unwrap();
+        tasks.complete_task(docs.id).await.unwrap();
+
+        let filter = TaskFilter { assignee: Some("bob".to_string()), ..Default::default() };
+        assert_eq!(tasks.list_tasks(&filter).await.unwrap().iter().map(|t| t.id).collect::<Vec<_>>(), vec![api.id]);
+        let filter = TaskFilter { include_done: true, ..filter };
+        assert_eq!(tasks.list_tasks(&filter).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_find_task_by_short_id() {
+        let tasks = setup().await;
+        let task = tasks.create_task(Task::new("alice", "Build API"), Vec::new()).await.unwrap();
+        assert_eq!(tasks.get_task_by_short_id(&task.short_id()).await.unwrap(), task);
+        assert!(matches!(tasks.get_task_by_short_id("zzzzzzzz").await, Err(TaskError::TaskNotFound(_))));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::domain::{Node, NodeId, Properties, PropertyValue, Timestamp};
+use crate::services::mail::domain::AgentId;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+pub type WorkItemId = NodeId;
+
+/// One step of a multi-step project. Stored as a work_item node with a
+/// depends_on edge to each item that has to be complete before it can start.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WorkItem {
+    pub id: WorkItemId,
+    pub title: String,
+    pub description: String,
+    /// Groups the steps of one project together
+    pub project: Option<String>,
+    pub created_by: AgentId,
+    pub assignee: Option<AgentId>,
+    pub completed_at: Option<Timestamp>,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+}
+
+impl WorkItem {
+    pub fn new(created_by: impl Into<AgentId>, title: impl Into<String>) -> Self {
+        let now = Utc::now();
+        Self {
+            id: WorkItemId::new_v4(),
+            title: title.into(),
+            description: String::new(),
+            project: None,
+            created_by: created_by.into(),
+            assignee: None,
+            completed_at: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn short_id(&self) -> String {
+        self.id.to_string()[..8].to_string()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.completed_at.is_some()
+    }
+
+    pub fn to_node(&self) -> Node {
+        let mut props = Properties::new();
+        props.insert("title".to_string(), PropertyValue::String(self.title.clone()));
+        props.insert("description".to_string(), PropertyValue::String(self.description.clone()));
+        props.insert("created_by".to_string(), PropertyValue::String(self.created_by.clone()));
+        if let Some(ref project) = self.project {
+            props.insert("project".to_string(), PropertyValue::String(project.clone()));
+        }
+        if let Some(ref assignee) = self.assignee {
+            props.insert("assignee".to_string(), PropertyValue::String(assignee.clone()));
+        }
+        if let Some(completed_at) = self.completed_at {
+            props.insert("completed_at".to_string(), PropertyValue::Timestamp(completed_at));
+        }
+
+        let mut node = Node::new("work_item", props);
+        node.id = self.id;
+        node.created_at = self.created_at;
+        node.updated_at = self.updated_at;
+        node
+    }
+
+    pub fn from_node(node: &Node) -> Option<Self> {
+        if node.node_type != "work_item" {
+            return None;
+        }
+
+        let text = |key: &str| node.get_property(key).and_then(|v| v.as_str()).map(str::to_string);
+
+        Some(Self {
+            id: node.id,
+            title: text("title")?,
+            description: text("description").unwrap_or_default(),
+            project: text("project"),
+            created_by: text("created_by")?,
+            assignee: text("assignee"),
+            completed_at: match node.get_property("completed_at") {
+                Some(PropertyValue::Timestamp(t)) => Some(*t),
+                _ => None,
+            },
+            created_at: node.created_at,
+            updated_at: node.updated_at,
+        })
+    }
+}
+
+/// Whether a work item can be picked up, worked out from its dependencies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkState {
+    /// Something it depends on is still incomplete
+    Waiting,
+    /// Everything it depends on is complete
+    Ready,
+    Complete,
+}
+
+impl WorkState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WorkState::Waiting => "waiting",
+            WorkState::Ready => "ready",
+            WorkState::Complete => "complete",
+        }
+    }
+}
+
+/// Work items with their states and the dependencies between them, for
+/// drawing as a DAG
+#[derive(Debug, Clone, Default)]
+pub struct WorkGraph {
+    pub items: Vec<(WorkItem, WorkState)>,
+    /// (item, what it depends on), both indexes into `items`
+    pub dependencies: Vec<(usize, usize)>,
+}
+
+impl WorkGraph {
+    /// Items arranged in columns: each item sits one column to the right of
+    /// the furthest item it depends on, so dependencies always point left
+    pub fn layers(&self) -> Vec<Vec<usize>> {
+        let mut depth = vec![0usize; self.items.len()];
+        // Longest path by relaxation; at most one pass per item in a DAG
+        for _ in 0..self.items.len() {
+            let mut changed = false;
+            for &(item, dependency) in &self.dependencies {
+                if depth[item] < depth[dependency] + 1 {
+                    depth[item] = depth[dependency] + 1;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        let columns = depth.iter().max().map_or(0, |d| d + 1);
+        let mut layers = vec![Vec::new(); columns];
+        for (index, d) in depth.into_iter().enumerate() {
+            layers[d].push(index);
+        }
+        layers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(items: usize, dependencies: Vec<(usize, usize)>) -> WorkGraph {
+        WorkGraph {
+            items: (0..items).map(|i| (WorkItem::new("alice", format!("Item {}", i)), WorkState::Waiting)).collect(),
+            dependencies,
+        }
+    }
+
+    #[test]
+    fn test_work_item_node_round_trip() {
+        let item = WorkItem {
+            description: "REST endpoints".to_string(),
+            project: Some("launch".to_string()),
+            assignee: Some("bob".to_string()),
+            completed_at: Some(Utc::now()),
+            ..WorkItem::new("alice", "Build API")
+        };
+        assert_eq!(WorkItem::from_node(&item.to_node()), Some(item));
+
+        let bare = WorkItem::new("alice", "Plan");
+        assert_eq!(WorkItem::from_node(&bare.to_node()), Some(bare));
+    }
+
+    #[test]
+    fn test_work_item_from_other_nodes() {
+        assert_eq!(WorkItem::from_node(&Node::new("task", Properties::new())), None);
+
+        // A work item without a title or creator is unreadable, not blank
+        let mut node = WorkItem::new("alice", "Plan").to_node();
+        node.properties.remove("title");
+        assert_eq!(WorkItem::from_node(&node), None);
+    }
+
+    #[test]
+    fn test_layers_follow_the_longest_path() {
+        // 3 depends on 1 and 2, which both depend on 0; 4 depends on 0 only
+        let diamond = graph(5, vec![(1, 0), (2, 0), (3, 1), (3, 2), (4, 0)]);
+        assert_eq!(diamond.layers(), vec![vec![0], vec![1, 2, 4], vec![3]]);
+
+        // Listed before its dependency, an item still lands to its right
+        let backwards = graph(3, vec![(0, 1), (1, 2)]);
+        assert_eq!(backwards.layers(), vec![vec![2], vec![1], vec![0]]);
+    }
+
+    #[test]
+    fn test_layers_of_unconnected_items() {
+        assert_eq!(graph(3, Vec::new()).layers(), vec![vec![0, 1, 2]]);
+        assert!(graph(0, Vec::new()).layers().is_empty());
+    }
+}
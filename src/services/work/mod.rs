@@ -0,0 +1,648 @@
+use crate::domain::{Edge, GraphQuery, Node, Properties, PropertyValue, string_to_node_id};
+use crate::services::mail::domain::{AgentId, SendOptions};
+use crate::services::mail::{MailError, MailService};
+use crate::services::work::domain::{WorkGraph, WorkItem, WorkItemId, WorkState};
+use crate::storage::{GraphStorage, StorageError};
+use async_trait::async_trait;
+use std::collections::{HashMap, HashSet, VecDeque};
+use thiserror::Error;
+
+pub mod domain;
+
+// Marks that an item's assignee was told it is ready. Its ID is fixed per
+// item, so only one of several completions racing to announce it succeeds.
+const READY_NOTICE: &str = "work_ready_notice";
+
+fn ready_notice_id(item_id: WorkItemId) -> uuid::Uuid {
+    string_to_node_id(&format!("{}:{}", READY_NOTICE, item_id))
+}
+
+fn ready_notice(item: &WorkItem) -> Node {
+    let mut props = Properties::new();
+    props.insert("item_id".to_string(), PropertyValue::String(item.id.to_string()));
+    let mut node = Node::new(READY_NOTICE, props);
+    node.id = ready_notice_id(item.id);
+    node
+}
+
+// Each new dependency between existing items claims the next revision in
+// the same commit as its edge. Two added at once claim the same revision, so
+// the loser starts over and sees the winner's edge when it checks for cycles.
+const DEPENDENCY_REVISION: &str = "work_dependency_revision";
+
+/// Times adding a dependency retries after losing a race before giving up
+const WRITE_ATTEMPTS: usize = 10;
+
+/// Revisions kept. An attempt that read an old revision clashes as long as
+/// the one after it is still stored, so this bounds how many other changes
+/// can commit while one attempt runs.
+const REVISIONS_KEPT: i64 = 50;
+
+fn dependency_revision_id(revision: i64) -> uuid::Uuid {
+    string_to_node_id(&format!("{}:{}", DEPENDENCY_REVISION, revision))
+}
+
+fn dependency_revision(revision: i64) -> Node {
+    let mut props = Properties::new();
+    props.insert("revision".to_string(), PropertyValue::Integer(revision));
+    let mut node = Node::new(DEPENDENCY_REVISION, props);
+    node.id = dependency_revision_id(revision);
+    node
+}
+
+#[derive(Error, Debug)]
+pub enum WorkError {
+    #[error("Work item not found: {0}")]
+    WorkItemNotFound(String),
+
+    #[error("Dependency cycle: {0}")]
+    Cycle(String),
+
+    #[error("Invalid operation: {0}")]
+    InvalidOperation(String),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error(transparent)]
+    Mail(#[from] MailError),
+}
+
+pub type Result<T> = std::result::Result<T, WorkError>;
+
+/// Steps of multi-step projects and what each one waits on. An item is
+/// ready once everything it depends on is complete; its assignee hears
+/// about that by mail.
+#[async_trait]
+pub trait WorkService: Send + Sync {
+    // Add a work item that depends on `depends_on`. If it is ready straight
+    // away its assignee is told, unless they created it themselves.
+    async fn create_work_item(&self, item: WorkItem, depends_on: Vec<WorkItemId>) -> Result<WorkItem>;
+
+    async fn get_work_item(&self, item_id: WorkItemId) -> Result<WorkItem>;
+
+    // Find a work item by short ID (8-char prefix) or full ID
+    async fn get_work_item_by_short_id(&self, short_id: &str) -> Result<WorkItem>;
+
+    // Make `item_id` wait for `depends_on`; refused if `depends_on` already
+    // (directly or not) waits for `item_id`
+    async fn add_dependency(&self, item_id: WorkItemId, depends_on: WorkItemId) -> Result<()>;
+
+    // Items `item_id` depends on, oldest first
+    async fn get_dependencies(&self, item_id: WorkItemId) -> Result<Vec<WorkItem>>;
+
+    async fn get_work_state(&self, item: &WorkItem) -> Result<WorkState>;
+
+    // Mark an item complete. Returns it with the items that became ready as
+    // a result; their assignees are mailed by `completed_by`, which defaults
+    // to the item's assignee (or else its creator).
+    async fn complete_work_item(&self, item_id: WorkItemId, completed_by: Option<AgentId>) -> Result<(WorkItem, Vec<WorkItem>)>;
+
+    // Incomplete items whose dependencies are all complete, oldest first
+    async fn ready_work_items(&self, assignee: Option<AgentId>, project: Option<&str>) -> Result<Vec<WorkItem>>;
+
+    // Every item (in one project, if given) with its state and dependencies
+    async fn get_work_graph(&self, project: Option<&str>) -> Result<WorkGraph>;
+}
+
+pub struct WorkServiceImpl<S: GraphStorage, M: MailService> {
+    storage: S,
+    mail: M,
+}
+
+impl<S: GraphStorage, M: MailService> WorkServiceImpl<S, M> {
+    pub fn new(storage: S, mail: M) -> Self {
+        Self { storage, mail }
+    }
+
+    async fn dependency_ids(&self, item_id: WorkItemId) -> Result<Vec<WorkItemId>> {
+        Ok(self.storage.get_edges_from(item_id, Some("depends_on")).await?
+            .iter()
+            .map(|edge| edge.to_node_id)
+            .collect())
+    }
+
+    async fn query_items(&self, assignee: Option<&AgentId>, project: Option<&str>) -> Result<Vec<WorkItem>> {
+        let mut query = GraphQuery::new().with_node_type("work_item");
+        if let Some(assignee) = assignee {
+            query = query.with_property("assignee", PropertyValue::String(assignee.clone()));
+        }
+        if let Some(project) = project {
+            query = query.with_property("project", PropertyValue::String(project.to_string()));
+        }
+        let mut items: Vec<WorkItem> = self.storage.query_nodes(&query).await?
+            .iter()
+            .filter_map(WorkItem::from_node)
+            .collect();
+        items.sort_by_key(|i| i.created_at);
+        Ok(items)
+    }
+
+    // Path of depends_on edges from `from` to `to`, both ends included
+    async fn dependency_path(&self, from: WorkItemId, to: WorkItemId) -> Result<Option<Vec<WorkItemId>>> {
+        let mut came_from: HashMap<WorkItemId, WorkItemId> = HashMap::new();
+        let mut seen = HashSet::from([from]);
+        let mut queue = VecDeque::from([from]);
+        while let Some(id) = queue.pop_front() {
+            if id == to {
+                let mut path = vec![to];
+                while let Some(previous) = came_from.get(path.last().unwrap()) {
+                    path.push(*previous);
+                }
+                path.reverse();
+                return Ok(Some(path));
+            }
+            for next in self.dependency_ids(id).await? {
+                if seen.insert(next) {
+                    came_from.insert(next, id);
+                    queue.push_back(next);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // The latest dependency revision claimed, 0 before the first
+    async fn dependency_revision(&self) -> Result<i64> {
+        let nodes = self.storage.query_nodes(&GraphQuery::new().with_node_type(DEPENDENCY_REVISION)).await?;
+        Ok(nodes.iter()
+            .filter_map(|node| match node.get_property("revision") {
+                Some(PropertyValue::Integer(revision)) => Some(*revision),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0))
+    }
+
+    // Whether `item` is incomplete and waits on nothing but `completed`
+    async fn ready_once(&self, item: &WorkItem, completed: WorkItemId) -> Result<bool> {
+        if item.is_complete() {
+            return Ok(false);
+        }
+        Ok(self.get_dependencies(item.id).await?
+            .iter()
+            .all(|d| d.id == completed || d.is_complete()))
+    }
+
+    async fn cycle_error(&self, item: &WorkItem, dependency: &WorkItem, path: Vec<WorkItemId>) -> WorkError {
+        let mut names = Vec::with_capacity(path.len() + 1);
+        for id in path.iter().chain([&dependency.id]) {
+            let title = self.get_work_item(*id).await.map(|i| i.title).unwrap_or_default();
+            names.push(format!("{} ({})", &id.to_string()[..8], title));
+        }
+        WorkError::Cycle(format!(
+            "{} already depends on {}: {}",
+            dependency.short_id(),
+            item.short_id(),
+            names.join(" → ")
+        ))
+    }
+
+    async fn notify_ready(&self, item: &WorkItem, sender: &AgentId) -> Result<()> {
+        let Some(ref assignee) = item.assignee else { return Ok(()) };
+
+        let mut body = format!("Everything \"{}\" depends on is complete, so it's ready to start.\n", item.title);
+        if let Some(ref project) = item.project {
+            body.push_str(&format!("Project: {}\n", project));
+        }
+        if !item.description.is_empty() {
+            body.push_str(&format!("\n{}\n", item.description));
+        }
+        body.push_str(&format!("\nRun `agent-office work done {}` when it's finished.", item.short_id()));
+
+        let options = SendOptions {
+            labels: vec!["work".to_string()],
+            ..Default::default()
+        };
+        self.mail.send_mail_with(
+            sender.clone(),
+            vec![assignee.clone()],
+            Vec::new(),
+            format!("Ready to start: {}", item.title),
+            body,
+            options,
+        ).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<S: GraphStorage, M: MailService> WorkService for WorkServiceImpl<S, M> {
+    async fn create_work_item(&self, item: WorkItem, depends_on: Vec<WorkItemId>) -> Result<WorkItem> {
+        if item.title.trim().is_empty() {
+            return Err(WorkError::InvalidOperation("A work item needs a title".to_string()));
+        }
+        self.mail.get_agent(item.created_by.clone()).await?;
+        if let Some(ref assignee) = item.assignee {
+            self.mail.get_agent(assignee.clone()).await?;
+        }
+        let mut dependencies = Vec::with_capacity(depends_on.len());
+        for id in depends_on {
+            let dependency = self.get_work_item(id).await?;
+            if !dependencies.iter().any(|d: &WorkItem| d.id == id) {
+                dependencies.push(dependency);
+            }
+        }
+
+        // A new item has nothing depending on it yet, so it can't close a cycle
+        let mut tx = self.storage.begin();
+        tx.create_node(&item.to_node());
+        for dependency in &dependencies {
+            tx.create_edge(&Edge::new("depends_on", item.id, dependency.id, Properties::new()));
+        }
+        self.storage.commit(tx).await?;
+
+        let ready = dependencies.iter().all(WorkItem::is_complete);
+        if ready && item.assignee.as_ref() != Some(&item.created_by) {
+            self.notify_ready(&item, &item.created_by).await?;
+        }
+        Ok(item)
+    }
+
+    async fn get_work_item(&self, item_id: WorkItemId) -> Result<WorkItem> {
+        match self.storage.get_node(item_id).await {
+            Ok(node) => WorkItem::from_node(&node).ok_or_else(|| WorkError::WorkItemNotFound(item_id.to_string())),
+            Err(StorageError::NodeNotFound(_)) => Err(WorkError::WorkItemNotFound(item_id.to_string())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get_work_item_by_short_id(&self, short_id: &str) -> Result<WorkItem> {
+        let items = self.query_items(None, None).await?;
+
+        let short_id_lower = short_id.to_lowercase();
+        let mut matching: Vec<_> = items.into_iter()
+            .filter(|i| i.id.to_string().starts_with(&short_id_lower))
+            .collect();
+
+        match matching.len() {
+            0 => Err(WorkError::WorkItemNotFound(short_id.to_string())),
+            1 => Ok(matching.remove(0)),
+            _ => Err(WorkError::InvalidOperation(
+                format!("Multiple work items match short ID '{}', please use full ID", short_id)
+            )),
+        }
+    }
+
+    async fn add_dependency(&self, item_id: WorkItemId, depends_on: WorkItemId) -> Result<()> {
+        let item = self.get_work_item(item_id).await?;
+        let dependency = self.get_work_item(depends_on).await?;
+        if item.id == dependency.id {
+            return Err(WorkError::Cycle(format!("{} can't depend on itself", item.short_id())));
+        }
+
+        for _ in 0..WRITE_ATTEMPTS {
+            // Read before the edges: anything added after this point claims
+            // the revision first and makes this attempt's commit fail
+            let revision = self.dependency_revision().await?;
+            if self.dependency_ids(item.id).await?.contains(&dependency.id) {
+                return Ok(());
+            }
+            if let Some(path) = self.dependency_path(dependency.id, item.id).await? {
+                return Err(self.cycle_error(&item, &dependency, path).await);
+            }
+
+            // Waiting again means the assignee should hear when it's ready again
+            let mut tx = self.storage.begin();
+            tx.create_edge(&Edge::new("depends_on", item.id, dependency.id, Properties::new()))
+                .create_node(&dependency_revision(revision + 1));
+            if !dependency.is_complete() && self.storage.get_node(ready_notice_id(item.id)).await.is_ok() {
+                tx.delete_node(ready_notice_id(item.id));
+            }
+            match self.storage.commit(tx).await {
+                Ok(()) => {}
+                Err(StorageError::ConstraintViolation(_)) => continue,
+                Err(e) => return Err(e.into()),
+            }
+
+            let stale = revision + 1 - REVISIONS_KEPT;
+            if stale > 0 {
+                match self.storage.delete_node(dependency_revision_id(stale)).await {
+                    Ok(()) | Err(StorageError::NodeNotFound(_)) => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            return Ok(());
+        }
+        Err(WorkError::InvalidOperation(format!(
+            "Dependencies kept changing while adding {} → {}, try again",
+            item.short_id(),
+            dependency.short_id()
+        )))
+    }
+
+    async fn get_dependencies(&self, item_id: WorkItemId) -> Result<Vec<WorkItem>> {
+        let ids = self.dependency_ids(item_id).await?;
+        let mut dependencies: Vec<WorkItem> = self.storage.get_nodes(&ids).await?
+            .iter()
+            .filter_map(WorkItem::from_node)
+            .collect();
+        dependencies.sort_by_key(|i| i.created_at);
+        Ok(dependencies)
+    }
+
+    async fn get_work_state(&self, item: &WorkItem) -> Result<WorkState> {
+        if item.is_complete() {
+            return Ok(WorkState::Complete);
+        }
+        let dependencies = self.get_dependencies(item.id).await?;
+        Ok(if dependencies.iter().all(WorkItem::is_complete) { WorkState::Ready } else { WorkState::Waiting })
+    }
+
+    async fn complete_work_item(&self, item_id: WorkItemId, completed_by: Option<AgentId>) -> Result<(WorkItem, Vec<WorkItem>)> {
+        let mut item = self.get_work_item(item_id).await?;
+        if item.is_complete() {
+            return Ok((item, Vec::new()));
+        }
+        if self.get_work_state(&item).await? == WorkState::Waiting {
+            return Err(WorkError::InvalidOperation(format!(
+                "{} is still waiting on items it depends on",
+                item.short_id()
+            )));
+        }
+        let sender = match completed_by {
+            Some(agent_id) => self.mail.get_agent(agent_id).await?.id,
+            None => item.assignee.clone().unwrap_or_else(|| item.created_by.clone()),
+        };
+
+        let now = chrono::Utc::now();
+        item.completed_at = Some(now);
+        item.updated_at = now;
+        let mut dependents = Vec::new();
+        for edge in self.storage.get_edges_to(item.id, Some("depends_on")).await? {
+            if let Ok(dependent) = self.get_work_item(edge.from_node_id).await {
+                dependents.push(dependent);
+            }
+        }
+
+        // Completing the item and claiming the notices for what it readies
+        // commit together. If the claim clashes, someone completed it first.
+        let mut ready = Vec::new();
+        let mut tx = self.storage.begin();
+        tx.update_node(&item.to_node());
+        for dependent in &dependents {
+            if self.ready_once(dependent, item.id).await? {
+                tx.create_node(&ready_notice(dependent));
+                ready.push(dependent.clone());
+            }
+        }
+        match self.storage.commit(tx).await {
+            Ok(()) => {}
+            Err(StorageError::ConstraintViolation(_)) => return Ok((self.get_work_item(item.id).await?, Vec::new())),
+            Err(e) => return Err(e.into()),
+        }
+
+        // An item that also waited on something completed at the same time
+        // may only look ready now; whoever stores its notice first tells them
+        for dependent in dependents {
+            if ready.iter().any(|r| r.id == dependent.id)
+                || self.get_work_state(&dependent).await? != WorkState::Ready
+            {
+                continue;
+            }
+            match self.storage.create_node(&ready_notice(&dependent)).await {
+                Ok(_) => ready.push(dependent),
+                Err(StorageError::ConstraintViolation(_)) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        ready.sort_by_key(|i| i.created_at);
+        // The item is complete whether or not the mail goes out
+        for dependent in &ready {
+            if let Err(e) = self.notify_ready(dependent, &sender).await {
+                eprintln!("Couldn't mail the ready notice for {}: {}", dependent.short_id(), e);
+            }
+        }
+        Ok((item, ready))
+    }
+
+    async fn ready_work_items(&self, assignee: Option<AgentId>, project: Option<&str>) -> Result<Vec<WorkItem>> {
+        let mut ready = Vec::new();
+        for item in self.query_items(assignee.as_ref(), project).await? {
+            if self.get_work_state(&item).await? == WorkState::Ready {
+                ready.push(item);
+            }
+        }
+        Ok(ready)
+    }
+
+    async fn get_work_graph(&self, project: Option<&str>) -> Result<WorkGraph> {
+        let items = self.query_items(None, project).await?;
+        let index: HashMap<WorkItemId, usize> = items.iter().enumerate().map(|(i, item)| (item.id, i)).collect();
+
+        let mut graph = WorkGraph::default();
+        for (i, item) in items.iter().enumerate() {
+            let dependency_ids = self.dependency_ids(item.id).await?;
+            // Dependencies outside the project still decide the state
+            let dependencies = self.storage.get_nodes(&dependency_ids).await?;
+            let state = if item.is_complete() {
+                WorkState::Complete
+            } else if dependencies.iter().filter_map(WorkItem::from_node).all(|d| d.is_complete()) {
+                WorkState::Ready
+            } else {
+                WorkState::Waiting
+            };
+            graph.items.push((item.clone(), state));
+            graph.dependencies.extend(dependency_ids.iter().filter_map(|id| index.get(id)).map(|&d| (i, d)));
+        }
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::mail::MailServiceImpl;
+    use crate::storage::memory::InMemoryStorage;
+
+    type Work = WorkServiceImpl<InMemoryStorage, MailServiceImpl<InMemoryStorage>>;
+
+    // A work service with agents alice and bob
+    async fn setup() -> Work {
+        let storage = InMemoryStorage::new();
+        let mail = MailServiceImpl::new(storage.clone());
+        mail.create_agent("alice").await.unwrap();
+        mail.create_agent("bob").await.unwrap();
+        WorkServiceImpl::new(storage, mail)
+    }
+
+    // An item in the launch project that alice created for `assignee`
+    fn item(title: &str, assignee: &str) -> WorkItem {
+        WorkItem {
+            assignee: Some(assignee.to_string()),
+            project: Some("launch".to_string()),
+            ..WorkItem::new("alice", title)
+        }
+    }
+
+    // Subjects of the unread "ready to start" mails `agent` got
+    async fn ready_mails(work: &Work, agent: &str) -> Vec<String> {
+        let (_, unread) = work.mail.check_unread_mail(agent.into(), false).await.unwrap();
+        unread.into_iter().map(|m| m.subject).filter(|s| s.starts_with("Ready to start")).collect()
+    }
+
+    #[tokio::test]
+    async fn test_item_waits_for_incomplete_dependencies() {
+        let work = setup().await;
+        let schema = work.create_work_item(item("Schema", "alice"), Vec::new()).await.unwrap();
+        let api = work.create_work_item(item("API", "bob"), vec![schema.id]).await.unwrap();
+
+        assert_eq!(work.get_work_state(&api).await.unwrap(), WorkState::Waiting);
+        assert!(work.ready_work_items(Some("bob".into()), None).await.unwrap().is_empty());
+        assert!(ready_mails(&work, "bob").await.is_empty());
+        assert!(matches!(work.complete_work_item(api.id, None).await, Err(WorkError::InvalidOperation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_item_ready_on_creation_is_announced_unless_self_assigned() {
+        let work = setup().await;
+        work.create_work_item(item("Schema", "alice"), Vec::new()).await.unwrap();
+        work.create_work_item(item("Fixtures", "bob"), Vec::new()).await.unwrap();
+
+        assert!(ready_mails(&work, "alice").await.is_empty());
+        assert_eq!(ready_mails(&work, "bob").await, vec!["Ready to start: Fixtures".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_completing_the_last_dependency_announces_the_item() {
+        let work = setup().await;
+        let schema = work.create_work_item(item("Schema", "alice"), Vec::new()).await.unwrap();
+        let api = work.create_work_item(item("API", "bob"), vec![schema.id]).await.unwrap();
+
+        let (done, ready) = work.complete_work_item(schema.id, None).await.unwrap();
+        assert!(done.is_complete());
+        assert_eq!(ready.iter().map(|i| i.id).collect::<Vec<_>>(), vec![api.id]);
+        let ready = work.ready_work_items(Some("bob".into()), Some("launch")).await.unwrap();
+        assert_eq!(ready.iter().map(|i| i.id).collect::<Vec<_>>(), vec![api.id]);
+
+        // Sent by whoever finished the dependency, its assignee by default
+        let (_, unread) = work.mail.check_unread_mail("bob".into(), false).await.unwrap();
+        assert_eq!(unread[0].subject, "Ready to start: API");
+        assert_eq!(unread[0].from_mailbox_id, string_to_node_id("alice"));
+        assert_eq!(unread[0].labels, vec!["work".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_item_cannot_depend_on_itself() {
+        let work = setup().await;
+        let api = work.create_work_item(item("API", "bob"), Vec::new()).await.unwrap();
+        assert!(matches!(work.add_dependency(api.id, api.id).await, Err(WorkError::Cycle(_))));
+    }
+
+    #[tokio::test]
+    async fn test_dependency_closing_a_loop_is_rejected() {
+        let work = setup().await;
+        let schema = work.create_work_item(item("Schema", "alice"), Vec::new()).await.unwrap();
+        let api = work.create_work_item(item("API", "bob"), vec![schema.id]).await.unwrap();
+        let docs = work.create_work_item(item("Docs", "bob"), vec![api.id]).await.unwrap();
+
+        // Docs → API → Schema; making Schema wait for Docs would close the loop
+        let err = work.add_dependency(schema.id, docs.id).await.unwrap_err();
+        assert!(matches!(err, WorkError::Cycle(ref msg) if msg.contains("(API)")));
+        assert!(work.get_dependencies(schema.id).await.unwrap().is_empty());
+
+        // Adding a dependency twice is a no-op, not a second edge
+        work.add_dependency(docs.id, api.id).await.unwrap();
+        assert_eq!(work.get_dependencies(docs.id).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_racing_dependencies_that_close_a_loop_let_all_but_one_through() {
+        let work = std::sync::Arc::new(setup().await);
+        for _ in 0..20 {
+            let mut ring = Vec::new();
+            for i in 0..8 {
+                ring.push(work.create_work_item(item(&format!("Step {}", i), "bob"), Vec::new()).await.unwrap().id);
+            }
+
+            // Each step waits on the next, and the last on the first
+            let adds = (0..ring.len()).map(|i| {
+                let work = work.clone();
+                let (item, depends_on) = (ring[i], ring[(i + 1) % ring.len()]);
+                tokio::spawn(async move { work.add_dependency(item, depends_on).await })
+            });
+            let results: Vec<_> = futures::future::join_all(adds).await
+                .into_iter()
+                .map(|joined| joined.unwrap())
+                .collect();
+            let refused: Vec<_> = results.iter().filter_map(|r| r.as_ref().err()).collect();
+            assert_eq!(refused.len(), 1, "{:?}", results);
+            assert!(matches!(refused[0], WorkError::Cycle(_)));
+            let mut edges = 0;
+            for id in &ring {
+                edges += work.get_dependencies(*id).await.unwrap().len();
+            }
+            assert_eq!(edges, ring.len() - 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_completion_stands_when_the_ready_mail_fails() {
+        let work = setup().await;
+        work.mail.create_agent("carol").await.unwrap();
+        let schema = work.create_work_item(item("Schema", "alice"), Vec::new()).await.unwrap();
+        let api = work.create_work_item(item("API", "carol"), vec![schema.id]).await.unwrap();
+        work.mail.delete_agent("carol".into()).await.unwrap();
+
+        let (schema, ready) = work.complete_work_item(schema.id, None).await.unwrap();
+        assert!(schema.is_complete());
+        assert_eq!(ready.iter().map(|i| i.id).collect::<Vec<_>>(), vec![api.id]);
+        assert!(work.storage.get_node(ready_notice_id(api.id)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_work_graph_states_and_layers() {
+        let work = setup().await;
+        let schema = work.create_work_item(item("Schema", "alice"), Vec::new()).await.unwrap();
+        let api = work.create_work_item(item("API", "bob"), vec![schema.id]).await.unwrap();
+        work.create_work_item(item("Docs", "bob"), vec![api.id]).await.unwrap();
+        work.create_work_item(WorkItem::new("alice", "Elsewhere"), Vec::new()).await.unwrap();
+        work.complete_work_item(schema.id, None).await.unwrap();
+
+        let graph = work.get_work_graph(Some("launch")).await.unwrap();
+        let states: Vec<WorkState> = graph.items.iter().map(|(_, s)| *s).collect();
+        assert_eq!(states, vec![WorkState::Complete, WorkState::Ready, WorkState::Waiting]);
+        assert_eq!(graph.dependencies, vec![(1, 0), (2, 1)]);
+        assert_eq!(graph.layers(), vec![vec![0], vec![1], vec![2]]);
+    }
+
+    #[tokio::test]
+    async fn test_item_waiting_on_two_completions_is_announced_once() {
+        let work = setup().await;
+
+        let schema = work.create_work_item(WorkItem::new("alice", "Schema"), Vec::new()).await.unwrap();
+        let fixtures = work.create_work_item(WorkItem::new("alice", "Fixtures"), Vec::new()).await.unwrap();
+        let api = WorkItem { assignee: Some("bob".to_string()), ..WorkItem::new("alice", "API") };
+        let api = work.create_work_item(api, vec![schema.id, fixtures.id]).await.unwrap();
+
+        let (first, second) = tokio::join!(
+            work.complete_work_item(schema.id, None),
+            work.complete_work_item(fixtures.id, None),
+        );
+        let announced: Vec<WorkItemId> = first.unwrap().1.into_iter().chain(second.unwrap().1).map(|i| i.id).collect();
+        assert_eq!(announced, vec![api.id]);
+        assert_eq!(ready_mails(&work, "bob").await, vec!["Ready to start: API".to_string()]);
+
+        // Completing again changes nothing and tells nobody
+        assert!(work.complete_work_item(schema.id, None).await.unwrap().1.is_empty());
+        assert_eq!(ready_mails(&work, "bob").await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_new_dependency_means_item_is_announced_again() {
+        let work = setup().await;
+
+        let schema = work.create_work_item(WorkItem::new("alice", "Schema"), Vec::new()).await.unwrap();
+        let api = WorkItem { assignee: Some("bob".to_string()), ..WorkItem::new("alice", "API") };
+        let api = work.create_work_item(api, vec![schema.id]).await.unwrap();
+        work.complete_work_item(schema.id, None).await.unwrap();
+
+        let auth = work.create_work_item(WorkItem::new("alice", "Auth"), Vec::new()).await.unwrap();
+        work.add_dependency(api.id, auth.id).await.unwrap();
+        assert_eq!(work.get_work_state(&api).await.unwrap(), WorkState::Waiting);
+        let (_, ready) = work.complete_work_item(auth.id, None).await.unwrap();
+        assert_eq!(ready.iter().map(|i| i.id).collect::<Vec<_>>(), vec![api.id]);
+        assert_eq!(ready_mails(&work, "bob").await.len(), 2);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::storage::{GraphStorage, Result, StorageError};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// Where attachment contents are kept. Blobs are content addressed, so the
+/// same file attached to many mails is stored once.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum BlobLocation {
+    /// The `blobs` table of the office database (in memory without one)
+    #[default]
+    Database,
+    /// One file per blob under this directory, sharded by hash prefix
+    Directory(PathBuf),
+}
+
+impl BlobLocation {
+    /// `AGENT_OFFICE_BLOB_DIR` when set, otherwise the database
+    pub fn from_env() -> Self {
+        match std::env::var("AGENT_OFFICE_BLOB_DIR") {
+            Ok(dir) if !dir.is_empty() => Self::Directory(PathBuf::from(dir)),
+            _ => Self::Database,
+        }
+    }
+
+    /// Store `data` and return its hash
+    pub async fn put(&self, storage: &impl GraphStorage, data: &[u8]) -> Result<String> {
+        let hash = content_hash(data);
+        match self {
+            Self::Database => storage.put_blob(&hash, data).await?,
+            Self::Directory(root) => {
+                let path = blob_path(root, &hash);
+                if !tokio::fs::try_exists(&path).await.map_err(io_error)? {
+                    let dir = path.parent().unwrap_or(root);
+                    tokio::fs::create_dir_all(dir).await.map_err(io_error)?;
+                    // Write under a temporary name first so readers never
+                    // see a half-written blob
+                    let partial = path.with_extension(format!("partial-{}", uuid::Uuid::new_v4()));
+                    tokio::fs::write(&partial, data).await.map_err(io_error)?;
+                    tokio::fs::rename(&partial, &path).await.map_err(io_error)?;
+                }
+            }
+        }
+        Ok(hash)
+    }
+
+    pub async fn get(&self, storage: &impl GraphStorage, hash: &str) -> Result<Vec<u8>> {
+        match self {
+            Self::Database => storage.get_blob(hash).await,
+            Self::Directory(root) => match tokio::fs::read(blob_path(root, hash)).await {
+                Ok(data) => Ok(data),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    Err(StorageError::BlobNotFound(hash.to_string()))
+                }
+                Err(e) => Err(io_error(e)),
+            },
+        }
+    }
+
+    pub async fn delete(&self, storage: &impl GraphStorage, hash: &str) -> Result<()> {
+        match self {
+            Self::Database => storage.delete_blob(hash).await,
+            Self::Directory(root) => match tokio::fs::remove_file(blob_path(root, hash)).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(io_error(e)),
+                _ => Ok(()),
+            },
+        }
+    }
+}
+
+/// SHA-256 of `data` as lowercase hex
+pub fn content_hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// `<root>/ab/abcdef...`; hashes are hex, so they can't escape `root`
+fn blob_path(root: &Path, hash: &str) -> PathBuf {
+    let shard = hash.get(..2).unwrap_or("00");
+    root.join(shard).join(hash)
+}
+
+fn io_error(e: std::io::Error) -> StorageError {
+    StorageError::DatabaseError(format!("blob store: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::InMemoryStorage;
+
+    #[tokio::test]
+    async fn test_directory_blobs_are_content_addressed() {
+        let root = std::env::temp_dir().join(format!("agent-office-blobs-{}", uuid::Uuid::new_v4()));
+        let location = BlobLocation::Directory(root.clone());
+        let storage = InMemoryStorage::new();
+
+        let hash = location.put(&storage, b"hello").await.unwrap();
+        assert_eq!(hash, content_hash(b"hello"));
+        assert_eq!(location.put(&storage, b"hello").await.unwrap(), hash);
+        assert_eq!(location.get(&storage, &hash).await.unwrap(), b"hello");
+
+        location.delete(&storage, &hash).await.unwrap();
+        assert!(matches!(location.get(&storage, &hash).await, Err(StorageError::BlobNotFound(_))));
+        // Deleting twice is fine
+        location.delete(&storage, &hash).await.unwrap();
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
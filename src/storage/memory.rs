@@ -1,7 +1,7 @@
-use crate::domain::{Edge, EdgeId, GraphQuery, Node, NodeId};
-use crate::storage::{EdgeDirection, GraphStorage, Result, StorageError, SearchQuery, SearchResults};
+use crate::domain::{Edge, EdgeId, GraphQuery, Node, NodeId, PropertyValue};
+use crate::storage::{full_text_terms, id_prefix_range, EdgeDirection, GraphStorage, Result, StorageError, SearchQuery, SearchResults, Transaction, WriteOp};
 use async_trait::async_trait;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -9,6 +9,8 @@ use tokio::sync::RwLock;
 pub struct InMemoryStorage {
     nodes: Arc<RwLock<HashMap<NodeId, Node>>>,
     edges: Arc<RwLock<HashMap<EdgeId, Edge>>>,
+    blobs: Arc<RwLock<HashMap<String, Vec<u8>>>>,
+    sequences: Arc<RwLock<HashMap<String, i64>>>,
 }
 
 impl InMemoryStorage {
@@ -16,45 +18,64 @@ impl InMemoryStorage {
         Self {
             nodes: Arc::new(RwLock::new(HashMap::new())),
             edges: Arc::new(RwLock::new(HashMap::new())),
+            blobs: Arc::new(RwLock::new(HashMap::new())),
+            sequences: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
-    fn matches_query(node: &Node, query: &GraphQuery) -> bool {
-        // Check node type filter
-        if let Some(ref types) = query.node_types {
-            if !types.contains(&node.node_type) {
-                return false;
+    /// Every string value in the node's properties, nested ones included,
+    /// lowercased. Text searches look at these and never at the keys.
+    fn string_values(node: &Node) -> Vec<String> {
+        fn collect(value: &serde_json::Value, out: &mut Vec<String>) {
+            match value {
+                serde_json::Value::String(text) => out.push(text.to_lowercase()),
+                serde_json::Value::Array(items) => items.iter().for_each(|item| collect(item, out)),
+                serde_json::Value::Object(map) => map.values().for_each(|item| collect(item, out)),
+                _ => {}
             }
         }
-
-        // Check property filters
-        if let Some(ref filters) = query.property_filters {
-            for (key, expected_value) in filters {
-                match node.properties.get(key) {
-                    Some(actual_value) if actual_value == expected_value => continue,
-                    _ => return false,
-                }
-            }
+        let mut values = Vec::new();
+        if let Ok(json) = serde_json::to_value(&node.properties) {
+            collect(&json, &mut values);
         }
-
-        true
+        values
     }
-    
+
     fn matches_search_query(node: &Node, query: &SearchQuery) -> bool {
         // Check node types
         if !query.node_types.is_empty() && !query.node_types.contains(&node.node_type) {
             return false;
         }
         
-        // Check text search
+        // Check text search, against string values only
         if let Some(ref search_text) = query.search_text {
             let search_lower = search_text.to_lowercase();
-            let node_text = serde_json::to_string(&node.properties).unwrap_or_default().to_lowercase();
-            if !node_text.contains(&search_lower) {
+            if !Self::string_values(node).iter().any(|value| value.contains(&search_lower)) {
                 return false;
             }
         }
         
+        // No full-text engine: every word and phrase must appear
+        if let Some(ref text) = query.full_text {
+            let values = Self::string_values(node);
+            if !full_text_terms(text).iter().all(|term| values.iter().any(|value| value.contains(term))) {
+                return false;
+            }
+        }
+        
+        if let Some(ref ids) = query.node_ids {
+            if !ids.contains(&node.id) {
+                return false;
+            }
+        }
+        
+        if let Some(ref prefix) = query.id_prefix {
+            match id_prefix_range(prefix) {
+                Some((low, high)) if (low..=high).contains(&node.id) => {}
+                _ => return false,
+            }
+        }
+        
         // Check created time range
         if let Some(after) = query.created_after {
             if node.created_at < after {
@@ -87,9 +108,89 @@ impl InMemoryStorage {
                 None => return false,
             }
         }
+        for (key, text) in &query.property_contains {
+            let found = node.properties.get(key)
+                .and_then(|v| v.as_str())
+                .is_some_and(|v| v.to_lowercase().contains(&text.to_lowercase()));
+            if !found {
+                return false;
+            }
+        }
+        
+        for (key, at) in &query.property_before {
+            match node.properties.get(key) {
+                Some(PropertyValue::Timestamp(t)) if t <= at => {}
+                _ => return false,
+            }
+        }
         
         true
     }
+
+    fn insert_node(nodes: &mut HashMap<NodeId, Node>, node: &Node) -> Result<()> {
+        if nodes.contains_key(&node.id) {
+            return Err(StorageError::ConstraintViolation(
+                format!("Node with ID {} already exists", node.id)
+            ));
+        }
+        nodes.insert(node.id, node.clone());
+        Ok(())
+    }
+
+    fn replace_node(nodes: &mut HashMap<NodeId, Node>, node: &Node) -> Result<()> {
+        if !nodes.contains_key(&node.id) {
+            return Err(StorageError::NodeNotFound(node.id));
+        }
+        nodes.insert(node.id, node.clone());
+        Ok(())
+    }
+
+    fn remove_node(
+        nodes: &mut HashMap<NodeId, Node>,
+        edges: &mut HashMap<EdgeId, Edge>,
+        id: NodeId,
+    ) -> Result<()> {
+        if !nodes.contains_key(&id) {
+            return Err(StorageError::NodeNotFound(id));
+        }
+        
+        // Remove all edges connected to this node
+        edges.retain(|_, edge| {
+            edge.from_node_id != id && edge.to_node_id != id
+        });
+        
+        nodes.remove(&id);
+        Ok(())
+    }
+
+    fn insert_edge(
+        nodes: &HashMap<NodeId, Node>,
+        edges: &mut HashMap<EdgeId, Edge>,
+        edge: &Edge,
+    ) -> Result<()> {
+        // Verify both nodes exist
+        if !nodes.contains_key(&edge.from_node_id) {
+            return Err(StorageError::NodeNotFound(edge.from_node_id));
+        }
+        if !nodes.contains_key(&edge.to_node_id) {
+            return Err(StorageError::NodeNotFound(edge.to_node_id));
+        }
+        edges.insert(edge.id, edge.clone());
+        Ok(())
+    }
+
+    fn replace_edge_properties(edges: &mut HashMap<EdgeId, Edge>, edge: &Edge) -> Result<Edge> {
+        let existing = edges
+            .get_mut(&edge.id)
+            .ok_or(StorageError::EdgeNotFound(edge.id))?;
+        existing.properties = edge.properties.clone();
+        Ok(existing.clone())
+    }
+
+    fn remove_edge(edges: &mut HashMap<EdgeId, Edge>, id: EdgeId) -> Result<()> {
+        edges.remove(&id).ok_or(StorageError::EdgeNotFound(id))?;
+        Ok(())
+    }
 }
 
 impl Default for InMemoryStorage {
@@ -102,12 +203,7 @@ impl Default for InMemoryStorage {
 impl GraphStorage for InMemoryStorage {
     async fn create_node(&self, node: &Node) -> Result<Node> {
         let mut nodes = self.nodes.write().await;
-        if nodes.contains_key(&node.id) {
-            return Err(StorageError::ConstraintViolation(
-                format!("Node with ID {} already exists", node.id)
-            ));
-        }
-        nodes.insert(node.id, node.clone());
+        Self::insert_node(&mut nodes, node)?;
         Ok(node.clone())
     }
 
@@ -118,37 +214,43 @@ impl GraphStorage for InMemoryStorage {
             .ok_or(StorageError::NodeNotFound(id))
     }
 
+    async fn get_nodes(&self, ids: &[NodeId]) -> Result<Vec<Node>> {
+        let nodes = self.nodes.read().await;
+        Ok(ids.iter().filter_map(|id| nodes.get(id).cloned()).collect())
+    }
+
     async fn update_node(&self, node: &Node) -> Result<Node> {
         let mut nodes = self.nodes.write().await;
-        if !nodes.contains_key(&node.id) {
-            return Err(StorageError::NodeNotFound(node.id));
-        }
-        nodes.insert(node.id, node.clone());
+        Self::replace_node(&mut nodes, node)?;
         Ok(node.clone())
     }
 
     async fn delete_node(&self, id: NodeId) -> Result<()> {
         let mut nodes = self.nodes.write().await;
         let mut edges = self.edges.write().await;
-        
-        if !nodes.contains_key(&id) {
-            return Err(StorageError::NodeNotFound(id));
-        }
-        
-        // Remove all edges connected to this node
-        edges.retain(|_, edge| {
-            edge.from_node_id != id && edge.to_node_id != id
-        });
-        
-        nodes.remove(&id);
-        Ok(())
+        Self::remove_node(&mut nodes, &mut edges, id)
     }
 
     async fn query_nodes(&self, query: &GraphQuery) -> Result<Vec<Node>> {
         let nodes = self.nodes.read().await;
+        
+        // Nodes touching an edge of one of the requested types, if any were given
+        let connected: Option<HashSet<NodeId>> = match query.edge_types {
+            Some(ref types) => {
+                let edges = self.edges.read().await;
+                Some(edges
+                    .values()
+                    .filter(|edge| types.contains(&edge.edge_type))
+                    .flat_map(|edge| [edge.from_node_id, edge.to_node_id])
+                    .collect())
+            }
+            None => None,
+        };
+        
         let mut results: Vec<Node> = nodes
             .values()
-            .filter(|node| Self::matches_query(node, query))
+            .filter(|node| query.matches_node(node))
+            .filter(|node| connected.as_ref().is_none_or(|ids| ids.contains(&node.id)))
             .cloned()
             .collect();
         
@@ -161,29 +263,28 @@ impl GraphStorage for InMemoryStorage {
 
     async fn create_edge(&self, edge: &Edge) -> Result<Edge> {
         let nodes = self.nodes.read().await;
-        
-        // Verify both nodes exist
-        if !nodes.contains_key(&edge.from_node_id) {
-            return Err(StorageError::NodeNotFound(edge.from_node_id));
-        }
-        if !nodes.contains_key(&edge.to_node_id) {
-            return Err(StorageError::NodeNotFound(edge.to_node_id));
-        }
-        
-        drop(nodes);
-        
         let mut edges = self.edges.write().await;
-        edges.insert(edge.id, edge.clone());
+        Self::insert_edge(&nodes, &mut edges, edge)?;
         Ok(edge.clone())
     }
 
+    async fn update_edge(&self, edge: &Edge) -> Result<Edge> {
+        let mut edges = self.edges.write().await;
+        Self::replace_edge_properties(&mut edges, edge)
+    }
+
+    async fn delete_edge(&self, id: EdgeId) -> Result<()> {
+        let mut edges = self.edges.write().await;
+        Self::remove_edge(&mut edges, id)
+    }
+
     async fn get_edges_from(&self, node_id: NodeId, edge_type: Option<&str>) -> Result<Vec<Edge>> {
         let edges = self.edges.read().await;
         let results: Vec<Edge> = edges
             .values()
             .filter(|edge| {
                 edge.from_node_id == node_id &&
-                edge_type.map_or(true, |et| edge.edge_type == et)
+                edge_type.is_none_or(|et| edge.edge_type == et)
             })
             .cloned()
             .collect();
@@ -196,7 +297,7 @@ impl GraphStorage for InMemoryStorage {
             .values()
             .filter(|edge| {
                 edge.to_node_id == node_id &&
-                edge_type.map_or(true, |et| edge.edge_type == et)
+                edge_type.is_none_or(|et| edge.edge_type == et)
             })
             .cloned()
             .collect();
@@ -215,7 +316,7 @@ impl GraphStorage for InMemoryStorage {
         let mut neighbor_ids: Vec<NodeId> = Vec::new();
         
         for edge in edges.values() {
-            let matches_type = edge_type.map_or(true, |et| edge.edge_type == et);
+            let matches_type = edge_type.is_none_or(|et| edge.edge_type == et);
             
             match direction {
                 EdgeDirection::Outgoing if edge.from_node_id == node_id && matches_type => {
@@ -240,10 +341,11 @@ impl GraphStorage for InMemoryStorage {
         let nodes = self.nodes.read().await;
         
         // Filter nodes based on query criteria
-        let results: Vec<Node> = nodes.values()
+        let mut results: Vec<Node> = nodes.values()
             .filter(|node| Self::matches_search_query(node, query))
             .cloned()
             .collect();
+        results.sort_by_key(|node| (std::cmp::Reverse(node.updated_at), node.id));
         
         // Apply pagination
         let offset = query.offset;
@@ -258,12 +360,62 @@ impl GraphStorage for InMemoryStorage {
             items: paginated,
         })
     }
+
+    async fn put_blob(&self, hash: &str, data: &[u8]) -> Result<()> {
+        let mut blobs = self.blobs.write().await;
+        blobs.entry(hash.to_string()).or_insert_with(|| data.to_vec());
+        Ok(())
+    }
+
+    async fn get_blob(&self, hash: &str) -> Result<Vec<u8>> {
+        let blobs = self.blobs.read().await;
+        blobs.get(hash)
+            .cloned()
+            .ok_or_else(|| StorageError::BlobNotFound(hash.to_string()))
+    }
+
+    async fn delete_blob(&self, hash: &str) -> Result<()> {
+        self.blobs.write().await.remove(hash);
+        Ok(())
+    }
+
+    async fn next_sequence(&self, name: &str) -> Result<i64> {
+        let mut sequences = self.sequences.write().await;
+        let value = sequences.entry(name.to_string()).or_insert(0);
+        *value += 1;
+        Ok(*value)
+    }
+
+    async fn commit(&self, tx: Transaction) -> Result<()> {
+        let mut nodes = self.nodes.write().await;
+        let mut edges = self.edges.write().await;
+        
+        // Apply every write to a staged copy so a failure leaves storage untouched
+        let mut staged_nodes = nodes.clone();
+        let mut staged_edges = edges.clone();
+        for op in tx.into_ops() {
+            match op {
+                WriteOp::CreateNode(node) => Self::insert_node(&mut staged_nodes, &node)?,
+                WriteOp::UpdateNode(node) => Self::replace_node(&mut staged_nodes, &node)?,
+                WriteOp::DeleteNode(id) => Self::remove_node(&mut staged_nodes, &mut staged_edges, id)?,
+                WriteOp::CreateEdge(edge) => Self::insert_edge(&staged_nodes, &mut staged_edges, &edge)?,
+                WriteOp::UpdateEdge(edge) => {
+                    Self::replace_edge_properties(&mut staged_edges, &edge)?;
+                }
+                WriteOp::DeleteEdge(id) => Self::remove_edge(&mut staged_edges, id)?,
+            }
+        }
+        
+        *nodes = staged_nodes;
+        *edges = staged_edges;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::Properties;
+    use crate::domain::{Properties, PropertyValue};
 
     #[tokio::test]
     async fn test_create_and_get_node() {
@@ -311,6 +463,64 @@ mod tests {
         assert_eq!(results[0].node_type, "agent");
     }
 
+    #[tokio::test]
+    async fn test_query_nodes_with_property_filters() {
+        let storage = InMemoryStorage::new();
+        
+        let mut props = Properties::new();
+        props.insert("read".to_string(), PropertyValue::Boolean(false));
+        props.insert("count".to_string(), PropertyValue::Integer(2));
+        props.insert("tags".to_string(), PropertyValue::List(vec![
+            PropertyValue::String("a".to_string()),
+            PropertyValue::String("b".to_string()),
+        ]));
+        let unread = Node::new("mail", props.clone());
+        props.insert("read".to_string(), PropertyValue::Boolean(true));
+        let read = Node::new("mail", props);
+        
+        storage.create_node(&unread).await.unwrap();
+        storage.create_node(&read).await.unwrap();
+        
+        let query = GraphQuery::new()
+            .with_node_type("mail")
+            .with_property("read", PropertyValue::Boolean(false))
+            .with_property("count", PropertyValue::Float(2.0))
+            .with_property("tags", PropertyValue::List(vec![PropertyValue::String("b".to_string())]));
+        let results = storage.query_nodes(&query).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, unread.id);
+        
+        // Typed comparison: the string "false" is not the boolean false
+        let query = GraphQuery::new()
+            .with_property("read", PropertyValue::String("false".to_string()));
+        assert!(storage.query_nodes(&query).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_query_nodes_with_prefix_and_edge_types() {
+        let storage = InMemoryStorage::new();
+        
+        let mut nodes = Vec::new();
+        for id in ["1", "1a", "2"] {
+            let mut props = Properties::new();
+            props.insert("luhmann_id".to_string(), PropertyValue::String(id.to_string()));
+            let node = Node::new("note", props);
+            storage.create_node(&node).await.unwrap();
+            nodes.push(node);
+        }
+        storage.create_edge(&Edge::new("continues", nodes[1].id, nodes[2].id, Properties::new())).await.unwrap();
+        
+        let query = GraphQuery::new().with_property_prefix("luhmann_id", "1");
+        assert_eq!(storage.query_nodes(&query).await.unwrap().len(), 2);
+        
+        let query = GraphQuery::new()
+            .with_property_prefix("luhmann_id", "1")
+            .with_edge_type("continues");
+        let results = storage.query_nodes(&query).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, nodes[1].id);
+    }
+
     #[tokio::test]
     async fn test_get_neighbors() {
         let storage = InMemoryStorage::new();
@@ -332,4 +542,74 @@ mod tests {
         let neighbors = storage.get_neighbors(agent.id, Some("owns"), EdgeDirection::Outgoing).await.unwrap();
         assert_eq!(neighbors.len(), 2);
     }
+
+    #[tokio::test]
+    async fn test_commit_applies_all_writes() {
+        let storage = InMemoryStorage::new();
+        
+        let agent = Node::new("agent", Properties::new());
+        let mail = Node::new("mail", Properties::new());
+        let edge = Edge::new("sent_from", agent.id, mail.id, Properties::new());
+        
+        let mut tx = storage.begin();
+        tx.create_node(&agent).create_node(&mail).create_edge(&edge);
+        storage.commit(tx).await.unwrap();
+        
+        assert!(storage.get_node(mail.id).await.is_ok());
+        let edges = storage.get_edges_from(agent.id, Some("sent_from")).await.unwrap();
+        assert_eq!(edges.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_commit_updates_and_deletes_edges() {
+        let storage = InMemoryStorage::new();
+        
+        let task = Node::new("task", Properties::new());
+        let alice = Node::new("agent", Properties::new());
+        let bob = Node::new("agent", Properties::new());
+        let old = Edge::new("assigned_to", task.id, alice.id, Properties::new());
+        let mut tx = storage.begin();
+        tx.create_node(&task).create_node(&alice).create_node(&bob).create_edge(&old);
+        storage.commit(tx).await.unwrap();
+        
+        // Reassigning swaps the edges together, or not at all
+        let new = Edge::new("assigned_to", task.id, bob.id, Properties::new());
+        let mut tx = storage.begin();
+        tx.delete_edge(old.id).create_edge(&new).delete_edge(old.id);
+        assert!(matches!(storage.commit(tx).await, Err(StorageError::EdgeNotFound(_))));
+        let edges = storage.get_edges_from(task.id, Some("assigned_to")).await.unwrap();
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].id, old.id);
+        
+        let mut labelled = new.clone();
+        labelled.properties.insert("note".to_string(), PropertyValue::String("urgent".to_string()));
+        let mut tx = storage.begin();
+        tx.delete_edge(old.id).create_edge(&new).update_edge(&labelled);
+        storage.commit(tx).await.unwrap();
+        let edges = storage.get_edges_from(task.id, Some("assigned_to")).await.unwrap();
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].to_node_id, bob.id);
+        assert!(edges[0].properties.contains_key("note"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_leaves_storage_untouched() {
+        let storage = InMemoryStorage::new();
+        
+        let agent = Node::new("agent", Properties::new());
+        storage.create_node(&agent).await.unwrap();
+        
+        // The edge points at a node that never gets created, so the whole batch must fail
+        let mail = Node::new("mail", Properties::new());
+        let missing = Node::new("agent", Properties::new());
+        let edge = Edge::new("sent_to", mail.id, missing.id, Properties::new());
+        
+        let mut tx = storage.begin();
+        tx.create_node(&mail).delete_node(agent.id).create_edge(&edge);
+        let result = storage.commit(tx).await;
+        
+        assert!(matches!(result, Err(StorageError::NodeNotFound(_))));
+        assert!(storage.get_node(agent.id).await.is_ok());
+        assert!(matches!(storage.get_node(mail.id).await, Err(StorageError::NodeNotFound(_))));
+    }
 }
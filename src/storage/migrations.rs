@@ -0,0 +1,83 @@
+use crate::domain::Timestamp;
+
+/// One numbered schema change. Each backend keeps its own list in its own SQL
+/// dialect, but versions and names must line up so `db status` means the same
+/// thing everywhere.
+///
+/// Migrations are append-only: once released, a migration's statements never
+/// change. Schema changes go into a new migration with the next version.
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub statements: &'static [&'static str],
+}
+
+/// Whether a migration has been applied to a database, and when
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: String,
+    pub applied_at: Option<Timestamp>,
+}
+
+/// Merge the versions recorded in `schema_migrations` with the migrations
+/// this build knows about. Applied versions missing from `known` (written by
+/// a newer release) are still listed so they aren't silently hidden.
+pub fn merge_status(
+    known: &[Migration],
+    applied: Vec<(i64, String, Timestamp)>,
+) -> Vec<MigrationStatus> {
+    let mut statuses: Vec<MigrationStatus> = known
+        .iter()
+        .map(|m| MigrationStatus {
+            version: m.version,
+            name: m.name.to_string(),
+            applied_at: applied
+                .iter()
+                .find(|(version, _, _)| *version == m.version)
+                .map(|(_, _, at)| *at),
+        })
+        .collect();
+
+    for (version, name, applied_at) in applied {
+        if !known.iter().any(|m| m.version == version) {
+            statuses.push(MigrationStatus {
+                version,
+                name,
+                applied_at: Some(applied_at),
+            });
+        }
+    }
+
+    statuses.sort_by_key(|s| s.version);
+    statuses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{postgres, sqlite};
+
+    #[test]
+    fn test_backends_share_migration_versions() {
+        let pg: Vec<_> = postgres::MIGRATIONS.iter().map(|m| (m.version, m.name)).collect();
+        let lite: Vec<_> = sqlite::MIGRATIONS.iter().map(|m| (m.version, m.name)).collect();
+        assert_eq!(pg, lite);
+
+        for (i, (version, _)) in pg.iter().enumerate() {
+            assert_eq!(*version, i as i64 + 1, "versions must be sequential from 1");
+        }
+    }
+
+    #[test]
+    fn test_merge_status_lists_unknown_versions() {
+        let known = [Migration { version: 1, name: "first", statements: &[] }];
+        let now = chrono::Utc::now();
+        let statuses = merge_status(&known, vec![(1, "first".into(), now), (7, "future".into(), now)]);
+
+        assert_eq!(statuses.len(), 2);
+        assert!(statuses[0].applied_at.is_some());
+        assert_eq!(statuses[1].name, "future");
+    }
+}
@@ -1,4 +1,4 @@
-use crate::domain::{Edge, GraphQuery, Node, NodeId};
+use crate::domain::{Edge, EdgeId, GraphQuery, Node, NodeId};
 use async_trait::async_trait;
 use thiserror::Error;
 use chrono::{DateTime, Utc};
@@ -8,7 +8,8 @@ use chrono::{DateTime, Utc};
 pub struct SearchQuery {
     /// Node types to search (e.g., "mail", "note", "agent")
     pub node_types: Vec<String>,
-    /// Full-text search pattern (PostgreSQL tsquery compatible)
+    /// Case-insensitive substring of any string value in the properties;
+    /// property names are never matched
     pub search_text: Option<String>,
     /// Created/modified after this time
     pub created_after: Option<DateTime<Utc>>,
@@ -16,8 +17,21 @@ pub struct SearchQuery {
     pub created_before: Option<DateTime<Utc>>,
     /// Updated after this time
     pub updated_after: Option<DateTime<Utc>>,
+    /// Words and "quoted phrases" that must all appear in the node's string
+    /// properties. Postgres runs this as a full-text search (so "deploy"
+    /// also finds "deploying") and returns the best matches first; the other
+    /// backends match each word or phrase as a case-insensitive substring.
+    pub full_text: Option<String>,
     /// Property filters
     pub property_filters: Vec<(String, String)>,
+    /// Case-insensitive substring filters on string properties
+    pub property_contains: Vec<(String, String)>,
+    /// Timestamp properties that must be at or before the given time
+    pub property_before: Vec<(String, DateTime<Utc>)>,
+    /// Only consider these nodes
+    pub node_ids: Option<Vec<NodeId>>,
+    /// Only nodes whose ID, in its hyphenated form, starts with this prefix
+    pub id_prefix: Option<String>,
     /// Maximum results to return
     pub limit: usize,
     /// Offset for pagination
@@ -34,13 +48,54 @@ impl Default for SearchQuery {
             created_after: None,
             created_before: None,
             updated_after: None,
+            full_text: None,
             property_filters: vec![],
+            property_contains: vec![],
+            property_before: vec![],
+            node_ids: None,
+            id_prefix: None,
             limit: 50,
             offset: 0,
         }
     }
 }
 
+/// The words and quoted phrases of a `SearchQuery::full_text`, lowercased,
+/// for backends without a full-text engine
+pub fn full_text_terms(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    for (i, part) in text.split('"').enumerate() {
+        if i % 2 == 1 {
+            // Inside quotes: the phrase as a whole
+            if !part.trim().is_empty() {
+                terms.push(part.trim().to_lowercase());
+            }
+        } else {
+            terms.extend(part.split_whitespace().map(str::to_lowercase));
+        }
+    }
+    terms
+}
+
+/// The lowest and highest IDs whose hyphenated form starts with `prefix`,
+/// so backends can match a short ID with a range scan. `None` when no ID
+/// can start with it.
+pub fn id_prefix_range(prefix: &str) -> Option<(NodeId, NodeId)> {
+    let prefix = prefix.to_lowercase();
+    let well_formed = prefix.len() <= 36
+        && prefix.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        });
+    if !well_formed {
+        return None;
+    }
+    let hex = prefix.replace('-', "");
+    let low = NodeId::parse_str(&format!("{hex:0<32}")).ok()?;
+    let high = NodeId::parse_str(&format!("{hex:f<32}")).ok()?;
+    Some((low, high))
+}
+
 /// Search results with pagination info
 #[derive(Debug, Clone)]
 pub struct SearchResults<T> {
@@ -52,6 +107,9 @@ pub enum StorageError {
     #[error("Node not found: {0}")]
     NodeNotFound(NodeId),
     
+    #[error("Edge not found: {0}")]
+    EdgeNotFound(EdgeId),
+    
     #[error("Database error: {0}")]
     DatabaseError(String),
     
@@ -60,21 +118,89 @@ pub enum StorageError {
     
     #[error("Constraint violation: {0}")]
     ConstraintViolation(String),
+    
+    #[error("Blob not found: {0}")]
+    BlobNotFound(String),
 }
 
 pub type Result<T> = std::result::Result<T, StorageError>;
 
+/// A single write staged inside a [`Transaction`]
+#[derive(Debug, Clone)]
+pub enum WriteOp {
+    CreateNode(Node),
+    UpdateNode(Node),
+    DeleteNode(NodeId),
+    CreateEdge(Edge),
+    /// Replace an edge's properties, as [`GraphStorage::update_edge`] does
+    UpdateEdge(Edge),
+    DeleteEdge(EdgeId),
+}
+
+/// A batch of graph writes that is applied all-or-nothing by
+/// [`GraphStorage::commit`]. Nothing touches storage until commit, so
+/// rolling back is simply dropping the transaction.
+#[derive(Debug, Clone, Default)]
+pub struct Transaction {
+    ops: Vec<WriteOp>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create_node(&mut self, node: &Node) -> &mut Self {
+        self.ops.push(WriteOp::CreateNode(node.clone()));
+        self
+    }
+
+    pub fn update_node(&mut self, node: &Node) -> &mut Self {
+        self.ops.push(WriteOp::UpdateNode(node.clone()));
+        self
+    }
+
+    pub fn delete_node(&mut self, id: NodeId) -> &mut Self {
+        self.ops.push(WriteOp::DeleteNode(id));
+        self
+    }
+
+    pub fn create_edge(&mut self, edge: &Edge) -> &mut Self {
+        self.ops.push(WriteOp::CreateEdge(edge.clone()));
+        self
+    }
+
+    pub fn update_edge(&mut self, edge: &Edge) -> &mut Self {
+        self.ops.push(WriteOp::UpdateEdge(edge.clone()));
+        self
+    }
+
+    pub fn delete_edge(&mut self, id: EdgeId) -> &mut Self {
+        self.ops.push(WriteOp::DeleteEdge(id));
+        self
+    }
+
+    pub fn into_ops(self) -> Vec<WriteOp> {
+        self.ops
+    }
+}
+
 #[async_trait]
 pub trait GraphStorage: Send + Sync {
     // Node operations
     async fn create_node(&self, node: &Node) -> Result<Node>;
     async fn get_node(&self, id: NodeId) -> Result<Node>;
+    // Fetch several nodes in one round trip; ids that don't exist are skipped
+    async fn get_nodes(&self, ids: &[NodeId]) -> Result<Vec<Node>>;
     async fn update_node(&self, node: &Node) -> Result<Node>;
     async fn delete_node(&self, id: NodeId) -> Result<()>;
     async fn query_nodes(&self, query: &GraphQuery) -> Result<Vec<Node>>;
     
     // Edge operations
     async fn create_edge(&self, edge: &Edge) -> Result<Edge>;
+    // Replace an edge's properties; its type and endpoints never change
+    async fn update_edge(&self, edge: &Edge) -> Result<Edge>;
+    async fn delete_edge(&self, id: EdgeId) -> Result<()>;
     async fn get_edges_from(&self, node_id: NodeId, edge_type: Option<&str>) -> Result<Vec<Edge>>;
     async fn get_edges_to(&self, node_id: NodeId, edge_type: Option<&str>) -> Result<Vec<Edge>>;
     
@@ -88,6 +214,22 @@ pub trait GraphStorage: Send + Sync {
     
     // Advanced search with full-text, time range, and pagination
     async fn search_nodes(&self, query: &SearchQuery) -> Result<SearchResults<Node>>;
+    
+    // Content-addressed blobs (attachment contents), keyed by the SHA-256 hex
+    // digest of their bytes; putting a blob that is already stored is a no-op
+    async fn put_blob(&self, hash: &str, data: &[u8]) -> Result<()>;
+    async fn get_blob(&self, hash: &str) -> Result<Vec<u8>>;
+    async fn delete_blob(&self, hash: &str) -> Result<()>;
+    
+    // Named counters: atomically increment and return the new value, starting
+    // from 1. Concurrent callers always get distinct values.
+    async fn next_sequence(&self, name: &str) -> Result<i64>;
+    
+    // Transactions: stage writes with `begin`, then apply them atomically with `commit`
+    fn begin(&self) -> Transaction {
+        Transaction::new()
+    }
+    async fn commit(&self, tx: Transaction) -> Result<()>;
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -96,5 +238,158 @@ pub enum EdgeDirection {
     Incoming,
 }
 
+pub mod blob;
 pub mod memory;
+pub mod migrations;
+pub mod notify;
 pub mod postgres;
+pub mod sqlite;
+
+/// Whichever backend the configured database URL selects.
+/// `sqlite:` URLs open a [`sqlite::SqliteStorage`], anything else is treated
+/// as a Postgres connection string, and no URL falls back to memory.
+/// Clones share the connection pool.
+#[derive(Clone)]
+pub enum AnyStorage {
+    Memory(memory::InMemoryStorage),
+    Postgres(postgres::PostgresStorage),
+    Sqlite(sqlite::SqliteStorage),
+}
+
+impl AnyStorage {
+    pub async fn connect(database_url: Option<&str>) -> Result<Self> {
+        match database_url {
+            None => Ok(Self::Memory(memory::InMemoryStorage::new())),
+            Some(url) if is_sqlite_url(url) => {
+                Ok(Self::Sqlite(sqlite::SqliteStorage::connect(url).await?))
+            }
+            Some(url) => {
+                let pool = sqlx::postgres::PgPool::connect(url)
+                    .await
+                    .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+                Ok(Self::Postgres(postgres::PostgresStorage::new(pool)))
+            }
+        }
+    }
+
+    /// Apply pending schema migrations; the in-memory backend has no schema
+    pub async fn migrate(&self) -> Result<Vec<&'static migrations::Migration>> {
+        match self {
+            Self::Memory(_) => Ok(Vec::new()),
+            Self::Postgres(s) => s.migrate().await,
+            Self::Sqlite(s) => s.migrate().await,
+        }
+    }
+
+    pub async fn migration_status(&self) -> Result<Vec<migrations::MigrationStatus>> {
+        match self {
+            Self::Memory(_) => Ok(Vec::new()),
+            Self::Postgres(s) => s.migration_status().await,
+            Self::Sqlite(s) => s.migration_status().await,
+        }
+    }
+
+    /// Drop all data and recreate the schema at the latest version
+    pub async fn reset_tables(&self) -> Result<()> {
+        match self {
+            Self::Memory(_) => Ok(()),
+            Self::Postgres(s) => s.reset_tables().await,
+            Self::Sqlite(s) => s.reset_tables().await,
+        }
+    }
+}
+
+pub fn is_sqlite_url(url: &str) -> bool {
+    url.starts_with("sqlite:")
+}
+
+/// Forward a call to whichever backend is inside an [`AnyStorage`]
+macro_rules! dispatch {
+    ($self:ident, $s:ident => $call:expr) => {
+        match $self {
+            AnyStorage::Memory($s) => $call,
+            AnyStorage::Postgres($s) => $call,
+            AnyStorage::Sqlite($s) => $call,
+        }
+    };
+}
+
+#[async_trait]
+impl GraphStorage for AnyStorage {
+    async fn create_node(&self, node: &Node) -> Result<Node> {
+        dispatch!(self, s => s.create_node(node).await)
+    }
+
+    async fn get_node(&self, id: NodeId) -> Result<Node> {
+        dispatch!(self, s => s.get_node(id).await)
+    }
+
+    async fn get_nodes(&self, ids: &[NodeId]) -> Result<Vec<Node>> {
+        dispatch!(self, s => s.get_nodes(ids).await)
+    }
+
+    async fn update_node(&self, node: &Node) -> Result<Node> {
+        dispatch!(self, s => s.update_node(node).await)
+    }
+
+    async fn delete_node(&self, id: NodeId) -> Result<()> {
+        dispatch!(self, s => s.delete_node(id).await)
+    }
+
+    async fn query_nodes(&self, query: &GraphQuery) -> Result<Vec<Node>> {
+        dispatch!(self, s => s.query_nodes(query).await)
+    }
+
+    async fn create_edge(&self, edge: &Edge) -> Result<Edge> {
+        dispatch!(self, s => s.create_edge(edge).await)
+    }
+
+    async fn update_edge(&self, edge: &Edge) -> Result<Edge> {
+        dispatch!(self, s => s.update_edge(edge).await)
+    }
+
+    async fn delete_edge(&self, id: EdgeId) -> Result<()> {
+        dispatch!(self, s => s.delete_edge(id).await)
+    }
+
+    async fn get_edges_from(&self, node_id: NodeId, edge_type: Option<&str>) -> Result<Vec<Edge>> {
+        dispatch!(self, s => s.get_edges_from(node_id, edge_type).await)
+    }
+
+    async fn get_edges_to(&self, node_id: NodeId, edge_type: Option<&str>) -> Result<Vec<Edge>> {
+        dispatch!(self, s => s.get_edges_to(node_id, edge_type).await)
+    }
+
+    async fn get_neighbors(
+        &self,
+        node_id: NodeId,
+        edge_type: Option<&str>,
+        direction: EdgeDirection,
+    ) -> Result<Vec<Node>> {
+        dispatch!(self, s => s.get_neighbors(node_id, edge_type, direction).await)
+    }
+
+    async fn search_nodes(&self, query: &SearchQuery) -> Result<SearchResults<Node>> {
+        dispatch!(self, s => s.search_nodes(query).await)
+    }
+
+    async fn put_blob(&self, hash: &str, data: &[u8]) -> Result<()> {
+        dispatch!(self, s => s.put_blob(hash, data).await)
+    }
+
+    async fn get_blob(&self, hash: &str) -> Result<Vec<u8>> {
+        dispatch!(self, s => s.get_blob(hash).await)
+    }
+
+    async fn delete_blob(&self, hash: &str) -> Result<()> {
+        dispatch!(self, s => s.delete_blob(hash).await)
+    }
+
+    async fn next_sequence(&self, name: &str) -> Result<i64> {
+        dispatch!(self, s => s.next_sequence(name).await)
+    }
+
+    async fn commit(&self, tx: Transaction) -> Result<()> {
+        dispatch!(self, s => s.commit(tx).await)
+    }
+}
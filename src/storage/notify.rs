@@ -0,0 +1,88 @@
+use crate::domain::NodeId;
+use crate::storage::{Result, StorageError};
+use sqlx::postgres::PgListener;
+
+/// Channel the `sent_to` edge trigger publishes on; payload is the mailbox id
+pub const MAIL_CHANNEL: &str = "agent_office_mail";
+
+/// Channel the schedules trigger publishes on; payload is the agent id
+pub const SCHEDULE_CHANNEL: &str = "agent_office_schedule";
+
+/// A change someone may need to wake up for
+#[derive(Debug, Clone, PartialEq)]
+pub enum Wakeup {
+    /// Mail was delivered to this mailbox
+    Mail(NodeId),
+    /// A schedule for this agent was created, changed, or removed
+    Schedule(String),
+    /// The connection dropped and was re-established; anything sent in
+    /// between was missed, so listeners should re-check everything
+    Reconnected,
+}
+
+/// Subscription to the notifications emitted by the Postgres triggers.
+/// SQLite has no equivalent, so callers there just keep polling.
+pub struct WakeupListener {
+    listener: PgListener,
+}
+
+impl WakeupListener {
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        let mut listener = PgListener::connect(database_url)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        listener
+            .listen_all([MAIL_CHANNEL, SCHEDULE_CHANNEL])
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        Ok(Self { listener })
+    }
+
+    /// Wait for the next notification. Payloads that don't parse are skipped.
+    pub async fn recv(&mut self) -> Result<Wakeup> {
+        loop {
+            let notification = self
+                .listener
+                .try_recv()
+                .await
+                .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+            let Some(notification) = notification else {
+                return Ok(Wakeup::Reconnected);
+            };
+
+            if let Some(wakeup) = parse_wakeup(notification.channel(), notification.payload()) {
+                return Ok(wakeup);
+            }
+        }
+    }
+}
+
+fn parse_wakeup(channel: &str, payload: &str) -> Option<Wakeup> {
+    match channel {
+        MAIL_CHANNEL => payload.parse().ok().map(Wakeup::Mail),
+        SCHEDULE_CHANNEL => Some(Wakeup::Schedule(payload.to_string())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::string_to_node_id;
+
+    #[test]
+    fn test_parse_wakeup_payloads() {
+        let mailbox = string_to_node_id("alice");
+        assert_eq!(
+            parse_wakeup(MAIL_CHANNEL, &mailbox.to_string()),
+            Some(Wakeup::Mail(mailbox))
+        );
+        assert_eq!(
+            parse_wakeup(SCHEDULE_CHANNEL, "alice"),
+            Some(Wakeup::Schedule("alice".to_string()))
+        );
+        assert_eq!(parse_wakeup(MAIL_CHANNEL, "not-a-uuid"), None);
+        assert_eq!(parse_wakeup("other", "alice"), None);
+    }
+}
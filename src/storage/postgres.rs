@@ -1,51 +1,32 @@
-use crate::domain::{Edge, GraphQuery, Node, NodeId, Properties};
-use crate::storage::{EdgeDirection, GraphStorage, Result, StorageError, SearchQuery, SearchResults};
+use crate::domain::{Edge, EdgeId, GraphQuery, Node, NodeId, Properties, PropertyValue, Timestamp};
+use std::collections::HashMap;
+use crate::storage::migrations::{merge_status, Migration, MigrationStatus};
+use crate::storage::{id_prefix_range, EdgeDirection, GraphStorage, Result, StorageError, SearchQuery, SearchResults, Transaction, WriteOp};
 use async_trait::async_trait;
-use sqlx::{Pool, Postgres, Row};
+use sqlx::postgres::PgRow;
+use sqlx::{PgExecutor, Pool, Postgres, QueryBuilder, Row};
 
-pub struct PostgresStorage {
-    pool: Pool<Postgres>,
-}
-
-impl PostgresStorage {
-    pub fn new(pool: Pool<Postgres>) -> Self {
-        Self { pool }
-    }
-
-    pub async fn setup_tables(&self) -> Result<()> {
-        // Execute each statement separately since SQLx doesn't support multiple statements in one query
-        
-        // Drop existing tables
-        sqlx::query("DROP TABLE IF EXISTS edges CASCADE")
-            .execute(&self.pool)
-            .await
-            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-            
-        sqlx::query("DROP TABLE IF EXISTS nodes CASCADE")
-            .execute(&self.pool)
-            .await
-            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-
-        // Create nodes table
-        sqlx::query(
+/// Schema history, applied in order by [`PostgresStorage::migrate`].
+/// Must stay in step with the SQLite list (same versions and names).
+///
+/// The first two use `IF NOT EXISTS` so databases created before migrations
+/// were tracked are adopted without touching their data.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_graph_tables",
+        statements: &[
             r#"
-            CREATE TABLE nodes (
+            CREATE TABLE IF NOT EXISTS nodes (
                 id UUID PRIMARY KEY,
                 node_type VARCHAR(255) NOT NULL,
                 properties JSONB NOT NULL DEFAULT '{}',
                 created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
                 updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
             )
-            "#
-        )
-        .execute(&self.pool)
-        .await
-        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-
-        // Create edges table
-        sqlx::query(
+            "#,
             r#"
-            CREATE TABLE edges (
+            CREATE TABLE IF NOT EXISTS edges (
                 id UUID PRIMARY KEY,
                 edge_type VARCHAR(255) NOT NULL,
                 from_node_id UUID NOT NULL,
@@ -55,83 +36,25 @@ impl PostgresStorage {
                 FOREIGN KEY (from_node_id) REFERENCES nodes(id) ON DELETE CASCADE,
                 FOREIGN KEY (to_node_id) REFERENCES nodes(id) ON DELETE CASCADE
             )
-            "#
-        )
-        .execute(&self.pool)
-        .await
-        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-
-        // Create indexes for basic lookups
-        sqlx::query("CREATE INDEX idx_nodes_type ON nodes(node_type)")
-            .execute(&self.pool)
-            .await
-            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-            
-        sqlx::query("CREATE INDEX idx_nodes_properties ON nodes USING GIN(properties)")
-            .execute(&self.pool)
-            .await
-            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-        
-        // Create time-based indexes for recency searches
-        sqlx::query("CREATE INDEX idx_nodes_created_at ON nodes(created_at DESC)")
-            .execute(&self.pool)
-            .await
-            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-            
-        sqlx::query("CREATE INDEX idx_nodes_updated_at ON nodes(updated_at DESC)")
-            .execute(&self.pool)
-            .await
-            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-        
-        // Create composite indexes for time range + type queries
-        sqlx::query("CREATE INDEX idx_nodes_type_created ON nodes(node_type, created_at DESC)")
-            .execute(&self.pool)
-            .await
-            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-            
-        sqlx::query("CREATE INDEX idx_nodes_type_updated ON nodes(node_type, updated_at DESC)")
-            .execute(&self.pool)
-            .await
-            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-            
-        sqlx::query("CREATE INDEX idx_edges_type ON edges(edge_type)")
-            .execute(&self.pool)
-            .await
-            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-            
-        sqlx::query("CREATE INDEX idx_edges_from ON edges(from_node_id)")
-            .execute(&self.pool)
-            .await
-            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-            
-        sqlx::query("CREATE INDEX idx_edges_to ON edges(to_node_id)")
-            .execute(&self.pool)
-            .await
-            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-            
-        sqlx::query("CREATE INDEX idx_edges_from_type ON edges(from_node_id, edge_type)")
-            .execute(&self.pool)
-            .await
-            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-            
-        sqlx::query("CREATE INDEX idx_edges_to_type ON edges(to_node_id, edge_type)")
-            .execute(&self.pool)
-            .await
-            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-        
-        // Create indexes on edge timestamps
-        sqlx::query("CREATE INDEX idx_edges_created_at ON edges(created_at DESC)")
-            .execute(&self.pool)
-            .await
-            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-
-        Ok(())
-    }
-
-    /// Migrate schedules table - creates if not exists (preserves existing data)
-    pub async fn migrate_schedules_table(&self) -> Result<()> {
-        // Create schedules table if not exists
-        sqlx::query(
+            "#,
+            "CREATE INDEX IF NOT EXISTS idx_nodes_type ON nodes(node_type)",
+            "CREATE INDEX IF NOT EXISTS idx_nodes_properties ON nodes USING GIN(properties)",
+            "CREATE INDEX IF NOT EXISTS idx_nodes_created_at ON nodes(created_at DESC)",
+            "CREATE INDEX IF NOT EXISTS idx_nodes_updated_at ON nodes(updated_at DESC)",
+            "CREATE INDEX IF NOT EXISTS idx_nodes_type_created ON nodes(node_type, created_at DESC)",
+            "CREATE INDEX IF NOT EXISTS idx_nodes_type_updated ON nodes(node_type, updated_at DESC)",
+            "CREATE INDEX IF NOT EXISTS idx_edges_type ON edges(edge_type)",
+            "CREATE INDEX IF NOT EXISTS idx_edges_from ON edges(from_node_id)",
+            "CREATE INDEX IF NOT EXISTS idx_edges_to ON edges(to_node_id)",
+            "CREATE INDEX IF NOT EXISTS idx_edges_from_type ON edges(from_node_id, edge_type)",
+            "CREATE INDEX IF NOT EXISTS idx_edges_to_type ON edges(to_node_id, edge_type)",
+            "CREATE INDEX IF NOT EXISTS idx_edges_created_at ON edges(created_at DESC)",
+        ],
+    },
+    Migration {
+        version: 2,
+        name: "create_schedules_table",
+        statements: &[
             r#"
             CREATE TABLE IF NOT EXISTS schedules (
                 id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
@@ -144,45 +67,498 @@ impl PostgresStorage {
                 updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
             )
             "#,
-        )
-        .execute(&self.pool)
-        .await
+            "CREATE INDEX IF NOT EXISTS idx_schedules_agent_id ON schedules(agent_id)",
+            "CREATE INDEX IF NOT EXISTS idx_schedules_active ON schedules(is_active)",
+        ],
+    },
+    Migration {
+        version: 3,
+        name: "notify_on_mail_and_schedules",
+        // Payload formats are parsed by `storage::notify`
+        statements: &[
+            r#"
+            CREATE OR REPLACE FUNCTION notify_mail_delivered() RETURNS trigger AS $$
+            BEGIN
+                PERFORM pg_notify('agent_office_mail', NEW.to_node_id::text);
+                RETURN NEW;
+            END;
+            $$ LANGUAGE plpgsql
+            "#,
+            r#"
+            CREATE TRIGGER edges_notify_mail_delivered
+                AFTER INSERT ON edges
+                FOR EACH ROW WHEN (NEW.edge_type = 'sent_to')
+                EXECUTE FUNCTION notify_mail_delivered()
+            "#,
+            r#"
+            CREATE OR REPLACE FUNCTION notify_schedule_changed() RETURNS trigger AS $$
+            BEGIN
+                IF TG_OP = 'DELETE' THEN
+                    PERFORM pg_notify('agent_office_schedule', OLD.agent_id);
+                ELSE
+                    PERFORM pg_notify('agent_office_schedule', NEW.agent_id);
+                END IF;
+                RETURN NULL;
+            END;
+            $$ LANGUAGE plpgsql
+            "#,
+            r#"
+            CREATE TRIGGER schedules_notify_changed
+                AFTER INSERT OR DELETE ON schedules
+                FOR EACH ROW EXECUTE FUNCTION notify_schedule_changed()
+            "#,
+            // Firing only touches last_fired_at, which nobody needs to hear about
+            r#"
+            CREATE TRIGGER schedules_notify_updated
+                AFTER UPDATE OF cron_expression, is_active, action ON schedules
+                FOR EACH ROW EXECUTE FUNCTION notify_schedule_changed()
+            "#,
+        ],
+    },
+    Migration {
+        version: 4,
+        name: "mail_recipient_lists",
+        // Single-recipient mail kept its read flag on the node; move it onto
+        // the sent_to edge, then turn to_mailbox_id into recipient lists
+        statements: &[
+            r#"
+            UPDATE edges e
+            SET properties = e.properties || jsonb_build_object(
+                'kind', 'to',
+                'read', COALESCE(n.properties->'read', 'false'::jsonb)
+            )
+            FROM nodes n
+            WHERE e.from_node_id = n.id
+              AND e.edge_type = 'sent_to'
+              AND n.node_type = 'mail'
+              AND NOT e.properties ? 'read'
+            "#,
+            r#"
+            UPDATE nodes
+            SET properties = (properties || jsonb_build_object(
+                'to_mailbox_ids', jsonb_build_array(properties->'to_mailbox_id'),
+                'cc_mailbox_ids', '[]'::jsonb,
+                'recipients', jsonb_build_array(properties->'to_mailbox_id')
+            )) - 'to_mailbox_id' - 'read'
+            WHERE node_type = 'mail' AND properties ? 'to_mailbox_id'
+            "#,
+        ],
+    },
+    Migration {
+        version: 5,
+        name: "create_blobs_table",
+        statements: &[
+            r#"
+            CREATE TABLE IF NOT EXISTS blobs (
+                hash TEXT PRIMARY KEY,
+                data BYTEA NOT NULL,
+                size BIGINT NOT NULL,
+                created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+            )
+            "#,
+        ],
+    },
+    Migration {
+        version: 6,
+        name: "create_sequences_table",
+        statements: &[
+            r#"
+            CREATE TABLE IF NOT EXISTS sequences (
+                name TEXT PRIMARY KEY,
+                value BIGINT NOT NULL
+            )
+            "#,
+        ],
+    },
+    Migration {
+        version: 7,
+        name: "full_text_search_index",
+        // Must match `FTS_DOCUMENT` exactly for searches to use it
+        statements: &[
+            r#"CREATE INDEX IF NOT EXISTS idx_nodes_fts ON nodes USING GIN (jsonb_to_tsvector('english', properties, '["string"]'))"#,
+        ],
+    },
+    Migration {
+        version: 8,
+        name: "create_leases_table",
+        statements: &[
+            r#"
+            CREATE TABLE IF NOT EXISTS leases (
+                name TEXT PRIMARY KEY,
+                holder TEXT NOT NULL,
+                acquired_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+            )
+            "#,
+        ],
+    },
+];
+
+/// The text full-text searches run against: every string property
+const FTS_DOCUMENT: &str = r#"jsonb_to_tsvector('english', properties, '["string"]')"#;
+
+/// Every string value in a node's properties, nested ones included
+const STRING_VALUES_PATH: &str = r#"strict $.** ? (@.type() == "string")"#;
+
+/// Builder for `SELECT`s over the nodes table.
+///
+/// Only fixed SQL fragments are pushed as text; every caller-supplied value
+/// (types, search text, property keys and values, limits) is sent as a bound
+/// parameter, so nothing coming from agents or the web UI ends up in the SQL.
+struct NodeSelect {
+    builder: QueryBuilder<'static, Postgres>,
+}
+
+/// Columns results can be ordered by
+#[derive(Debug, Clone, Copy)]
+enum NodeOrder {
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl NodeSelect {
+    fn new() -> Self {
+        Self {
+            builder: QueryBuilder::new(
+                "SELECT id, node_type, properties, created_at, updated_at FROM nodes WHERE TRUE",
+            ),
+        }
+    }
+
+    /// Restrict to the given node types; an empty slice matches every type
+    fn node_types(&mut self, types: &[String]) -> &mut Self {
+        if !types.is_empty() {
+            self.builder
+                .push(" AND node_type = ANY(")
+                .push_bind(types.to_vec())
+                .push(")");
+        }
+        self
+    }
+
+    /// Case-insensitive substring match against any string value in the
+    /// properties, at any depth; keys are never matched
+    fn text_contains(&mut self, text: &str) -> &mut Self {
+        self.builder
+            .push(" AND EXISTS (SELECT 1 FROM jsonb_path_query(properties, CAST(")
+            .push_bind(STRING_VALUES_PATH)
+            .push(" AS jsonpath)) AS v(value) WHERE v.value #>> ARRAY[]::text[] ILIKE ")
+            .push_bind(contains_pattern(text))
+            .push(")");
+        self
+    }
+
+    /// Full-text match over every string property, in web-search syntax
+    /// (words, "quoted phrases", `or`, `-word`)
+    fn full_text(&mut self, text: &str) -> &mut Self {
+        self.builder
+            .push(" AND ")
+            .push(FTS_DOCUMENT)
+            .push(" @@ websearch_to_tsquery('english', ")
+            .push_bind(text.to_string())
+            .push(")");
+        self
+    }
+
+    /// Case-insensitive substring match against string property `key`
+    fn property_contains(&mut self, key: &str, text: &str) -> &mut Self {
+        self.builder
+            .push(" AND properties->>")
+            .push_bind(key.to_string())
+            .push(" ILIKE ")
+            .push_bind(contains_pattern(text));
+        self
+    }
+
+    fn node_ids(&mut self, ids: &[NodeId]) -> &mut Self {
+        self.builder.push(" AND id = ANY(").push_bind(ids.to_vec()).push(")");
+        self
+    }
+
+    /// Match nodes whose ID starts with `prefix`; nothing if no ID can
+    fn id_prefix(&mut self, prefix: &str) -> &mut Self {
+        match id_prefix_range(prefix) {
+            Some((low, high)) => {
+                self.builder.push(" AND id BETWEEN ").push_bind(low)
+                    .push(" AND ").push_bind(high);
+            }
+            None => {
+                self.builder.push(" AND FALSE");
+            }
+        }
+        self
+    }
+
+    /// Match nodes whose property `key` has the given text value
+    fn property_equals(&mut self, key: &str, value: &str) -> &mut Self {
+        self.builder
+            .push(" AND properties->>")
+            .push_bind(key.to_string())
+            .push(" = ")
+            .push_bind(value.to_string());
+        self
+    }
+
+    /// Match nodes whose timestamp property `key` is at or before `at`
+    fn property_before(&mut self, key: &str, at: Timestamp) -> &mut Self {
+        self.builder
+            .push(" AND (properties->>")
+            .push_bind(key.to_string())
+            .push(")::timestamptz <= ")
+            .push_bind(at);
+        self
+    }
+
+    /// JSONB containment: nodes whose properties contain every filter value
+    fn properties_contain(&mut self, filters: &HashMap<String, PropertyValue>) -> Result<&mut Self> {
+        if !filters.is_empty() {
+            let filters_json = serde_json::to_value(filters)
+                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            self.builder.push(" AND properties @> ").push_bind(filters_json);
+        }
+        Ok(self)
+    }
+
+    /// Match nodes whose string property `key` starts with `prefix`
+    fn property_starts_with(&mut self, key: &str, prefix: &str) -> &mut Self {
+        self.builder
+            .push(" AND starts_with(properties->>")
+            .push_bind(key.to_string())
+            .push(", ")
+            .push_bind(prefix.to_string())
+            .push(")");
+        self
+    }
+
+    /// Match nodes with at least one incoming or outgoing edge of these types
+    fn has_edge_types(&mut self, types: &[String]) -> &mut Self {
+        self.builder
+            .push(" AND EXISTS (SELECT 1 FROM edges WHERE (edges.from_node_id = nodes.id OR edges.to_node_id = nodes.id) AND edges.edge_type = ANY(")
+            .push_bind(types.to_vec())
+            .push("))");
+        self
+    }
+
+    fn created_after(&mut self, at: Timestamp) -> &mut Self {
+        self.builder.push(" AND created_at >= ").push_bind(at);
+        self
+    }
+
+    fn created_before(&mut self, at: Timestamp) -> &mut Self {
+        self.builder.push(" AND created_at <= ").push_bind(at);
+        self
+    }
+
+    fn updated_after(&mut self, at: Timestamp) -> &mut Self {
+        self.builder.push(" AND updated_at >= ").push_bind(at);
+        self
+    }
+
+    /// Best full-text matches for `text` first, newest first among equals
+    fn order_by_rank(&mut self, text: &str) -> &mut Self {
+        self.builder
+            .push(" ORDER BY ts_rank(")
+            .push(FTS_DOCUMENT)
+            .push(", websearch_to_tsquery('english', ")
+            .push_bind(text.to_string())
+            .push(")) DESC, updated_at DESC");
+        self
+    }
+
+    fn order_by_desc(&mut self, order: NodeOrder) -> &mut Self {
+        self.builder.push(match order {
+            NodeOrder::CreatedAt => " ORDER BY created_at DESC, id",
+            NodeOrder::UpdatedAt => " ORDER BY updated_at DESC, id",
+        });
+        self
+    }
+
+    fn limit(&mut self, limit: usize) -> &mut Self {
+        self.builder.push(" LIMIT ").push_bind(limit as i64);
+        self
+    }
+
+    fn offset(&mut self, offset: usize) -> &mut Self {
+        self.builder.push(" OFFSET ").push_bind(offset as i64);
+        self
+    }
+
+    async fn fetch_all<'e>(mut self, executor: impl PgExecutor<'e>) -> Result<Vec<Node>> {
+        let rows = self
+            .builder
+            .build()
+            .fetch_all(executor)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        rows.iter().map(node_from_row).collect()
+    }
+}
+
+/// ILIKE pattern matching `text` anywhere, with wildcards in it escaped
+fn contains_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+fn node_from_row(row: &PgRow) -> Result<Node> {
+    let properties_json: serde_json::Value = row.try_get("properties")
         .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+    let properties: Properties = serde_json::from_value(properties_json)
+        .map_err(|e| StorageError::SerializationError(e.to_string()))?;
 
-        // Create indexes
-        sqlx::query(
-            "CREATE INDEX IF NOT EXISTS idx_schedules_agent_id ON schedules(agent_id)"
+    Ok(Node {
+        id: row.try_get("id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+        node_type: row.try_get("node_type").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+        properties,
+        created_at: row.try_get("created_at").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+        updated_at: row.try_get("updated_at").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+    })
+}
+
+fn edge_from_row(row: &PgRow) -> Result<Edge> {
+    let properties_json: serde_json::Value = row.try_get("properties")
+        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+    let properties = serde_json::from_value(properties_json)
+        .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+    Ok(Edge {
+        id: row.try_get("id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+        edge_type: row.try_get("edge_type").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+        from_node_id: row.try_get("from_node_id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+        to_node_id: row.try_get("to_node_id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+        properties,
+        created_at: row.try_get("created_at").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+    })
+}
+
+#[derive(Clone)]
+pub struct PostgresStorage {
+    pool: Pool<Postgres>,
+}
+
+impl PostgresStorage {
+    pub fn new(pool: Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    pub fn pool(&self) -> &Pool<Postgres> {
+        &self.pool
+    }
+
+    /// Drop every table and rebuild the schema from scratch
+    pub async fn reset_tables(&self) -> Result<()> {
+        for table in ["edges", "nodes", "schedules", "leases", "blobs", "sequences", "schema_migrations"] {
+            sqlx::query(&format!("DROP TABLE IF EXISTS {} CASCADE", table))
+                .execute(&self.pool)
+                .await
+                .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        }
+        self.migrate().await?;
+        Ok(())
+    }
+
+    /// Apply every migration not yet recorded in `schema_migrations`, each in
+    /// its own transaction. Returns the migrations that were applied.
+    pub async fn migrate(&self) -> Result<Vec<&'static Migration>> {
+        self.ensure_migrations_table().await?;
+
+        let mut applied = Vec::new();
+        for migration in MIGRATIONS {
+            let mut tx = self
+                .pool
+                .begin()
+                .await
+                .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+            // Claim the version first: a concurrent migrator blocks on the
+            // row until we commit, then finds it taken and skips
+            let claimed = sqlx::query(
+                r#"
+                INSERT INTO schema_migrations (version, name, applied_at)
+                VALUES ($1, $2, NOW())
+                ON CONFLICT (version) DO NOTHING
+                "#,
+            )
+            .bind(migration.version)
+            .bind(migration.name)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?
+            .rows_affected();
+            if claimed == 0 {
+                continue;
+            }
+
+            for statement in migration.statements {
+                sqlx::query(statement)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| {
+                        StorageError::DatabaseError(format!(
+                            "migration {} ({}) failed: {}",
+                            migration.version, migration.name, e
+                        ))
+                    })?;
+            }
+
+            tx.commit()
+                .await
+                .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+            applied.push(migration);
+        }
+        Ok(applied)
+    }
+
+    /// Every known migration plus anything recorded by a newer release
+    pub async fn migration_status(&self) -> Result<Vec<MigrationStatus>> {
+        self.ensure_migrations_table().await?;
+
+        let rows = sqlx::query(
+            "SELECT version, name, applied_at FROM schema_migrations ORDER BY version",
         )
-        .execute(&self.pool)
+        .fetch_all(&self.pool)
         .await
         .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
 
+        let applied = rows
+            .iter()
+            .map(|row| {
+                Ok((
+                    row.try_get("version").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+                    row.try_get("name").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+                    row.try_get("applied_at").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(merge_status(MIGRATIONS, applied))
+    }
+
+    async fn ensure_migrations_table(&self) -> Result<()> {
         sqlx::query(
-            "CREATE INDEX IF NOT EXISTS idx_schedules_active ON schedules(is_active)"
+            r#"
+            CREATE TABLE IF NOT EXISTS schema_migrations (
+                version BIGINT PRIMARY KEY,
+                name VARCHAR(255) NOT NULL,
+                applied_at TIMESTAMP WITH TIME ZONE NOT NULL
+            )
+            "#,
         )
         .execute(&self.pool)
         .await
         .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-
         Ok(())
     }
-    
-    /// Helper function to convert properties JSONB to searchable text
-    #[allow(dead_code)]
-    fn properties_to_search_text(properties: &Properties) -> String {
-        let mut texts = Vec::new();
-        for (_, value) in properties {
-            if let serde_json::Value::String(s) = serde_json::to_value(value).unwrap_or_default() {
-                texts.push(s);
-            }
-        }
-        texts.join(" ")
-    }
-}
 
-#[async_trait]
-impl GraphStorage for PostgresStorage {
-    async fn create_node(&self, node: &Node) -> Result<Node> {
+    // Write helpers shared by the single-statement methods and `commit`.
+    // They accept any executor so they can run on the pool or inside a transaction.
+
+    async fn insert_node<'e>(executor: impl PgExecutor<'e>, node: &Node) -> Result<()> {
         let properties_json = serde_json::to_value(&node.properties)
             .map_err(|e| StorageError::SerializationError(e.to_string()))?;
 
@@ -197,46 +573,21 @@ impl GraphStorage for PostgresStorage {
         .bind(properties_json)
         .bind(node.created_at)
         .bind(node.updated_at)
-        .execute(&self.pool)
-        .await
-        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-
-        Ok(node.clone())
-    }
-
-    async fn get_node(&self, id: NodeId) -> Result<Node> {
-        let row = sqlx::query(
-            r#"
-            SELECT id, node_type, properties, created_at, updated_at
-            FROM nodes
-            WHERE id = $1
-            "#
-        )
-        .bind(id)
-        .fetch_optional(&self.pool)
+        .execute(executor)
         .await
-        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-
-        match row {
-            Some(row) => {
-                let properties_json: serde_json::Value = row.try_get("properties")
-                    .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-                let properties = serde_json::from_value(properties_json)
-                    .map_err(|e| StorageError::SerializationError(e.to_string()))?;
-
-                Ok(Node {
-                    id: row.try_get("id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                    node_type: row.try_get("node_type").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                    properties,
-                    created_at: row.try_get("created_at").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                    updated_at: row.try_get("updated_at").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                })
+        .map_err(|e| match e {
+            // Same error the in-memory store gives, so callers can tell a
+            // taken ID from a broken connection
+            sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+                StorageError::ConstraintViolation(format!("Node with ID {} already exists", node.id))
             }
-            None => Err(StorageError::NodeNotFound(id)),
-        }
+            e => StorageError::DatabaseError(e.to_string()),
+        })?;
+
+        Ok(())
     }
 
-    async fn update_node(&self, node: &Node) -> Result<Node> {
+    async fn replace_node<'e>(executor: impl PgExecutor<'e>, node: &Node) -> Result<()> {
         let properties_json = serde_json::to_value(&node.properties)
             .map_err(|e| StorageError::SerializationError(e.to_string()))?;
 
@@ -251,7 +602,7 @@ impl GraphStorage for PostgresStorage {
         .bind(&node.node_type)
         .bind(properties_json)
         .bind(node.updated_at)
-        .execute(&self.pool)
+        .execute(executor)
         .await
         .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
 
@@ -259,13 +610,14 @@ impl GraphStorage for PostgresStorage {
             return Err(StorageError::NodeNotFound(node.id));
         }
 
-        Ok(node.clone())
+        Ok(())
     }
 
-    async fn delete_node(&self, id: NodeId) -> Result<()> {
+    async fn remove_node<'e>(executor: impl PgExecutor<'e>, id: NodeId) -> Result<()> {
+        // Edges are removed by the ON DELETE CASCADE foreign keys
         let result = sqlx::query("DELETE FROM nodes WHERE id = $1")
             .bind(id)
-            .execute(&self.pool)
+            .execute(executor)
             .await
             .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
 
@@ -276,54 +628,7 @@ impl GraphStorage for PostgresStorage {
         Ok(())
     }
 
-    async fn query_nodes(&self, query: &GraphQuery) -> Result<Vec<Node>> {
-        let mut sql = String::from("SELECT id, node_type, properties, created_at, updated_at FROM nodes WHERE 1=1");
-        
-        // Handle node_types with IN clause instead of ANY for better compatibility
-        if let Some(ref types) = query.node_types {
-            if types.len() == 1 {
-                // Single type - use direct equality
-                sql.push_str(&format!(" AND node_type = '{}'", types[0]));
-            } else if !types.is_empty() {
-                // Multiple types - use IN clause
-                let type_list: Vec<String> = types.iter()
-                    .map(|t| format!("'{}'", t.replace("'", "''")))
-                    .collect();
-                sql.push_str(&format!(" AND node_type IN ({})", type_list.join(", ")));
-            }
-        }
-
-        sql.push_str(" ORDER BY created_at DESC");
-
-        if let Some(limit) = query.limit {
-            sql.push_str(&format!(" LIMIT {}", limit));
-        }
-
-        let rows = sqlx::query(&sql)
-            .fetch_all(&self.pool)
-            .await
-            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-
-        let mut nodes = Vec::new();
-        for row in rows {
-            let properties_json: serde_json::Value = row.try_get("properties")
-                .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-            let properties = serde_json::from_value(properties_json)
-                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
-
-            nodes.push(Node {
-                id: row.try_get("id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                node_type: row.try_get("node_type").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                properties,
-                created_at: row.try_get("created_at").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                updated_at: row.try_get("updated_at").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-            });
-        }
-
-        Ok(nodes)
-    }
-
-    async fn create_edge(&self, edge: &Edge) -> Result<Edge> {
+    async fn insert_edge<'e>(executor: impl PgExecutor<'e>, edge: &Edge) -> Result<()> {
         let properties_json = serde_json::to_value(&edge.properties)
             .map_err(|e| StorageError::SerializationError(e.to_string()))?;
 
@@ -339,13 +644,145 @@ impl GraphStorage for PostgresStorage {
         .bind(edge.to_node_id)
         .bind(properties_json)
         .bind(edge.created_at)
-        .execute(&self.pool)
+        .execute(executor)
+        .await
+        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn replace_edge_properties<'e>(executor: impl PgExecutor<'e>, edge: &Edge) -> Result<()> {
+        let properties_json = serde_json::to_value(&edge.properties)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+        let result = sqlx::query("UPDATE edges SET properties = $2 WHERE id = $1")
+            .bind(edge.id)
+            .bind(properties_json)
+            .execute(executor)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(StorageError::EdgeNotFound(edge.id));
+        }
+        Ok(())
+    }
+
+    async fn remove_edge<'e>(executor: impl PgExecutor<'e>, id: EdgeId) -> Result<()> {
+        let result = sqlx::query("DELETE FROM edges WHERE id = $1")
+            .bind(id)
+            .execute(executor)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(StorageError::EdgeNotFound(id));
+        }
+        Ok(())
+    }
+    
+    /// Helper function to convert properties JSONB to searchable text
+    #[allow(dead_code)]
+    fn properties_to_search_text(properties: &Properties) -> String {
+        let mut texts = Vec::new();
+        for value in properties.values() {
+            if let serde_json::Value::String(s) = serde_json::to_value(value).unwrap_or_default() {
+                texts.push(s);
+            }
+        }
+        texts.join(" ")
+    }
+}
+
+#[async_trait]
+impl GraphStorage for PostgresStorage {
+    async fn create_node(&self, node: &Node) -> Result<Node> {
+        Self::insert_node(&self.pool, node).await?;
+        Ok(node.clone())
+    }
+
+    async fn get_node(&self, id: NodeId) -> Result<Node> {
+        let row = sqlx::query(
+            r#"
+            SELECT id, node_type, properties, created_at, updated_at
+            FROM nodes
+            WHERE id = $1
+            "#
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        match row {
+            Some(row) => node_from_row(&row),
+            None => Err(StorageError::NodeNotFound(id)),
+        }
+    }
+
+    async fn get_nodes(&self, ids: &[NodeId]) -> Result<Vec<Node>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, node_type, properties, created_at, updated_at
+            FROM nodes
+            WHERE id = ANY($1)
+            "#
+        )
+        .bind(ids)
+        .fetch_all(&self.pool)
         .await
         .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
 
+        rows.iter().map(node_from_row).collect()
+    }
+
+    async fn update_node(&self, node: &Node) -> Result<Node> {
+        Self::replace_node(&self.pool, node).await?;
+        Ok(node.clone())
+    }
+
+    async fn delete_node(&self, id: NodeId) -> Result<()> {
+        Self::remove_node(&self.pool, id).await
+    }
+
+    async fn query_nodes(&self, query: &GraphQuery) -> Result<Vec<Node>> {
+        let mut select = NodeSelect::new();
+        if let Some(ref types) = query.node_types {
+            select.node_types(types);
+        }
+        if let Some(ref types) = query.edge_types {
+            select.has_edge_types(types);
+        }
+        if let Some(ref filters) = query.property_filters {
+            select.properties_contain(filters)?;
+        }
+        if let Some(ref prefixes) = query.property_prefixes {
+            for (key, prefix) in prefixes {
+                select.property_starts_with(key, prefix);
+            }
+        }
+        select.order_by_desc(NodeOrder::CreatedAt);
+        if let Some(limit) = query.limit {
+            select.limit(limit);
+        }
+
+        select.fetch_all(&self.pool).await
+    }
+
+    async fn create_edge(&self, edge: &Edge) -> Result<Edge> {
+        Self::insert_edge(&self.pool, edge).await?;
+        Ok(edge.clone())
+    }
+
+    async fn update_edge(&self, edge: &Edge) -> Result<Edge> {
+        Self::replace_edge_properties(&self.pool, edge).await?;
         Ok(edge.clone())
     }
 
+    async fn delete_edge(&self, id: EdgeId) -> Result<()> {
+        Self::remove_edge(&self.pool, id).await
+    }
+
     async fn get_edges_from(&self, node_id: NodeId, edge_type: Option<&str>) -> Result<Vec<Edge>> {
         let rows = if let Some(et) = edge_type {
             sqlx::query(
@@ -375,24 +812,7 @@ impl GraphStorage for PostgresStorage {
         }
         .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
 
-        let mut edges = Vec::new();
-        for row in rows {
-            let properties_json: serde_json::Value = row.try_get("properties")
-                .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-            let properties = serde_json::from_value(properties_json)
-                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
-
-            edges.push(Edge {
-                id: row.try_get("id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                edge_type: row.try_get("edge_type").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                from_node_id: row.try_get("from_node_id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                to_node_id: row.try_get("to_node_id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                properties,
-                created_at: row.try_get("created_at").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-            });
-        }
-
-        Ok(edges)
+        rows.iter().map(edge_from_row).collect()
     }
 
     async fn get_edges_to(&self, node_id: NodeId, edge_type: Option<&str>) -> Result<Vec<Edge>> {
@@ -424,24 +844,7 @@ impl GraphStorage for PostgresStorage {
         }
         .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
 
-        let mut edges = Vec::new();
-        for row in rows {
-            let properties_json: serde_json::Value = row.try_get("properties")
-                .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-            let properties = serde_json::from_value(properties_json)
-                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
-
-            edges.push(Edge {
-                id: row.try_get("id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                edge_type: row.try_get("edge_type").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                from_node_id: row.try_get("from_node_id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                to_node_id: row.try_get("to_node_id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                properties,
-                created_at: row.try_get("created_at").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-            });
-        }
-
-        Ok(edges)
+        rows.iter().map(edge_from_row).collect()
     }
 
     async fn get_neighbors(
@@ -452,112 +855,171 @@ impl GraphStorage for PostgresStorage {
     ) -> Result<Vec<Node>> {
         let mut neighbors = Vec::new();
 
-        match direction {
-            EdgeDirection::Outgoing => {
-                let edges = self.get_edges_from(node_id, edge_type).await?;
-                for edge in edges {
-                    if let Ok(node) = self.get_node(edge.to_node_id).await {
-                        neighbors.push(node);
-                    }
+        if direction == EdgeDirection::Outgoing {
+            let edges = self.get_edges_from(node_id, edge_type).await?;
+            for edge in edges {
+                if let Ok(node) = self.get_node(edge.to_node_id).await {
+                    neighbors.push(node);
                 }
             }
-            _ => {}
         }
 
-        match direction {
-            EdgeDirection::Incoming => {
-                let edges = self.get_edges_to(node_id, edge_type).await?;
-                for edge in edges {
-                    if let Ok(node) = self.get_node(edge.from_node_id).await {
-                        neighbors.push(node);
-                    }
+        if direction == EdgeDirection::Incoming {
+            let edges = self.get_edges_to(node_id, edge_type).await?;
+            for edge in edges {
+                if let Ok(node) = self.get_node(edge.from_node_id).await {
+                    neighbors.push(node);
                 }
             }
-            _ => {}
         }
 
         Ok(neighbors)
     }
 
     async fn search_nodes(&self, query: &SearchQuery) -> Result<SearchResults<Node>> {
-        let offset = query.offset;
-        let limit = query.limit;
-        
-        // Build the SQL query
-        let mut sql = String::from(
-            "SELECT id, node_type, properties, created_at, updated_at FROM nodes WHERE 1=1"
-        );
-        
-        // Add node type filters
-        if !query.node_types.is_empty() {
-            let types: Vec<String> = query.node_types.iter()
-                .map(|t| format!("'{}'", t.replace("'", "''")))
-                .collect();
-            sql.push_str(&format!(" AND node_type IN ({})", types.join(", ")));
-        }
-        
-        // Add text search filter (case-insensitive LIKE on properties)
+        let mut select = NodeSelect::new();
+        select.node_types(&query.node_types);
         if let Some(ref search_text) = query.search_text {
-            let escaped = search_text.replace("'", "''").replace("%", "\\%").replace("_", "\\_");
-            sql.push_str(&format!(
-                " AND properties::text ILIKE '%{}%'",
-                escaped
-            ));
-        }
-        
-        // Add time range filters
+            select.text_contains(search_text);
+        }
         if let Some(after) = query.created_after {
-            sql.push_str(&format!(" AND created_at >= '{}'", after.format("%Y-%m-%d %H:%M:%S")));
+            select.created_after(after);
         }
         if let Some(before) = query.created_before {
-            sql.push_str(&format!(" AND created_at <= '{}'", before.format("%Y-%m-%d %H:%M:%S")));
+            select.created_before(before);
         }
         if let Some(after) = query.updated_after {
-            sql.push_str(&format!(" AND updated_at >= '{}'", after.format("%Y-%m-%d %H:%M:%S")));
+            select.updated_after(after);
+        }
+        if let Some(ref text) = query.full_text {
+            select.full_text(text);
         }
-        
-        // Add property filters
         for (key, value) in &query.property_filters {
-            let escaped_key = key.replace("'", "''");
-            let escaped_value = value.replace("'", "''");
-            sql.push_str(&format!(
-                " AND properties->>'{}' = '{}'",
-                escaped_key, escaped_value
-            ));
-        }
-        
-        // Add ordering by updated_at
-        sql.push_str(" ORDER BY updated_at DESC");
-        
-        // Add limit and offset
-        sql.push_str(&format!(" LIMIT {} OFFSET {}", limit + 1, offset));
-        
-        // Execute query
-        let rows = sqlx::query(&sql)
-            .fetch_all(&self.pool)
-            .await
-            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-        
-        // Parse results
-        let mut nodes = Vec::new();
-        
-        for row in rows.into_iter().take(limit) {
-            let properties_json: serde_json::Value = row.try_get("properties")
-                .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
-            let properties: Properties = serde_json::from_value(properties_json)
-                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
-            
-            nodes.push(Node {
-                id: row.try_get("id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                node_type: row.try_get("node_type").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                properties,
-                created_at: row.try_get("created_at").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-                updated_at: row.try_get("updated_at").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
-            });
-        }
-        
+            select.property_equals(key, value);
+        }
+        for (key, text) in &query.property_contains {
+            select.property_contains(key, text);
+        }
+        for (key, at) in &query.property_before {
+            select.property_before(key, *at);
+        }
+        if let Some(ref ids) = query.node_ids {
+            select.node_ids(ids);
+        }
+        if let Some(ref prefix) = query.id_prefix {
+            select.id_prefix(prefix);
+        }
+        match query.full_text {
+            Some(ref text) => select.order_by_rank(text),
+            None => select.order_by_desc(NodeOrder::UpdatedAt),
+        };
+        select.limit(query.limit).offset(query.offset);
+
         Ok(SearchResults {
-            items: nodes,
+            items: select.fetch_all(&self.pool).await?,
         })
     }
+
+    async fn put_blob(&self, hash: &str, data: &[u8]) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO blobs (hash, data, size)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (hash) DO NOTHING
+            "#
+        )
+        .bind(hash)
+        .bind(data)
+        .bind(data.len() as i64)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_blob(&self, hash: &str) -> Result<Vec<u8>> {
+        let row = sqlx::query("SELECT data FROM blobs WHERE hash = $1")
+            .bind(hash)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        match row {
+            Some(row) => Ok(row.get("data")),
+            None => Err(StorageError::BlobNotFound(hash.to_string())),
+        }
+    }
+
+    async fn delete_blob(&self, hash: &str) -> Result<()> {
+        sqlx::query("DELETE FROM blobs WHERE hash = $1")
+            .bind(hash)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn next_sequence(&self, name: &str) -> Result<i64> {
+        // The upsert takes a row lock, so concurrent callers queue up
+        let row = sqlx::query(
+            r#"
+            INSERT INTO sequences (name, value)
+            VALUES ($1, 1)
+            ON CONFLICT (name) DO UPDATE SET value = sequences.value + 1
+            RETURNING value
+            "#
+        )
+        .bind(name)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        Ok(row.get("value"))
+    }
+
+    async fn commit(&self, tx: Transaction) -> Result<()> {
+        let mut db_tx = self.pool.begin()
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        // Any error returns early and drops `db_tx`, which rolls the transaction back
+        for op in tx.into_ops() {
+            match op {
+                WriteOp::CreateNode(node) => Self::insert_node(&mut *db_tx, &node).await?,
+                WriteOp::UpdateNode(node) => Self::replace_node(&mut *db_tx, &node).await?,
+                WriteOp::DeleteNode(id) => Self::remove_node(&mut *db_tx, id).await?,
+                WriteOp::CreateEdge(edge) => Self::insert_edge(&mut *db_tx, &edge).await?,
+                WriteOp::UpdateEdge(edge) => Self::replace_edge_properties(&mut *db_tx, &edge).await?,
+                WriteOp::DeleteEdge(id) => Self::remove_edge(&mut *db_tx, id).await?,
+            }
+        }
+
+        db_tx.commit()
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_select_binds_caller_values() {
+        let hostile = "mail' OR '1'='1";
+        let mut select = NodeSelect::new();
+        select
+            .node_types(&[hostile.to_string()])
+            .text_contains(hostile)
+            .property_equals(hostile, hostile)
+            .order_by_desc(NodeOrder::UpdatedAt)
+            .limit(10)
+            .offset(5);
+
+        let sql = select.builder.sql();
+        assert!(!sql.contains(hostile));
+        assert!(!sql.contains('\''));
+        assert!(sql.contains("node_type = ANY($1)"));
+        assert!(sql.contains("properties->>$4 = $5"));
+        assert!(sql.ends_with("LIMIT $6 OFFSET $7"));
+    }
 }
@@ -0,0 +1,1165 @@
+use crate::domain::{Edge, EdgeId, GraphQuery, Node, NodeId, Properties, PropertyValue};
+use crate::storage::migrations::{merge_status, Migration, MigrationStatus};
+use crate::storage::{full_text_terms, id_prefix_range, EdgeDirection, GraphStorage, Result, StorageError, SearchQuery, SearchResults, Transaction, WriteOp};
+use async_trait::async_trait;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow};
+use sqlx::{Pool, QueryBuilder, Row, Sqlite, SqliteExecutor};
+use std::str::FromStr;
+
+/// Schema history, applied in order by [`SqliteStorage::migrate`].
+/// Must stay in step with the Postgres list (same versions and names).
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_graph_tables",
+        statements: &[
+            r#"
+            CREATE TABLE IF NOT EXISTS nodes (
+                id BLOB PRIMARY KEY,
+                node_type TEXT NOT NULL,
+                properties TEXT NOT NULL DEFAULT '{}',
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS edges (
+                id BLOB PRIMARY KEY,
+                edge_type TEXT NOT NULL,
+                from_node_id BLOB NOT NULL REFERENCES nodes(id) ON DELETE CASCADE,
+                to_node_id BLOB NOT NULL REFERENCES nodes(id) ON DELETE CASCADE,
+                properties TEXT NOT NULL DEFAULT '{}',
+                created_at TEXT NOT NULL
+            )
+            "#,
+            "CREATE INDEX IF NOT EXISTS idx_nodes_type_created ON nodes(node_type, created_at DESC)",
+            "CREATE INDEX IF NOT EXISTS idx_nodes_type_updated ON nodes(node_type, updated_at DESC)",
+            "CREATE INDEX IF NOT EXISTS idx_edges_from_type ON edges(from_node_id, edge_type)",
+            "CREATE INDEX IF NOT EXISTS idx_edges_to_type ON edges(to_node_id, edge_type)",
+        ],
+    },
+    Migration {
+        version: 2,
+        name: "create_schedules_table",
+        statements: &[
+            r#"
+            CREATE TABLE IF NOT EXISTS schedules (
+                id BLOB PRIMARY KEY,
+                agent_id TEXT NOT NULL,
+                cron_expression TEXT NOT NULL,
+                action TEXT NOT NULL,
+                is_active BOOLEAN NOT NULL DEFAULT 1,
+                last_fired_at TEXT,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )
+            "#,
+            "CREATE INDEX IF NOT EXISTS idx_schedules_agent_id ON schedules(agent_id)",
+            "CREATE INDEX IF NOT EXISTS idx_schedules_active ON schedules(is_active)",
+        ],
+    },
+    // SQLite has no LISTEN/NOTIFY; agents on SQLite keep polling
+    Migration {
+        version: 3,
+        name: "notify_on_mail_and_schedules",
+        statements: &[],
+    },
+    Migration {
+        version: 4,
+        name: "mail_recipient_lists",
+        // Single-recipient mail kept its read flag on the node; move it onto
+        // the sent_to edge, then turn to_mailbox_id into recipient lists
+        statements: &[
+            r#"
+            UPDATE edges
+            SET properties = json_set(
+                properties,
+                '$.kind', 'to',
+                '$.read', (
+                    SELECT CASE WHEN json_extract(n.properties, '$.read') THEN json('true') ELSE json('false') END
+                    FROM nodes n WHERE n.id = edges.from_node_id
+                )
+            )
+            WHERE edge_type = 'sent_to'
+              AND json_extract(properties, '$.read') IS NULL
+              AND from_node_id IN (SELECT id FROM nodes WHERE node_type = 'mail')
+            "#,
+            r#"
+            UPDATE nodes
+            SET properties = json_remove(
+                json_set(
+                    properties,
+                    '$.to_mailbox_ids', json_array(json_extract(properties, '$.to_mailbox_id')),
+                    '$.cc_mailbox_ids', json_array(),
+                    '$.recipients', json_array(json_extract(properties, '$.to_mailbox_id'))
+                ),
+                '$.to_mailbox_id',
+                '$.read'
+            )
+            WHERE node_type = 'mail' AND json_extract(properties, '$.to_mailbox_id') IS NOT NULL
+            "#,
+        ],
+    },
+    Migration {
+        version: 5,
+        name: "create_blobs_table",
+        statements: &[
+            r#"
+            CREATE TABLE IF NOT EXISTS blobs (
+                hash TEXT PRIMARY KEY,
+                data BLOB NOT NULL,
+                size INTEGER NOT NULL,
+                created_at TEXT NOT NULL
+            )
+            "#,
+        ],
+    },
+    Migration {
+        version: 6,
+        name: "create_sequences_table",
+        statements: &[
+            r#"
+            CREATE TABLE IF NOT EXISTS sequences (
+                name TEXT PRIMARY KEY,
+                value INTEGER NOT NULL
+            )
+            "#,
+        ],
+    },
+    // No full-text engine; searches fall back to LIKE
+    Migration {
+        version: 7,
+        name: "full_text_search_index",
+        statements: &[],
+    },
+    Migration {
+        version: 8,
+        name: "create_leases_table",
+        statements: &[
+            r#"
+            CREATE TABLE IF NOT EXISTS leases (
+                name TEXT PRIMARY KEY,
+                holder TEXT NOT NULL,
+                acquired_at TEXT NOT NULL,
+                expires_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )
+            "#,
+        ],
+    },
+];
+
+/// Graph storage in a single SQLite file, for offices that run on one machine
+#[derive(Clone)]
+pub struct SqliteStorage {
+    pool: Pool<Sqlite>,
+}
+
+impl SqliteStorage {
+    pub fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    /// Open (creating if needed) the database at a `sqlite://` URL. Its
+    /// tables come from [`Self::migrate`], which the CLI runs once at startup.
+    pub async fn connect(url: &str) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(url)
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?
+            .create_if_missing(true)
+            .foreign_keys(true);
+
+        // Each connection to `sqlite::memory:` gets its own empty database,
+        // which goes away with it, so keep exactly one open for good
+        let pool_options = if url.contains(":memory:") {
+            SqlitePoolOptions::new()
+                .max_connections(1)
+                .min_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+        } else {
+            SqlitePoolOptions::new().max_connections(5)
+        };
+        let pool = pool_options
+            .connect_with(options)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        Ok(Self::new(pool))
+    }
+
+    pub fn pool(&self) -> &Pool<Sqlite> {
+        &self.pool
+    }
+
+    /// Drop every table and rebuild the schema from scratch
+    pub async fn reset_tables(&self) -> Result<()> {
+        for table in ["edges", "nodes", "schedules", "leases", "blobs", "sequences", "schema_migrations"] {
+            sqlx::query(&format!("DROP TABLE IF EXISTS {}", table))
+                .execute(&self.pool)
+                .await
+                .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        }
+        self.migrate().await?;
+        Ok(())
+    }
+
+    /// Apply every migration not yet recorded in `schema_migrations`, each in
+    /// its own transaction. Returns the migrations that were applied.
+    pub async fn migrate(&self) -> Result<Vec<&'static Migration>> {
+        self.ensure_migrations_table().await?;
+
+        let mut applied = Vec::new();
+        for migration in MIGRATIONS {
+            let mut tx = self
+                .pool
+                .begin()
+                .await
+                .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+            // Claim the version first: the write lock makes a concurrent
+            // process wait, and it then sees the row and skips the migration
+            let claimed = sqlx::query(
+                "INSERT OR IGNORE INTO schema_migrations (version, name, applied_at) VALUES ($1, $2, $3)",
+            )
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(chrono::Utc::now())
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?
+            .rows_affected();
+            if claimed == 0 {
+                continue;
+            }
+
+            for statement in migration.statements {
+                sqlx::query(statement)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| {
+                        StorageError::DatabaseError(format!(
+                            "migration {} ({}) failed: {}",
+                            migration.version, migration.name, e
+                        ))
+                    })?;
+            }
+
+            tx.commit()
+                .await
+                .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+            applied.push(migration);
+        }
+        Ok(applied)
+    }
+
+    /// Every known migration plus anything recorded by a newer release
+    pub async fn migration_status(&self) -> Result<Vec<MigrationStatus>> {
+        self.ensure_migrations_table().await?;
+
+        let rows = sqlx::query(
+            "SELECT version, name, applied_at FROM schema_migrations ORDER BY version",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        let applied = rows
+            .iter()
+            .map(|row| {
+                Ok((
+                    row.try_get("version").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+                    row.try_get("name").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+                    row.try_get("applied_at").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(merge_status(MIGRATIONS, applied))
+    }
+
+    async fn ensure_migrations_table(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS schema_migrations (
+                version INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                applied_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    // Write helpers shared by the single-statement methods and `commit`
+
+    async fn insert_node<'e>(executor: impl SqliteExecutor<'e>, node: &Node) -> Result<()> {
+        let properties_json = serde_json::to_string(&node.properties)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO nodes (id, node_type, properties, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#
+        )
+        .bind(node.id)
+        .bind(&node.node_type)
+        .bind(properties_json)
+        .bind(node.created_at)
+        .bind(node.updated_at)
+        .execute(executor)
+        .await
+        .map_err(|e| match e {
+            // Same error the in-memory store gives, so callers can tell a
+            // taken ID from a broken connection
+            sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+                StorageError::ConstraintViolation(format!("Node with ID {} already exists", node.id))
+            }
+            e => StorageError::DatabaseError(e.to_string()),
+        })?;
+
+        Ok(())
+    }
+
+    async fn replace_node<'e>(executor: impl SqliteExecutor<'e>, node: &Node) -> Result<()> {
+        let properties_json = serde_json::to_string(&node.properties)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+        let result = sqlx::query(
+            r#"
+            UPDATE nodes
+            SET node_type = $2, properties = $3, updated_at = $4
+            WHERE id = $1
+            "#
+        )
+        .bind(node.id)
+        .bind(&node.node_type)
+        .bind(properties_json)
+        .bind(node.updated_at)
+        .execute(executor)
+        .await
+        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(StorageError::NodeNotFound(node.id));
+        }
+
+        Ok(())
+    }
+
+    async fn remove_node<'e>(executor: impl SqliteExecutor<'e>, id: NodeId) -> Result<()> {
+        // Edges are removed by the ON DELETE CASCADE foreign keys
+        let result = sqlx::query("DELETE FROM nodes WHERE id = $1")
+            .bind(id)
+            .execute(executor)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(StorageError::NodeNotFound(id));
+        }
+
+        Ok(())
+    }
+
+    async fn insert_edge<'e>(executor: impl SqliteExecutor<'e>, edge: &Edge) -> Result<()> {
+        let properties_json = serde_json::to_string(&edge.properties)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO edges (id, edge_type, from_node_id, to_node_id, properties, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#
+        )
+        .bind(edge.id)
+        .bind(&edge.edge_type)
+        .bind(edge.from_node_id)
+        .bind(edge.to_node_id)
+        .bind(properties_json)
+        .bind(edge.created_at)
+        .execute(executor)
+        .await
+        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn replace_edge_properties<'e>(executor: impl SqliteExecutor<'e>, edge: &Edge) -> Result<()> {
+        let properties_json = serde_json::to_string(&edge.properties)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+        let result = sqlx::query("UPDATE edges SET properties = $2 WHERE id = $1")
+            .bind(edge.id)
+            .bind(properties_json)
+            .execute(executor)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(StorageError::EdgeNotFound(edge.id));
+        }
+        Ok(())
+    }
+
+    async fn remove_edge<'e>(executor: impl SqliteExecutor<'e>, id: EdgeId) -> Result<()> {
+        let result = sqlx::query("DELETE FROM edges WHERE id = $1")
+            .bind(id)
+            .execute(executor)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(StorageError::EdgeNotFound(id));
+        }
+        Ok(())
+    }
+
+    async fn fetch_edges(&self, column: EdgeColumn, node_id: NodeId, edge_type: Option<&str>) -> Result<Vec<Edge>> {
+        let mut builder = QueryBuilder::<Sqlite>::new(
+            "SELECT id, edge_type, from_node_id, to_node_id, properties, created_at FROM edges WHERE ",
+        );
+        builder.push(match column {
+            EdgeColumn::From => "from_node_id = ",
+            EdgeColumn::To => "to_node_id = ",
+        });
+        builder.push_bind(node_id);
+        if let Some(et) = edge_type {
+            builder.push(" AND edge_type = ").push_bind(et.to_string());
+        }
+        builder.push(" ORDER BY created_at DESC");
+
+        let rows = builder
+            .build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        rows.iter().map(edge_from_row).collect()
+    }
+
+    /// One page of `search_nodes`, over `ids` instead of `query.node_ids`
+    async fn search_page(&self, query: &SearchQuery, ids: Option<&[NodeId]>, limit: usize, offset: usize) -> Result<Vec<Node>> {
+        let mut builder = select_nodes();
+        push_node_types(&mut builder, &query.node_types);
+
+        // Only string values are searched, never the keys around them.
+        // LIKE is case-insensitive for ASCII in SQLite.
+        if let Some(ref search_text) = query.search_text {
+            push_text_contains(&mut builder, search_text);
+        }
+        // No full-text engine here: every word and phrase must appear
+        if let Some(ref text) = query.full_text {
+            for term in full_text_terms(text) {
+                push_text_contains(&mut builder, &term);
+            }
+        }
+        if let Some(after) = query.created_after {
+            builder.push(" AND created_at >= ").push_bind(after);
+        }
+        if let Some(before) = query.created_before {
+            builder.push(" AND created_at <= ").push_bind(before);
+        }
+        if let Some(after) = query.updated_after {
+            builder.push(" AND updated_at >= ").push_bind(after);
+        }
+        for (key, value) in &query.property_filters {
+            match json_path(key) {
+                Some(path) => {
+                    builder.push(" AND json_extract(properties, ").push_bind(path)
+                        .push(") = ").push_bind(value.clone());
+                }
+                None => {
+                    builder.push(" AND 1=0");
+                }
+            }
+        }
+        for (key, text) in &query.property_contains {
+            match json_path(key) {
+                Some(path) => {
+                    builder.push(" AND json_extract(properties, ").push_bind(path)
+                        .push(") LIKE ").push_bind(contains_pattern(text))
+                        .push(" ESCAPE '\\'");
+                }
+                None => {
+                    builder.push(" AND 1=0");
+                }
+            }
+        }
+        // julianday() reads RFC 3339 whatever the number of fractional
+        // digits, where comparing the strings would not
+        for (key, at) in &query.property_before {
+            match json_path(key) {
+                Some(path) => {
+                    builder.push(" AND julianday(json_extract(properties, ").push_bind(path)
+                        .push(")) <= julianday(").push_bind(at.to_rfc3339()).push(")");
+                }
+                None => {
+                    builder.push(" AND 1=0");
+                }
+            }
+        }
+        if let Some(ids) = ids {
+            if ids.is_empty() {
+                builder.push(" AND 1=0");
+            } else {
+                builder.push(" AND id IN (");
+                let mut separated = builder.separated(", ");
+                for id in ids {
+                    separated.push_bind(*id);
+                }
+                separated.push_unseparated(")");
+            }
+        }
+        if let Some(ref prefix) = query.id_prefix {
+            match id_prefix_range(prefix) {
+                Some((low, high)) => {
+                    builder.push(" AND id BETWEEN ").push_bind(low)
+                        .push(" AND ").push_bind(high);
+                }
+                None => {
+                    builder.push(" AND 1=0");
+                }
+            }
+        }
+        builder.push(" ORDER BY updated_at DESC, id LIMIT ")
+            .push_bind(limit as i64)
+            .push(" OFFSET ")
+            .push_bind(offset as i64);
+
+        let rows = builder
+            .build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        rows.iter().map(node_from_row).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum EdgeColumn {
+    From,
+    To,
+}
+
+/// JSON path for a top-level property, or `None` for keys SQLite can't quote
+fn json_path(key: &str) -> Option<String> {
+    if key.contains('"') {
+        None
+    } else {
+        Some(format!("$.\"{}\"", key))
+    }
+}
+
+/// LIKE pattern matching `text` anywhere, with wildcards in it escaped
+fn contains_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// Match nodes with a string value, at any depth, containing `text`
+fn push_text_contains(builder: &mut QueryBuilder<'static, Sqlite>, text: &str) {
+    builder
+        .push(" AND EXISTS (SELECT 1 FROM json_tree(nodes.properties) WHERE json_tree.type = 'text' AND json_tree.value LIKE ")
+        .push_bind(contains_pattern(text))
+        .push(" ESCAPE '\\')");
+}
+
+/// Start a nodes `SELECT`; every caller-supplied value is bound as a parameter
+fn select_nodes() -> QueryBuilder<'static, Sqlite> {
+    QueryBuilder::new("SELECT id, node_type, properties, created_at, updated_at FROM nodes WHERE 1=1")
+}
+
+fn push_node_types(builder: &mut QueryBuilder<'static, Sqlite>, types: &[String]) {
+    if types.is_empty() {
+        return;
+    }
+    builder.push(" AND node_type IN (");
+    let mut separated = builder.separated(", ");
+    for node_type in types {
+        separated.push_bind(node_type.clone());
+    }
+    separated.push_unseparated(")");
+}
+
+fn node_from_row(row: &SqliteRow) -> Result<Node> {
+    let properties_json: String = row.try_get("properties")
+        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+    let properties: Properties = serde_json::from_str(&properties_json)
+        .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+    Ok(Node {
+        id: row.try_get("id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+        node_type: row.try_get("node_type").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+        properties,
+        created_at: row.try_get("created_at").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+        updated_at: row.try_get("updated_at").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+    })
+}
+
+fn edge_from_row(row: &SqliteRow) -> Result<Edge> {
+    let properties_json: String = row.try_get("properties")
+        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+    let properties = serde_json::from_str(&properties_json)
+        .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+    Ok(Edge {
+        id: row.try_get("id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+        edge_type: row.try_get("edge_type").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+        from_node_id: row.try_get("from_node_id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+        to_node_id: row.try_get("to_node_id").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+        properties,
+        created_at: row.try_get("created_at").map_err(|e| StorageError::DatabaseError(e.to_string()))?,
+    })
+}
+
+#[async_trait]
+impl GraphStorage for SqliteStorage {
+    async fn create_node(&self, node: &Node) -> Result<Node> {
+        Self::insert_node(&self.pool, node).await?;
+        Ok(node.clone())
+    }
+
+    async fn get_node(&self, id: NodeId) -> Result<Node> {
+        let row = sqlx::query(
+            r#"
+            SELECT id, node_type, properties, created_at, updated_at
+            FROM nodes
+            WHERE id = $1
+            "#
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        match row {
+            Some(row) => node_from_row(&row),
+            None => Err(StorageError::NodeNotFound(id)),
+        }
+    }
+
+    async fn get_nodes(&self, ids: &[NodeId]) -> Result<Vec<Node>> {
+        let mut nodes = Vec::with_capacity(ids.len());
+        // Stay well under SQLite's bound-parameter limit
+        for chunk in ids.chunks(500) {
+            let mut builder = QueryBuilder::<Sqlite>::new(
+                "SELECT id, node_type, properties, created_at, updated_at FROM nodes WHERE id IN (",
+            );
+            let mut separated = builder.separated(", ");
+            for id in chunk {
+                separated.push_bind(*id);
+            }
+            builder.push(")");
+
+            let rows = builder
+                .build()
+                .fetch_all(&self.pool)
+                .await
+                .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+            for row in &rows {
+                nodes.push(node_from_row(row)?);
+            }
+        }
+        Ok(nodes)
+    }
+
+    async fn update_node(&self, node: &Node) -> Result<Node> {
+        Self::replace_node(&self.pool, node).await?;
+        Ok(node.clone())
+    }
+
+    async fn delete_node(&self, id: NodeId) -> Result<()> {
+        Self::remove_node(&self.pool, id).await
+    }
+
+    async fn query_nodes(&self, query: &GraphQuery) -> Result<Vec<Node>> {
+        let mut builder = select_nodes();
+        if let Some(ref types) = query.node_types {
+            push_node_types(&mut builder, types);
+        }
+        if let Some(ref types) = query.edge_types {
+            if types.is_empty() {
+                return Ok(Vec::new());
+            }
+            builder.push(
+                " AND EXISTS (SELECT 1 FROM edges WHERE (edges.from_node_id = nodes.id OR edges.to_node_id = nodes.id) AND edges.edge_type IN (",
+            );
+            let mut separated = builder.separated(", ");
+            for edge_type in types {
+                separated.push_bind(edge_type.clone());
+            }
+            separated.push_unseparated("))");
+        }
+
+        // Scalar filters narrow the rows in SQL; `matches_node` below applies
+        // the exact typed containment rules shared with the other backends
+        if let Some(ref filters) = query.property_filters {
+            for (key, value) in filters {
+                let Some(path) = json_path(key) else { continue };
+                match value {
+                    PropertyValue::String(s) => {
+                        builder.push(" AND json_extract(properties, ").push_bind(path)
+                            .push(") = ").push_bind(s.clone());
+                    }
+                    PropertyValue::Boolean(b) => {
+                        builder.push(" AND json_extract(properties, ").push_bind(path)
+                            .push(") = ").push_bind(*b);
+                    }
+                    PropertyValue::Integer(n) => {
+                        builder.push(" AND json_extract(properties, ").push_bind(path)
+                            .push(") = ").push_bind(*n);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if let Some(ref prefixes) = query.property_prefixes {
+            for (key, prefix) in prefixes {
+                let Some(path) = json_path(key) else { continue };
+                builder.push(" AND instr(json_extract(properties, ").push_bind(path)
+                    .push("), ").push_bind(prefix.clone()).push(") = 1");
+            }
+        }
+        builder.push(" ORDER BY created_at DESC");
+
+        let rows = builder
+            .build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        let mut nodes = Vec::new();
+        for row in &rows {
+            let node = node_from_row(row)?;
+            if query.matches_node(&node) {
+                nodes.push(node);
+            }
+        }
+        if let Some(limit) = query.limit {
+            nodes.truncate(limit);
+        }
+
+        Ok(nodes)
+    }
+
+    async fn create_edge(&self, edge: &Edge) -> Result<Edge> {
+        Self::insert_edge(&self.pool, edge).await?;
+        Ok(edge.clone())
+    }
+
+    async fn update_edge(&self, edge: &Edge) -> Result<Edge> {
+        Self::replace_edge_properties(&self.pool, edge).await?;
+        Ok(edge.clone())
+    }
+
+    async fn delete_edge(&self, id: EdgeId) -> Result<()> {
+        Self::remove_edge(&self.pool, id).await
+    }
+
+    async fn get_edges_from(&self, node_id: NodeId, edge_type: Option<&str>) -> Result<Vec<Edge>> {
+        self.fetch_edges(EdgeColumn::From, node_id, edge_type).await
+    }
+
+    async fn get_edges_to(&self, node_id: NodeId, edge_type: Option<&str>) -> Result<Vec<Edge>> {
+        self.fetch_edges(EdgeColumn::To, node_id, edge_type).await
+    }
+
+    async fn get_neighbors(
+        &self,
+        node_id: NodeId,
+        edge_type: Option<&str>,
+        direction: EdgeDirection,
+    ) -> Result<Vec<Node>> {
+        let edges = match direction {
+            EdgeDirection::Outgoing => self.get_edges_from(node_id, edge_type).await?,
+            EdgeDirection::Incoming => self.get_edges_to(node_id, edge_type).await?,
+        };
+
+        let mut neighbors = Vec::new();
+        for edge in edges {
+            let neighbor_id = match direction {
+                EdgeDirection::Outgoing => edge.to_node_id,
+                EdgeDirection::Incoming => edge.from_node_id,
+            };
+            if let Ok(node) = self.get_node(neighbor_id).await {
+                neighbors.push(node);
+            }
+        }
+
+        Ok(neighbors)
+    }
+
+    async fn search_nodes(&self, query: &SearchQuery) -> Result<SearchResults<Node>> {
+        let Some(ref ids) = query.node_ids else {
+            let items = self.search_page(query, None, query.limit, query.offset).await?;
+            return Ok(SearchResults { items });
+        };
+        // Stay well under SQLite's bound-parameter limit: take the first
+        // offset + limit hits of each chunk, then page through them together
+        let mut items = Vec::new();
+        for chunk in ids.chunks(500) {
+            items.extend(self.search_page(query, Some(chunk), query.offset + query.limit, 0).await?);
+        }
+        items.sort_by_key(|node| (std::cmp::Reverse(node.updated_at), node.id));
+        Ok(SearchResults {
+            items: items.into_iter().skip(query.offset).take(query.limit).collect(),
+        })
+    }
+
+    async fn put_blob(&self, hash: &str, data: &[u8]) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO blobs (hash, data, size, created_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (hash) DO NOTHING
+            "#
+        )
+        .bind(hash)
+        .bind(data)
+        .bind(data.len() as i64)
+        .bind(chrono::Utc::now())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_blob(&self, hash: &str) -> Result<Vec<u8>> {
+        let row = sqlx::query("SELECT data FROM blobs WHERE hash = ?")
+            .bind(hash)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        match row {
+            Some(row) => Ok(row.get("data")),
+            None => Err(StorageError::BlobNotFound(hash.to_string())),
+        }
+    }
+
+    async fn delete_blob(&self, hash: &str) -> Result<()> {
+        sqlx::query("DELETE FROM blobs WHERE hash = ?")
+            .bind(hash)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn next_sequence(&self, name: &str) -> Result<i64> {
+        // SQLite serializes writers, so the upsert can't hand out a value twice
+        let row = sqlx::query(
+            r#"
+            INSERT INTO sequences (name, value)
+            VALUES (?, 1)
+            ON CONFLICT (name) DO UPDATE SET value = value + 1
+            RETURNING value
+            "#
+        )
+        .bind(name)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+        Ok(row.get("value"))
+    }
+
+    async fn commit(&self, tx: Transaction) -> Result<()> {
+        let mut db_tx = self.pool.begin()
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        // Any error returns early and drops `db_tx`, which rolls the transaction back
+        for op in tx.into_ops() {
+            match op {
+                WriteOp::CreateNode(node) => Self::insert_node(&mut *db_tx, &node).await?,
+                WriteOp::UpdateNode(node) => Self::replace_node(&mut *db_tx, &node).await?,
+                WriteOp::DeleteNode(id) => Self::remove_node(&mut *db_tx, id).await?,
+                WriteOp::CreateEdge(edge) => Self::insert_edge(&mut *db_tx, &edge).await?,
+                WriteOp::UpdateEdge(edge) => Self::replace_edge_properties(&mut *db_tx, &edge).await?,
+                WriteOp::DeleteEdge(id) => Self::remove_edge(&mut *db_tx, id).await?,
+            }
+        }
+
+        db_tx.commit()
+            .await
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::InMemoryStorage;
+
+    async fn storage() -> SqliteStorage {
+        let storage = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+        storage.migrate().await.unwrap();
+        storage
+    }
+
+    #[tokio::test]
+    async fn test_node_round_trip() {
+        let storage = storage().await;
+        let mut props = Properties::new();
+        props.insert("name".to_string(), PropertyValue::String("alice".to_string()));
+        props.insert("read".to_string(), PropertyValue::Boolean(false));
+        let node = Node::new("agent", props);
+
+        storage.create_node(&node).await.unwrap();
+        let fetched = storage.get_node(node.id).await.unwrap();
+        assert_eq!(fetched.node_type, "agent");
+        assert_eq!(fetched.properties, node.properties);
+
+        storage.delete_node(node.id).await.unwrap();
+        assert!(matches!(storage.get_node(node.id).await, Err(StorageError::NodeNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_nodes_skips_missing_ids() {
+        let storage = storage().await;
+        let a = Node::new("mail", Properties::new());
+        let b = Node::new("mail", Properties::new());
+        storage.create_node(&a).await.unwrap();
+        storage.create_node(&b).await.unwrap();
+
+        let fetched = storage.get_nodes(&[a.id, NodeId::new_v4(), b.id]).await.unwrap();
+        assert_eq!(fetched.len(), 2);
+        assert!(storage.get_nodes(&[]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_query_nodes_with_filters() {
+        let storage = storage().await;
+
+        let mut nodes = Vec::new();
+        for (id, read) in [("1", false), ("1a", true), ("2", false)] {
+            let mut props = Properties::new();
+            props.insert("luhmann_id".to_string(), PropertyValue::String(id.to_string()));
+            props.insert("read".to_string(), PropertyValue::Boolean(read));
+            let node = Node::new("note", props);
+            storage.create_node(&node).await.unwrap();
+            nodes.push(node);
+        }
+        storage.create_edge(&Edge::new("continues", nodes[1].id, nodes[2].id, Properties::new())).await.unwrap();
+
+        let query = GraphQuery::new().with_node_type("note").with_property_prefix("luhmann_id", "1");
+        assert_eq!(storage.query_nodes(&query).await.unwrap().len(), 2);
+
+        let query = GraphQuery::new().with_property("read", PropertyValue::Boolean(false));
+        assert_eq!(storage.query_nodes(&query).await.unwrap().len(), 2);
+
+        let query = GraphQuery::new().with_property("read", PropertyValue::String("false".to_string()));
+        assert!(storage.query_nodes(&query).await.unwrap().is_empty());
+
+        let query = GraphQuery::new().with_edge_type("continues").with_property("read", PropertyValue::Boolean(false));
+        let results = storage.query_nodes(&query).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, nodes[2].id);
+    }
+
+    #[tokio::test]
+    async fn test_search_binds_text() {
+        let storage = storage().await;
+        let mut props = Properties::new();
+        props.insert("subject".to_string(), PropertyValue::String("50% off".to_string()));
+        storage.create_node(&Node::new("mail", props)).await.unwrap();
+
+        let query = SearchQuery {
+            node_types: vec!["mail".to_string()],
+            search_text: Some("50%".to_string()),
+            ..SearchQuery::default()
+        };
+        assert_eq!(storage.search_nodes(&query).await.unwrap().items.len(), 1);
+
+        let query = SearchQuery {
+            search_text: Some("' OR 1=1 --".to_string()),
+            ..SearchQuery::default()
+        };
+        assert!(storage.search_nodes(&query).await.unwrap().items.is_empty());
+    }
+
+    // The same nodes in SQLite and in memory, for checking both answer a
+    // search the same way
+    async fn both_with(nodes: &[Node]) -> (SqliteStorage, InMemoryStorage) {
+        let sqlite = storage().await;
+        let memory = InMemoryStorage::new();
+        for node in nodes {
+            sqlite.create_node(node).await.unwrap();
+            memory.create_node(node).await.unwrap();
+        }
+        (sqlite, memory)
+    }
+
+    async fn search_ids(storage: &impl GraphStorage, query: &SearchQuery) -> Vec<NodeId> {
+        storage.search_nodes(query).await.unwrap().items.iter().map(|n| n.id).collect()
+    }
+
+    #[tokio::test]
+    async fn test_text_search_matches_values_not_keys_like_memory() {
+        let mut subject = Properties::new();
+        subject.insert("subject".to_string(), PropertyValue::String("Weekly report".to_string()));
+        let mut nested = Properties::new();
+        nested.insert("labels".to_string(), PropertyValue::List(vec![PropertyValue::String("subject-line".to_string())]));
+        let (sqlite, memory) = both_with(&[Node::new("mail", subject.clone()), Node::new("mail", nested.clone())]).await;
+
+        for (search_text, full_text) in [
+            (Some("subject"), None),
+            (None, Some("subject")),
+            (Some("REPORT"), None),
+            (None, Some("\"weekly report\"")),
+            (Some("\":\""), None),
+        ] {
+            let query = SearchQuery {
+                search_text: search_text.map(str::to_string),
+                full_text: full_text.map(str::to_string),
+                ..SearchQuery::default()
+            };
+            assert_eq!(search_ids(&sqlite, &query).await, search_ids(&memory, &query).await, "{query:?}");
+        }
+
+        // "subject" is only a key in the first node, but a value in the second
+        let query = SearchQuery { search_text: Some("subject".to_string()), ..SearchQuery::default() };
+        assert_eq!(search_ids(&sqlite, &query).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_search_pages_through_many_node_ids_like_memory() {
+        let nodes: Vec<Node> = (0..1200).map(|_| Node::new("note", Properties::new())).collect();
+        let (sqlite, memory) = both_with(&nodes).await;
+        let ids: Vec<NodeId> = nodes.iter().map(|n| n.id).step_by(2).collect();
+
+        for offset in [0, 480, 590] {
+            let query = SearchQuery {
+                node_ids: Some(ids.clone()),
+                limit: 20,
+                offset,
+                ..SearchQuery::default()
+            };
+            let found = search_ids(&sqlite, &query).await;
+            assert_eq!(found.len(), 20.min(600 - offset));
+            assert_eq!(found, search_ids(&memory, &query).await);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_by_timestamp_property() {
+        let noon = chrono::DateTime::parse_from_rfc3339("2026-03-01T12:00:00Z").unwrap().to_utc();
+        let at = |offset: chrono::Duration| {
+            let mut props = Properties::new();
+            props.insert("deliver_at".to_string(), PropertyValue::Timestamp(noon + offset));
+            Node::new("marker", props)
+        };
+        let due = at(-chrono::Duration::milliseconds(1500));
+        let (sqlite, memory) = both_with(&[due.clone(), at(chrono::Duration::milliseconds(500)), at(chrono::Duration::hours(1))]).await;
+
+        let query = SearchQuery {
+            property_before: vec![("deliver_at".to_string(), noon)],
+            ..SearchQuery::default()
+        };
+        assert_eq!(search_ids(&sqlite, &query).await, vec![due.id]);
+        assert_eq!(search_ids(&memory, &query).await, vec![due.id]);
+    }
+
+    #[tokio::test]
+    async fn test_search_by_id_prefix() {
+        let storage = storage().await;
+        let mut node = Node::new("note", Properties::new());
+        node.id = NodeId::parse_str("3fa85f64-5717-4562-b3fc-2c963f66afa6").unwrap();
+        storage.create_node(&node).await.unwrap();
+        let mut other = Node::new("note", Properties::new());
+        other.id = NodeId::parse_str("3fa95f64-5717-4562-b3fc-2c963f66afa6").unwrap();
+        storage.create_node(&other).await.unwrap();
+
+        let search = |prefix: &str| SearchQuery {
+            id_prefix: Some(prefix.to_string()),
+            ..SearchQuery::default()
+        };
+        assert_eq!(storage.search_nodes(&search("3fa8")).await.unwrap().items.len(), 1);
+        assert_eq!(storage.search_nodes(&search("3FA85F64-57")).await.unwrap().items.len(), 1);
+        assert_eq!(storage.search_nodes(&search("3fa")).await.unwrap().items.len(), 2);
+        assert!(storage.search_nodes(&search("3fa85f645")).await.unwrap().items.is_empty());
+        assert!(storage.search_nodes(&search("zz")).await.unwrap().items.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_rolls_back() {
+        let storage = storage().await;
+        let node = Node::new("note", Properties::new());
+        let dangling = Edge::new("references", node.id, NodeId::new_v4(), Properties::new());
+
+        let mut tx = storage.begin();
+        tx.create_node(&node).create_edge(&dangling);
+        assert!(storage.commit(tx).await.is_err());
+        assert!(storage.get_node(node.id).await.is_err());
+
+        // Deleting a node cascades to its edges
+        let other = Node::new("note", Properties::new());
+        let edge = Edge::new("references", node.id, other.id, Properties::new());
+        let mut tx = storage.begin();
+        tx.create_node(&node).create_node(&other).create_edge(&edge);
+        storage.commit(tx).await.unwrap();
+        storage.delete_node(other.id).await.unwrap();
+        assert!(storage.get_edges_from(node.id, None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_commit_updates_and_deletes_edges() {
+        let storage = storage().await;
+        let node = Node::new("note", Properties::new());
+        let other = Node::new("note", Properties::new());
+        let edge = Edge::new("references", node.id, other.id, Properties::new());
+        let mut tx = storage.begin();
+        tx.create_node(&node).create_node(&other).create_edge(&edge);
+        storage.commit(tx).await.unwrap();
+
+        // A failing delete rolls back the update staged before it
+        let mut updated = edge.clone();
+        updated.properties.insert("kind".to_string(), PropertyValue::String("see also".to_string()));
+        let mut tx = storage.begin();
+        tx.update_edge(&updated).delete_edge(EdgeId::new_v4());
+        assert!(matches!(storage.commit(tx).await, Err(StorageError::EdgeNotFound(_))));
+        assert!(!storage.get_edges_from(node.id, None).await.unwrap()[0].properties.contains_key("kind"));
+
+        let mut tx = storage.begin();
+        tx.update_edge(&updated);
+        storage.commit(tx).await.unwrap();
+        assert!(storage.get_edges_from(node.id, None).await.unwrap()[0].properties.contains_key("kind"));
+
+        let mut tx = storage.begin();
+        tx.delete_edge(edge.id);
+        storage.commit(tx).await.unwrap();
+        assert!(storage.get_edges_from(node.id, None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_is_idempotent_and_keeps_data() {
+        let storage = storage().await;
+        let node = Node::new("agent", Properties::new());
+        storage.create_node(&node).await.unwrap();
+
+        // storage() already applied everything
+        assert!(storage.migrate().await.unwrap().is_empty());
+        assert!(storage.get_node(node.id).await.is_ok());
+
+        let statuses = storage.migration_status().await.unwrap();
+        assert_eq!(statuses.len(), MIGRATIONS.len());
+        assert!(statuses.iter().all(|s| s.applied_at.is_some()));
+    }
+
+    #[tokio::test]
+    async fn test_reset_tables_clears_data() {
+        let storage = storage().await;
+        let node = Node::new("agent", Properties::new());
+        storage.create_node(&node).await.unwrap();
+
+        storage.reset_tables().await.unwrap();
+        assert!(storage.get_node(node.id).await.is_err());
+        assert!(storage.migration_status().await.unwrap().iter().all(|s| s.applied_at.is_some()));
+    }
+}
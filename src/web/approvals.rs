@@ -0,0 +1,158 @@
+use axum::response::Html;
+use std::collections::HashMap;
+
+use crate::services::approval::domain::{ApprovalRequest, ApprovalStatus};
+use crate::services::approval::{ApprovalService, ApprovalServiceImpl};
+use crate::services::mail::MailServiceImpl;
+use crate::storage::AnyStorage;
+
+use super::mail_service;
+use super::schedules::{html_escape, urldecode};
+
+async fn approval_service(database_url: Option<&str>) -> Option<ApprovalServiceImpl<AnyStorage, MailServiceImpl<AnyStorage>>> {
+    let storage = AnyStorage::connect(database_url).await.ok()?;
+    let mail_storage = AnyStorage::connect(database_url).await.ok()?;
+    Some(ApprovalServiceImpl::new(storage, mail_service(mail_storage)))
+}
+
+// Pending requests for the dashboard, each with a form to decide it
+pub async fn approvals_panel(database_url: Option<&str>) -> String {
+    let pending = match approval_service(database_url).await {
+        Some(service) => service.list_requests(None, true).await.unwrap_or_default(),
+        None => Vec::new(),
+    };
+
+    let mut html = format!(
+        "<h2>Pending Approvals <span class=\"section-count\">{} waiting</span></h2>",
+        pending.len()
+    );
+    if pending.is_empty() {
+        html.push_str("<p class=\"empty-state\">No agent is waiting for sign-off</p>");
+        return html;
+    }
+    html.push_str("<div class=\"approval-list\">");
+    for request in &pending {
+        html.push_str(&approval_card(request, "", "", None));
+    }
+    html.push_str("</div>");
+    // Whoever decides is whoever the send form last sent mail as
+    html.push_str(
+        "<script>document.querySelectorAll('.approval-decided-by').forEach(function (input) {\
+            if (!input.value) input.value = localStorage.getItem('send-mail-from') || '';\
+        });</script>",
+    );
+    html
+}
+
+fn approval_card(request: &ApprovalRequest, decided_by: &str, comment: &str, error: Option<String>) -> String {
+    let mut options = String::new();
+    for (index, option) in request.options.iter().enumerate() {
+        options.push_str(&format!(
+            "<label class=\"approval-option\"><input type=\"radio\" name=\"option\" value=\"{}\"{}> {}</label>",
+            index,
+            if request.options.len() == 1 { " checked" } else { "" },
+            html_escape(option)
+        ));
+    }
+    format!(
+        r#"<div class="approval-card">
+            {error}
+            <div class="approval-header">
+                <strong>{title}</strong>
+                <span class="badge badge-warning">from {requested_by}</span>
+                <small class="textmuted">{created_at}</small>
+            </div>
+            {description}
+            <form class="approval-form" hx-target="closest .approval-card" hx-swap="outerHTML">
+                {options}
+                <div class="form-row">
+                    <div class="form-group">
+                        <label>Your ID</label>
+                        <input type="text" name="decided_by" class="approval-decided-by" value="{decided_by}" placeholder="human" required>
+                    </div>
+                    <div class="form-group">
+                        <label>Comment</label>
+                        <textarea name="comment" rows="2" placeholder="Sent to {requested_by} with your decision">{comment}</textarea>
+                    </div>
+                </div>
+                <div class="form-actions">
+                    <button type="submit" class="btn btn-success" hx-post="/approvals/{id}/approve">Approve</button>
+                    <button type="submit" class="btn btn-danger" hx-post="/approvals/{id}/reject">Reject</button>
+                </div>
+            </form>
+        </div>"#,
+        error = error.map(|e| format!("<div class=\"error\">{}</div>", html_escape(&e))).unwrap_or_default(),
+        title = html_escape(&request.title),
+        requested_by = html_escape(&request.requested_by),
+        created_at = request.created_at.format("%Y-%m-%d %H:%M"),
+        description = if request.description.is_empty() {
+            String::new()
+        } else {
+            format!("<p class=\"approval-description\">{}</p>", html_escape(&request.description))
+        },
+        options = options,
+        decided_by = html_escape(decided_by),
+        comment = html_escape(comment),
+        id = request.id,
+    )
+}
+
+// Approve a request, with the option picked in the form
+pub async fn approve_request(database_url: Option<String>, request_id: String, body: axum::body::Bytes) -> Html<String> {
+    decide_request(database_url, request_id, body, ApprovalStatus::Approved).await
+}
+
+pub async fn reject_request(database_url: Option<String>, request_id: String, body: axum::body::Bytes) -> Html<String> {
+    decide_request(database_url, request_id, body, ApprovalStatus::Rejected).await
+}
+
+async fn decide_request(
+    database_url: Option<String>,
+    request_id: String,
+    body: axum::body::Bytes,
+    status: ApprovalStatus,
+) -> Html<String> {
+    let body_str = String::from_utf8_lossy(&body);
+    let params: HashMap<String, String> = body_str
+        .split('&')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next()?.to_string();
+            let value = urldecode(parts.next().unwrap_or(""));
+            Some((key, value))
+        })
+        .collect();
+    let decided_by = params.get("decided_by").map(|v| v.trim().to_lowercase()).unwrap_or_default();
+    let comment = params.get("comment").cloned().unwrap_or_default();
+
+    let Some(service) = approval_service(database_url.as_deref()).await else {
+        return Html("<div class=\"error\">Failed to connect to database</div>".to_string());
+    };
+    let request = match service.get_request_by_short_id(&request_id).await {
+        Ok(r) => r,
+        Err(_) => return Html("<div class=\"error\">Approval request not found</div>".to_string()),
+    };
+    if decided_by.is_empty() {
+        return Html(approval_card(&request, "", &comment, Some("Enter your ID so the decision can be mailed from you".to_string())));
+    }
+
+    // Options are sent by position, so their text doesn't have to survive the form encoding
+    let option = params.get("option")
+        .and_then(|i| i.parse::<usize>().ok())
+        .and_then(|i| request.options.get(i).cloned());
+    let result = match status {
+        ApprovalStatus::Rejected => service.reject(request.id, decided_by.clone(), comment.clone()).await,
+        _ => service.approve(request.id, decided_by.clone(), option, comment.clone()).await,
+    };
+    match result {
+        Ok(decided) => Html(format!(
+            "<div class=\"approval-card approval-decided\">{} <strong>{}</strong> {}{} &mdash; {} has been mailed</div>",
+            if decided.status == ApprovalStatus::Approved { "✅" } else { "❌" },
+            html_escape(&decided.title),
+            decided.status.as_str(),
+            decided.chosen_option.map(|o| format!(": {}", html_escape(&o))).unwrap_or_default(),
+            html_escape(&decided.requested_by),
+        )),
+        Err(e) => Html(approval_card(&request, &decided_by, &comment, Some(e.to_string()))),
+    }
+}
@@ -0,0 +1,75 @@
+use axum::response::Html;
+use std::collections::HashMap;
+
+use crate::services::kv::{KvService, KvServiceImpl};
+use crate::storage::AnyStorage;
+use crate::web::templates;
+
+use super::schedules::html_escape;
+
+// Every key on the blackboard with its current value, by namespace
+pub async fn kv_view(database_url: Option<String>, params: HashMap<String, String>) -> Html<String> {
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html(templates::error_page("Failed to connect to database")),
+    };
+    let service = KvServiceImpl::new(storage);
+
+    let all = match service.list(None).await {
+        Ok(entries) => entries,
+        Err(_) => return Html(templates::error_page("Failed to load keys")),
+    };
+    let namespace = params.get("namespace").map(|n| n.trim()).filter(|n| !n.is_empty());
+    let entries: Vec<_> = all.iter()
+        .filter(|e| namespace.is_none_or(|n| e.key.namespace == n))
+        .collect();
+
+    let mut namespaces: Vec<&str> = all.iter().map(|e| e.key.namespace.as_str()).collect();
+    namespaces.dedup();
+
+    let mut content = String::new();
+    content.push_str("<div class=\"back-link\">");
+    content.push_str("<a href=\"/\" class=\"btn btn-secondary btn-sm\">&larr; Back to Dashboard</a>");
+    content.push_str("</div>");
+    content.push_str(&format!(
+        "<h2>Blackboard{} <span class=\"section-count\">{} keys</span></h2>",
+        namespace.map(|n| format!(": {}", html_escape(n))).unwrap_or_default(),
+        entries.len()
+    ));
+    if namespaces.len() > 1 {
+        content.push_str("<div class=\"work-projects\">");
+        content.push_str("<a href=\"/kv\" class=\"btn btn-sm btn-secondary\">All</a>");
+        for n in namespaces {
+            content.push_str(&format!(
+                "<a href=\"/kv?namespace={}\" class=\"btn btn-sm btn-secondary\">{}</a>",
+                urlencoding::encode(n),
+                html_escape(n)
+            ));
+        }
+        content.push_str("</div>");
+    }
+
+    if entries.is_empty() {
+        content.push_str("<p class=\"empty-state\">Nothing on the blackboard yet. Set a key with <code>agent-office kv set</code>.</p>");
+        return Html(templates::wrap_content(content));
+    }
+
+    content.push_str("<table class=\"data-table\"><thead><tr>");
+    content.push_str("<th>Key</th><th>Value</th><th>Version</th><th>Set by</th><th>Updated</th><th>Expires</th>");
+    content.push_str("</tr></thead><tbody>");
+    for entry in entries {
+        content.push_str(&format!(
+            "<tr><td><span class=\"textmuted\">{}/</span><strong>{}</strong></td><td><code class=\"kv-value\">{}</code></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            html_escape(&entry.key.namespace),
+            html_escape(&entry.key.key),
+            html_escape(entry.value.as_deref().unwrap_or_default()),
+            entry.version,
+            html_escape(&entry.updated_by),
+            entry.updated_at.format("%Y-%m-%d %H:%M"),
+            entry.expires_at.map(|t| t.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_else(|| "never".to_string()),
+        ));
+    }
+    content.push_str("</tbody></table>");
+
+    Html(templates::wrap_content(content))
+}
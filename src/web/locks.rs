@@ -0,0 +1,87 @@
+use axum::response::Html;
+
+use crate::services::lease::{LeaseService, LeaseServiceImpl};
+
+use super::schedules::{html_escape, urldecode};
+
+// Who holds which lock, for the dashboard, with a way to take a lock back
+// from an agent that has wandered off
+pub async fn locks_panel(database_url: Option<&str>) -> String {
+    let mut html = String::from("<div id=\"locks-panel\">");
+    html.push_str(&locks_panel_inner(database_url, None).await);
+    html.push_str("</div>");
+    html
+}
+
+async fn locks_panel_inner(database_url: Option<&str>, message: Option<String>) -> String {
+    let leases = match database_url {
+        Some(url) => match LeaseServiceImpl::connect(url).await {
+            Ok(service) => service.list_leases().await.unwrap_or_default(),
+            Err(_) => Vec::new(),
+        },
+        None => Vec::new(),
+    };
+
+    let mut html = format!("<h2>Locks <span class=\"section-count\">{} held</span></h2>", leases.len());
+    if let Some(message) = message {
+        html.push_str(&message);
+    }
+    if leases.is_empty() {
+        html.push_str("<p class=\"empty-state\">No locks held</p>");
+        return html;
+    }
+
+    html.push_str("<table class=\"data-table\"><thead><tr>");
+    html.push_str("<th>Lock</th><th>Holder</th><th>Since</th><th>Expires</th><th></th>");
+    html.push_str("</tr></thead><tbody>");
+    for lease in &leases {
+        html.push_str(&format!(
+            r##"<tr>
+                <td><strong>{name}</strong></td>
+                <td>{holder}</td>
+                <td>{since}</td>
+                <td>{expires}</td>
+                <td>
+                    <form hx-post="/locks/release" hx-target="#locks-panel" hx-swap="innerHTML"
+                          hx-confirm="Take '{name}' away from {holder}?">
+                        <input type="hidden" name="name" value="{name}">
+                        <button type="submit" class="btn btn-sm btn-danger">Force release</button>
+                    </form>
+                </td>
+            </tr>"##,
+            name = html_escape(&lease.name),
+            holder = html_escape(&lease.holder),
+            since = lease.acquired_at.format("%Y-%m-%d %H:%M:%S"),
+            expires = lease.expires_at.format("%Y-%m-%d %H:%M:%S"),
+        ));
+    }
+    html.push_str("</tbody></table>");
+    html
+}
+
+// Release a lock whoever holds it, then redraw the panel
+pub async fn force_release_lock(database_url: Option<String>, body: axum::body::Bytes) -> Html<String> {
+    let body_str = String::from_utf8_lossy(&body);
+    let name = body_str
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "name")
+        .map(|(_, value)| urldecode(value))
+        .unwrap_or_default();
+
+    let Some(url) = database_url.as_deref() else {
+        return Html("<div class=\"send-result error\">Locks need a database</div>".to_string());
+    };
+    let message = match LeaseServiceImpl::connect(url).await {
+        Ok(service) => match service.force_release(&name).await {
+            Ok(lease) => format!(
+                "<div class=\"send-result success\">Released {} from {}</div>",
+                html_escape(&lease.name),
+                html_escape(&lease.holder)
+            ),
+            Err(e) => format!("<div class=\"send-result error\">{}</div>", html_escape(&e.to_string())),
+        },
+        Err(_) => "<div class=\"send-result error\">Failed to connect to database</div>".to_string(),
+    };
+    Html(locks_panel_inner(Some(url), Some(message)).await)
+}
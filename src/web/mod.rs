@@ -1,20 +1,44 @@
 use axum::{
-    extract::Path,
-    response::Html,
+    extract::{Path, Query},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Response},
     routing::{get, post},
     Router,
 };
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::time::Duration;
 
 pub mod templates;
+mod approvals;
+mod kv;
+mod locks;
+mod rules;
 mod schedules;
-use schedules::{agent_schedule_view, create_schedule, update_schedule, delete_schedule, toggle_schedule};
+mod work;
+use approvals::{approvals_panel, approve_request, reject_request};
+use kv::kv_view;
+use locks::{force_release_lock, locks_panel};
+use rules::{agent_rules_view, create_rule, delete_rule, toggle_rule};
+use schedules::{agent_schedule_view, create_schedule, update_schedule, delete_schedule, html_escape, toggle_schedule};
+use work::work_view;
 
+use crate::domain::string_to_node_id;
 use crate::services::mail::{MailService, MailServiceImpl};
+use crate::services::mail::domain::{
+    Attachment, AttachmentLimits, Folder, InboxFilter, Mail, MailId, MailSearch, MailboxId, Priority, RetentionPolicy, SendOptions,
+};
 use crate::services::kb::{KnowledgeBaseService, KnowledgeBaseServiceImpl};
 use crate::services::kb::domain::LuhmannId;
+use crate::services::schedule::ScheduleServiceImpl;
 // Schedule handlers are in schedules module
-use crate::storage::{memory::InMemoryStorage, postgres::PostgresStorage};
+use crate::storage::AnyStorage;
+use crate::storage::blob::BlobLocation;
+
+/// Mail service with attachments configured from the environment
+fn mail_service(storage: AnyStorage) -> MailServiceImpl<AnyStorage> {
+    MailServiceImpl::new(storage).with_attachments(BlobLocation::from_env(), AttachmentLimits::from_env())
+}
 
 /// Render markdown content to HTML using pulldown-cmark.
 fn render_markdown(content: &str) -> String {
@@ -39,11 +63,7 @@ fn plain_text_preview(content: &str, max_chars: usize) -> String {
         match event {
             Event::Text(t) | Event::Code(t) => text.push_str(&t),
             Event::SoftBreak | Event::HardBreak => text.push(' '),
-            Event::Start(Tag::Paragraph) => {
-                if !text.is_empty() {
-                    text.push(' ');
-                }
-            }
+            Event::Start(Tag::Paragraph) if !text.is_empty() => text.push(' '),
             Event::End(TagEnd::Paragraph) => text.push(' '),
             _ => {}
         }
@@ -63,12 +83,77 @@ fn plain_text_preview(content: &str, max_chars: usize) -> String {
     }
 }
 
+/// Attachments of each mail in a list, for rendering download links
+async fn attachments_by_mail(service: &impl MailService, mails: &[Mail]) -> HashMap<MailId, Vec<Attachment>> {
+    let mut attachments = HashMap::new();
+    for mail in mails {
+        let files = service.get_attachments(mail.id).await.unwrap_or_default();
+        if !files.is_empty() {
+            attachments.insert(mail.id, files);
+        }
+    }
+    attachments
+}
+
+/// Download links for a mail's attachments, or nothing
+fn attachment_links(attachments: Option<&Vec<Attachment>>) -> String {
+    let Some(attachments) = attachments else {
+        return String::new();
+    };
+    let links: String = attachments.iter()
+        .map(|a| format!(
+            r#"<li>📎 <a href="/mail/attachment/{}">{}</a> <span class="mail-meta">{}</span></li>"#,
+            a.id, html_escape(&a.filename), a.size_label()
+        ))
+        .collect();
+    format!(r#"<ul class="mail-attachments">{}</ul>"#, links)
+}
+
+/// Display names for every agent's mailbox, so mail lists can show who
+/// sent or received each message with a single lookup
+async fn mailbox_names(service: &impl MailService) -> HashMap<MailboxId, String> {
+    service.list_agents().await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|agent| (string_to_node_id(&agent.id), agent.name))
+        .collect()
+}
+
+/// "alice, bob" for a list of mailboxes; unknown ones show as a short id
+fn join_names(ids: &[MailboxId], names: &HashMap<MailboxId, String>) -> String {
+    ids.iter()
+        .map(|id| names.get(id).cloned().unwrap_or_else(|| id.to_string()[..8].to_string()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// "To: alice, bob • Cc: carol"
+fn recipient_line(mail: &Mail, names: &HashMap<MailboxId, String>) -> String {
+    let mut line = format!("To: {}", join_names(&mail.to_mailbox_ids, names));
+    if !mail.cc_mailbox_ids.is_empty() {
+        line.push_str(&format!(" • Cc: {}", join_names(&mail.cc_mailbox_ids, names)));
+    }
+    line
+}
+
+/// `gc` processes due delayed mail and runs the retention policy in the
+/// background at the given interval
 pub async fn run_web_server(
     database_url: Option<String>,
     host: String,
     port: u16,
+    gc: Option<(RetentionPolicy, Duration)>,
 ) -> anyhow::Result<()> {
-    let app = create_router(database_url);
+    if let Some((policy, every)) = gc {
+        println!("🧹 Applying the mail retention policy every {} hour(s)", every.as_secs() / 3600);
+        tokio::spawn(retention_task(database_url.clone(), policy, every));
+    }
+    // One pool for the schedules pages, rather than one per request
+    let schedules = match database_url.as_deref() {
+        Some(url) => ScheduleServiceImpl::from_storage(&AnyStorage::connect(Some(url)).await?),
+        None => None,
+    };
+    let app = create_router(database_url, schedules);
     
     let addr: SocketAddr = format!("{}:{}", host, port).parse()?;
     println!("🌐 Starting web server on http://{}", addr);
@@ -81,7 +166,34 @@ pub async fn run_web_server(
     Ok(())
 }
 
-fn create_router(database_url: Option<String>) -> Router {
+async fn retention_task(database_url: Option<String>, policy: RetentionPolicy, every: Duration) {
+    let mut ticker = tokio::time::interval(every);
+    loop {
+        ticker.tick().await;
+        let storage = match AnyStorage::connect(database_url.as_deref()).await {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("Retention run skipped, could not connect: {}", e);
+                continue;
+            }
+        };
+        let service = mail_service(storage);
+        match service.arrive_due_mail(chrono::Utc::now()).await {
+            Ok(0) => {}
+            Ok(arrived) => println!("📬 Processed {} delayed mail(s) that had come due", arrived),
+            Err(e) => eprintln!("Delayed mail run failed: {}", e),
+        }
+        match service.apply_retention(&policy, chrono::Utc::now()).await {
+            Ok(report) => println!(
+                "🧹 Retention: archived {} read mail(s), deleted {} expired mail(s)",
+                report.archived, report.deleted
+            ),
+            Err(e) => eprintln!("Retention run failed: {}", e),
+        }
+    }
+}
+
+fn create_router(database_url: Option<String>, schedules: Option<ScheduleServiceImpl>) -> Router {
     use std::sync::Arc;
     let db_url = Arc::new(database_url.clone());
     let db_url2 = Arc::new(database_url.clone());
@@ -112,11 +224,15 @@ fn create_router(database_url: Option<String>) -> Router {
         // Inbox view
         .route("/mail/inbox/{agent_id}", get({
             let db = db_url2.clone();
-            move |Path(agent_id): Path<String>| inbox_view((*db).clone(), agent_id)
+            move |Path(agent_id): Path<String>, Query(params): Query<HashMap<String, String>>| inbox_view((*db).clone(), agent_id, params)
+        }))
+        .route("/mail/inbox/{agent_id}/{mail_id}/read", post({
+            let db = db_url2.clone();
+            move |Path((agent_id, mail_id)): Path<(String, String)>| mark_mail_read((*db).clone(), agent_id, mail_id)
         }))
-        .route("/mail/{mail_id}/read", post({
+        .route("/mail/inbox/{agent_id}/{mail_id}/move/{folder}", post({
             let db = db_url2.clone();
-            move |Path(mail_id): Path<String>| mark_mail_read((*db).clone(), mail_id)
+            move |Path((agent_id, mail_id, folder)): Path<(String, String, String)>| move_mail((*db).clone(), agent_id, mail_id, folder)
         }))
         .route("/mail/inbox/{agent_id}/read-all", post({
             let db = db_url2.clone();
@@ -128,6 +244,24 @@ fn create_router(database_url: Option<String>) -> Router {
             let db = db_url8.clone();
             move |Path(agent_id): Path<String>| outbox_view((*db).clone(), agent_id)
         }))
+        .route("/mail/outbox/{agent_id}/{mail_id}/cancel", post({
+            let db = db_url8.clone();
+            move |Path((agent_id, mail_id)): Path<(String, String)>| cancel_mail((*db).clone(), agent_id, mail_id)
+        }))
+        
+        // Conversation view
+        .route("/mail/thread/{mail_id}", get({
+            let db = db_url8.clone();
+            move |Path(mail_id): Path<String>, Query(params): Query<HashMap<String, String>>| {
+                thread_view((*db).clone(), mail_id, params.get("agent").cloned())
+            }
+        }))
+        
+        // Attachment download
+        .route("/mail/attachment/{attachment_id}", get({
+            let db = db_url8.clone();
+            move |Path(attachment_id): Path<String>| download_attachment((*db).clone(), attachment_id)
+        }))
         
         // Update agent status
         .route("/agents/{agent_id}/status", post({
@@ -150,23 +284,74 @@ fn create_router(database_url: Option<String>) -> Router {
         // Schedule management
         .route("/agents/{agent_id}/schedule", get({
             let db = db_url9.clone();
-            move |Path(agent_id): Path<String>| agent_schedule_view((*db).clone(), agent_id)
+            let schedules = schedules.clone();
+            move |Path(agent_id): Path<String>| agent_schedule_view((*db).clone(), schedules.clone(), agent_id)
         }))
         .route("/agents/{agent_id}/schedule", post({
             let db = db_url10.clone();
-            move |Path(agent_id): Path<String>, body: axum::body::Bytes| create_schedule((*db).clone(), agent_id, body)
+            let schedules = schedules.clone();
+            move |Path(agent_id): Path<String>, body: axum::body::Bytes| create_schedule((*db).clone(), schedules.clone(), agent_id, body)
         }))
         .route("/schedules/{schedule_id}/toggle", post({
             let db = db_url11.clone();
-            move |Path(schedule_id): Path<String>| toggle_schedule((*db).clone(), schedule_id)
+            let schedules = schedules.clone();
+            move |Path(schedule_id): Path<String>| toggle_schedule((*db).clone(), schedules.clone(), schedule_id)
         }))
         .route("/schedules/{schedule_id}/update", post({
             let db = db_url11.clone();
-            move |Path(schedule_id): Path<String>, body: axum::body::Bytes| update_schedule((*db).clone(), schedule_id, body)
+            let schedules = schedules.clone();
+            move |Path(schedule_id): Path<String>, body: axum::body::Bytes| update_schedule((*db).clone(), schedules.clone(), schedule_id, body)
         }))
         .route("/schedules/{schedule_id}/delete", post({
             let db = db_url11.clone();
-            move |Path(schedule_id): Path<String>| delete_schedule((*db).clone(), schedule_id)
+            let schedules = schedules.clone();
+            move |Path(schedule_id): Path<String>| delete_schedule((*db).clone(), schedules.clone(), schedule_id)
+        }))
+        
+        // Mail rules
+        .route("/agents/{agent_id}/rules", get({
+            let db = db_url9.clone();
+            move |Path(agent_id): Path<String>| agent_rules_view((*db).clone(), agent_id)
+        }))
+        .route("/agents/{agent_id}/rules", post({
+            let db = db_url10.clone();
+            move |Path(agent_id): Path<String>, body: axum::body::Bytes| create_rule((*db).clone(), agent_id, body)
+        }))
+        .route("/rules/{rule_id}/toggle", post({
+            let db = db_url11.clone();
+            move |Path(rule_id): Path<String>| toggle_rule((*db).clone(), rule_id)
+        }))
+        .route("/rules/{rule_id}/delete", post({
+            let db = db_url11.clone();
+            move |Path(rule_id): Path<String>| delete_rule((*db).clone(), rule_id)
+        }))
+        
+        // Approval requests
+        .route("/approvals/{request_id}/approve", post({
+            let db = db_url12.clone();
+            move |Path(request_id): Path<String>, body: axum::body::Bytes| approve_request((*db).clone(), request_id, body)
+        }))
+        .route("/approvals/{request_id}/reject", post({
+            let db = db_url12.clone();
+            move |Path(request_id): Path<String>, body: axum::body::Bytes| reject_request((*db).clone(), request_id, body)
+        }))
+        
+        // Locks
+        .route("/locks/release", post({
+            let db = db_url12.clone();
+            move |body: axum::body::Bytes| force_release_lock((*db).clone(), body)
+        }))
+        
+        // Work items and their dependencies
+        .route("/work", get({
+            let db = db_url4.clone();
+            move |Query(params): Query<HashMap<String, String>>| work_view((*db).clone(), params)
+        }))
+        
+        // Blackboard keys
+        .route("/kv", get({
+            let db = db_url4.clone();
+            move |Query(params): Query<HashMap<String, String>>| kv_view((*db).clone(), params)
         }))
         
         // KB - Knowledge Base
@@ -195,26 +380,18 @@ fn create_router(database_url: Option<String>) -> Router {
 
 // Dashboard / Home - Show agents with their mailboxes
 async fn dashboard(database_url: Option<String>) -> Html<String> {
-    let agents = if let Some(url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html(templates::error_page("Failed to connect to database")),
-        };
-        let storage = PostgresStorage::new(pool);
-        let service = MailServiceImpl::new(storage);
-        
-        match service.list_agents().await {
-            Ok(agents) => agents,
-            Err(_) => return Html(templates::error_page("Failed to load agents")),
-        }
-    } else {
-        let storage = InMemoryStorage::new();
-        let service = MailServiceImpl::new(storage);
-        
-        match service.list_agents().await {
-            Ok(agents) => agents,
-            Err(_) => return Html(templates::error_page("Failed to load agents")),
-        }
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html(templates::error_page("Failed to connect to database")),
+    };
+    let service = mail_service(storage);
+    let agents = match service.list_agents().await {
+        Ok(agents) => agents,
+        Err(_) => return Html(templates::error_page("Failed to load agents")),
+    };
+    let groups = match service.list_groups().await {
+        Ok(groups) => groups,
+        Err(_) => return Html(templates::error_page("Failed to load groups")),
     };
     
     let mut agent_cards = String::new();
@@ -231,8 +408,9 @@ async fn dashboard(database_url: Option<String>) -> Html<String> {
                 <a href="/mail/inbox/{}" class="btn btn-sm">📥 Inbox</a>
                 <a href="/mail/outbox/{}" class="btn btn-sm">📤 Outbox</a>
                 <a href="/agents/{}/schedule" class="btn btn-sm">⏰ Schedules</a>
+                <a href="/agents/{}/rules" class="btn btn-sm">🔀 Rules</a>
             </div>"#,
-            agent.id, agent.id, agent.id
+            agent.id, agent.id, agent.id, agent.id
         );
         
         // Quick status toggle button (only show if not already offline)
@@ -280,12 +458,36 @@ async fn dashboard(database_url: Option<String>) -> Html<String> {
             disp = session_display
         );
         
+        // Group membership as badges
+        let agent_groups = service.get_agent_groups(agent.id.clone()).await.unwrap_or_default();
+        let group_badges = if agent_groups.is_empty() {
+            String::new()
+        } else {
+            let badges: Vec<String> = agent_groups.iter()
+                .map(|g| format!(r#"<span class="tag-badge">@{}</span>"#, g.name))
+                .collect();
+            format!(r#"<div class="agent-groups">{}</div>"#, badges.join(""))
+        };
+        
+        // Out-of-office reply, answering only while the agent is away
+        let auto_reply = match agent.auto_reply {
+            Some(ref auto_reply) => format!(
+                r#"<div class="agent-auto-reply{}" title="{}">🏖️ Auto-reply{}</div>"#,
+                if agent.is_away() { "" } else { " inactive" },
+                html_escape(&auto_reply.message),
+                auto_reply.delegate.as_ref().map(|d| format!(" → {}", html_escape(d))).unwrap_or_default()
+            ),
+            None => String::new(),
+        };
+        
         agent_cards.push_str(&format!(
             r#"<div class="agent-card">
                 <div class="agent-info">
                     <h3>{}</h3>
                     <span class="status {}" id="agent-status-{}">{}</span>
                     {}
+                    {}
+                    {}
                 </div>
                 <div class="agentsession">
                     {}
@@ -295,7 +497,21 @@ async fn dashboard(database_url: Option<String>) -> Html<String> {
                     {}
                 </div>
             </div>"#,
-            agent.name, status_class, agent.id, agent.status, status_button, session_editor, mailbox_list
+            agent.name, status_class, agent.id, agent.status, status_button, group_badges, auto_reply, session_editor, mailbox_list
+        ));
+    }
+    
+    let mut group_rows = String::new();
+    for group in &groups {
+        let members = service.get_group_members(&group.name).await.unwrap_or_default();
+        let names: Vec<&str> = members.iter().map(|a| a.name.as_str()).collect();
+        group_rows.push_str(&format!(
+            r#"<tr>
+                <td><strong>@{}</strong></td>
+                <td>{}</td>
+            </tr>"#,
+            group.name,
+            if names.is_empty() { "<span class=\"textmuted\">No members</span>".to_string() } else { names.join(", ") }
         ));
     }
     
@@ -311,17 +527,27 @@ async fn dashboard(database_url: Option<String>) -> Html<String> {
                   onsubmit="saveFormFields()">
                 <div class="form-row">
                     <div class="form-group">
-                        <label for="send-to">To (Agent ID)</label>
-                        <input type="text" id="send-to" name="to" placeholder="agent-name" required>
+                        <label for="send-to">To (Agent IDs or @group, comma separated)</label>
+                        <input type="text" id="send-to" name="to" placeholder="agent-name, @group" required>
                     </div>
                     <div class="form-group">
                         <label for="send-from">From (Your ID)</label>
                         <input type="text" id="send-from" name="from" placeholder="human">
                     </div>
                 </div>
-                <div class="form-group">
-                    <label for="send-subject">Subject</label>
-                    <input type="text" id="send-subject" name="subject" placeholder="Message subject (optional)">
+                <div class="form-row">
+                    <div class="form-group">
+                        <label for="send-subject">Subject</label>
+                        <input type="text" id="send-subject" name="subject" placeholder="Message subject (optional)">
+                    </div>
+                    <div class="form-group">
+                        <label for="send-priority">Priority</label>
+                        <select id="send-priority" name="priority">
+                            <option value="normal">normal</option>
+                            <option value="urgent">urgent</option>
+                            <option value="low">low</option>
+                        </select>
+                    </div>
                 </div>
                 <div class="form-group">
                     <label for="send-body">Message</label>
@@ -355,16 +581,41 @@ async fn dashboard(database_url: Option<String>) -> Html<String> {
             document.addEventListener('DOMContentLoaded', loadFormFields);
         </script>
         
+        {}
+        
+        {}
+        
         <h2>Dashboard <span class="section-count">{} agents</span></h2>
         <div class="agent-list">
             {}
         </div>
+        
+        <h2>Groups <span class="section-count">{} groups</span></h2>
+        <table class="data-table">
+            <thead>
+                <tr>
+                    <th>Group</th>
+                    <th>Members</th>
+                </tr>
+            </thead>
+            <tbody>
+                {}
+            </tbody>
+        </table>
         "##,
+        approvals_panel(database_url.as_deref()).await,
+        locks_panel(database_url.as_deref()).await,
         agents.len(),
         if agent_cards.is_empty() {
             "<p class='empty-state'>No agents registered yet</p>".to_string()
         } else {
             agent_cards
+        },
+        groups.len(),
+        if group_rows.is_empty() {
+            "<tr><td colspan=\"2\" class=\"empty-state\">No groups yet (create one with <code>group create</code>)</td></tr>".to_string()
+        } else {
+            group_rows
         }
     );
     
@@ -373,21 +624,12 @@ async fn dashboard(database_url: Option<String>) -> Html<String> {
 
 // List all agents
 async fn list_agents(database_url: Option<String>) -> Html<String> {
-    let agents = if let Some(url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html(templates::error_page("Failed to connect to database")),
-        };
-        let storage = PostgresStorage::new(pool);
-        let service = MailServiceImpl::new(storage);
-        
-        match service.list_agents().await {
-            Ok(agents) => agents,
-            Err(_) => return Html(templates::error_page("Failed to load agents")),
-        }
-    } else {
-        let storage = InMemoryStorage::new();
-        let service = MailServiceImpl::new(storage);
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html(templates::error_page("Failed to connect to database")),
+    };
+    let agents = {
+        let service = mail_service(storage);
         
         match service.list_agents().await {
             Ok(agents) => agents,
@@ -442,20 +684,11 @@ async fn list_agents(database_url: Option<String>) -> Html<String> {
 
 // KB - List all notes
 async fn kb_list_notes(database_url: Option<String>) -> Html<String> {
-    let notes = if let Some(url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html(templates::error_page("Failed to connect to database")),
-        };
-        let storage = PostgresStorage::new(pool);
-        let service = KnowledgeBaseServiceImpl::new(storage);
-        
-        match service.list_notes().await {
-            Ok(notes) => notes,
-            Err(_) => return Html(templates::error_page("Failed to load notes")),
-        }
-    } else {
-        let storage = InMemoryStorage::new();
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html(templates::error_page("Failed to connect to database")),
+    };
+    let notes = {
         let service = KnowledgeBaseServiceImpl::new(storage);
         
         match service.list_notes().await {
@@ -540,59 +773,11 @@ async fn kb_view_note(database_url: Option<String>, note_id: String) -> Html<Str
         None => return Html(templates::error_page(&format!("Invalid Luhmann ID: {}", note_id))),
     };
     
-    let (note, children, parent, links, backlinks) = if let Some(url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html(templates::error_page("Failed to connect to database")),
-        };
-        let storage = PostgresStorage::new(pool);
-        let service = KnowledgeBaseServiceImpl::new(storage);
-        
-        let note = match service.get_note(&id).await {
-            Ok(n) => n,
-            Err(_) => return Html(templates::error_page(&format!("Note '{}' not found", note_id))),
-        };
-        
-        // Get children
-        let all_notes = match service.list_notes().await {
-            Ok(n) => n,
-            Err(_) => vec![],
-        };
-        let children: Vec<_> = all_notes.iter()
-            .filter(|n| n.id.parent().as_ref() == Some(&id))
-            .cloned()
-            .collect();
-        
-        // Get parent
-        let parent = if let Some(parent_id) = id.parent() {
-            service.get_note(&parent_id).await.ok()
-        } else {
-            None
-        };
-        
-        // Get links
-        let links = match service.get_links(&id).await {
-            Ok(l) => {
-                let mut linked_notes = vec![];
-                for link in l {
-                    if let Ok(target) = service.get_note(&link.to_note_id).await {
-                        linked_notes.push(target);
-                    }
-                }
-                linked_notes
-            },
-            Err(_) => vec![],
-        };
-        
-        // Get backlinks via context
-        let ctx = match service.get_context(&id).await {
-            Ok(c) => c.backlinks,
-            Err(_) => vec![],
-        };
-        
-        (note, children, parent, links, ctx)
-    } else {
-        let storage = InMemoryStorage::new();
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html(templates::error_page("Failed to connect to database")),
+    };
+    let (note, children, parent, links, backlinks) = {
         let service = KnowledgeBaseServiceImpl::new(storage);
         
         let note = match service.get_note(&id).await {
@@ -601,10 +786,7 @@ async fn kb_view_note(database_url: Option<String>, note_id: String) -> Html<Str
         };
         
         // Get children
-        let all_notes = match service.list_notes().await {
-            Ok(n) => n,
-            Err(_) => vec![],
-        };
+        let all_notes = service.list_notes().await.unwrap_or_default();
         let children: Vec<_> = all_notes.iter()
             .filter(|n| n.id.parent().as_ref() == Some(&id))
             .cloned()
@@ -768,12 +950,11 @@ async fn kb_tree_view(database_url: Option<String>, prefix: String) -> Html<Stri
         None => return Html(templates::error_page(&format!("Invalid prefix: {}", prefix))),
     };
     
-    let (notes_in_tree, parent_note) = if let Some(url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html(templates::error_page("Failed to connect to database")),
-        };
-        let storage = PostgresStorage::new(pool);
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html(templates::error_page("Failed to connect to database")),
+    };
+    let (notes_in_tree, parent_note) = {
         let service = KnowledgeBaseServiceImpl::new(storage);
         
         let all_notes = match service.list_notes().await {
@@ -794,27 +975,6 @@ async fn kb_tree_view(database_url: Option<String>, prefix: String) -> Html<Stri
             None
         };
         
-        (notes_in_tree, parent)
-    } else {
-        let storage = InMemoryStorage::new();
-        let service = KnowledgeBaseServiceImpl::new(storage);
-        
-        let all_notes = match service.list_notes().await {
-            Ok(n) => n,
-            Err(_) => return Html(templates::error_page("Failed to load notes")),
-        };
-        
-        let notes_in_tree: Vec<_> = all_notes.iter()
-            .filter(|n| n.id.to_string().starts_with(&prefix))
-            .cloned()
-            .collect();
-        
-        let parent = if let Some(parent_id) = prefix_id.parent() {
-            service.get_note(&parent_id).await.ok()
-        } else {
-            None
-        };
-        
         (notes_in_tree, parent)
     };
     
@@ -867,18 +1027,12 @@ async fn kb_tree_view(database_url: Option<String>, prefix: String) -> Html<Stri
 
 // Set agent status to offline
 async fn set_agent_status(database_url: Option<String>, agent_id: String) -> Html<String> {
-    let result = if let Some(url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html(templates::error_page("Failed to connect to database")),
-        };
-        let storage = PostgresStorage::new(pool);
-        let service = MailServiceImpl::new(storage);
-        
-        service.set_agent_status(agent_id, "offline").await
-    } else {
-        let storage = InMemoryStorage::new();
-        let service = MailServiceImpl::new(storage);
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html(templates::error_page("Failed to connect to database")),
+    };
+    let result = {
+        let service = mail_service(storage);
         
         service.set_agent_status(agent_id, "offline").await
     };
@@ -904,23 +1058,16 @@ async fn set_agent_session(database_url: Option<String>, agent_id: String, body:
         .split('&')
         .find(|p| p.starts_with("session_id="))
         .and_then(|p| p.split('=').nth(1))
-        .map(|s| urlencoding::decode(s).ok())
-        .flatten()
+        .and_then(|s| urlencoding::decode(s).ok())
         .map(|s| if s.trim().is_empty() { None } else { Some(s.into_owned()) })
         .unwrap_or(None);
     
-    let result = if let Some(url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html(templates::error_page("Failed to connect to database")),
-        };
-        let storage = PostgresStorage::new(pool);
-        let service = MailServiceImpl::new(storage);
-        
-        service.set_agent_session(agent_id, session_id).await
-    } else {
-        let storage = InMemoryStorage::new();
-        let service = MailServiceImpl::new(storage);
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html(templates::error_page("Failed to connect to database")),
+    };
+    let result = {
+        let service = mail_service(storage);
         
         service.set_agent_session(agent_id, session_id).await
     };
@@ -937,35 +1084,103 @@ async fn set_agent_session(database_url: Option<String>, agent_id: String, body:
     }
 }
 
-// Inbox view - Show mail for an agent
-async fn inbox_view(database_url: Option<String>, agent_id: String) -> Html<String> {
-    let (inbox_mail, agent_name) = if let Some(url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html(templates::error_page("Failed to connect to database")),
-        };
-        let storage = PostgresStorage::new(pool);
-        let service = MailServiceImpl::new(storage);
-        
-        let agent = match service.get_agent(agent_id.clone()).await {
-            Ok(a) => a,
-            Err(_) => return Html(templates::error_page(&format!("Agent '{}' not found", agent_id))),
-        };
-        
-        let mailbox = match service.get_agent_mailbox(agent_id.clone()).await {
-            Ok(m) => m,
-            Err(_) => return Html(templates::error_page("Failed to get mailbox")),
-        };
-        
-        let mail = match service.get_mailbox_inbox(mailbox.id).await {
-            Ok(m) => m,
-            Err(_) => vec![],
-        };
-        
-        (mail, agent.name)
+/// One inbox card with read, priority and label badges and folder actions
+/// A mail's structured payload as pretty-printed JSON, or nothing
+fn payload_block(m: &Mail) -> String {
+    let Some(ref payload) = m.payload else {
+        return String::new();
+    };
+    let json = serde_json::to_string_pretty(payload).unwrap_or_default();
+    format!(
+        r#"<details class="mail-payload" open><summary>Payload <span class="badge badge-secondary">{}</span></summary><pre>{}</pre></details>"#,
+        html_escape(m.content_type.as_deref().unwrap_or("application/json")),
+        html_escape(&json)
+    )
+}
+
+fn inbox_mail_card(agent_id: &str, m: &Mail, attachments: Option<&Vec<Attachment>>) -> String {
+    let status_class = if m.read { "read" } else { "unread" };
+    let mail_id_short = &m.id.to_string()[..8];
+    let number = m.seq.map(|n| format!("#{} • ", n)).unwrap_or_default();
+    
+    let mark_read_button = if !m.read {
+        format!(
+            r##"<button class="btn btn-sm btn-secondary" hx-post="/mail/inbox/{}/{}/read" hx-target="#mail-{}" hx-swap="outerHTML">Mark as Read</button>"##,
+            agent_id, m.id, mail_id_short
+        )
+    } else {
+        String::new()
+    };
+    
+    // Filing buttons remove the card from the current view
+    let move_buttons: String = [Folder::Inbox, Folder::Archive, Folder::Trash]
+        .iter()
+        .filter(|f| **f != m.folder)
+        .map(|f| {
+            let label = match f {
+                Folder::Inbox => "Move to Inbox",
+                Folder::Archive => "Archive",
+                Folder::Trash => "Trash",
+            };
+            format!(
+                r##"<button class="btn btn-sm btn-secondary" hx-post="/mail/inbox/{}/{}/move/{}" hx-target="#mail-{}" hx-swap="outerHTML">{}</button>"##,
+                agent_id, m.id, f.as_str(), mail_id_short, label
+            )
+        })
+        .collect();
+    
+    let read_badge = if m.read { 
+        r#"<span class="badge badge-secondary">Read</span>"# 
+    } else { 
+        r#"<span class="badge badge-success">Unread</span>"# 
+    };
+    
+    let priority_badge = match m.priority {
+        Priority::Urgent => r#"<span class="badge badge-error">Urgent</span>"#,
+        Priority::Low => r#"<span class="badge badge-secondary">Low</span>"#,
+        Priority::Normal => "",
+    };
+    
+    let labels_html = if m.labels.is_empty() {
+        String::new()
     } else {
-        let storage = InMemoryStorage::new();
-        let service = MailServiceImpl::new(storage);
+        let badges: Vec<String> = m.labels.iter()
+            .map(|l| format!(r#"<a href="/mail/inbox/{}?label={}" class="tag-badge">#{}</a>"#, agent_id, l, l))
+            .collect();
+        format!(r#"<div class="note-tags">{}</div>"#, badges.join(""))
+    };
+    
+    format!(
+        r##"<div id="mail-{}" class="mail-card {}">
+            <div class="mail-header">
+                <span class="mail-subject">{}</span>
+                <span class="mail-meta">{}{} {}{}</span>
+            </div>
+            {}
+            <div class="mail-body">{}</div>
+            {}{}
+            <div class="mail-actions">{}{}<a href="/mail/thread/{}" class="btn btn-sm btn-secondary">Conversation</a></div>
+        </div>"##,
+        mail_id_short, status_class, m.subject, number, m.created_at.format("%Y-%m-%d %H:%M"), 
+        priority_badge, read_badge, labels_html, m.body, payload_block(m), attachment_links(attachments),
+        mark_read_button, move_buttons, m.id
+    )
+}
+
+// Inbox view - Show mail for an agent, filtered by folder, label and priority
+async fn inbox_view(database_url: Option<String>, agent_id: String, params: HashMap<String, String>) -> Html<String> {
+    let folder: Folder = params.get("folder").and_then(|f| f.parse().ok()).unwrap_or_default();
+    let label = params.get("label").filter(|l| !l.is_empty()).cloned();
+    let priority: Option<Priority> = params.get("priority").and_then(|p| p.parse().ok());
+    let filter = InboxFilter { folder: Some(folder), label: label.clone(), priority };
+    let query = params.get("q").map(|q| q.trim().to_string()).filter(|q| !q.is_empty());
+    
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html(templates::error_page("Failed to connect to database")),
+    };
+    let (inbox_mail, attachments, agent_name, search_error) = {
+        let service = mail_service(storage);
         
         let agent = match service.get_agent(agent_id.clone()).await {
             Ok(a) => a,
@@ -977,54 +1192,35 @@ async fn inbox_view(database_url: Option<String>, agent_id: String) -> Html<Stri
             Err(_) => return Html(templates::error_page("Failed to get mailbox")),
         };
         
-        let mail = match service.get_mailbox_inbox(mailbox.id).await {
-            Ok(m) => m,
-            Err(_) => vec![],
+        // A search looks through every folder, using the same syntax as
+        // `mail search`; only received mail is listed here
+        let found = match query {
+            Some(ref q) => match MailSearch::parse(q, &chrono::Local) {
+                Ok(search) => service.search_mail(agent_id.clone(), &search, 100).await
+                    .map(|mail| mail.into_iter().filter(|m| m.recipients().any(|(id, _)| id == mailbox.id)).collect())
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            },
+            None => Ok(service.get_mailbox_mail(mailbox.id, &filter).await.unwrap_or_default()),
+        };
+        let (mail, search_error) = match found {
+            Ok(mail) => (mail, None),
+            Err(e) => (Vec::new(), Some(e)),
         };
+        let attachments = attachments_by_mail(&service, &mail).await;
         
-        (mail, agent.name)
+        (mail, attachments, agent.name, search_error)
     };
     
     // Count unread messages
     let unread_count = inbox_mail.iter().filter(|m| !m.read).count();
     
     let mail_html = inbox_mail.iter()
-        .map(|m| {
-            let status_class = if m.read { "read" } else { "unread" };
-            let mail_id_short = &m.id.to_string()[..8];
-            
-            let mark_read_button = if !m.read {
-                format!(
-                    r##"<button class="btn btn-sm btn-secondary" hx-post="/mail/{}/read" hx-target="#mail-{}" hx-swap="outerHTML">Mark as Read</button>"##,
-                    m.id, mail_id_short
-                )
-            } else {
-                String::new()
-            };
-            
-            let read_badge = if m.read { 
-                r#"<span class="badge badge-secondary">Read</span>"# 
-            } else { 
-                r#"<span class="badge badge-success">Unread</span>"# 
-            };
-            
-            format!(
-                r##"<div id="mail-{}" class="mail-card {}">
-                    <div class="mail-header">
-                        <span class="mail-subject">{}</span>
-                        <span class="mail-meta">{} {}</span>
-                    </div>
-                    <div class="mail-body">{}</div>
-                    <div class="mail-actions">{}</div>
-                </div>"##,
-                mail_id_short, status_class, m.subject, m.created_at.format("%Y-%m-%d %H:%M"), 
-                read_badge, m.body, mark_read_button
-            )
-        })
+        .map(|m| inbox_mail_card(&agent_id, m, attachments.get(&m.id)))
         .collect::<String>();
     
-    // Mark All as Read button (only show if there are unread messages)
-    let mark_all_button = if unread_count > 0 {
+    // Mark All as Read button (only show in the unfiltered inbox with unread messages)
+    let mark_all_button = if unread_count > 0 && folder == Folder::Inbox && label.is_none() && priority.is_none() && query.is_none() {
         format!(
             r##"<button class="btn btn-sm btn-success" hx-post="/mail/inbox/{}/read-all" hx-target="#mail-list" hx-swap="innerHTML">✓ Mark All as Read ({} unread)</button>"##,
             agent_id, unread_count
@@ -1033,6 +1229,23 @@ async fn inbox_view(database_url: Option<String>, agent_id: String) -> Html<Stri
         String::new()
     };
     
+    let folder_links: String = [Folder::Inbox, Folder::Archive, Folder::Trash]
+        .iter()
+        .map(|f| {
+            let class = if *f == folder { "btn btn-sm btn-primary" } else { "btn btn-sm btn-secondary" };
+            format!(r#"<a href="/mail/inbox/{}?folder={}" class="{}">{}</a>"#, agent_id, f.as_str(), class, f.as_str())
+        })
+        .collect();
+    
+    let priority_options: String = [None, Some(Priority::Urgent), Some(Priority::Normal), Some(Priority::Low)]
+        .iter()
+        .map(|p| {
+            let value = p.map(|p| p.as_str()).unwrap_or("");
+            let selected = if *p == priority { " selected" } else { "" };
+            format!(r#"<option value="{}"{}>{}</option>"#, value, selected, p.map(|p| p.as_str()).unwrap_or("any priority"))
+        })
+        .collect();
+    
     let content = format!(
         r##"
         <div class="back-link">
@@ -1042,6 +1255,17 @@ async fn inbox_view(database_url: Option<String>, agent_id: String) -> Html<Stri
             <h2>Inbox: {} <span class="section-count">{} messages</span></h2>
             {}
         </div>
+        <form class="mail-filters" method="get" action="/mail/inbox/{}">
+            <input type="search" name="q" value="{}" placeholder="from:bob subject:deploy is:unread label:ci &quot;exact phrase&quot;">
+            <button type="submit" class="btn btn-sm btn-primary">Search</button>
+        </form>
+        <form class="mail-filters" method="get" action="/mail/inbox/{}">
+            {}
+            <input type="hidden" name="folder" value="{}">
+            <input type="text" name="label" value="{}" placeholder="label">
+            <select name="priority">{}</select>
+            <button type="submit" class="btn btn-sm btn-secondary">Filter</button>
+        </form>
         <div id="mail-list" class="mail-list">
             {}
         </div>
@@ -1049,8 +1273,19 @@ async fn inbox_view(database_url: Option<String>, agent_id: String) -> Html<Stri
         agent_name,
         inbox_mail.len(),
         mark_all_button,
-        if mail_html.is_empty() {
-            "<p class='empty-state'>No mail in inbox</p>".to_string()
+        agent_id,
+        html_escape(query.as_deref().unwrap_or_default()),
+        agent_id,
+        folder_links,
+        folder.as_str(),
+        label.unwrap_or_default(),
+        priority_options,
+        if let Some(error) = search_error {
+            format!("<div class='error'>{}</div>", html_escape(&error))
+        } else if mail_html.is_empty() && query.is_some() {
+            "<p class='empty-state'>No mail matches your search</p>".to_string()
+        } else if mail_html.is_empty() {
+            format!("<p class='empty-state'>No mail in {}</p>", folder.as_str())
         } else {
             mail_html
         }
@@ -1059,35 +1294,40 @@ async fn inbox_view(database_url: Option<String>, agent_id: String) -> Html<Stri
     Html(templates::wrap_content(content))
 }
 
+// Download an attachment under its original file name
+async fn download_attachment(database_url: Option<String>, attachment_id: String) -> Response {
+    let Ok(attachment_id) = uuid::Uuid::parse_str(&attachment_id) else {
+        return (StatusCode::BAD_REQUEST, Html(templates::error_page(&format!("Invalid attachment ID '{}'", attachment_id)))).into_response();
+    };
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, Html(templates::error_page("Failed to connect to database"))).into_response(),
+    };
+    
+    match mail_service(storage).read_attachment(attachment_id).await {
+        Ok((attachment, data)) => {
+            // Plain ASCII fallback name for old clients, the real one in filename*
+            let fallback: String = attachment.filename.chars()
+                .map(|c| if c.is_ascii_graphic() && c != '"' && c != '\\' { c } else { '_' })
+                .collect();
+            let disposition = format!(
+                "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+                fallback, urlencoding::encode(&attachment.filename)
+            );
+            ([(header::CONTENT_TYPE, attachment.content_type), (header::CONTENT_DISPOSITION, disposition)], data).into_response()
+        }
+        Err(_) => (StatusCode::NOT_FOUND, Html(templates::error_page("Attachment not found"))).into_response(),
+    }
+}
+
 // Outbox view - Show sent messages for an agent
 async fn outbox_view(database_url: Option<String>, agent_id: String) -> Html<String> {
-    let (outbox_mail, agent_name) = if let Some(url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html(templates::error_page("Failed to connect to database")),
-        };
-        let storage = PostgresStorage::new(pool);
-        let service = MailServiceImpl::new(storage);
-        
-        let agent = match service.get_agent(agent_id.clone()).await {
-            Ok(a) => a,
-            Err(_) => return Html(templates::error_page(&format!("Agent '{}' not found", agent_id))),
-        };
-        
-        let mailbox = match service.get_agent_mailbox(agent_id.clone()).await {
-            Ok(m) => m,
-            Err(_) => return Html(templates::error_page("Failed to get mailbox")),
-        };
-        
-        let mail = match service.get_mailbox_outbox(mailbox.id).await {
-            Ok(m) => m,
-            Err(_) => vec![],
-        };
-        
-        (mail, agent.name)
-    } else {
-        let storage = InMemoryStorage::new();
-        let service = MailServiceImpl::new(storage);
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html(templates::error_page("Failed to connect to database")),
+    };
+    let (outbox_mail, agent_name, names, attachments) = {
+        let service = mail_service(storage);
         
         let agent = match service.get_agent(agent_id.clone()).await {
             Ok(a) => a,
@@ -1099,25 +1339,60 @@ async fn outbox_view(database_url: Option<String>, agent_id: String) -> Html<Str
             Err(_) => return Html(templates::error_page("Failed to get mailbox")),
         };
         
-        let mail = match service.get_mailbox_outbox(mailbox.id).await {
-            Ok(m) => m,
-            Err(_) => vec![],
-        };
+        let mail = service.get_mailbox_outbox(mailbox.id).await.unwrap_or_default();
+        let attachments = attachments_by_mail(&service, &mail).await;
+        let mut outbox = Vec::with_capacity(mail.len());
+        for m in mail {
+            let deliveries = service.get_deliveries(m.id).await.unwrap_or_default();
+            outbox.push((m, deliveries));
+        }
+        let names = mailbox_names(&service).await;
         
-        (mail, agent.name)
+        (outbox, agent.name, names, attachments)
     };
     
+    let now = chrono::Utc::now();
     let mail_html = outbox_mail.iter()
-        .map(|m| {
+        .map(|(m, deliveries)| {
+            // One receipt per recipient: delivered / read at / by
+            let receipts: String = deliveries.iter()
+                .map(|d| {
+                    let name = names.get(&d.mailbox_id).map(String::as_str).unwrap_or("Unknown");
+                    let badge = if d.is_pending(now) {
+                        r#"<span class="badge badge-warning">Scheduled</span>"#
+                    } else if d.read {
+                        r#"<span class="badge badge-success">Read</span>"#
+                    } else {
+                        r#"<span class="badge badge-secondary">Unread</span>"#
+                    };
+                    format!(
+                        r#"<li><strong>{}</strong>{} <span class="mail-meta">{}</span></li>"#,
+                        name, badge, d.status_line()
+                    )
+                })
+                .collect();
+            // Delayed mail can still be withdrawn until it goes out
+            let cancel = if m.is_pending(now) {
+                format!(
+                    r##"<button class="btn btn-sm btn-secondary" hx-post="/mail/outbox/{}/{}/cancel" hx-target="#mail-{}" hx-swap="outerHTML" hx-confirm="Cancel this mail?">Cancel</button>"##,
+                    agent_id, m.id, m.id
+                )
+            } else {
+                String::new()
+            };
             format!(
-                r#"<div class="mail-card sent">
+                r#"<div class="mail-card sent" id="mail-{}">
                     <div class="mail-header">
                         <span class="mail-subject">{}</span>
-                        <span class="mail-meta">To: {} • {}</span>
+                        <span class="mail-meta">{}{} • {}</span>
                     </div>
                     <div class="mail-body">{}</div>
+                    {}{}
+                    <ul class="mail-receipts">{}</ul>
+                    <div class="mail-actions"><a href="/mail/thread/{}" class="btn btn-sm btn-secondary">Conversation</a>{}</div>
                 </div>"#,
-                m.subject, m.to_mailbox_id, m.created_at.format("%Y-%m-%d %H:%M"), m.body
+                m.id, m.subject, m.seq.map(|n| format!("#{} • ", n)).unwrap_or_default(), recipient_line(m, &names), m.created_at.format("%Y-%m-%d %H:%M"), m.body,
+                payload_block(m), attachment_links(attachments.get(&m.id)), receipts, m.id, cancel
             )
         })
         .collect::<String>();
@@ -1144,6 +1419,83 @@ async fn outbox_view(database_url: Option<String>, agent_id: String) -> Html<Str
     Html(templates::wrap_content(content))
 }
 
+// Thread view - Every message of a conversation, oldest first. Short IDs
+// are looked up in the mailbox of the `agent` query parameter.
+async fn thread_view(database_url: Option<String>, mail_id: String, agent: Option<String>) -> Html<String> {
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html(templates::error_page("Failed to connect to database")),
+    };
+    let (thread, names, attachments) = {
+        let service = mail_service(storage);
+        
+        // Full ids, or a mail number such as alice#42 (alice%2342 in the URL)
+        let mail_id = match service.resolve_mail(&mail_id, agent).await {
+            Ok(mail) => mail.id,
+            Err(_) => return Html(templates::error_page(&format!("Invalid mail ID '{}'", html_escape(&mail_id)))),
+        };
+        let thread = match service.get_thread(mail_id).await {
+            Ok(t) => t,
+            Err(_) => return Html(templates::error_page("Mail not found")),
+        };
+        
+        let names = mailbox_names(&service).await;
+        let attachments = attachments_by_mail(&service, &thread).await;
+        
+        (thread, names, attachments)
+    };
+    
+    let subject = thread.first().map(|m| m.subject.clone()).unwrap_or_default();
+    
+    let mail_html = thread.iter()
+        .map(|m| {
+            let status_class = if m.read { "read" } else { "unread" };
+            let reply_note = match m.in_reply_to {
+                Some(parent_id) => format!(" • reply to [{}]", &parent_id.to_string()[..8]),
+                None => String::new(),
+            };
+            
+            format!(
+                r#"<div id="mail-{}" class="mail-card {}">
+                    <div class="mail-header">
+                        <span class="mail-subject">{} &rarr; {}</span>
+                        <span class="mail-meta">[{}] {}{}</span>
+                    </div>
+                    <div class="mail-body">{}</div>
+                    {}{}
+                </div>"#,
+                &m.id.to_string()[..8],
+                status_class,
+                join_names(&[m.from_mailbox_id], &names),
+                recipient_line(m, &names),
+                &m.id.to_string()[..8],
+                m.created_at.format("%Y-%m-%d %H:%M"),
+                reply_note,
+                render_markdown(&m.body),
+                payload_block(m),
+                attachment_links(attachments.get(&m.id))
+            )
+        })
+        .collect::<String>();
+    
+    let content = format!(
+        r#"
+        <div class="back-link">
+            <a href="/" class="btn btn-secondary btn-sm">&larr; Back to Dashboard</a>
+        </div>
+        <h2>{} <span class="section-count">{} messages</span></h2>
+        <div class="mail-list">
+            {}
+        </div>
+        "#,
+        subject,
+        thread.len(),
+        mail_html
+    );
+    
+    Html(templates::wrap_content(content))
+}
+
 // Send mail to agent from human
 async fn send_mail(database_url: Option<String>, body: axum::body::Bytes) -> Html<String> {
     // Parse form data from body
@@ -1183,11 +1535,10 @@ async fn send_mail(database_url: Option<String>, body: axum::body::Bytes) -> Htm
     let from_human = params.get("from").cloned().unwrap_or_default();
     let subject = params.get("subject").cloned().unwrap_or_default();
     let body_text = params.get("body").cloned().unwrap_or_default();
+    let priority: Priority = params.get("priority").and_then(|p| p.parse().ok()).unwrap_or_default();
     
     if to_agent.is_empty() || body_text.is_empty() {
-        return Html(format!(
-            r#"<div class="send-result error">Error: To and body are required</div>"#
-        ));
+        return Html(r#"<div class="send-result error">Error: To and body are required</div>"#.to_string());
     }
     
     // Use provided subject or default to "Message from {sender}"
@@ -1197,29 +1548,26 @@ async fn send_mail(database_url: Option<String>, body: axum::body::Bytes) -> Htm
         subject
     };
     
-    let result = if let Some(url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html(templates::error_page("Failed to connect to database")),
-        };
-        let storage = PostgresStorage::new(pool);
-        let service = MailServiceImpl::new(storage);
-        
-        service.send_agent_to_agent(
-            from_human.clone(),
-            to_agent.clone(),
-            subject,
-            body_text.clone(),
-        ).await
-    } else {
-        let storage = InMemoryStorage::new();
-        let service = MailServiceImpl::new(storage);
-        
-        service.send_agent_to_agent(
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html(templates::error_page("Failed to connect to database")),
+    };
+    let result = {
+        let service = mail_service(storage);
+        
+        // Several recipients and @group addresses, as on the command line
+        let recipients: Vec<String> = to_agent
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        service.send_mail_with(
             from_human.clone(),
-            to_agent.clone(),
+            recipients,
+            Vec::new(),
             subject,
             body_text.clone(),
+            SendOptions { priority, ..SendOptions::default() },
         ).await
     };
     
@@ -1228,37 +1576,24 @@ async fn send_mail(database_url: Option<String>, body: axum::body::Bytes) -> Htm
             r#"<div class="send-result success">✓ Message sent to {}</div>"#,
             to_agent
         )),
-        Err(_) => Html(format!(
-            r#"<div class="send-result error">✗ Failed to send message</div>"#
-        )),
+        Err(_) => Html(r#"<div class="send-result error">✗ Failed to send message</div>"#.to_string()),
     }
 }
 
 // Mark a single mail as read
-async fn mark_mail_read(database_url: Option<String>, mail_id: String) -> Html<String> {
-    let result = if let Some(url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html("<div class='error'>Database connection failed</div>".to_string()),
-        };
-        let storage = PostgresStorage::new(pool);
-        let service = MailServiceImpl::new(storage);
-        
-        // Try to parse as UUID first
-        if let Ok(id) = uuid::Uuid::parse_str(&mail_id) {
-            service.mark_mail_as_read(id).await
-        } else {
-            // Try as short ID
-            service.mark_mail_as_read_by_short_id(&mail_id).await
-        }
-    } else {
-        let storage = InMemoryStorage::new();
-        let service = MailServiceImpl::new(storage);
+async fn mark_mail_read(database_url: Option<String>, agent_id: String, mail_id: String) -> Html<String> {
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html("<div class='error'>Database connection failed</div>".to_string()),
+    };
+    let result = {
+        let service = mail_service(storage);
         
+        // Full id, or a number or id prefix within the agent's mailbox
         if let Ok(id) = uuid::Uuid::parse_str(&mail_id) {
-            service.mark_mail_as_read(id).await
+            service.mark_mail_as_read(id, Some(agent_id), Some("web".to_string())).await
         } else {
-            service.mark_mail_as_read_by_short_id(&mail_id).await
+            service.mark_mail_as_read_by_short_id(&mail_id, Some(agent_id), Some("web".to_string())).await
         }
     };
     
@@ -1268,15 +1603,53 @@ async fn mark_mail_read(database_url: Option<String>, mail_id: String) -> Html<S
     }
 }
 
+// File a mail in another folder; the card leaves the current view
+async fn move_mail(database_url: Option<String>, agent_id: String, mail_id: String, folder: String) -> Html<String> {
+    let Ok(folder) = folder.parse::<Folder>() else {
+        return Html(r#"<span class="badge badge-error">✗ Failed</span>"#.to_string());
+    };
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html("<div class='error'>Database connection failed</div>".to_string()),
+    };
+    let service = mail_service(storage);
+    
+    let Ok(mail) = service.resolve_mail(&mail_id, Some(agent_id.clone())).await else {
+        return Html(r#"<span class="badge badge-error">✗ Failed</span>"#.to_string());
+    };
+    match service.move_mail(mail.id, Some(agent_id), folder).await {
+        Ok(_) => Html(String::new()),
+        Err(_) => Html(r#"<span class="badge badge-error">✗ Failed</span>"#.to_string()),
+    }
+}
+
+// Withdraw delayed mail from the outbox; the card disappears
+async fn cancel_mail(database_url: Option<String>, agent_id: String, mail_id: String) -> Html<String> {
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html("<div class='error'>Database connection failed</div>".to_string()),
+    };
+    let service = mail_service(storage);
+    
+    // Only the sender gets to cancel
+    let mail_id = match service.resolve_mail(&mail_id, Some(agent_id.clone())).await {
+        Ok(mail) => mail.id,
+        Err(_) => return Html(r#"<span class="badge badge-error">✗ Failed</span>"#.to_string()),
+    };
+    match service.cancel_mail(mail_id, agent_id).await {
+        Ok(_) => Html(String::new()),
+        Err(_) => Html(r#"<span class="badge badge-error">✗ Failed</span>"#.to_string()),
+    }
+}
+
 // Mark all mail in inbox as read
 async fn mark_all_mail_read(database_url: Option<String>, agent_id: String) -> Html<String> {
-    let result = if let Some(url) = database_url.clone() {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html("<div class='error'>Database connection failed</div>".to_string()),
-        };
-        let storage = PostgresStorage::new(pool);
-        let service = MailServiceImpl::new(storage);
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html("<div class='error'>Database connection failed</div>".to_string()),
+    };
+    let result = {
+        let service = mail_service(storage);
         
         // Get mailbox and mark all unread mail as read
         match service.get_agent_mailbox(agent_id.clone()).await {
@@ -1285,33 +1658,8 @@ async fn mark_all_mail_read(database_url: Option<String>, agent_id: String) -> H
                     Ok(mail) => {
                         let mut marked_count = 0;
                         for m in mail {
-                            if !m.read {
-                                if let Ok(_) = service.mark_mail_as_read(m.id).await {
-                                    marked_count += 1;
-                                }
-                            }
-                        }
-                        Ok(marked_count)
-                    }
-                    Err(_) => Err(()),
-                }
-            }
-            Err(_) => Err(()),
-        }
-    } else {
-        let storage = InMemoryStorage::new();
-        let service = MailServiceImpl::new(storage);
-        
-        match service.get_agent_mailbox(agent_id.clone()).await {
-            Ok(mailbox) => {
-                match service.get_mailbox_inbox(mailbox.id).await {
-                    Ok(mail) => {
-                        let mut marked_count = 0;
-                        for m in mail {
-                            if !m.read {
-                                if let Ok(_) = service.mark_mail_as_read(m.id).await {
-                                    marked_count += 1;
-                                }
+                            if !m.read && service.mark_mail_as_read(m.id, Some(agent_id.clone()), Some("web".to_string())).await.is_ok() {
+                                marked_count += 1;
                             }
                         }
                         Ok(marked_count)
@@ -1334,71 +1682,25 @@ async fn mark_all_mail_read(database_url: Option<String>, agent_id: String) -> H
 
 // Helper function to return just the mail list HTML (for HTMX updates)
 async fn inbox_mail_list(database_url: Option<String>, agent_id: String) -> Html<String> {
-    let inbox_mail = if let Some(url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html("<div class='error'>Database connection failed</div>".to_string()),
-        };
-        let storage = PostgresStorage::new(pool);
-        let service = MailServiceImpl::new(storage);
-        
-        match service.get_agent_mailbox(agent_id.clone()).await {
-            Ok(mailbox) => {
-                match service.get_mailbox_inbox(mailbox.id).await {
-                    Ok(mail) => mail,
-                    Err(_) => vec![],
-                }
-            }
-            Err(_) => vec![],
-        }
-    } else {
-        let storage = InMemoryStorage::new();
-        let service = MailServiceImpl::new(storage);
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html("<div class='error'>Database connection failed</div>".to_string()),
+    };
+    let (inbox_mail, attachments) = {
+        let service = mail_service(storage);
         
-        match service.get_agent_mailbox(agent_id.clone()).await {
+        let mail = match service.get_agent_mailbox(agent_id.clone()).await {
             Ok(mailbox) => {
-                match service.get_mailbox_inbox(mailbox.id).await {
-                    Ok(mail) => mail,
-                    Err(_) => vec![],
-                }
+                service.get_mailbox_inbox(mailbox.id).await.unwrap_or_default()
             }
             Err(_) => vec![],
-        }
+        };
+        let attachments = attachments_by_mail(&service, &mail).await;
+        (mail, attachments)
     };
     
     let mail_html = inbox_mail.iter()
-        .map(|m| {
-            let status_class = if m.read { "read" } else { "unread" };
-            let mail_id_short = &m.id.to_string()[..8];
-            
-            let mark_read_button = if !m.read {
-                format!(
-                    r##"<button class="btn btn-sm btn-secondary" hx-post="/mail/{}/read" hx-target="#mail-{}" hx-swap="outerHTML">Mark as Read</button>"##,
-                    m.id, mail_id_short
-                )
-            } else {
-                String::new()
-            };
-            
-            let read_badge = if m.read { 
-                r#"<span class="badge badge-secondary">Read</span>"# 
-            } else { 
-                r#"<span class="badge badge-success">Unread</span>"# 
-            };
-            
-            format!(
-                r##"<div id="mail-{}" class="mail-card {}">
-                    <div class="mail-header">
-                        <span class="mail-subject">{}</span>
-                        <span class="mail-meta">{} {}</span>
-                    </div>
-                    <div class="mail-body">{}</div>
-                    <div class="mail-actions">{}</div>
-                </div>"##,
-                mail_id_short, status_class, m.subject, m.created_at.format("%Y-%m-%d %H:%M"), 
-                read_badge, m.body, mark_read_button
-            )
-        })
+        .map(|m| inbox_mail_card(&agent_id, m, attachments.get(&m.id)))
         .collect::<String>();
     
     Html(if mail_html.is_empty() {
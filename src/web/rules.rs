@@ -0,0 +1,187 @@
+use axum::response::Html;
+
+use crate::services::mail::MailService;
+use crate::services::mail::domain::{Folder, MailRule};
+use crate::storage::AnyStorage;
+use crate::web::templates;
+
+use super::mail_service;
+use super::schedules::{html_escape, urldecode};
+
+// View an agent's mail rules, with a form to add one
+pub async fn agent_rules_view(database_url: Option<String>, agent_id: String) -> Html<String> {
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html(templates::error_page("Failed to connect to database")),
+    };
+    let service = mail_service(storage);
+
+    let agent = match service.get_agent(agent_id.clone()).await {
+        Ok(a) => a,
+        Err(_) => return Html(templates::error_page(&format!("Agent '{}' not found", agent_id))),
+    };
+    let rules = service.list_mail_rules(agent_id.clone()).await.unwrap_or_default();
+
+    let mut content = String::new();
+    content.push_str("<div class=\"back-link\">");
+    content.push_str("<a href=\"/\" class=\"btn btn-secondary btn-sm\">&larr; Back to Dashboard</a>");
+    content.push_str("</div>");
+    content.push_str(&format!(
+        "<h2>Mail rules for {} <span class=\"section-count\">{} total</span></h2>",
+        html_escape(&agent.name),
+        rules.len()
+    ));
+    content.push_str("<p><small>Rules run in order as mail is delivered. Every condition given must match.</small></p>");
+    content.push_str("<div id=\"rules-list\" class=\"schedules-container\">");
+    content.push_str(&rules_list_html(&rules));
+    content.push_str("</div>");
+
+    content.push_str("<h3>Add Rule</h3>");
+    content.push_str(&format!(
+        "<form class=\"schedule-form\" hx-post=\"/agents/{}/rules\" hx-target=\"#rules-list\" hx-swap=\"innerHTML\">",
+        html_escape(&agent_id)
+    ));
+    content.push_str("<div class=\"form-group\"><label>Name</label><input type=\"text\" name=\"name\" placeholder=\"ci-failures\" required></div>");
+    content.push_str("<div class=\"form-group\"><label>From agent</label><input type=\"text\" name=\"from\" placeholder=\"ci_bot (any sender if empty)\"></div>");
+    content.push_str("<div class=\"form-group\"><label>Subject contains</label><input type=\"text\" name=\"subject_contains\" placeholder=\"FAILED\"></div>");
+    content.push_str("<div class=\"form-group\"><label>Body contains</label><input type=\"text\" name=\"body_contains\"></div>");
+    content.push_str("<div class=\"form-group\"><label>Add labels</label><input type=\"text\" name=\"labels\" placeholder=\"ci, build (comma separated)\"></div>");
+    content.push_str("<div class=\"form-group\"><label>Move to</label><select name=\"move_to\">");
+    content.push_str("<option value=\"\">(leave in inbox)</option><option value=\"archive\">archive</option><option value=\"trash\">trash</option>");
+    content.push_str("</select></div>");
+    content.push_str("<div class=\"form-group\"><label><input type=\"checkbox\" name=\"mark_read\"> Mark read</label></div>");
+    content.push_str("<div class=\"form-group\"><label>Forward to</label><input type=\"text\" name=\"forward_to\" placeholder=\"triage (comma separated)\"></div>");
+    content.push_str("<button type=\"submit\" class=\"btn btn-success\">Add Rule</button>");
+    content.push_str("</form>");
+
+    Html(templates::wrap_content(content))
+}
+
+fn rules_list_html(rules: &[MailRule]) -> String {
+    if rules.is_empty() {
+        return "<p class=\"empty-state\">No rules yet. Add one below.</p>".to_string();
+    }
+
+    let mut html = String::new();
+    for rule in rules {
+        let status_badge = if rule.enabled {
+            "<span class=\"badge badge-success\">Enabled</span>"
+        } else {
+            "<span class=\"badge badge-secondary\">Disabled</span>"
+        };
+        html.push_str("<div class=\"schedule-card\">");
+        html.push_str("<div class=\"schedule-card-header\">");
+        html.push_str(&format!(
+            "<div class=\"schedule-title\">{} {}</div>",
+            html_escape(&rule.name),
+            status_badge
+        ));
+        html.push_str("<div class=\"schedule-actions\">");
+        html.push_str(&format!(
+            "<button class=\"btn btn-sm btn-secondary\" hx-post=\"/rules/{}/toggle\" hx-target=\"#rules-list\" hx-swap=\"innerHTML\">{}</button>",
+            rule.id,
+            if rule.enabled { "Disable" } else { "Enable" }
+        ));
+        html.push_str(&format!(
+            "<button class=\"btn btn-sm btn-danger\" hx-post=\"/rules/{}/delete\" hx-confirm=\"Delete this rule?\" hx-target=\"#rules-list\" hx-swap=\"innerHTML\">Delete</button>",
+            rule.id
+        ));
+        html.push_str("</div>");
+        html.push_str("</div>");
+        html.push_str(&format!(
+            "<div class=\"schedule-body\"><div class=\"schedule-detail\"><strong>When:</strong> {}<br><strong>Then:</strong> {}</div><div class=\"schedule-meta\">ID: {}</div></div>",
+            html_escape(&rule.conditions_label()),
+            html_escape(&rule.actions_label()),
+            &rule.id.to_string()[..8]
+        ));
+        html.push_str("</div>");
+    }
+    html
+}
+
+// Rules list fragment after a change, or the error that stopped it
+async fn rules_fragment(service: &impl MailService, agent_id: String, error: Option<String>) -> Html<String> {
+    let rules = service.list_mail_rules(agent_id).await.unwrap_or_default();
+    let mut html = String::new();
+    if let Some(error) = error {
+        html.push_str(&format!("<div class=\"error\">{}</div>", html_escape(&error)));
+    }
+    html.push_str(&rules_list_html(&rules));
+    Html(html)
+}
+
+// Create a rule via the web form
+pub async fn create_rule(database_url: Option<String>, agent_id: String, body: axum::body::Bytes) -> Html<String> {
+    let body_str = String::from_utf8_lossy(&body);
+    let params: std::collections::HashMap<String, String> = body_str
+        .split('&')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next()?.to_string();
+            let value = urldecode(parts.next().unwrap_or(""));
+            Some((key, value))
+        })
+        .collect();
+
+    let text = |key: &str| params.get(key).map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    let list = |key: &str| -> Vec<String> {
+        params.get(key)
+            .map(|v| v.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default()
+    };
+    let rule = MailRule {
+        from: text("from"),
+        subject_contains: text("subject_contains"),
+        body_contains: text("body_contains"),
+        add_labels: list("labels"),
+        move_to: text("move_to").and_then(|f| f.parse::<Folder>().ok()),
+        mark_read: params.contains_key("mark_read"),
+        forward_to: list("forward_to"),
+        ..MailRule::new(agent_id.clone(), text("name").unwrap_or_default())
+    };
+
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html("<div class=\"error\">Failed to connect to database</div>".to_string()),
+    };
+    let service = mail_service(storage);
+
+    let error = service.add_mail_rule(rule).await.err().map(|e| format!("Failed to add rule: {}", e));
+    rules_fragment(&service, agent_id, error).await
+}
+
+// Turn a rule on or off
+pub async fn toggle_rule(database_url: Option<String>, rule_id: String) -> Html<String> {
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html("<div class=\"error\">Failed to connect to database</div>".to_string()),
+    };
+    let service = mail_service(storage);
+
+    let rule = match service.get_mail_rule_by_short_id(&rule_id).await {
+        Ok(r) => r,
+        Err(_) => return Html("<div class=\"error\">Rule not found</div>".to_string()),
+    };
+    let agent_id = rule.agent_id.clone();
+    let enabled = !rule.enabled;
+    let error = service.update_mail_rule(MailRule { enabled, ..rule }).await.err()
+        .map(|e| format!("Failed to update rule: {}", e));
+    rules_fragment(&service, agent_id, error).await
+}
+
+// Delete a rule
+pub async fn delete_rule(database_url: Option<String>, rule_id: String) -> Html<String> {
+    let storage = match AnyStorage::connect(database_url.as_deref()).await {
+        Ok(s) => s,
+        Err(_) => return Html("<div class=\"error\">Failed to connect to database</div>".to_string()),
+    };
+    let service = mail_service(storage);
+
+    let rule = match service.get_mail_rule_by_short_id(&rule_id).await {
+        Ok(r) => r,
+        Err(_) => return Html("<div class=\"error\">Rule not found</div>".to_string()),
+    };
+    let error = service.remove_mail_rule(rule.id).await.err()
+        .map(|e| format!("Failed to delete rule: {}", e));
+    rules_fragment(&service, rule.agent_id, error).await
+}
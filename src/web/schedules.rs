@@ -2,28 +2,24 @@ use axum::response::Html;
 
 use crate::services::mail::{MailService, MailServiceImpl};
 use crate::services::schedule::{ScheduleService, ScheduleServiceImpl};
-use crate::storage::postgres::PostgresStorage;
+use crate::storage::AnyStorage;
 use crate::web::templates;
 
 // View agent schedules
-pub async fn agent_schedule_view(database_url: Option<String>, agent_id: String) -> Html<String> {
-    let (agent, schedules, schedule_service_opt) = if let Some(ref url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(url).await {
-            Ok(p) => p,
+pub async fn agent_schedule_view(database_url: Option<String>, schedule_service: Option<ScheduleServiceImpl>, agent_id: String) -> Html<String> {
+    let (agent, schedules, schedule_service_opt) = if let (Some(url), Some(schedule_service)) = (database_url.as_deref(), schedule_service) {
+        let storage = match AnyStorage::connect(Some(url)).await {
+            Ok(s) => s,
             Err(_) => return Html(templates::error_page("Failed to connect to database")),
         };
-        let mail_service = MailServiceImpl::new(PostgresStorage::new(pool.clone()));
-        let schedule_service = ScheduleServiceImpl::new(pool);
+        let mail_service = MailServiceImpl::new(storage);
         
         let agent = match mail_service.get_agent(agent_id.clone()).await {
             Ok(a) => a,
             Err(_) => return Html(templates::error_page(&format!("Agent '{}' not found", agent_id))),
         };
         
-        let schedules = match schedule_service.list_schedules_by_agent(&agent_id).await {
-            Ok(s) => s,
-            Err(_) => vec![],
-        };
+        let schedules = schedule_service.list_schedules_by_agent(&agent_id).await.unwrap_or_default();
         
         (agent, schedules, Some(schedule_service))
     } else {
@@ -185,7 +181,7 @@ function toggleEditForm(id) {
 }
 
 // Create new schedule via web form
-pub async fn create_schedule(database_url: Option<String>, agent_id: String, body: axum::body::Bytes) -> Html<String> {
+pub async fn create_schedule(database_url: Option<String>, schedule_service: Option<ScheduleServiceImpl>, agent_id: String, body: axum::body::Bytes) -> Html<String> {
     let body_str = String::from_utf8_lossy(&body);
     let params: std::collections::HashMap<String, String> = body_str
         .split('&')
@@ -203,15 +199,10 @@ pub async fn create_schedule(database_url: Option<String>, agent_id: String, bod
     let cron = urldecode(&cron);
     let action = urldecode(&action);
     
-    if let Some(url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html("<div class=\"error\">Failed to connect to database</div>".to_string()),
-        };
-        let schedule_service = ScheduleServiceImpl::new(pool);
+    if let (Some(url), Some(schedule_service)) = (database_url, schedule_service) {
         
         match schedule_service.create_schedule(agent_id.clone(), cron, action).await {
-            Ok(_) => agent_schedule_view(Some(url), agent_id).await,
+            Ok(_) => agent_schedule_view(Some(url), Some(schedule_service), agent_id).await,
             Err(e) => Html(format!("<div class=\"error\">Failed to create schedule: {}</div>", e)),
         }
     } else {
@@ -220,7 +211,7 @@ pub async fn create_schedule(database_url: Option<String>, agent_id: String, bod
 }
 
 // Update schedule
-pub async fn update_schedule(database_url: Option<String>, schedule_id: String, body: axum::body::Bytes) -> Html<String> {
+pub async fn update_schedule(database_url: Option<String>, schedule_service: Option<ScheduleServiceImpl>, schedule_id: String, body: axum::body::Bytes) -> Html<String> {
     let id = match uuid::Uuid::parse_str(&schedule_id) {
         Ok(u) => u,
         Err(_) => return Html("<div class=\"error\">Invalid schedule ID</div>".to_string()),
@@ -243,12 +234,7 @@ pub async fn update_schedule(database_url: Option<String>, schedule_id: String,
     let cron = cron.map(|c| urldecode(&c));
     let action = action.map(|a| urldecode(&a));
     
-    if let Some(url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html("<div class=\"error\">Failed to connect to database</div>".to_string()),
-        };
-        let schedule_service = ScheduleServiceImpl::new(pool);
+    if let (Some(url), Some(schedule_service)) = (database_url, schedule_service) {
         
         // Get agent_id for redirect
         let agent_id = match schedule_service.get_schedule(id).await {
@@ -257,7 +243,7 @@ pub async fn update_schedule(database_url: Option<String>, schedule_id: String,
         };
         
         match schedule_service.update_schedule(id, cron, action).await {
-            Ok(_) => agent_schedule_view(Some(url), agent_id).await,
+            Ok(_) => agent_schedule_view(Some(url), Some(schedule_service), agent_id).await,
             Err(e) => Html(format!("<div class=\"error\">Failed to update schedule: {}</div>", e)),
         }
     } else {
@@ -266,18 +252,13 @@ pub async fn update_schedule(database_url: Option<String>, schedule_id: String,
 }
 
 // Delete schedule
-pub async fn delete_schedule(database_url: Option<String>, schedule_id: String) -> Html<String> {
+pub async fn delete_schedule(database_url: Option<String>, schedule_service: Option<ScheduleServiceImpl>, schedule_id: String) -> Html<String> {
     let id = match uuid::Uuid::parse_str(&schedule_id) {
         Ok(u) => u,
         Err(_) => return Html("<div class=\"error\">Invalid schedule ID</div>".to_string()),
     };
     
-    if let Some(url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html("<div class=\"error\">Failed to connect to database</div>".to_string()),
-        };
-        let schedule_service = ScheduleServiceImpl::new(pool);
+    if let (Some(url), Some(schedule_service)) = (database_url, schedule_service) {
         
         // Get agent_id before deleting
         let agent_id = match schedule_service.get_schedule(id).await {
@@ -286,7 +267,7 @@ pub async fn delete_schedule(database_url: Option<String>, schedule_id: String)
         };
         
         match schedule_service.delete_schedule(id).await {
-            Ok(_) => agent_schedule_view(Some(url), agent_id).await,
+            Ok(_) => agent_schedule_view(Some(url), Some(schedule_service), agent_id).await,
             Err(e) => Html(format!("<div class=\"error\">Failed to delete schedule: {}</div>", e)),
         }
     } else {
@@ -295,18 +276,13 @@ pub async fn delete_schedule(database_url: Option<String>, schedule_id: String)
 }
 
 // Toggle schedule on/off
-pub async fn toggle_schedule(database_url: Option<String>, schedule_id: String) -> Html<String> {
+pub async fn toggle_schedule(database_url: Option<String>, schedule_service: Option<ScheduleServiceImpl>, schedule_id: String) -> Html<String> {
     let id = match uuid::Uuid::parse_str(&schedule_id) {
         Ok(u) => u,
         Err(_) => return Html("<div class=\"error\">Invalid schedule ID</div>".to_string()),
     };
     
-    if let Some(url) = database_url {
-        let pool = match sqlx::postgres::PgPool::connect(&url).await {
-            Ok(p) => p,
-            Err(_) => return Html("<div class=\"error\">Failed to connect to database</div>".to_string()),
-        };
-        let schedule_service = ScheduleServiceImpl::new(pool);
+    if let (Some(url), Some(schedule_service)) = (database_url, schedule_service) {
         
         // Get the agent_id from the schedule so we can return the updated list
         let agent_id = match schedule_service.get_schedule(id).await {
@@ -315,7 +291,7 @@ pub async fn toggle_schedule(database_url: Option<String>, schedule_id: String)
         };
         
         match schedule_service.toggle_schedule(id).await {
-            Ok(_) => agent_schedule_view(Some(url), agent_id).await,
+            Ok(_) => agent_schedule_view(Some(url), Some(schedule_service), agent_id).await,
             Err(e) => Html(format!("<div class=\"error\">Failed to toggle schedule: {}</div>", e)),
         }
     } else {
@@ -324,7 +300,7 @@ pub async fn toggle_schedule(database_url: Option<String>, schedule_id: String)
 }
 
 // Simple URL decode function
-fn urldecode(s: &str) -> String {
+pub(super) fn urldecode(s: &str) -> String {
     let mut result = String::with_capacity(s.len());
     let chars: Vec<char> = s.chars().collect();
     let mut i = 0;
@@ -350,7 +326,7 @@ fn urldecode(s: &str) -> String {
 }
 
 // Simple HTML escape function
-fn html_escape(s: &str) -> String {
+pub(super) fn html_escape(s: &str) -> String {
     s.replace('&', "&amp;")
         .replace('<', "&lt;")
         .replace('>', "&gt;")
@@ -22,6 +22,8 @@ pub const HTML_HEADER: &str = r#"<!DOCTYPE html>
                     <a href="/">Dashboard</a>
                     <a href="/agents">Agents</a>
                     <a href="/kb">KB</a>
+                    <a href="/work">Work</a>
+                    <a href="/kv">Blackboard</a>
                     <a href="/agents">⏰ Schedules</a>
                 </div>
             </div>
@@ -995,7 +997,7 @@ textarea.form-control {
     overflow: hidden;
 }
 
-.note-tags, .note-tags-detail {
+.note-tags, .note-tags-detail, .agent-groups {
     display: flex;
     flex-wrap: wrap;
     gap: 4px;
@@ -1803,6 +1805,21 @@ textarea.form-control {
     color: var(--color-text-muted);
 }
 
+.agent-auto-reply {
+    font-size: 12px;
+    color: var(--color-text-muted);
+    margin-top: 4px;
+}
+
+.agent-auto-reply.inactive {
+    opacity: 0.5;
+}
+
+.badge-warning {
+    background: rgba(245, 158, 11, 0.1);
+    color: #b45309;
+}
+
 .empty-state {
     color: var(--color-text-muted);
     font-style: italic;
@@ -1940,11 +1957,191 @@ textarea.form-control {
     margin-left: 8px;
 }
 
+.mail-filters {
+    display: flex;
+    flex-wrap: wrap;
+    align-items: center;
+    gap: 8px;
+    margin-bottom: 16px;
+}
+
+.mail-filters input,
+.mail-filters select {
+    width: auto;
+}
+
+.mail-receipts {
+    list-style: none;
+    margin: 10px 0 0;
+    padding: 0;
+    font-size: 12px;
+}
+
+.mail-receipts li {
+    display: flex;
+    align-items: center;
+    gap: 8px;
+    padding: 2px 0;
+}
+
+.mail-receipts .badge {
+    margin-left: 0;
+}
+
+.mail-attachments {
+    list-style: none;
+    margin: 10px 0 0;
+    padding: 0;
+    font-size: 12px;
+}
+
+.mail-attachments li {
+    padding: 2px 0;
+}
+
+.mail-payload {
+    margin-top: 10px;
+    font-size: 12px;
+}
+
+.mail-payload summary {
+    cursor: pointer;
+    color: var(--color-text-secondary);
+}
+
+.mail-payload pre {
+    margin: 6px 0 0;
+    padding: 8px 10px;
+    background: var(--color-surface-sunken);
+    border: 1px solid var(--color-border-light);
+    border-radius: var(--radius-md);
+    font-family: var(--font-mono);
+    overflow-x: auto;
+}
+
 .badge-error {
     background: rgba(220, 53, 69, 0.1);
     color: #dc3545;
 }
 
+/* --- Work item graph --- */
+.work-projects {
+    display: flex;
+    flex-wrap: wrap;
+    gap: 8px;
+    margin-bottom: 16px;
+}
+
+.work-graph {
+    overflow-x: auto;
+    padding: 12px;
+    background: var(--color-surface);
+    border: 1px solid var(--color-border);
+    border-radius: 12px;
+}
+
+.work-edge {
+    fill: none;
+    stroke: var(--color-text-muted);
+    stroke-width: 1.5;
+}
+
+.work-edge-head {
+    fill: var(--color-text-muted);
+}
+
+.work-node rect {
+    stroke-width: 1.5;
+}
+
+.work-node-title {
+    font-family: var(--font-sans);
+    font-size: 13px;
+    font-weight: 600;
+    fill: var(--color-text);
+}
+
+.work-node-detail {
+    font-family: var(--font-mono);
+    font-size: 11px;
+    fill: var(--color-text-secondary);
+}
+
+.work-node-ready rect, .work-badge-ready {
+    fill: var(--color-success-bg);
+    background: var(--color-success-bg);
+    stroke: var(--color-success);
+    color: var(--color-success);
+}
+
+.work-node-waiting rect, .work-badge-waiting {
+    fill: #fffbeb;
+    background: #fffbeb;
+    stroke: #f59e0b;
+    color: #b45309;
+}
+
+.work-node-complete rect, .work-badge-complete {
+    fill: var(--color-surface-sunken);
+    background: var(--color-surface-sunken);
+    stroke: var(--color-border);
+    color: var(--color-text-muted);
+}
+
+/* --- Blackboard --- */
+.kv-value {
+    white-space: pre-wrap;
+    word-break: break-word;
+}
+
+/* --- Approval requests --- */
+.approval-list {
+    display: flex;
+    flex-direction: column;
+    gap: 12px;
+    margin-bottom: 24px;
+}
+
+.approval-card {
+    background: var(--color-surface);
+    border: 1px solid var(--color-warning-border);
+    border-left: 4px solid var(--color-warning);
+    border-radius: var(--radius-lg);
+    padding: 16px 20px;
+}
+
+.approval-card.approval-decided {
+    border-color: var(--color-border-light);
+    color: var(--color-text-secondary);
+}
+
+.approval-header {
+    display: flex;
+    align-items: center;
+    gap: 10px;
+    flex-wrap: wrap;
+}
+
+.approval-description {
+    white-space: pre-wrap;
+    margin: 8px 0 12px;
+}
+
+.approval-option {
+    display: block;
+    margin-bottom: 6px;
+    font-size: 14px;
+}
+
+.approval-form .form-row {
+    margin: 12px 0;
+}
+
+.approval-form .form-actions {
+    display: flex;
+    gap: 8px;
+}
+
 "##;
 
 pub fn wrap_content(content: impl AsRef<str>) -> String {
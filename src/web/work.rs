@@ -0,0 +1,144 @@
+use axum::response::Html;
+use std::collections::HashMap;
+
+use crate::services::work::domain::{WorkGraph, WorkState};
+use crate::services::work::{WorkService, WorkServiceImpl};
+use crate::storage::AnyStorage;
+use crate::web::templates;
+
+use super::mail_service;
+use super::schedules::html_escape;
+
+const NODE_WIDTH: usize = 220;
+const NODE_HEIGHT: usize = 52;
+const COLUMN_GAP: usize = 70;
+const ROW_GAP: usize = 20;
+const MARGIN: usize = 10;
+
+// Dependency graph of work items, optionally for one project
+pub async fn work_view(database_url: Option<String>, params: HashMap<String, String>) -> Html<String> {
+    let (storage, mail_storage) = match (
+        AnyStorage::connect(database_url.as_deref()).await,
+        AnyStorage::connect(database_url.as_deref()).await,
+    ) {
+        (Ok(s), Ok(m)) => (s, m),
+        _ => return Html(templates::error_page("Failed to connect to database")),
+    };
+    let service = WorkServiceImpl::new(storage, mail_service(mail_storage));
+
+    let project = params.get("project").map(|p| p.trim()).filter(|p| !p.is_empty());
+    let all = match service.get_work_graph(None).await {
+        Ok(graph) => graph,
+        Err(_) => return Html(templates::error_page("Failed to load work items")),
+    };
+    let graph = match project {
+        Some(project) => service.get_work_graph(Some(project)).await.unwrap_or_default(),
+        None => all.clone(),
+    };
+
+    let mut projects: Vec<&str> = all.items.iter().filter_map(|(item, _)| item.project.as_deref()).collect();
+    projects.sort();
+    projects.dedup();
+
+    let mut content = String::new();
+    content.push_str("<div class=\"back-link\">");
+    content.push_str("<a href=\"/\" class=\"btn btn-secondary btn-sm\">&larr; Back to Dashboard</a>");
+    content.push_str("</div>");
+    content.push_str(&format!(
+        "<h2>Work{} <span class=\"section-count\">{} items</span></h2>",
+        project.map(|p| format!(": {}", html_escape(p))).unwrap_or_default(),
+        graph.items.len()
+    ));
+    if !projects.is_empty() {
+        content.push_str("<div class=\"work-projects\">");
+        content.push_str("<a href=\"/work\" class=\"btn btn-sm btn-secondary\">All</a>");
+        for p in projects {
+            content.push_str(&format!(
+                "<a href=\"/work?project={}\" class=\"btn btn-sm btn-secondary\">{}</a>",
+                urlencoding::encode(p),
+                html_escape(p)
+            ));
+        }
+        content.push_str("</div>");
+    }
+
+    if graph.items.is_empty() {
+        content.push_str("<p class=\"empty-state\">No work items yet. Add some with <code>agent-office work add</code>.</p>");
+    } else {
+        content.push_str("<p><small>Arrows point from each item to the items waiting on it.</small> ");
+        for state in [WorkState::Ready, WorkState::Waiting, WorkState::Complete] {
+            content.push_str(&format!("<span class=\"badge work-badge-{0}\">{0}</span> ", state.as_str()));
+        }
+        content.push_str("</p>");
+        content.push_str(&format!("<div class=\"work-graph\">{}</div>", graph_svg(&graph)));
+    }
+
+    Html(templates::wrap_content(content))
+}
+
+/// The graph as an SVG, one column per dependency depth
+fn graph_svg(graph: &WorkGraph) -> String {
+    let layers = graph.layers();
+    let mut position = vec![(0, 0); graph.items.len()];
+    for (column, layer) in layers.iter().enumerate() {
+        for (row, &index) in layer.iter().enumerate() {
+            position[index] = (
+                MARGIN + column * (NODE_WIDTH + COLUMN_GAP),
+                MARGIN + row * (NODE_HEIGHT + ROW_GAP),
+            );
+        }
+    }
+    let rows = layers.iter().map(Vec::len).max().unwrap_or(0);
+    let width = 2 * MARGIN + layers.len() * (NODE_WIDTH + COLUMN_GAP) - COLUMN_GAP;
+    // Edges that skip a column dip below the row so they don't run behind
+    // the items in between
+    let dip = NODE_HEIGHT + ROW_GAP;
+    let skips = graph.dependencies.iter().any(|&(item, dependency)| position[item].0 - position[dependency].0 > NODE_WIDTH + COLUMN_GAP);
+    let height = 2 * MARGIN + rows * (NODE_HEIGHT + ROW_GAP) - ROW_GAP + if skips { dip } else { 0 };
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">",
+        width, height
+    );
+    svg.push_str("<defs><marker id=\"work-arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"7\" markerHeight=\"7\" orient=\"auto\"><path d=\"M0,0 L10,5 L0,10 z\" class=\"work-edge-head\"/></marker></defs>");
+
+    for &(item, dependency) in &graph.dependencies {
+        let (from_x, from_y) = position[dependency];
+        let (to_x, to_y) = position[item];
+        let (x1, y1) = (from_x + NODE_WIDTH, from_y + NODE_HEIGHT / 2);
+        let (x2, y2) = (to_x, to_y + NODE_HEIGHT / 2);
+        let bend = COLUMN_GAP / 2;
+        let (c1, c2) = if to_x - from_x > NODE_WIDTH + COLUMN_GAP {
+            let low = y1.max(y2) + dip;
+            ((x1 + bend, low), (x2 - bend, low))
+        } else {
+            ((x1 + bend, y1), (x2 - bend, y2))
+        };
+        svg.push_str(&format!(
+            "<path class=\"work-edge\" d=\"M{} {} C{} {}, {} {}, {} {}\" marker-end=\"url(#work-arrow)\"/>",
+            x1, y1, c1.0, c1.1, c2.0, c2.1, x2, y2
+        ));
+    }
+
+    for (index, (item, state)) in graph.items.iter().enumerate() {
+        let (x, y) = position[index];
+        let mut title: String = item.title.chars().take(28).collect();
+        if item.title.chars().count() > 28 {
+            title.push('…');
+        }
+        let detail = match item.assignee {
+            Some(ref assignee) => format!("{} · {}", item.short_id(), assignee),
+            None => item.short_id(),
+        };
+        svg.push_str(&format!(
+            "<g class=\"work-node work-node-{}\"><title>{}</title><rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"8\"/><text x=\"{}\" y=\"{}\" class=\"work-node-title\">{}</text><text x=\"{}\" y=\"{}\" class=\"work-node-detail\">{}</text></g>",
+            state.as_str(),
+            html_escape(&item.title),
+            x, y, NODE_WIDTH, NODE_HEIGHT,
+            x + 12, y + 22, html_escape(&title),
+            x + 12, y + 40, html_escape(&detail),
+        ));
+    }
+    svg.push_str("</svg>");
+    svg
+}
@@ -62,14 +62,14 @@ async fn test_full_mail_workflow() {
     }
     
     // Mark mail as read
-    service.mark_mail_as_read(mail1.id).await.unwrap();
+    service.mark_mail_as_read(mail1.id, None, None).await.unwrap();
     println!("\nMarked mail {} as read", mail1.id);
     
     // Check Alice's outbox
     let alice_outbox = service.get_mailbox_outbox(alice_mailbox.id).await.unwrap();
     println!("\nAlice's outbox has {} mail(s)", alice_outbox.len());
     for mail in &alice_outbox {
-        let recipient = service.get_agent_by_mailbox(mail.to_mailbox_id).await.unwrap();
+        let recipient = service.get_agent_by_mailbox(mail.to_mailbox_ids[0]).await.unwrap();
         println!("  {} - {} (to {})", mail.id, mail.subject, recipient.name);
     }
     